@@ -47,6 +47,10 @@ pub enum BspError {
     NomParsingError,
     #[error("Bsp version is not 30: {version}")]
     BspVersion { version: i32 },
+    #[error("BSP has no world model")]
+    NoWorldModel,
+    #[error("Visibility computation is cancelled")]
+    VisCancelled,
     #[error("Cannot read file `{path}`: {source}")]
     IOError {
         #[source]
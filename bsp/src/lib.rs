@@ -1,14 +1,21 @@
-mod constants;
+pub mod constants;
 pub mod error;
+mod lightmap;
 mod parser;
+mod trace;
 mod types;
 mod utils;
+mod vis;
 mod writer;
 
-pub use parser::parse_bsp;
+pub use parser::{parse_bsp, parse_entities, parse_entities_exact};
 pub use types::Bsp;
+pub use writer::{entity_lump, replace_entity_lump, replace_entity_lump_bytes, write_entities};
 
+pub use lightmap::{LightmapExtents, LUXEL_SIZE, MAX_LIGHTMAPS, TEX_SPECIAL, UNUSED_STYLE};
+pub use trace::{Trace, TracePlane, DIST_EPSILON, HULL_SIZES};
 pub use types::*;
+pub use vis::{VisMode, VisOptions, VisProgress, VisStage, VisStats};
 
 pub use glam::Vec3;
 
@@ -71,6 +78,45 @@ mod test {
         Bsp::from_bytes(&file_again).unwrap();
     }
 
+    #[test]
+    fn replace_entities_bad_header() {
+        let mut file = include_bytes!("tests/normal.bsp").to_vec();
+
+        // negative length of the entity lump
+        file[8..12].copy_from_slice(&(-1i32).to_le_bytes());
+        assert!(entity_lump(&file).is_err());
+
+        file[8..12].copy_from_slice(&i32::MAX.to_le_bytes());
+        file[4..8].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(replace_entity_lump_bytes(&file, b"").is_err());
+    }
+
+    #[test]
+    fn replace_entities() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.entities[0].insert("message".to_string(), "hello".to_string());
+
+        let res = replace_entity_lump(file, &bsp.entities).unwrap();
+        let parsed = Bsp::from_bytes(&res).unwrap();
+
+        assert_eq!(parsed.entities, bsp.entities);
+
+        let lump = |bytes: &[u8], idx: usize| {
+            let header = 4 + idx * 8;
+            let read = |offset: usize| {
+                i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+            };
+
+            bytes[read(header)..read(header) + read(header + 4)].to_vec()
+        };
+
+        for idx in 1..constants::HEADER_LUMPS {
+            assert_eq!(lump(file, idx), lump(&res, idx));
+        }
+    }
+
     #[test]
     fn parse_c1a3d() {
         let file = include_bytes!("tests/c1a3d.bsp");
@@ -0,0 +1,153 @@
+//! Where the luxels of every face are inside the lighting lump.
+use crate::{Bsp, Vec3};
+
+/// Texture units per luxel
+pub const LUXEL_SIZE: f64 = 16.;
+
+/// Max light styles of a face
+pub const MAX_LIGHTMAPS: usize = 4;
+
+/// Surface is neither lightmapped nor subdivided
+pub const TEX_SPECIAL: u32 = 1;
+
+/// Style slots set to this are not used
+pub const UNUSED_STYLE: u8 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightmapExtents {
+    /// Texture coordinates of the first luxel, in luxels
+    pub mins: [i32; 2],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl LightmapExtents {
+    pub fn luxel_count(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+impl Bsp {
+    /// Vertices of the face in winding order
+    pub fn face_vertices(&self, face_idx: usize) -> Vec<Vec3> {
+        let face = &self.faces[face_idx];
+
+        (0..face.edge_count as i32)
+            .map(|idx| {
+                let surf_edge = self.surf_edges[(face.first_edge + idx) as usize];
+                let edge = self.edges[surf_edge.unsigned_abs() as usize];
+                let vertex = if surf_edge >= 0 { edge[0] } else { edge[1] };
+
+                self.vertices[vertex as usize]
+            })
+            .collect()
+    }
+
+    /// Lightmap size of the face, same as `CalcSurfaceExtents` of the engine.
+    ///
+    /// Returns `None` for faces without lightmap.
+    pub fn face_lightmap_extents(&self, face_idx: usize) -> Option<LightmapExtents> {
+        let face = &self.faces[face_idx];
+        let texinfo = &self.texinfo[face.texinfo as usize];
+
+        if texinfo.flags & TEX_SPECIAL != 0 {
+            return None;
+        }
+
+        let vertices = self.face_vertices(face_idx);
+
+        if vertices.is_empty() {
+            return None;
+        }
+
+        let axes = [(texinfo.u, texinfo.u_offset), (texinfo.v, texinfo.v_offset)];
+
+        let (mins, maxs) = vertices.iter().fold(
+            ([f64::MAX; 2], [f64::MIN; 2]),
+            |(mut mins, mut maxs), vertex| {
+                for (axis, (vector, offset)) in axes.iter().enumerate() {
+                    let value = vertex.x as f64 * vector.x as f64
+                        + vertex.y as f64 * vector.y as f64
+                        + vertex.z as f64 * vector.z as f64
+                        + *offset as f64;
+
+                    mins[axis] = mins[axis].min(value);
+                    maxs[axis] = maxs[axis].max(value);
+                }
+
+                (mins, maxs)
+            },
+        );
+
+        let mins_luxel = mins.map(|e| (e / LUXEL_SIZE).floor() as i32);
+        let maxs_luxel = maxs.map(|e| (e / LUXEL_SIZE).ceil() as i32);
+
+        Some(LightmapExtents {
+            mins: mins_luxel,
+            width: (maxs_luxel[0] - mins_luxel[0]) as usize + 1,
+            height: (maxs_luxel[1] - mins_luxel[1]) as usize + 1,
+        })
+    }
+
+    /// Range of luxels in [`Bsp::lightmap`] of a style slot of the face.
+    ///
+    /// Returns `None` if the face has no lightmap for the slot.
+    pub fn face_lightmap_range(
+        &self,
+        face_idx: usize,
+        style_slot: usize,
+    ) -> Option<std::ops::Range<usize>> {
+        let face = &self.faces[face_idx];
+
+        if face.lightmap_offset < 0
+            || *face.styles.get(style_slot)? == UNUSED_STYLE
+            // styles are packed at the start
+            || face.styles[..style_slot].contains(&UNUSED_STYLE)
+        {
+            return None;
+        }
+
+        let extents = self.face_lightmap_extents(face_idx)?;
+
+        // offset is in bytes
+        let start = face.lightmap_offset as usize / 3 + style_slot * extents.luxel_count();
+        let range = start..start + extents.luxel_count();
+
+        (range.end <= self.lightmap.len()).then_some(range)
+    }
+
+    /// Luxels of a style slot of the face, row by row
+    pub fn face_lightmap(&self, face_idx: usize, style_slot: usize) -> Option<&[[u8; 3]]> {
+        self.face_lightmap_range(face_idx, style_slot)
+            .map(|range| &self.lightmap[range])
+    }
+
+    pub fn face_lightmap_mut(
+        &mut self,
+        face_idx: usize,
+        style_slot: usize,
+    ) -> Option<&mut [[u8; 3]]> {
+        self.face_lightmap_range(face_idx, style_slot)
+            .map(|range| &mut self.lightmap[range])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn faces_fit_lump() {
+        let bsp = &Bsp::from_bytes(include_bytes!("tests/c1a3d.bsp")).unwrap();
+
+        let luxels = (0..bsp.faces.len())
+            .flat_map(|face_idx| {
+                (0..MAX_LIGHTMAPS).filter_map(move |slot| bsp.face_lightmap_range(face_idx, slot))
+            })
+            .map(|range| range.len())
+            .sum::<usize>();
+
+        // every luxel belongs to exactly one face
+        assert_eq!(luxels, bsp.lightmap.len());
+    }
+}
@@ -46,12 +46,31 @@ fn parse_entity(i: &'_ str) -> SResult<'_, Entity> {
 }
 
 // hacky stuffs to avoid parsing bytes :DD
-fn parse_entities(i: &'_ [u8]) -> Result<Vec<Entity>, BspEntitiesError> {
+fn parse_entities_with_rest(i: &'_ [u8]) -> Result<(String, Vec<Entity>), BspEntitiesError> {
     // HOLY FUCKING RETARD
     let s = String::from_utf8_lossy(i).replace(std::char::REPLACEMENT_CHARACTER, "");
 
-    let (_, res) =
-        many0(between_braces(parse_entity))(s.as_str()).map_err(|_| BspEntitiesError::Parse)?;
+    // the lump ends with null
+    let s = s.split('\0').next().unwrap_or_default();
+
+    let (rest, res) =
+        many0(between_braces(parse_entity))(s).map_err(|_| BspEntitiesError::Parse)?;
+
+    Ok((rest.to_string(), res))
+}
+
+/// Anything after the last entity is ignored like the engine does
+pub fn parse_entities(i: &'_ [u8]) -> Result<Vec<Entity>, BspEntitiesError> {
+    parse_entities_with_rest(i).map(|(_, res)| res)
+}
+
+/// Same as [`parse_entities`] but text after the last entity is an error
+pub fn parse_entities_exact(i: &'_ [u8]) -> Result<Vec<Entity>, BspEntitiesError> {
+    let (rest, res) = parse_entities_with_rest(i)?;
+
+    if !rest.trim().is_empty() {
+        return BspEntitiesError::Parse.to_result();
+    }
 
     Ok(res)
 }
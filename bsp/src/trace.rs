@@ -0,0 +1,338 @@
+//! Collision against the hulls like `SV_RecursiveHullCheck` of the engine.
+use crate::{constants::MAX_MAP_HULLS, Bsp, LeafContent, Vec3};
+
+/// Impact points are kept this far in front of the plane
+pub const DIST_EPSILON: f32 = 0.03125;
+
+/// Mins and maxs of the box every hull is expanded by.
+///
+/// Hull 0 is a point, 1 is standing, 2 is large and 3 is crouching.
+pub const HULL_SIZES: [[[f32; 3]; 2]; MAX_MAP_HULLS] = [
+    [[0., 0., 0.], [0., 0., 0.]],
+    [[-16., -16., -36.], [16., 16., 36.]],
+    [[-32., -32., -32.], [32., 32., 32.]],
+    [[-16., -16., -18.], [16., 16., 18.]],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TracePlane {
+    /// Facing where the trace comes from
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trace {
+    /// Never leaves solid
+    pub all_solid: bool,
+    /// Starts in solid
+    pub start_solid: bool,
+    /// Goes through empty space
+    pub in_open: bool,
+    /// Goes through water, slime or lava
+    pub in_water: bool,
+    /// How far it gets before hitting something, 1 is all the way
+    pub fraction: f32,
+    pub end_position: Vec3,
+    /// What is hit, `None` if nothing is
+    pub plane: Option<TracePlane>,
+    /// Contents at the end position
+    pub contents: LeafContent,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum HullChild {
+    Node(usize),
+    Contents(LeafContent),
+}
+
+/// Hull 0 is the nodes and leaves, others are the clipnodes
+struct Hull<'a> {
+    bsp: &'a Bsp,
+    hull: usize,
+}
+
+impl Hull<'_> {
+    fn child(&self, num: i32) -> HullChild {
+        if num >= 0 {
+            return HullChild::Node(num as usize);
+        }
+
+        if self.hull == 0 {
+            let leaf = (-(num + 1)) as usize;
+
+            return HullChild::Contents(
+                self.bsp
+                    .leaves
+                    .get(leaf)
+                    .map(|leaf| leaf.contents)
+                    .unwrap_or(LeafContent::ContentsSolid),
+            );
+        }
+
+        HullChild::Contents(LeafContent::try_from(num).unwrap_or(LeafContent::Unknown))
+    }
+
+    /// Plane index and children of the node
+    fn node(&self, node: usize) -> (usize, [i32; 2]) {
+        if self.hull == 0 {
+            let node = &self.bsp.nodes[node];
+
+            (node.plane as usize, node.children.map(|child| child as i32))
+        } else {
+            let node = &self.bsp.clipnodes[node];
+
+            (node.plane as usize, node.children.map(|child| child as i32))
+        }
+    }
+
+    /// Distance of the point in front of the plane
+    fn distance(&self, plane: usize, point: Vec3) -> f32 {
+        let plane = &self.bsp.planes[plane];
+
+        plane.normal.dot(point) - plane.distance
+    }
+
+    fn point_contents(&self, mut num: i32, point: Vec3) -> LeafContent {
+        loop {
+            match self.child(num) {
+                HullChild::Contents(contents) => return contents,
+                HullChild::Node(node) => {
+                    let (plane, children) = self.node(node);
+
+                    num = if self.distance(plane, point) < 0. {
+                        children[1]
+                    } else {
+                        children[0]
+                    };
+                }
+            }
+        }
+    }
+
+    /// Returns false once something is hit
+    fn recursive_check(
+        &self,
+        head_node: i32,
+        num: i32,
+        (p1f, p2f): (f32, f32),
+        (p1, p2): (Vec3, Vec3),
+        trace: &mut Trace,
+    ) -> bool {
+        let node = match self.child(num) {
+            HullChild::Contents(contents) => {
+                if contents == LeafContent::ContentsSolid {
+                    trace.start_solid = true;
+                } else {
+                    trace.all_solid = false;
+
+                    if contents == LeafContent::ContentsEmpty {
+                        trace.in_open = true;
+                    } else {
+                        trace.in_water = true;
+                    }
+                }
+
+                return true;
+            }
+            HullChild::Node(node) => node,
+        };
+
+        let (plane_idx, children) = self.node(node);
+        let t1 = self.distance(plane_idx, p1);
+        let t2 = self.distance(plane_idx, p2);
+
+        if t1 >= 0. && t2 >= 0. {
+            return self.recursive_check(head_node, children[0], (p1f, p2f), (p1, p2), trace);
+        }
+
+        if t1 < 0. && t2 < 0. {
+            return self.recursive_check(head_node, children[1], (p1f, p2f), (p1, p2), trace);
+        }
+
+        // crossing point is put on the near side
+        let mut frac = if t1 < 0. {
+            (t1 + DIST_EPSILON) / (t1 - t2)
+        } else {
+            (t1 - DIST_EPSILON) / (t1 - t2)
+        }
+        .clamp(0., 1.);
+
+        let mut midf = p1f + (p2f - p1f) * frac;
+        let mut mid = p1 + (p2 - p1) * frac;
+
+        let side = (t1 < 0.) as usize;
+
+        // up to the node
+        if !self.recursive_check(head_node, children[side], (p1f, midf), (p1, mid), trace) {
+            return false;
+        }
+
+        // past the node
+        if self.point_contents(children[side ^ 1], mid) != LeafContent::ContentsSolid {
+            return self.recursive_check(
+                head_node,
+                children[side ^ 1],
+                (midf, p2f),
+                (mid, p2),
+                trace,
+            );
+        }
+
+        // never got out of solid
+        if trace.all_solid {
+            return false;
+        }
+
+        // other side is solid, this is the impact
+        let plane = &self.bsp.planes[plane_idx];
+
+        trace.plane = Some(if side == 0 {
+            TracePlane {
+                normal: plane.normal,
+                distance: plane.distance,
+            }
+        } else {
+            TracePlane {
+                normal: -plane.normal,
+                distance: -plane.distance,
+            }
+        });
+
+        // the engine also backs up when it ends up in solid
+        while self.point_contents(head_node, mid) == LeafContent::ContentsSolid {
+            frac -= 0.1;
+
+            if frac < 0. {
+                trace.fraction = midf;
+                trace.end_position = mid;
+                return false;
+            }
+
+            midf = p1f + (p2f - p1f) * frac;
+            mid = p1 + (p2 - p1) * frac;
+        }
+
+        trace.fraction = midf;
+        trace.end_position = mid;
+
+        false
+    }
+}
+
+impl Bsp {
+    /// Contents of the world at the point, for a hull centered on it.
+    ///
+    /// # Panics
+    ///
+    /// If the hull is not less than [`MAX_MAP_HULLS`].
+    pub fn point_contents(&self, hull: usize, point: Vec3) -> LeafContent {
+        self.model_point_contents(0, hull, point)
+    }
+
+    /// Same as [`Bsp::point_contents`] for a brush model, the point is relative to its origin
+    pub fn model_point_contents(&self, model: usize, hull: usize, point: Vec3) -> LeafContent {
+        let head_node = self.models[model].head_nodes[hull];
+
+        Hull { bsp: self, hull }.point_contents(head_node, point)
+    }
+
+    /// Moves a hull centered on `start` towards `end` through the world until it hits solid.
+    ///
+    /// # Panics
+    ///
+    /// If the hull is not less than [`MAX_MAP_HULLS`].
+    pub fn trace(&self, hull: usize, start: Vec3, end: Vec3) -> Trace {
+        self.trace_model(0, hull, start, end)
+    }
+
+    /// Same as [`Bsp::trace`] for a brush model, the points are relative to its origin
+    pub fn trace_model(&self, model: usize, hull: usize, start: Vec3, end: Vec3) -> Trace {
+        let head_node = self.models[model].head_nodes[hull];
+        let hull = Hull { bsp: self, hull };
+
+        let mut trace = Trace {
+            all_solid: true,
+            start_solid: false,
+            in_open: false,
+            in_water: false,
+            fraction: 1.,
+            end_position: end,
+            plane: None,
+            contents: LeafContent::ContentsEmpty,
+        };
+
+        hull.recursive_check(head_node, head_node, (0., 1.), (start, end), &mut trace);
+        trace.contents = hull.point_contents(head_node, trace.end_position);
+
+        trace
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn player_start(bsp: &Bsp) -> Vec3 {
+        let origin = bsp
+            .entities
+            .iter()
+            .find(|entity| entity.get("classname").map(String::as_str) == Some("info_player_start"))
+            .and_then(|entity| entity.get("origin"))
+            .unwrap()
+            .split_whitespace()
+            .map(|value| value.parse::<f32>().unwrap())
+            .collect::<Vec<f32>>();
+
+        Vec3::from_slice(&origin)
+    }
+
+    #[test]
+    fn outside_is_solid() {
+        let bsp = Bsp::from_bytes(include_bytes!("tests/c1a3d.bsp")).unwrap();
+
+        for hull in 0..MAX_MAP_HULLS {
+            assert_eq!(
+                bsp.point_contents(hull, Vec3::splat(20000.)),
+                LeafContent::ContentsSolid
+            );
+        }
+    }
+
+    #[test]
+    fn land_on_floor() {
+        let bsp = Bsp::from_bytes(include_bytes!("tests/c1a3d.bsp")).unwrap();
+        let start = player_start(&bsp);
+
+        for hull in [1, 3] {
+            let trace = bsp.trace(hull, start, start - Vec3::Z * 4096.);
+
+            assert!(!trace.start_solid);
+            assert!(trace.fraction < 1.);
+
+            let plane = trace.plane.unwrap();
+
+            // floor faces up and the hull rests on it
+            assert!(plane.normal.z > 0.7);
+            assert!(
+                (plane.normal.dot(trace.end_position) - plane.distance).abs() <= DIST_EPSILON * 2.
+            );
+            assert_ne!(
+                bsp.point_contents(hull, trace.end_position),
+                LeafContent::ContentsSolid
+            );
+        }
+    }
+
+    #[test]
+    fn start_in_solid() {
+        let bsp = Bsp::from_bytes(include_bytes!("tests/c1a3d.bsp")).unwrap();
+        let outside = Vec3::splat(20000.);
+
+        let trace = bsp.trace(1, outside, outside + Vec3::X);
+
+        assert!(trace.all_solid);
+        assert!(trace.start_solid);
+        assert_eq!(trace.contents, LeafContent::ContentsSolid);
+    }
+}
@@ -1,6 +1,5 @@
-use std::collections::HashMap;
-
 use glam::Vec3;
+use indexmap::IndexMap;
 use wad::types::MipTex;
 
 use nom::IResult as _IResult;
@@ -16,7 +15,8 @@ pub struct LumpHeader {
     pub length: i32,
 }
 
-pub type Entity = HashMap<String, String>;
+/// Keys are in the same order as in the entity lump
+pub type Entity = IndexMap<String, String>;
 
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -130,7 +130,7 @@ pub struct ClipNode {
     pub children: [i16; 2],
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub enum LeafContent {
     ContentsEmpty = -1,
@@ -0,0 +1,397 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use super::{
+    portal::LeafPortal,
+    winding::{VisPlane, Winding},
+};
+
+const ON_EPSILON: f64 = 0.1;
+
+/// Fixed size set of portals or leaves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet(Vec<u64>);
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        self.0[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    pub fn set(&mut self, idx: usize) {
+        self.0[idx / 64] |= 1 << (idx % 64);
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.iter().map(|e| e.count_ones() as usize).sum()
+    }
+
+    pub fn union_with(&mut self, other: &Self) {
+        self.0
+            .iter_mut()
+            .zip(other.0.iter())
+            .for_each(|(a, b)| *a |= b);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word_idx, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| word_idx * 64 + bit)
+        })
+    }
+}
+
+/// One way portal
+pub struct FlowPortal {
+    /// Normal points into `leaf`
+    pub plane: VisPlane,
+    pub winding: Winding,
+    /// Leaf the portal leads into, index into the vis leaves
+    pub leaf: usize,
+    /// Portals that might be seen through this portal
+    pub flood: BitSet,
+    /// Portals seen through this portal
+    pub vis: Option<BitSet>,
+}
+
+pub struct Flow {
+    pub portals: Vec<FlowPortal>,
+    /// Portals leading out of every leaf
+    pub leaf_portals: Vec<Vec<usize>>,
+}
+
+impl Flow {
+    /// `portals` leaves are BSP leaf indices, vis leaf 0 is BSP leaf 1
+    pub fn new(portals: Vec<LeafPortal>, leaf_count: usize) -> Self {
+        let mut res = Self {
+            portals: vec![],
+            leaf_portals: vec![vec![]; leaf_count],
+        };
+
+        let portal_count = portals.len() * 2;
+
+        for portal in portals {
+            let [front, back] = portal.leaves.map(|leaf| leaf - 1);
+
+            res.leaf_portals[front].push(res.portals.len());
+            res.portals.push(FlowPortal {
+                plane: portal.plane.flip(),
+                winding: portal.winding.clone(),
+                leaf: back,
+                flood: BitSet::new(portal_count),
+                vis: None,
+            });
+
+            res.leaf_portals[back].push(res.portals.len());
+            res.portals.push(FlowPortal {
+                plane: portal.plane,
+                winding: portal.winding,
+                leaf: front,
+                flood: BitSet::new(portal_count),
+                vis: None,
+            });
+        }
+
+        res
+    }
+
+    /// Portals in front of the portal that also have the portal behind them
+    fn portal_front(&self, portal: usize) -> BitSet {
+        let source = &self.portals[portal];
+        let mut res = BitSet::new(self.portals.len());
+
+        for (idx, target) in self.portals.iter().enumerate() {
+            if idx == portal {
+                continue;
+            }
+
+            let target_in_front = target
+                .winding
+                .0
+                .iter()
+                .any(|point| source.plane.distance_to(*point) > ON_EPSILON);
+
+            let source_behind = source
+                .winding
+                .0
+                .iter()
+                .any(|point| target.plane.distance_to(*point) < -ON_EPSILON);
+
+            if target_in_front && source_behind {
+                res.set(idx);
+            }
+        }
+
+        res
+    }
+
+    /// Rough visibility by flooding through portals in front of each other
+    pub fn base_vis(&mut self, progress: impl Fn(usize) + Sync, cancel: &AtomicBool) -> bool {
+        let done = AtomicUsize::new(0);
+
+        let floods = (0..self.portals.len())
+            .into_par_iter()
+            .map(|portal| {
+                if cancel.load(Ordering::Relaxed) {
+                    return None;
+                }
+
+                let front = self.portal_front(portal);
+                let mut flood = BitSet::new(self.portals.len());
+                let mut stack = vec![self.portals[portal].leaf];
+
+                while let Some(leaf) = stack.pop() {
+                    for &next in &self.leaf_portals[leaf] {
+                        if !front.get(next) || flood.get(next) {
+                            continue;
+                        }
+
+                        flood.set(next);
+                        stack.push(self.portals[next].leaf);
+                    }
+                }
+
+                progress(done.fetch_add(1, Ordering::Relaxed) + 1);
+
+                Some(flood)
+            })
+            .collect::<Option<Vec<BitSet>>>();
+
+        let Some(floods) = floods else {
+            return false;
+        };
+
+        self.portals
+            .iter_mut()
+            .zip(floods)
+            .for_each(|(portal, flood)| portal.flood = flood);
+
+        true
+    }
+
+    /// Exact visibility by clipping portals with separating planes.
+    ///
+    /// Portals seeing fewer portals go first so later ones can reuse their results.
+    pub fn full_vis(&mut self, progress: impl Fn(usize) + Sync, cancel: &AtomicBool) -> bool {
+        const BATCH_SIZE: usize = 64;
+
+        let mut order = (0..self.portals.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|&portal| self.portals[portal].flood.count());
+
+        for (batch_idx, batch) in order.chunks(BATCH_SIZE).enumerate() {
+            let results = batch
+                .par_iter()
+                .map(|&portal| self.portal_flow(portal, cancel))
+                .collect::<Vec<BitSet>>();
+
+            if cancel.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            batch
+                .iter()
+                .zip(results)
+                .for_each(|(&portal, vis)| self.portals[portal].vis = Some(vis));
+
+            progress(batch_idx * BATCH_SIZE + batch.len());
+        }
+
+        true
+    }
+
+    fn portal_flow(&self, portal: usize, cancel: &AtomicBool) -> BitSet {
+        let base = &self.portals[portal];
+
+        let mut thread = FlowThread {
+            flow: self,
+            base_plane: base.plane,
+            vis: BitSet::new(self.portals.len()),
+            cancel,
+        };
+
+        let head = FlowStack {
+            source: base.winding.clone(),
+            pass: None,
+            mightsee: base.flood.clone(),
+        };
+
+        thread.leaf_flow(base.leaf, &head);
+
+        thread.vis
+    }
+}
+
+struct FlowThread<'a> {
+    flow: &'a Flow,
+    /// Plane of the portal being flowed
+    base_plane: VisPlane,
+    vis: BitSet,
+    cancel: &'a AtomicBool,
+}
+
+struct FlowStack {
+    source: Winding,
+    /// `None` for the leaf right behind the base portal
+    pass: Option<Winding>,
+    mightsee: BitSet,
+}
+
+impl FlowThread<'_> {
+    fn leaf_flow(&mut self, leaf: usize, previous: &FlowStack) {
+        if self.cancel.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let flow = self.flow;
+
+        for &portal_idx in &flow.leaf_portals[leaf] {
+            if !previous.mightsee.get(portal_idx) {
+                continue;
+            }
+
+            let portal = &flow.portals[portal_idx];
+            let test = portal.vis.as_ref().unwrap_or(&portal.flood);
+
+            let mut mightsee = previous.mightsee.clone();
+            let mut more = false;
+
+            for ((might, test), vis) in mightsee.0.iter_mut().zip(&test.0).zip(&self.vis.0) {
+                *might &= test;
+                more |= *might & !vis != 0;
+            }
+
+            // nothing new to see
+            if !more && self.vis.get(portal_idx) {
+                continue;
+            }
+
+            let Some(pass) = portal.winding.chop(&self.base_plane, ON_EPSILON) else {
+                continue;
+            };
+
+            let Some(source) = previous.source.chop(&portal.plane.flip(), ON_EPSILON) else {
+                continue;
+            };
+
+            let Some(previous_pass) = &previous.pass else {
+                // the leaf right behind the base portal can only be blocked if coplanar
+                self.vis.set(portal_idx);
+
+                self.leaf_flow(
+                    portal.leaf,
+                    &FlowStack {
+                        source,
+                        pass: Some(pass),
+                        mightsee,
+                    },
+                );
+
+                continue;
+            };
+
+            let Some(pass) = clip_to_separators(&source, previous_pass, pass, false) else {
+                continue;
+            };
+
+            let Some(pass) = clip_to_separators(previous_pass, &source, pass, true) else {
+                continue;
+            };
+
+            self.vis.set(portal_idx);
+
+            self.leaf_flow(
+                portal.leaf,
+                &FlowStack {
+                    source,
+                    pass: Some(pass),
+                    mightsee,
+                },
+            );
+        }
+    }
+}
+
+/// Clips `target` to the planes going through an edge of `source` and a point of `pass`
+/// that have `source` and `pass` on different sides
+fn clip_to_separators(
+    source: &Winding,
+    pass: &Winding,
+    mut target: Winding,
+    flip_clip: bool,
+) -> Option<Winding> {
+    let source_len = source.0.len();
+
+    for idx in 0..source_len {
+        let next = (idx + 1) % source_len;
+        let edge = source.0[next] - source.0[idx];
+
+        for (pass_idx, &pass_point) in pass.0.iter().enumerate() {
+            let normal = edge.cross(pass_point - source.0[idx]);
+            let length = normal.length();
+
+            if length < ON_EPSILON {
+                continue;
+            }
+
+            let normal = normal / length;
+            let mut plane = VisPlane {
+                normal,
+                distance: pass_point.dot(normal),
+            };
+
+            // source should be on the back
+            let source_side = source
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(point_idx, _)| *point_idx != idx && *point_idx != next)
+                .map(|(_, point)| plane.distance_to(*point))
+                .find(|distance| distance.abs() > ON_EPSILON);
+
+            match source_side {
+                // planar with source portal
+                None => continue,
+                Some(distance) if distance > ON_EPSILON => plane = plane.flip(),
+                Some(_) => (),
+            }
+
+            // pass should be in front
+            let mut in_front = false;
+            let mut is_separating = true;
+
+            for (point_idx, point) in pass.0.iter().enumerate() {
+                if point_idx == pass_idx {
+                    continue;
+                }
+
+                let distance = plane.distance_to(*point);
+
+                if distance < -ON_EPSILON {
+                    is_separating = false;
+                    break;
+                }
+
+                if distance > ON_EPSILON {
+                    in_front = true;
+                }
+            }
+
+            if !is_separating || !in_front {
+                continue;
+            }
+
+            if flip_clip {
+                plane = plane.flip();
+            }
+
+            target = target.chop(&plane, ON_EPSILON)?;
+        }
+    }
+
+    Some(target)
+}
@@ -0,0 +1,276 @@
+//! Computes the potentially visible set of every leaf, the same job as hlvis.
+//!
+//! Portals are made from the BSP tree so the .prt file is not needed.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{error::BspError, Bsp};
+
+mod flow;
+mod portal;
+mod winding;
+
+use flow::{BitSet, Flow};
+use portal::make_leaf_portals;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisMode {
+    /// Only floods through portals in front of each other, like `hlvis -fast`
+    Fast,
+    /// Clips portals with separating planes
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisStage {
+    Portals,
+    BaseVis,
+    PortalFlow,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VisProgress {
+    pub stage: VisStage,
+    pub done: usize,
+    pub total: usize,
+}
+
+pub struct VisOptions {
+    pub mode: VisMode,
+    /// Called from many threads as work gets done
+    pub progress: Option<Box<dyn Fn(VisProgress) + Send + Sync>>,
+    /// Setting it stops the computation with [`BspError::VisCancelled`]
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for VisOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VisOptions {
+    pub fn new() -> Self {
+        Self {
+            mode: VisMode::Full,
+            progress: None,
+            cancel: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VisStats {
+    /// One way portals, twice the number of portals in a .prt file
+    pub portal_count: usize,
+    pub leaf_count: usize,
+    pub average_visible_leaves: f64,
+}
+
+/// Zero bytes are written as a zero followed by how many there are
+fn compress_row(row: &[u8]) -> Vec<u8> {
+    let mut res = vec![];
+    let mut idx = 0;
+
+    while idx < row.len() {
+        if row[idx] != 0 {
+            res.push(row[idx]);
+            idx += 1;
+            continue;
+        }
+
+        let zero_count = row[idx..]
+            .iter()
+            .take(255)
+            .take_while(|&&byte| byte == 0)
+            .count();
+
+        res.extend([0, zero_count as u8]);
+        idx += zero_count;
+    }
+
+    res
+}
+
+impl Bsp {
+    /// Computes visibility of the world leaves and replaces the visibility lump
+    pub fn compute_vis(&mut self, options: &VisOptions) -> Result<VisStats, BspError> {
+        let Some(world) = self.models.first() else {
+            return Err(BspError::NoWorldModel);
+        };
+
+        let leaf_count =
+            (world.vis_leaves_count.max(0) as usize).min(self.leaves.len().saturating_sub(1));
+
+        let no_cancel = AtomicBool::new(false);
+        let cancel = options.cancel.as_deref().unwrap_or(&no_cancel);
+
+        let report = |stage: VisStage, done: usize, total: usize| {
+            if let Some(progress) = &options.progress {
+                progress(VisProgress { stage, done, total });
+            }
+        };
+
+        report(VisStage::Portals, 0, 1);
+
+        let portals = make_leaf_portals(self);
+
+        report(VisStage::Portals, 1, 1);
+
+        let mut flow = Flow::new(portals, leaf_count);
+        let portal_count = flow.portals.len();
+
+        if !flow.base_vis(|done| report(VisStage::BaseVis, done, portal_count), cancel) {
+            return Err(BspError::VisCancelled);
+        }
+
+        if options.mode == VisMode::Full
+            && !flow.full_vis(
+                |done| report(VisStage::PortalFlow, done, portal_count),
+                cancel,
+            )
+        {
+            return Err(BspError::VisCancelled);
+        }
+
+        if cancel.load(Ordering::Relaxed) {
+            return Err(BspError::VisCancelled);
+        }
+
+        let row_size = leaf_count.div_ceil(8);
+        let mut visible_leaves = 0;
+
+        self.visibility.clear();
+
+        for leaf in 0..leaf_count {
+            let mut portals = BitSet::new(portal_count);
+
+            let mut row = vec![0u8; row_size];
+            row[leaf / 8] |= 1 << (leaf % 8);
+
+            for &portal in &flow.leaf_portals[leaf] {
+                let portal = &flow.portals[portal];
+
+                // neighbors are seen even if nothing past them is
+                row[portal.leaf / 8] |= 1 << (portal.leaf % 8);
+                portals.union_with(portal.vis.as_ref().unwrap_or(&portal.flood));
+            }
+
+            for portal in portals.iter() {
+                let other = flow.portals[portal].leaf;
+                row[other / 8] |= 1 << (other % 8);
+            }
+
+            visible_leaves += row
+                .iter()
+                .map(|byte| byte.count_ones() as usize)
+                .sum::<usize>();
+
+            // vis leaf 0 is leaf 1 in the BSP
+            self.leaves[leaf + 1].vis_offset = self.visibility.len() as i32;
+            self.visibility.extend(compress_row(&row));
+        }
+
+        Ok(VisStats {
+            portal_count,
+            leaf_count,
+            average_visible_leaves: visible_leaves as f64 / leaf_count.max(1) as f64,
+        })
+    }
+
+    /// Leaves visible from the leaf, decompressed from the visibility lump.
+    ///
+    /// Index 0 is leaf 1. Returns `None` if the leaf has no visibility data.
+    pub fn leaf_pvs(&self, leaf: usize) -> Option<Vec<bool>> {
+        let leaf_count = self.models.first()?.vis_leaves_count.max(0) as usize;
+        let offset = self.leaves.get(leaf)?.vis_offset;
+
+        if leaf == 0 || offset < 0 {
+            return None;
+        }
+
+        let mut bytes = self.visibility.get(offset as usize..)?.iter();
+        let mut row = Vec::with_capacity(leaf_count.div_ceil(8));
+
+        while row.len() < leaf_count.div_ceil(8) {
+            match *bytes.next()? {
+                0 => row.extend(std::iter::repeat_n(0, *bytes.next()? as usize)),
+                byte => row.push(byte),
+            }
+        }
+
+        Some(
+            (0..leaf_count)
+                .map(|leaf| row[leaf / 8] & (1 << (leaf % 8)) != 0)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vis(mode: VisMode) -> Bsp {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../tests/normal.bsp")).unwrap();
+
+        bsp.compute_vis(&VisOptions {
+            mode,
+            ..Default::default()
+        })
+        .unwrap();
+
+        bsp
+    }
+
+    #[test]
+    fn compress() {
+        assert_eq!(compress_row(&[1, 0, 0, 0, 2]), vec![1, 0, 3, 2]);
+        assert_eq!(compress_row(&[0; 300]), vec![0, 255, 0, 45]);
+    }
+
+    #[test]
+    fn sees_itself() {
+        let bsp = vis(VisMode::Full);
+        let leaf_count = bsp.models[0].vis_leaves_count as usize;
+
+        for leaf in 1..=leaf_count {
+            assert!(bsp.leaf_pvs(leaf).unwrap()[leaf - 1]);
+        }
+    }
+
+    #[test]
+    fn full_within_fast() {
+        let (fast, full) = (vis(VisMode::Fast), vis(VisMode::Full));
+        let leaf_count = fast.models[0].vis_leaves_count as usize;
+
+        for leaf in 1..=leaf_count {
+            let (fast, full) = (fast.leaf_pvs(leaf).unwrap(), full.leaf_pvs(leaf).unwrap());
+
+            assert!(fast.into_iter().zip(full).all(|(fast, full)| fast || !full));
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../tests/normal.bsp")).unwrap();
+
+        let res = bsp.compute_vis(&VisOptions {
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        });
+
+        assert!(matches!(res, Err(BspError::VisCancelled)));
+    }
+
+    #[test]
+    fn write_read() {
+        let bsp = vis(VisMode::Full);
+        let parsed = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+
+        assert_eq!(parsed.visibility, bsp.visibility);
+        assert_eq!(parsed.leaf_pvs(1), bsp.leaf_pvs(1));
+    }
+}
@@ -0,0 +1,310 @@
+use glam::DVec3;
+
+use crate::{Bsp, LeafContent};
+
+use super::winding::{VisPlane, Winding};
+
+/// Size of the windings portals are cut from
+const BASE_WINDING_SIZE: f64 = 65536.;
+
+/// Same as qbsp, clipping new portals by the node portals
+const NODE_PORTAL_EPSILON: f64 = 0.1;
+
+/// Same as qbsp, splitting portals into children
+const SPLIT_WINDING_EPSILON: f64 = 0.001;
+
+/// Gap between the world bounds and the head node portals
+const BOUNDS_PADDING: f64 = 8.;
+
+struct TreeNode {
+    plane: Option<VisPlane>,
+    children: [usize; 2],
+    /// BSP leaf index, `None` for nodes and the outside
+    leaf: Option<usize>,
+    portals: Vec<usize>,
+}
+
+struct TreePortal {
+    /// Normal points to `nodes[0]`
+    plane: VisPlane,
+    nodes: [usize; 2],
+    winding: Winding,
+}
+
+/// Portal between two leaves that can see each other
+pub struct LeafPortal {
+    /// Normal points to `leaves[0]`
+    pub plane: VisPlane,
+    /// BSP leaf indices
+    pub leaves: [usize; 2],
+    pub winding: Winding,
+}
+
+/// Bsp nodes where every reference to a leaf is its own node, the shared solid leaf would
+/// otherwise connect everything.
+struct PortalTree {
+    nodes: Vec<TreeNode>,
+    portals: Vec<TreePortal>,
+}
+
+impl PortalTree {
+    fn new(bsp: &Bsp, head: i32) -> Self {
+        let mut res = Self {
+            nodes: vec![],
+            portals: vec![],
+        };
+
+        res.add_node_r(bsp, head);
+
+        res
+    }
+
+    fn add_node_r(&mut self, bsp: &Bsp, node: i32) -> usize {
+        let idx = self.nodes.len();
+
+        if node < 0 {
+            self.nodes.push(TreeNode {
+                plane: None,
+                children: [0; 2],
+                leaf: Some((-node - 1) as usize),
+                portals: vec![],
+            });
+
+            return idx;
+        }
+
+        let bsp_node = &bsp.nodes[node as usize];
+        let plane = &bsp.planes[bsp_node.plane as usize];
+
+        self.nodes.push(TreeNode {
+            plane: Some(VisPlane {
+                normal: DVec3::new(
+                    plane.normal.x as f64,
+                    plane.normal.y as f64,
+                    plane.normal.z as f64,
+                ),
+                distance: plane.distance as f64,
+            }),
+            children: [0; 2],
+            leaf: None,
+            portals: vec![],
+        });
+
+        let front = self.add_node_r(bsp, bsp_node.children[0] as i32);
+        let back = self.add_node_r(bsp, bsp_node.children[1] as i32);
+
+        self.nodes[idx].children = [front, back];
+
+        idx
+    }
+
+    fn add_portal(&mut self, portal: TreePortal) {
+        let idx = self.portals.len();
+
+        self.nodes[portal.nodes[0]].portals.push(idx);
+        self.nodes[portal.nodes[1]].portals.push(idx);
+        self.portals.push(portal);
+    }
+
+    fn link_portal(&mut self, portal: usize, nodes: [usize; 2]) {
+        self.portals[portal].nodes = nodes;
+        self.nodes[nodes[0]].portals.push(portal);
+        self.nodes[nodes[1]].portals.push(portal);
+    }
+
+    /// Six portals around the world connecting the head node to the outside
+    fn make_head_portals(&mut self, head: usize, mins: DVec3, maxs: DVec3) {
+        let outside = self.nodes.len();
+
+        self.nodes.push(TreeNode {
+            plane: None,
+            children: [0; 2],
+            leaf: None,
+            portals: vec![],
+        });
+
+        let (mins, maxs) = (mins - BOUNDS_PADDING, maxs + BOUNDS_PADDING);
+
+        // normals point into the world
+        let planes = (0..3)
+            .flat_map(|axis| {
+                let mut normal = DVec3::ZERO;
+                normal[axis] = 1.;
+
+                [
+                    VisPlane {
+                        normal,
+                        distance: mins[axis],
+                    },
+                    VisPlane {
+                        normal: -normal,
+                        distance: -maxs[axis],
+                    },
+                ]
+            })
+            .collect::<Vec<VisPlane>>();
+
+        for (idx, plane) in planes.iter().enumerate() {
+            let winding = planes
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != idx)
+                .try_fold(
+                    Winding::base(plane, BASE_WINDING_SIZE),
+                    |winding, (_, other)| winding.chop(other, NODE_PORTAL_EPSILON),
+                );
+
+            if let Some(winding) = winding {
+                self.add_portal(TreePortal {
+                    plane: *plane,
+                    nodes: [head, outside],
+                    winding,
+                });
+            }
+        }
+    }
+
+    fn make_node_portal(&mut self, node: usize, plane: &VisPlane) {
+        let mut winding = Some(Winding::base(plane, BASE_WINDING_SIZE));
+
+        for &portal in &self.nodes[node].portals {
+            let Some(curr) = winding else {
+                break;
+            };
+
+            let portal = &self.portals[portal];
+
+            // keep the part inside the node
+            let clip_plane = if portal.nodes[0] == node {
+                portal.plane
+            } else {
+                portal.plane.flip()
+            };
+
+            winding = curr.chop(&clip_plane, NODE_PORTAL_EPSILON);
+        }
+
+        let Some(winding) = winding else {
+            return;
+        };
+
+        if winding.is_tiny() {
+            return;
+        }
+
+        let children = self.nodes[node].children;
+
+        self.add_portal(TreePortal {
+            plane: *plane,
+            nodes: children,
+            winding,
+        });
+    }
+
+    /// Moves the portals of the node down to its children
+    fn split_node_portals(&mut self, node: usize, plane: &VisPlane) {
+        let [front, back] = self.nodes[node].children;
+        let portals = std::mem::take(&mut self.nodes[node].portals);
+
+        for portal in portals {
+            let side = (self.portals[portal].nodes[1] == node) as usize;
+            let other = self.portals[portal].nodes[side ^ 1];
+
+            self.nodes[other].portals.retain(|&curr| curr != portal);
+
+            let with_child = |child: usize| {
+                if side == 0 {
+                    [child, other]
+                } else {
+                    [other, child]
+                }
+            };
+
+            let (front_winding, back_winding) = self.portals[portal]
+                .winding
+                .split(plane, SPLIT_WINDING_EPSILON);
+
+            let front_winding = front_winding.filter(|winding| !winding.is_tiny());
+            let back_winding = back_winding.filter(|winding| !winding.is_tiny());
+
+            match (front_winding, back_winding) {
+                (None, None) => (),
+                (Some(_), None) => self.link_portal(portal, with_child(front)),
+                (None, Some(_)) => self.link_portal(portal, with_child(back)),
+                (Some(front_winding), Some(back_winding)) => {
+                    let portal_plane = self.portals[portal].plane;
+
+                    self.portals[portal].winding = front_winding;
+                    self.link_portal(portal, with_child(front));
+
+                    self.add_portal(TreePortal {
+                        plane: portal_plane,
+                        nodes: with_child(back),
+                        winding: back_winding,
+                    });
+                }
+            }
+        }
+    }
+
+    fn make_portals_r(&mut self, node: usize) {
+        let Some(plane) = self.nodes[node].plane else {
+            return;
+        };
+
+        self.make_node_portal(node, &plane);
+        self.split_node_portals(node, &plane);
+
+        let [front, back] = self.nodes[node].children;
+
+        self.make_portals_r(front);
+        self.make_portals_r(back);
+    }
+}
+
+/// Same as hlbsp, sky leaves only connect to other sky leaves
+fn is_see_through(bsp: &Bsp, leaves: [usize; 2]) -> bool {
+    let [front, back] = leaves.map(|leaf| bsp.leaves[leaf].contents);
+
+    front != LeafContent::ContentsSolid
+        && back != LeafContent::ContentsSolid
+        && (front == LeafContent::ContentsSky) == (back == LeafContent::ContentsSky)
+}
+
+/// Portals between leaves of the world model
+pub fn make_leaf_portals(bsp: &Bsp) -> Vec<LeafPortal> {
+    let Some(world) = bsp.models.first() else {
+        return vec![];
+    };
+
+    let to_dvec3 = |v: glam::Vec3| DVec3::new(v.x as f64, v.y as f64, v.z as f64);
+
+    let mut tree = PortalTree::new(bsp, world.head_nodes[0]);
+
+    tree.make_head_portals(0, to_dvec3(world.mins), to_dvec3(world.maxs));
+    tree.make_portals_r(0);
+
+    let vis_leaves = world.vis_leaves_count.max(0) as usize;
+    let is_vis_leaf = |leaf: Option<usize>| leaf.filter(|leaf| (1..=vis_leaves).contains(leaf));
+
+    tree.portals
+        .into_iter()
+        .enumerate()
+        // split portals with both halves gone are not linked to their nodes anymore
+        .filter(|(idx, portal)| tree.nodes[portal.nodes[0]].portals.contains(idx))
+        .filter_map(|(_, portal)| {
+            let front = is_vis_leaf(tree.nodes[portal.nodes[0]].leaf)?;
+            let back = is_vis_leaf(tree.nodes[portal.nodes[1]].leaf)?;
+
+            if !is_see_through(bsp, [front, back]) {
+                return None;
+            }
+
+            Some(LeafPortal {
+                plane: portal.plane,
+                leaves: [front, back],
+                winding: portal.winding,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,168 @@
+use glam::DVec3;
+
+/// Edges shorter than this do not count towards a winding being big enough
+const EDGE_LENGTH: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct VisPlane {
+    pub normal: DVec3,
+    pub distance: f64,
+}
+
+impl VisPlane {
+    pub fn flip(&self) -> Self {
+        Self {
+            normal: -self.normal,
+            distance: -self.distance,
+        }
+    }
+
+    pub fn distance_to(&self, point: DVec3) -> f64 {
+        self.normal.dot(point) - self.distance
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Winding(pub Vec<DVec3>);
+
+impl Winding {
+    /// A big square on the plane
+    pub fn base(plane: &VisPlane, size: f64) -> Self {
+        let normal = plane.normal;
+        let abs = normal.abs();
+
+        let up = if abs.z >= abs.x && abs.z >= abs.y {
+            DVec3::X
+        } else {
+            DVec3::Z
+        };
+
+        let up = (up - normal * up.dot(normal)).normalize() * size;
+        let right = up.cross(normal);
+        let origin = normal * plane.distance;
+
+        Self(vec![
+            origin - right + up,
+            origin + right + up,
+            origin + right - up,
+            origin - right - up,
+        ])
+    }
+
+    fn sides(&self, plane: &VisPlane, epsilon: f64) -> (Vec<f64>, Vec<i8>, [usize; 3]) {
+        let mut counts = [0; 3];
+
+        let distances = self
+            .0
+            .iter()
+            .map(|point| plane.distance_to(*point))
+            .collect::<Vec<f64>>();
+
+        let sides = distances
+            .iter()
+            .map(|&distance| {
+                let side = if distance > epsilon {
+                    0
+                } else if distance < -epsilon {
+                    1
+                } else {
+                    2
+                };
+
+                counts[side as usize] += 1;
+                side
+            })
+            .collect();
+
+        (distances, sides, counts)
+    }
+
+    fn split_points(&self, plane: &VisPlane, distances: &[f64], sides: &[i8]) -> (Self, Self) {
+        let mut front = vec![];
+        let mut back = vec![];
+
+        for (idx, &point) in self.0.iter().enumerate() {
+            match sides[idx] {
+                0 => front.push(point),
+                1 => back.push(point),
+                _ => {
+                    front.push(point);
+                    back.push(point);
+                    continue;
+                }
+            }
+
+            let next_idx = (idx + 1) % self.0.len();
+
+            if sides[next_idx] == 2 || sides[next_idx] == sides[idx] {
+                continue;
+            }
+
+            let next = self.0[next_idx];
+            let fraction = distances[idx] / (distances[idx] - distances[next_idx]);
+
+            // keep axial coordinates exact
+            let mid = DVec3::from_array(std::array::from_fn(|axis| {
+                if plane.normal[axis] == 1. {
+                    plane.distance
+                } else if plane.normal[axis] == -1. {
+                    -plane.distance
+                } else {
+                    point[axis] + fraction * (next[axis] - point[axis])
+                }
+            }));
+
+            front.push(mid);
+            back.push(mid);
+        }
+
+        (Self(front), Self(back))
+    }
+
+    /// Splits the winding into the part in front of the plane and the part behind.
+    ///
+    /// A winding lying on the plane goes to the back.
+    pub fn split(&self, plane: &VisPlane, epsilon: f64) -> (Option<Self>, Option<Self>) {
+        let (distances, sides, counts) = self.sides(plane, epsilon);
+
+        if counts[0] == 0 {
+            return (None, Some(self.clone()));
+        }
+
+        if counts[1] == 0 {
+            return (Some(self.clone()), None);
+        }
+
+        let (front, back) = self.split_points(plane, &distances, &sides);
+
+        (Some(front), Some(back))
+    }
+
+    /// Keeps the part in front of the plane.
+    ///
+    /// A winding lying on the plane is kept.
+    pub fn chop(&self, plane: &VisPlane, epsilon: f64) -> Option<Self> {
+        let (distances, sides, counts) = self.sides(plane, epsilon);
+
+        if counts[1] == 0 {
+            return Some(self.clone());
+        }
+
+        if counts[0] == 0 {
+            return None;
+        }
+
+        Some(self.split_points(plane, &distances, &sides).0)
+    }
+
+    pub fn is_tiny(&self) -> bool {
+        (0..self.0.len())
+            .filter(|&idx| {
+                let next = self.0[(idx + 1) % self.0.len()];
+
+                (next - self.0[idx]).length() > EDGE_LENGTH
+            })
+            .count()
+            < 3
+    }
+}
@@ -9,12 +9,12 @@ use byte_writer::ByteWriter;
 
 use crate::{
     constants::{
-        HEADER_LUMPS, HEADER_LUMP_SIZE, LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES, LUMP_FACES,
-        LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES, LUMP_PLANES,
-        LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY,
+        BSP_VERSION, HEADER_LUMPS, HEADER_LUMP_SIZE, LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES,
+        LUMP_FACES, LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES,
+        LUMP_PLANES, LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY,
     },
     error::BspError,
-    parse_bsp, Bsp, ClipNode, Face, Leaf, Model, TexInfo,
+    parse_bsp, Bsp, ClipNode, Entity, Face, Leaf, Model, TexInfo,
 };
 
 impl Bsp {
@@ -68,25 +68,9 @@ impl Bsp {
         // write entities
         {
             let offset = writer.get_offset();
-            let mut entity_str = String::new();
-
-            self.entities.iter().for_each(|entity| {
-                // start with "{" then "\n"
-                // "\n" at the end of key-value pair
-                // " " to separate between key and value
-                // ends "}" and no need for "\n" because it is from previous pair
-                entity_str += "{\n";
-
-                entity.iter().for_each(|(key, value)| {
-                    entity_str += format!("\"{}\" \"{}\"\n", key, value).as_str()
-                });
-
-                // "\n" will separate entity
-                entity_str += "}\n";
-            });
 
             // null at the end for some reasons
-            writer.append_string(entity_str.as_str());
+            writer.append_string(write_entities(&self.entities).as_str());
             writer.append_u8(0);
 
             let length = writer.get_offset() - offset;
@@ -438,3 +422,114 @@ impl Bsp {
         writer.data
     }
 }
+
+/// Entity lump text without the null terminator
+pub fn write_entities(entities: &[Entity]) -> String {
+    let mut entity_str = String::new();
+
+    entities.iter().for_each(|entity| {
+        // start with "{" then "\n"
+        // "\n" at the end of key-value pair
+        // " " to separate between key and value
+        // ends "}" and no need for "\n" because it is from previous pair
+        entity_str += "{\n";
+
+        entity
+            .iter()
+            .for_each(|(key, value)| entity_str += format!("\"{}\" \"{}\"\n", key, value).as_str());
+
+        // "\n" will separate entity
+        entity_str += "}\n";
+    });
+
+    entity_str
+}
+
+fn lump_slices(bytes: &[u8]) -> Result<Vec<&[u8]>, BspError> {
+    let read_i32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|slice| i32::from_le_bytes(slice.try_into().unwrap()))
+            .ok_or(BspError::LumpParseError)
+    };
+
+    let version = read_i32(0)?;
+
+    if version != BSP_VERSION {
+        return BspError::BspVersion { version }.to_result();
+    }
+
+    (0..HEADER_LUMPS)
+        .map(|idx| {
+            let header = 4 + idx * HEADER_LUMP_SIZE;
+            let (offset, length) = (read_i32(header)?, read_i32(header + 4)?);
+
+            // negative values from a broken header are rejected before making the range
+            let offset = usize::try_from(offset).ok();
+            let length = usize::try_from(length).ok();
+
+            offset
+                .zip(length)
+                .and_then(|(offset, length)| Some(offset..offset.checked_add(length)?))
+                .and_then(|range| bytes.get(range))
+                .ok_or(BspError::LumpParseError)
+        })
+        .collect()
+}
+
+/// Entity lump of a BSP file as is, without the null terminator
+pub fn entity_lump(bytes: &[u8]) -> Result<&[u8], BspError> {
+    let lump = lump_slices(bytes)?[LUMP_ENTITIES];
+    let end = lump.iter().rposition(|&b| b != 0).map_or(0, |idx| idx + 1);
+
+    Ok(&lump[..end])
+}
+
+/// Replaces the entity lump of a BSP file.
+///
+/// Unlike [`Bsp::write_to_bytes`], other lumps are copied over byte for byte in the same order.
+pub fn replace_entity_lump(bytes: &[u8], entities: &[Entity]) -> Result<Vec<u8>, BspError> {
+    replace_entity_lump_bytes(bytes, write_entities(entities).as_bytes())
+}
+
+/// Same as [`replace_entity_lump`] but with the entity text as is, without the null terminator
+pub fn replace_entity_lump_bytes(bytes: &[u8], entity_text: &[u8]) -> Result<Vec<u8>, BspError> {
+    let read_i32 =
+        |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+    let lumps = lump_slices(bytes)?;
+
+    let mut entity_lump = entity_text.to_vec();
+    entity_lump.push(0);
+
+    let mut order = (0..HEADER_LUMPS).collect::<Vec<usize>>();
+    order.sort_by_key(|&idx| read_i32(4 + idx * HEADER_LUMP_SIZE));
+
+    let mut writer = ByteWriter::new();
+
+    writer.append_i32(BSP_VERSION);
+
+    let lump_headers_offset = writer.get_offset();
+    writer.append_u8_slice(&[0u8; HEADER_LUMP_SIZE * HEADER_LUMPS]);
+
+    for idx in order {
+        let lump = if idx == LUMP_ENTITIES {
+            entity_lump.as_slice()
+        } else {
+            lumps[idx]
+        };
+
+        let offset = writer.get_offset();
+        writer.append_u8_slice(lump);
+
+        let header = lump_headers_offset + idx * HEADER_LUMP_SIZE;
+
+        writer.replace_with_i32(header, offset as i32);
+        writer.replace_with_i32(header + 4, lump.len() as i32);
+
+        // lumps start at 4 byte boundaries
+        writer.align_size(4);
+    }
+
+    Ok(writer.data)
+}
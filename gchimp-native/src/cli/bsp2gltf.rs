@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use gchimp::modules::bsp2gltf::{Bsp2GltfFormat, Bsp2GltfOptions, bsp2gltf};
+
+use super::{Cli, CliRes};
+
+pub struct Bsp2Gltf;
+impl Cli for Bsp2Gltf {
+    fn name(&self) -> &'static str {
+        "bsp2gltf"
+    }
+
+    // .bsp file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut options = Bsp2GltfOptions::default();
+        let mut bsp_path = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+
+            match flag {
+                "--obj" => {
+                    options.format = Bsp2GltfFormat::Obj;
+                    continue;
+                }
+                "--no-lightmap" => {
+                    options.lightmap = false;
+                    continue;
+                }
+                "--props" => {
+                    options.props = true;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if !matches!(flag, "--wad" | "--game" | "--scale") {
+                if bsp_path.is_some() {
+                    self.cli_help();
+                    return CliRes::Err;
+                }
+
+                bsp_path = Some(arg.clone());
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                self.cli_help();
+                return CliRes::Err;
+            };
+
+            match flag {
+                "--wad" => options.wad_paths.push(PathBuf::from(value)),
+                "--game" => options.game_dir = Some(PathBuf::from(value)),
+                "--scale" => {
+                    let Ok(scale) = value.parse::<f32>() else {
+                        println!("Cannot parse scale {value}");
+                        return CliRes::Err;
+                    };
+
+                    options.scale = scale;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let Some(bsp_path) = bsp_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        match bsp2gltf(&bsp_path, &options) {
+            Ok(res) => {
+                res.missing_textures
+                    .iter()
+                    .for_each(|texture| println!("Cannot find texture {texture}"));
+                res.missing_props
+                    .iter()
+                    .for_each(|model| println!("Cannot find model {model}"));
+
+                println!("Exported {}", res.path.display());
+
+                CliRes::Ok
+            }
+            Err(err) => {
+                println!("{}", err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Exports the world and brush entities of a BSP as glTF next to it, one primitive per texture.
+Textures and lightmaps go into <bsp name>_textures.
+
+The lightmap atlas is on the second UV set. glTF has no lightmap slot so materials list the
+lightmap of every light style in their extras.
+Entities are nodes at their origins with their keys in the extras.
+
+External textures are looked up in the --wad files first and then the WADs of the game folder.
+The game folder defaults to the one the BSP is in.
+
+--obj writes OBJ without lightmaps or props instead
+--props places MDL models of entities like cycler
+--scale multiplies every position, 0.0254 turns units into meters
+
+<.bsp> [--obj] [--no-lightmap] [--props] [--scale <scale>] [--game <game folder>] [--wad <.wad>]...
+"
+        )
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use gchimp::modules::bsp2mdl::{Bsp2MdlBake, Bsp2MdlOptions, bsp2mdl};
+
+use super::{Cli, CliRes};
+
+pub struct Bsp2Mdl;
+impl Cli for Bsp2Mdl {
+    fn name(&self) -> &'static str {
+        "bsp2mdl"
+    }
+
+    // .bsp file, models and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut options = Bsp2MdlOptions::default();
+        let mut bsp_path = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+
+            match flag {
+                "--lightmap-only" => {
+                    options.bake = Bsp2MdlBake::Lightmap;
+                    continue;
+                }
+                "--shaded" => {
+                    options.fullbright = false;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if !matches!(flag, "--wad" | "--game" | "--pixels") {
+                if bsp_path.is_none() {
+                    bsp_path = Some(arg.clone());
+                    continue;
+                }
+
+                // *12 or 12
+                let Ok(model_idx) = flag.trim_start_matches('*').parse::<usize>() else {
+                    println!("Cannot parse model {flag}");
+                    return CliRes::Err;
+                };
+
+                options.models.push(model_idx);
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                self.cli_help();
+                return CliRes::Err;
+            };
+
+            match flag {
+                "--wad" => options.wad_paths.push(PathBuf::from(value)),
+                "--game" => options.game_dir = Some(PathBuf::from(value)),
+                "--pixels" => {
+                    let Ok(pixels) = value.parse::<u32>() else {
+                        println!("Cannot parse pixels per luxel {value}");
+                        return CliRes::Err;
+                    };
+
+                    options.pixels_per_luxel = pixels;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let Some(bsp_path) = bsp_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        match bsp2mdl(&bsp_path, &options) {
+            Ok(res) => {
+                res.missing_textures
+                    .iter()
+                    .for_each(|texture| println!("Cannot find texture {texture}"));
+
+                res.paths
+                    .iter()
+                    .for_each(|path| println!("Converted {}", path.display()));
+
+                CliRes::Ok
+            }
+            Err(err) => {
+                println!("{}", err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Converts brush models of a BSP into MDL props next to it with the lightmap baked into the textures.
+Models are given as *12 or 12, every brush entity is converted if there is none.
+
+Every face gets its own spot in the baked textures. Only the normal light style is baked.
+Place the prop at the origin of the entity that used the brush model.
+
+External textures are looked up in the --wad files first and then the WADs of the game folder.
+
+--lightmap-only bakes only the lighting without the textures
+--shaded lets the engine shade the prop on top of the baked lighting
+--pixels sets baked pixels per luxel, default is 4
+
+<.bsp> [model]... [--lightmap-only] [--shaded] [--pixels <count>] [--game <game folder>] [--wad <.wad>]...
+"
+        )
+    }
+}
@@ -0,0 +1,62 @@
+use gchimp::modules::bsp_ent::{export_entities, import_entities};
+
+use super::{Cli, CliRes};
+
+pub struct BspEnt;
+impl Cli for BspEnt {
+    fn name(&self) -> &'static str {
+        "bsp-ent"
+    }
+
+    // export|import, .bsp file, optional .ent file
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp_path = std::path::Path::new(&args[1]);
+
+        let res = match (args[0].as_str(), args.get(2)) {
+            ("export", None) => export_entities(bsp_path).map(|ent_path| {
+                println!("Exported entities to {}", ent_path.display());
+            }),
+            ("import", ent_path) => {
+                let ent_path = ent_path
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(|| bsp_path.with_extension("ent"));
+
+                import_entities(bsp_path, ent_path)
+            }
+            _ => {
+                self.cli_help();
+                return CliRes::Err;
+            }
+        };
+
+        match res {
+            Ok(_) => CliRes::Ok,
+            Err(err) => {
+                println!("{}", err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Exports the entity lump of a BSP to a .ent file or imports it back.
+
+Importing overwrites the BSP and keeps every other lump as is.
+Entity text is copied byte for byte so text that is not UTF-8 is kept.
+The .ent file defaults to the one next to the BSP.
+
+export <.bsp>
+import <.bsp> [.ent]
+"
+        )
+    }
+}
@@ -0,0 +1,90 @@
+use bsp::Bsp;
+
+use gchimp::modules::bsp_limits::{BspLimitsOptions, bsp_limits, bsp_limits_to_json};
+
+use super::{Cli, CliRes};
+
+pub struct BspLimits;
+impl Cli for BspLimits {
+    fn name(&self) -> &'static str {
+        "limits"
+    }
+
+    // .bsp file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut options = BspLimitsOptions::default();
+        let mut json = false;
+        let mut bsp_path = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => json = true,
+                "--wpoly" => {
+                    let Some(Ok(threshold)) = args.next().map(|value| value.parse::<usize>())
+                    else {
+                        self.cli_help();
+                        return CliRes::Err;
+                    };
+
+                    options.wpoly_threshold = threshold;
+                }
+                _ => {
+                    if bsp_path.is_some() {
+                        self.cli_help();
+                        return CliRes::Err;
+                    }
+
+                    bsp_path = Some(arg.clone());
+                }
+            }
+        }
+
+        let Some(bsp_path) = bsp_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        let bsp = match Bsp::from_file(&bsp_path) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open bsp file: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        let report = bsp_limits(&bsp, &options);
+
+        if json {
+            match bsp_limits_to_json(&report) {
+                Ok(res) => println!("{res}"),
+                Err(err) => {
+                    println!("Cannot write JSON: {err}");
+                    return CliRes::Err;
+                }
+            }
+        } else {
+            print!("{report}");
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Reports what a compiled BSP uses against the compiler and engine limits.
+
+Also counts lightmap blocks (allocblock), clipnodes per hull and the worst case
+world polygons (wpoly) per leaf from the PVS.
+Faces with bad surface extents and leaves over the wpoly threshold are listed with coordinates.
+
+--wpoly sets the threshold, default is 800
+
+<.bsp> [--json] [--wpoly <threshold>]
+"
+        )
+    }
+}
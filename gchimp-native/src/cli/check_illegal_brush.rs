@@ -1,6 +1,8 @@
 use map::Map;
 
-use gchimp::modules::check_illegal_brush::check_illegal_brush;
+use gchimp::modules::check_illegal_brush::{
+    CheckIllegalBrushOptions, brush_diagnostics_to_json, check_illegal_brush,
+};
 
 use super::{Cli, CliRes};
 
@@ -14,14 +16,37 @@ impl Cli for CheckIllegalBrush {
     fn cli(&self) -> CliRes {
         let args: Vec<String> = std::env::args().skip(2).collect();
 
+        let json = args.iter().any(|arg| arg == "--json");
+        let args: Vec<&String> = args.iter().filter(|arg| *arg != "--json").collect();
+
         if args.len() != 1 {
             self.cli_help();
             return CliRes::Err;
         }
 
-        let map = Map::from_file(&args[0]).unwrap();
+        let map = match Map::from_file(args[0]) {
+            Ok(map) => map,
+            Err(err) => {
+                println!("Cannot open map file: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        let diagnostics = check_illegal_brush(&map, &CheckIllegalBrushOptions::default());
 
-        check_illegal_brush(&map);
+        if json {
+            match brush_diagnostics_to_json(&diagnostics) {
+                Ok(res) => println!("{res}"),
+                Err(err) => {
+                    println!("Cannot write JSON: {err}");
+                    return CliRes::Err;
+                }
+            }
+        } else {
+            diagnostics
+                .iter()
+                .for_each(|diagnostic| println!("{diagnostic}"));
+        }
 
         CliRes::Ok
     }
@@ -31,7 +56,10 @@ impl Cli for CheckIllegalBrush {
             "\
 Map compiler does not tell you enough info about illegal brushes. Here it does.
 
-<.map>
+Checks for duplicate or non-convex planes, zero area faces, brushes with no volume,
+off-grid or out of bounds vertices, microbrushes and broken texture axes.
+
+<.map> [--json]
 "
         )
     }
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use gchimp::modules::dem2cam::{CamFormat, CamSmoothing, Dem2CamOptions, dem2cam};
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Dem2CamCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Writes the camera path of the demo next to it
+    ///
+    /// Formats are cam for HLAE, csv, blender for a Blender .py script or ae for After Effects
+    Dem2cam {
+        /// Path to .dem file
+        path: PathBuf,
+        /// Format of the camera path
+        #[arg(long, default_value = "cam")]
+        format: String,
+        /// Resamples the camera path to this framerate
+        #[arg(long)]
+        fps: Option<f32>,
+        /// Curves through every frame when resampling
+        #[arg(
+            long = "catmull-rom",
+            default_value_t = false,
+            conflicts_with = "low_pass"
+        )]
+        catmull_rom: bool,
+        /// Smooths the camera over about this many seconds
+        #[arg(long = "low-pass")]
+        low_pass: Option<f32>,
+        /// Output time is demo time multiplied by this, 2 is half speed
+        #[arg(long = "time-scale", default_value_t = 1.)]
+        time_scale: f32,
+    },
+}
+
+pub struct Dem2Cam;
+impl Cli for Dem2Cam {
+    fn name(&self) -> &'static str {
+        "dem2cam"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = Dem2CamCli::parse();
+        let Commands::Dem2cam {
+            path,
+            format,
+            fps,
+            catmull_rom,
+            low_pass,
+            time_scale,
+        } = cli.command;
+
+        let format = match format.parse::<CamFormat>() {
+            Ok(format) => format,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let smoothing = if catmull_rom {
+            Some(CamSmoothing::CatmullRom)
+        } else {
+            low_pass.map(|time_constant| CamSmoothing::LowPass { time_constant })
+        };
+
+        let options = Dem2CamOptions {
+            frametime: fps.map(|fps| 1. / fps),
+            format,
+            smoothing,
+            time_scale,
+            ..Dem2CamOptions::new()
+        };
+
+        match dem2cam(&path, &options) {
+            Ok(out_path) => {
+                println!("Written {}", out_path.display());
+                CliRes::Ok
+            }
+            Err(err) => {
+                println!("Cannot process {}: {}", path.display(), err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        // handled by clap
+        unreachable!()
+    }
+}
@@ -0,0 +1,574 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use gchimp::{
+    modules::demdoc::{
+        anonymize::{AnonymizeOptions, anonymize_demo_file},
+        change_map::change_map_file,
+        check_doctored::check_doctored,
+        concat::concat_demos_file,
+        convert_ghost::{ConvertGhostOptions, convert_ghost_file},
+        demos_in_folder,
+        ghost2dem::{ghost_to_demo_file, ghosts_in_folder},
+        inspect::{InspectOptions, inspect_demo_file, inspection_to_json},
+        is_demdoc_output,
+        jump_stats::jump_stats_file,
+        kz_stats::add_kz_stats_file,
+        race::{RaceOptions, race_demo_file},
+        trim::{DemoRange, trim_demo_file},
+    },
+    utils::dem_stuffs::get_ghost::GhostFormat,
+};
+
+use super::*;
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct DemDocCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    Demdoc {
+        #[command(subcommand)]
+        op: Op,
+    },
+}
+
+/// Every path can be a folder to process everything inside it.
+///
+/// Processed demos are written next to the input as <name>_<operation>.dem like <name>_trim.dem
+#[derive(Debug, Subcommand)]
+#[command(rename_all = "snake_case")]
+enum Op {
+    /// Removes player names, steam IDs, IPs and chat from the demo
+    Anonymize {
+        /// Path to .dem file or folder
+        path: PathBuf,
+        /// Players are renamed to this followed by their slot
+        #[arg(long, default_value = "Player")]
+        name: String,
+        /// Removes every console command instead of only say commands
+        #[arg(long = "strip-console-commands", default_value_t = false)]
+        strip_console_commands: bool,
+    },
+    /// Changes the map of the demo
+    ChangeMap {
+        /// Path to .dem file or folder
+        path: PathBuf,
+        /// Path to the new .bsp
+        bsp: PathBuf,
+    },
+    /// Concatenates demos recorded on the same map in order
+    ///
+    /// A folder concatenates every demo inside by name
+    Concat {
+        /// Paths to .dem files or a folder
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Checks if the demo has been processed by DemDoc
+    ///
+    /// Exits with an error if any demo is doctored
+    CheckDoctored {
+        /// Path to .dem file or folder
+        path: PathBuf,
+    },
+    /// Converts a ghost into another ghost format
+    ///
+    /// Formats are dem, simen, sg, rj or gchimp
+    ConvertGhost {
+        /// Path to ghost file or folder
+        path: PathBuf,
+        /// Format to convert to
+        format: String,
+        /// Resamples the ghost to this framerate
+        #[arg(long)]
+        fps: Option<f64>,
+        /// Framerate of ghosts without frametime like sg, simen ghosts use the time in their header
+        #[arg(long = "ghost-fps")]
+        ghost_fps: Option<f64>,
+        /// Path to the .bsp the ghost runs on, needed for dem
+        #[arg(long)]
+        bsp: Option<PathBuf>,
+    },
+    /// Converts a ghost into a demo
+    ///
+    /// Ghosts are .dem, .simen.txt, .sg.json, .rj.json or .gchimp.json
+    Ghost2dem {
+        /// Path to ghost file or folder
+        path: PathBuf,
+        /// Path to the .bsp the ghost runs on
+        bsp: PathBuf,
+    },
+    /// Prints the summary, frames and net messages of the demo
+    ///
+    /// Anomalies like time going back or missing frames are listed in the summary
+    Inspect {
+        /// Path to .dem file or folder
+        path: PathBuf,
+        /// Only frames and messages of this type like ClientData or SvcSound
+        #[arg(long = "type")]
+        types: Vec<String>,
+        /// Demo time range in seconds as <start>:<end>
+        #[arg(long)]
+        time: Option<String>,
+        /// Only messages mentioning this entity
+        #[arg(long)]
+        entity: Option<u16>,
+        /// Only prints the summary
+        #[arg(long, default_value_t = false)]
+        summary: bool,
+        /// Prints JSON instead
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Writes the stats of every jump in the demo as <name>_jumps.json
+    JumpStats {
+        /// Path to .dem file or folder
+        path: PathBuf,
+        /// Path to the .bsp of the demo to find the edges
+        #[arg(long)]
+        bsp: Option<PathBuf>,
+    },
+    /// Races ghosts against each other in one demo
+    ///
+    /// The first ghost is the point of view. A folder races every ghost inside by name.
+    /// Per ghost options are given once for each ghost in the same order.
+    Race {
+        /// Paths to ghost files or a folder
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Path to the .bsp the ghosts run on
+        #[arg(long, required = true)]
+        bsp: PathBuf,
+        /// Framerate of the demo
+        #[arg(long, default_value_t = 100.)]
+        fps: f64,
+        /// Seconds before the ghost starts
+        #[arg(long = "offset", allow_negative_numbers = true)]
+        offsets: Vec<f64>,
+        /// Name shown in the label
+        #[arg(long = "name")]
+        names: Vec<String>,
+        /// Player model like gign
+        #[arg(long = "model")]
+        models: Vec<String>,
+        /// Colour as <r>,<g>,<b>
+        #[arg(long = "color", value_parser = parse_color)]
+        colors: Vec<[u8; 3]>,
+        /// Hides the ghost names
+        #[arg(long = "no-labels", default_value_t = false)]
+        no_labels: bool,
+    },
+    /// Keeps only a part of the demo
+    ///
+    /// Ranges are <start>:<end> with either end left out to go to the start or the end
+    Trim {
+        /// Path to .dem file or folder
+        path: PathBuf,
+        /// Demo time range in seconds
+        #[arg(long, conflicts_with = "frame", required_unless_present = "frame")]
+        time: Option<String>,
+        /// Demo frame range
+        #[arg(long)]
+        frame: Option<String>,
+    },
+    /// Adds KZ stats to the demo
+    ///
+    /// Everything is added if nothing is given
+    KzStats {
+        /// Path to .dem file or folder
+        path: PathBuf,
+        /// Shows pressed keys
+        #[arg(long, default_value_t = false)]
+        keys: bool,
+        /// Shows horizontal speed
+        #[arg(long, default_value_t = false)]
+        speedometer: bool,
+        /// Shows the stats of every jump when landing
+        #[arg(long = "jump-stats", default_value_t = false)]
+        jump_stats: bool,
+    },
+}
+
+pub struct DemDoc;
+
+impl Cli for DemDoc {
+    fn name(&self) -> &'static str {
+        "demdoc"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = DemDocCli::parse();
+
+        let Commands::Demdoc { op } = cli.command;
+
+        match op {
+            Op::Anonymize {
+                path,
+                name,
+                strip_console_commands,
+            } => {
+                let options = AnonymizeOptions {
+                    name,
+                    strip_console_commands,
+                };
+
+                run_each(&path, demos_to_process, |demo| {
+                    anonymize_demo_file(demo, &options)
+                })
+            }
+            Op::ChangeMap { path, bsp } => {
+                run_each(&path, demos_to_process, |demo| change_map_file(demo, &bsp))
+            }
+            Op::Concat { paths } => run_concat(&paths),
+            Op::CheckDoctored { path } => run_check_doctored(&path),
+            Op::ConvertGhost {
+                path,
+                format,
+                fps,
+                ghost_fps,
+                bsp,
+            } => {
+                let format = match format.parse::<GhostFormat>() {
+                    Ok(format) => format,
+                    Err(err) => {
+                        println!("{}", err);
+                        return CliRes::Err;
+                    }
+                };
+
+                let options = ConvertGhostOptions {
+                    fps,
+                    ghost_fps,
+                    bsp,
+                };
+
+                // so running again does not convert what it wrote
+                let ghosts_to_convert = |folder: &Path| {
+                    let mut paths = ghosts_in_folder(folder)?;
+                    paths.retain(|path| GhostFormat::from_path(path) != Some(format));
+
+                    Ok(paths)
+                };
+
+                run_each(&path, ghosts_to_convert, |ghost| {
+                    convert_ghost_file(ghost, format, &options)
+                })
+            }
+            Op::Ghost2dem { path, bsp } => run_each(&path, ghosts_in_folder, |ghost| {
+                ghost_to_demo_file(ghost, &bsp)
+            }),
+            Op::Inspect {
+                path,
+                types,
+                time,
+                entity,
+                summary,
+                json,
+            } => {
+                let (start, end) = match time {
+                    Some(time) => match parse_range(&time, 0., f32::INFINITY) {
+                        Some(range) => range,
+                        None => {
+                            println!("Cannot parse range");
+                            return CliRes::Err;
+                        }
+                    },
+                    None => (0., f32::INFINITY),
+                };
+
+                let options = InspectOptions {
+                    types,
+                    start,
+                    end,
+                    entity,
+                    frames: !summary,
+                };
+
+                run_inspect(&path, &options, json)
+            }
+            Op::JumpStats { path, bsp } => run_each(&path, demos_to_process, |demo| {
+                jump_stats_file(demo, bsp.as_deref())
+            }),
+            Op::Race {
+                paths,
+                bsp,
+                fps,
+                offsets,
+                names,
+                models,
+                colors,
+                no_labels,
+            } => {
+                let options = RaceOptions {
+                    fps,
+                    labels: !no_labels,
+                    names,
+                    models,
+                    colors,
+                    offsets,
+                };
+
+                run_race(&paths, &bsp, &options)
+            }
+            Op::Trim { path, time, frame } => {
+                let range = match (time, frame) {
+                    (Some(time), _) => parse_range(&time, 0., f32::INFINITY)
+                        .map(|(start, end)| DemoRange::Time { start, end }),
+                    (_, Some(frame)) => parse_range(&frame, 0, i32::MAX)
+                        .map(|(start, end)| DemoRange::Frame { start, end }),
+                    (None, None) => unreachable!(),
+                };
+
+                let Some(range) = range else {
+                    println!("Cannot parse range");
+                    return CliRes::Err;
+                };
+
+                run_each(&path, demos_to_process, |demo| trim_demo_file(demo, range))
+            }
+            Op::KzStats {
+                path,
+                keys,
+                speedometer,
+                jump_stats,
+            } => {
+                let (keys, speedometer, jump_stats) = if keys || speedometer || jump_stats {
+                    (keys, speedometer, jump_stats)
+                } else {
+                    (true, true, true)
+                };
+
+                run_each(&path, demos_to_process, |demo| {
+                    add_kz_stats_file(demo, |addons| {
+                        if keys {
+                            addons.add_keys();
+                        }
+
+                        if speedometer {
+                            addons.add_speedometer();
+                        }
+
+                        if jump_stats {
+                            addons.add_jump_stats();
+                        }
+                    })
+                })
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        // handled by clap
+        unreachable!()
+    }
+}
+
+/// `<start>:<end>`, `<start>:` or `:<end>`
+fn parse_range<T: std::str::FromStr>(range: &str, min: T, max: T) -> Option<(T, T)> {
+    let (start, end) = range.split_once(':')?;
+
+    let start = if start.is_empty() {
+        min
+    } else {
+        start.parse().ok()?
+    };
+
+    let end = if end.is_empty() {
+        max
+    } else {
+        end.parse().ok()?
+    };
+
+    Some((start, end))
+}
+
+/// `<r>,<g>,<b>`
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let channels = color
+        .split(',')
+        .map(|channel| channel.trim().parse::<u8>())
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|err| err.to_string())?;
+
+    channels
+        .try_into()
+        .map_err(|_| "Colour needs 3 numbers".to_string())
+}
+
+/// Demos in the folder without the ones written by demdoc so running again does not stack up
+fn demos_to_process(folder: &Path) -> eyre::Result<Vec<PathBuf>> {
+    let mut paths = demos_in_folder(folder)?;
+    paths.retain(|path| !is_demdoc_output(path));
+
+    Ok(paths)
+}
+
+/// The file itself or what is found inside the folder
+fn inputs(
+    path: &Path,
+    in_folder: impl Fn(&Path) -> eyre::Result<Vec<PathBuf>>,
+) -> eyre::Result<Vec<PathBuf>> {
+    if path.is_dir() {
+        let paths = in_folder(path)?;
+
+        if paths.is_empty() {
+            return Err(eyre::eyre!("Nothing to process in {}", path.display()));
+        }
+
+        Ok(paths)
+    } else {
+        Ok(vec![path.to_path_buf()])
+    }
+}
+
+/// Keeps going through the folder when one fails
+fn run_each(
+    path: &Path,
+    in_folder: impl Fn(&Path) -> eyre::Result<Vec<PathBuf>>,
+    f: impl Fn(&Path) -> eyre::Result<PathBuf>,
+) -> CliRes {
+    let paths = match inputs(path, in_folder) {
+        Ok(paths) => paths,
+        Err(err) => {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+    };
+
+    let mut failed = false;
+
+    for path in paths {
+        match f(&path) {
+            Ok(out_path) => println!("Written {}", out_path.display()),
+            Err(err) => {
+                println!("Cannot process {}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed { CliRes::Err } else { CliRes::Ok }
+}
+
+fn run_check_doctored(path: &Path) -> CliRes {
+    let paths = match inputs(path, demos_in_folder) {
+        Ok(paths) => paths,
+        Err(err) => {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+    };
+
+    let mut failed = false;
+
+    for path in paths {
+        match check_doctored(&path) {
+            Ok((path, doctored)) => {
+                if doctored {
+                    println!("Doctored {}", path.display());
+                    failed = true;
+                } else {
+                    println!("Not doctored {}", path.display());
+                }
+            }
+            Err(err) => {
+                println!("Cannot check {}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed { CliRes::Err } else { CliRes::Ok }
+}
+
+fn run_inspect(path: &Path, options: &InspectOptions, json: bool) -> CliRes {
+    let paths = match inputs(path, demos_in_folder) {
+        Ok(paths) => paths,
+        Err(err) => {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+    };
+
+    let mut failed = false;
+
+    for path in paths {
+        let res = inspect_demo_file(&path, options).and_then(|inspection| {
+            if json {
+                inspection_to_json(&inspection)
+            } else {
+                Ok(format!("{}\n{}", path.display(), inspection))
+            }
+        });
+
+        match res {
+            Ok(output) => println!("{output}"),
+            Err(err) => {
+                println!("Cannot inspect {}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed { CliRes::Err } else { CliRes::Ok }
+}
+
+fn run_concat(paths: &[PathBuf]) -> CliRes {
+    let paths = if let [folder] = paths
+        && folder.is_dir()
+    {
+        match inputs(folder, demos_to_process) {
+            Ok(paths) => paths,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+    } else {
+        paths.to_vec()
+    };
+
+    match concat_demos_file(&paths) {
+        Ok(out_path) => {
+            println!("Written {}", out_path.display());
+            CliRes::Ok
+        }
+        Err(err) => {
+            println!("{}", err);
+            CliRes::Err
+        }
+    }
+}
+
+fn run_race(paths: &[PathBuf], bsp: &Path, options: &RaceOptions) -> CliRes {
+    let paths = if let [folder] = paths
+        && folder.is_dir()
+    {
+        match inputs(folder, ghosts_in_folder) {
+            Ok(paths) => paths,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+    } else {
+        paths.to_vec()
+    };
+
+    match race_demo_file(&paths, bsp, options) {
+        Ok(out_path) => {
+            println!("Written {}", out_path.display());
+            CliRes::Ok
+        }
+        Err(err) => {
+            println!("{}", err);
+            CliRes::Err
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use map::Map;
+
+use gchimp::modules::leak_check::{LeakCheckOptions, leak_check, leaks_to_json};
+
+use super::{Cli, CliRes};
+
+pub struct LeakCheck;
+impl Cli for LeakCheck {
+    fn name(&self) -> &'static str {
+        "leak"
+    }
+
+    // .map file
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let json = args.iter().any(|arg| arg == "--json");
+        let args: Vec<&String> = args.iter().filter(|arg| *arg != "--json").collect();
+
+        if args.len() != 1 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let map = match Map::from_file(args[0]) {
+            Ok(map) => map,
+            Err(err) => {
+                println!("Cannot open map file: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        let leaks = leak_check(&map);
+
+        if json {
+            match leaks_to_json(&leaks) {
+                Ok(res) => println!("{res}"),
+                Err(err) => {
+                    println!("Cannot write JSON: {err}");
+                    return CliRes::Err;
+                }
+            }
+        } else if leaks.is_empty() {
+            println!("No leak found");
+        } else {
+            leaks.iter().for_each(|leak| println!("{leak}"));
+        }
+
+        // hlbsp only writes the first leak
+        if let Some(leak) = leaks.first()
+            && let Err(err) = leak.write_pointfile(args[0], &LeakCheckOptions::default())
+        {
+            println!("Cannot write pointfile: {err}");
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Finds leaks without compiling the map.
+
+Floods the map from the void and reports the point entities it reaches.
+Writes .pts and .lin next to the map for the first leak.
+
+<.map> [--json]
+"
+        )
+    }
+}
@@ -0,0 +1,102 @@
+use gchimp::modules::lightmap_edit::{
+    LightmapAdjust, LightmapLayout, adjust_lightmap_file, export_lightmaps_file,
+    import_lightmaps_file,
+};
+
+use super::{Cli, CliRes};
+
+pub struct LightmapEdit;
+impl Cli for LightmapEdit {
+    fn name(&self) -> &'static str {
+        "lightmap"
+    }
+
+    // export|import|adjust, .bsp file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let (command, bsp_path) = (args[0].as_str(), &args[1]);
+
+        let layout = if args.iter().any(|arg| arg == "--atlas") {
+            LightmapLayout::Atlas
+        } else {
+            LightmapLayout::PerFace
+        };
+
+        let res = match command {
+            "export" => export_lightmaps_file(bsp_path, layout)
+                .map(|out_dir| println!("Exported lightmaps to {}", out_dir.display())),
+            "import" => import_lightmaps_file(bsp_path, layout)
+                .map(|count| println!("Imported {count} lightmap images")),
+            "adjust" => {
+                let mut adjust = LightmapAdjust::default();
+                let mut args = args.iter().skip(2);
+
+                while let Some(flag) = args.next() {
+                    let count = if flag == "--tint" { 3 } else { 1 };
+
+                    let values = args
+                        .by_ref()
+                        .take(count)
+                        .map(|value| value.parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>();
+
+                    let Ok(values) = values.as_deref() else {
+                        println!("Cannot parse value of {flag}");
+                        return CliRes::Err;
+                    };
+
+                    match (flag.as_str(), values) {
+                        ("--gamma", &[gamma]) => adjust.gamma = gamma,
+                        ("--scale", &[scale]) => adjust.scale = scale,
+                        ("--tint", &[r, g, b]) => adjust.tint = [r, g, b],
+                        _ => {
+                            self.cli_help();
+                            return CliRes::Err;
+                        }
+                    }
+                }
+
+                adjust_lightmap_file(bsp_path, &adjust)
+            }
+            _ => {
+                self.cli_help();
+                return CliRes::Err;
+            }
+        };
+
+        match res {
+            Ok(_) => CliRes::Ok,
+            Err(err) => {
+                println!("{}", err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Edits lightmaps of a compiled BSP.
+
+export writes PNG images into <bsp name>_lightmaps next to the BSP.
+import reads them back and overwrites the BSP. Missing images are left as is.
+--atlas packs every face into one image per light style instead of one image per face.
+
+adjust changes every luxel and overwrites the BSP, in order:
+--gamma, same as RAD gamma, lower is brighter
+--scale multiplies the brightness
+--tint multiplies each color channel
+
+export <.bsp> [--atlas]
+import <.bsp> [--atlas]
+adjust <.bsp> [--gamma <gamma>] [--scale <scale>] [--tint <r> <g> <b>]
+"
+        )
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+
+use gchimp::modules::{
+    leak_check::LeakCheckOptions,
+    map2bsp::{Map2BspOptions, map2bsp_file},
+};
+
+use super::{Cli, CliRes};
+
+pub struct Map2Bsp;
+impl Cli for Map2Bsp {
+    fn name(&self) -> &'static str {
+        "map2bsp"
+    }
+
+    // .map file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut options = Map2BspOptions::default();
+        let mut map_path = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--embed" => options.embed_textures = true,
+                "--wad" => {
+                    let Some(wad) = args.next() else {
+                        self.cli_help();
+                        return CliRes::Err;
+                    };
+
+                    options.wad_paths.push(PathBuf::from(wad));
+                }
+                _ if map_path.is_none() => map_path = Some(arg.clone()),
+                _ => {
+                    self.cli_help();
+                    return CliRes::Err;
+                }
+            }
+        }
+
+        let Some(map_path) = map_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        let res = match map2bsp_file(&map_path, &options) {
+            Ok(res) => res,
+            Err(err) => {
+                println!("Cannot compile map: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        res.warnings
+            .iter()
+            .for_each(|warning| println!("{warning}"));
+        res.leaks.iter().for_each(|leak| println!("{leak}"));
+
+        if let Some(leak) = res.leaks.first()
+            && let Err(err) = leak.write_pointfile(&map_path, &LeakCheckOptions::default())
+        {
+            println!("Cannot write pointfile: {err}");
+            return CliRes::Err;
+        }
+
+        println!(
+            "Compiled {} models, {} faces, {} leaves",
+            res.bsp.models.len(),
+            res.bsp.faces.len(),
+            res.bsp.leaves.len()
+        );
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Compiles a map into a BSP without visibility and lighting.
+
+Textures are looked up in the worldspawn \"wad\" key and then in every --wad.
+Writes the .bsp next to the map. Writes .pts and .lin if the map leaks.
+
+<.map> [--embed] [--wad <.wad>]...
+"
+        )
+    }
+}
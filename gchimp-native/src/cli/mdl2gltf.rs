@@ -0,0 +1,80 @@
+use gchimp::modules::mdl2gltf::{Mdl2GltfOptions, mdl2gltf};
+
+use super::{Cli, CliRes};
+
+pub struct Mdl2Gltf;
+impl Cli for Mdl2Gltf {
+    fn name(&self) -> &'static str {
+        "mdl2gltf"
+    }
+
+    // .mdl file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut options = Mdl2GltfOptions::default();
+        let mut mdl_path = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-animations" => options.animations = false,
+                "--scale" => {
+                    let Some(value) = args.next() else {
+                        self.cli_help();
+                        return CliRes::Err;
+                    };
+
+                    let Ok(scale) = value.parse::<f32>() else {
+                        println!("Cannot parse scale {value}");
+                        return CliRes::Err;
+                    };
+
+                    options.scale = scale;
+                }
+                _ => {
+                    if mdl_path.is_some() {
+                        self.cli_help();
+                        return CliRes::Err;
+                    }
+
+                    mdl_path = Some(arg.clone());
+                }
+            }
+        }
+
+        let Some(mdl_path) = mdl_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        match mdl2gltf(&mdl_path, &options) {
+            Ok(path) => {
+                println!("Exported {}", path.display());
+
+                CliRes::Ok
+            }
+            Err(err) => {
+                println!("{}", err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Exports a model as glTF next to it with its bones as a skin.
+Textures go into <mdl name>_textures. Textures in <mdl name>T.mdl are used when present.
+
+Every submodel is a separate mesh with its bodypart in the extras.
+Every sequence is an animation using its first blend.
+
+--no-animations skips the sequences
+--scale multiplies every position, 0.0254 turns units into meters
+
+<.mdl> [--no-animations] [--scale <scale>]
+"
+        )
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use gchimp::modules::mdl_lint::{mdl_lint_file, mdl_lint_to_json, mdls_in_folder};
+
+use super::{Cli, CliRes};
+
+pub struct MdlLint;
+impl Cli for MdlLint {
+    fn name(&self) -> &'static str {
+        "mdl_lint"
+    }
+
+    // .mdl file or folder and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut json = false;
+        let mut path = None;
+
+        for arg in &args {
+            match arg.as_str() {
+                "--json" => json = true,
+                _ => {
+                    if path.is_some() {
+                        self.cli_help();
+                        return CliRes::Err;
+                    }
+
+                    path = Some(PathBuf::from(arg));
+                }
+            }
+        }
+
+        let Some(path) = path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        let paths = if path.is_dir() {
+            mdls_in_folder(&path)
+        } else {
+            vec![path]
+        };
+
+        if paths.is_empty() {
+            println!("There is no .mdl to check");
+            return CliRes::Err;
+        }
+
+        let mut reports = vec![];
+        let mut failed = false;
+
+        for path in paths {
+            match mdl_lint_file(&path) {
+                Ok(report) => {
+                    failed |= report.has_errors();
+
+                    if !json {
+                        println!("{report}");
+                    }
+
+                    reports.push(report);
+                }
+                Err(err) => {
+                    // json output goes to stdout so it stays parsable
+                    eprintln!("Cannot open {}: {err}", path.display());
+                    failed = true;
+                }
+            }
+        }
+
+        if json {
+            match mdl_lint_to_json(&reports) {
+                Ok(res) => println!("{res}"),
+                Err(err) => {
+                    println!("Cannot write JSON: {err}");
+                    return CliRes::Err;
+                }
+            }
+        }
+
+        if failed { CliRes::Err } else { CliRes::Ok }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Checks studio models against the engine limits and what usually breaks them.
+
+Vertices and normals per model, textures, texture sizes, bones, controllers,
+skin families, sequences without frames, numbers that are not numbers,
+texture coordinates outside of the texture and the length in the header.
+
+A folder checks every .mdl inside it and its subfolders.
+Exits with an error if any model has an error.
+
+<.mdl or folder> [--json]
+"
+        )
+    }
+}
@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::mdl_texture::{
+    MdlTextureEdit, describe_textures, edit_mdl_textures_file, extract_textures_file, find_texture,
+    texture_flag_from_name,
+};
+use mdl::Mdl;
+
+use super::*;
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct MdlTextureCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+#[command(rename_all = "snake_case")]
+enum Commands {
+    MdlTexture {
+        #[command(subcommand)]
+        op: Op,
+    },
+}
+
+/// Textures are given by index or by name like chick.bmp
+///
+/// Edited models overwrite the input unless --out is given
+#[derive(Debug, Subcommand)]
+#[command(rename_all = "snake_case")]
+enum Op {
+    /// Prints textures and skin families
+    List {
+        /// Path to .mdl
+        path: PathBuf,
+    },
+    /// Writes every texture as PNG into <mdl name>_textures next to the model
+    Extract {
+        /// Path to .mdl
+        path: PathBuf,
+    },
+    /// Replaces the pixels of a texture with an image
+    ///
+    /// The image keeps its own size unless resized
+    Replace {
+        /// Path to .mdl
+        path: PathBuf,
+        texture: String,
+        /// Path to the image
+        image: PathBuf,
+        /// New size as <width>x<height>
+        #[arg(long, value_parser = parse_size, conflicts_with = "keep_size")]
+        resize: Option<(u32, u32)>,
+        /// Resizes the image to the current texture size
+        #[arg(long = "keep-size", default_value_t = false)]
+        keep_size: bool,
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    Rename {
+        /// Path to .mdl
+        path: PathBuf,
+        texture: String,
+        name: String,
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Turns a texture flag on or off
+    ///
+    /// Flags are chrome, additive, masked, flatshade or fullbright
+    Flag {
+        /// Path to .mdl
+        path: PathBuf,
+        texture: String,
+        flag: String,
+        /// Turns the flag off instead
+        #[arg(long, default_value_t = false)]
+        off: bool,
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Adds a skin family based on the first one
+    ///
+    /// Each replacement is <texture>=<texture or image>. Images are added as new textures.
+    AddSkin {
+        /// Path to .mdl
+        path: PathBuf,
+        #[arg(required = true, value_parser = parse_replacement)]
+        replacements: Vec<(String, String)>,
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    RemoveSkin {
+        /// Path to .mdl
+        path: PathBuf,
+        /// Skin family index
+        family: usize,
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+pub struct MdlTexture;
+
+impl Cli for MdlTexture {
+    fn name(&self) -> &'static str {
+        "mdl_texture"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = MdlTextureCli::parse();
+
+        let Commands::MdlTexture { op } = cli.command;
+
+        let (path, edit, out) = match op {
+            Op::List { path } => {
+                return match Mdl::open_from_file(&path) {
+                    Ok(mdl) => {
+                        print!("{}", describe_textures(&mdl));
+                        CliRes::Ok
+                    }
+                    Err(err) => {
+                        println!("{}", err);
+                        CliRes::Err
+                    }
+                };
+            }
+            Op::Extract { path } => {
+                return match extract_textures_file(&path) {
+                    Ok(out_dir) => {
+                        println!("Extracted textures to {}", out_dir.display());
+                        CliRes::Ok
+                    }
+                    Err(err) => {
+                        println!("{}", err);
+                        CliRes::Err
+                    }
+                };
+            }
+            Op::Replace {
+                path,
+                texture,
+                image,
+                resize,
+                keep_size,
+                out,
+            } => {
+                let resize = if keep_size {
+                    match texture_size(&path, &texture) {
+                        Ok(dimensions) => Some(dimensions),
+                        Err(err) => {
+                            println!("{}", err);
+                            return CliRes::Err;
+                        }
+                    }
+                } else {
+                    resize
+                };
+
+                let edit = MdlTextureEdit::Replace {
+                    texture,
+                    image,
+                    resize,
+                };
+
+                (path, edit, out)
+            }
+            Op::Rename {
+                path,
+                texture,
+                name,
+                out,
+            } => (path, MdlTextureEdit::Rename { texture, name }, out),
+            Op::Flag {
+                path,
+                texture,
+                flag,
+                off,
+                out,
+            } => {
+                let Some(flag) = texture_flag_from_name(&flag) else {
+                    println!("Unknown texture flag `{flag}`");
+                    return CliRes::Err;
+                };
+
+                let edit = MdlTextureEdit::SetFlag {
+                    texture,
+                    flag,
+                    enabled: !off,
+                };
+
+                (path, edit, out)
+            }
+            Op::AddSkin {
+                path,
+                replacements,
+                out,
+            } => (path, MdlTextureEdit::AddSkinFamily { replacements }, out),
+            Op::RemoveSkin { path, family, out } => {
+                (path, MdlTextureEdit::RemoveSkinFamily { family }, out)
+            }
+        };
+
+        match edit_mdl_textures_file(&path, &[edit], out.as_deref()) {
+            Ok(()) => CliRes::Ok,
+            Err(err) => {
+                println!("{}", err);
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        // handled by clap
+        unreachable!()
+    }
+}
+
+fn texture_size(path: &Path, texture: &str) -> eyre::Result<(u32, u32)> {
+    let mdl = Mdl::open_from_file(path)?;
+    let index = find_texture(&mdl, texture)?;
+
+    Ok(mdl.textures[index].dimensions())
+}
+
+/// `<width>x<height>`
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let (width, height) = size
+        .split_once('x')
+        .ok_or_else(|| "Size is <width>x<height>".to_string())?;
+
+    let width = width.parse::<u32>().map_err(|err| err.to_string())?;
+    let height = height.parse::<u32>().map_err(|err| err.to_string())?;
+
+    Ok((width, height))
+}
+
+/// `<texture>=<texture or image>`
+fn parse_replacement(replacement: &str) -> Result<(String, String), String> {
+    replacement
+        .split_once('=')
+        .map(|(texture, replacement)| (texture.to_string(), replacement.to_string()))
+        .ok_or_else(|| "Replacement is <texture>=<texture or image>".to_string())
+}
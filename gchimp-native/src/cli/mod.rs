@@ -1,12 +1,25 @@
 use map::Map;
 
+mod bsp2gltf;
+mod bsp2mdl;
+mod bsp_ent;
+mod bsp_limits;
 mod check_illegal_brush;
 mod check_missing_texture;
 mod custom_script;
+mod dem2cam;
+mod demdoc;
 mod join_mdl;
+mod leak_check;
 mod light_scale;
+mod lightmap_edit;
 mod loop_wave;
+mod map2bsp;
 mod map2mdl;
+mod mdl2gltf;
+mod mdl_lint;
+mod mdl_texture;
+mod rad;
 mod rename_texture;
 mod resmake;
 mod rotate_prop_static;
@@ -14,6 +27,8 @@ mod s2g;
 pub mod smd_compile;
 mod split_model;
 mod texture_scale;
+mod vis;
+mod wad_embed;
 
 pub enum CliRes {
     NoCli,
@@ -77,6 +92,21 @@ pub fn cli() -> CliRes {
         &smd_compile::SmdCompile,
         &rename_texture::RenameTexture,
         &join_mdl::JoinMdl,
+        &leak_check::LeakCheck,
+        &map2bsp::Map2Bsp,
+        &rad::Rad,
+        &vis::Vis,
+        &bsp_ent::BspEnt,
+        &wad_embed::WadEmbed,
+        &lightmap_edit::LightmapEdit,
+        &bsp2gltf::Bsp2Gltf,
+        &bsp2mdl::Bsp2Mdl,
+        &bsp_limits::BspLimits,
+        &mdl_lint::MdlLint,
+        &mdl_texture::MdlTexture,
+        &mdl2gltf::Mdl2Gltf,
+        &demdoc::DemDoc,
+        &dem2cam::Dem2Cam,
     ];
 
     let help = || {
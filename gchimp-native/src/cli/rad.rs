@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+
+use gchimp::modules::rad::{RadOptions, parse_lights_rad, rad_file};
+
+use super::{Cli, CliRes};
+
+pub struct Rad;
+impl Cli for Rad {
+    fn name(&self) -> &'static str {
+        "rad"
+    }
+
+    // .bsp file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let mut options = RadOptions::default();
+        let mut bsp_path = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+
+            if !matches!(flag, "--bounces" | "--gamma" | "--lights" | "--wad") {
+                if bsp_path.is_some() {
+                    self.cli_help();
+                    return CliRes::Err;
+                }
+
+                bsp_path = Some(arg.clone());
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                self.cli_help();
+                return CliRes::Err;
+            };
+
+            match flag {
+                "--bounces" => {
+                    let Ok(bounces) = value.parse::<u32>() else {
+                        println!("Cannot parse bounces: {value}");
+                        return CliRes::Err;
+                    };
+
+                    options.bounces = bounces;
+                }
+                "--gamma" => {
+                    let Ok(gamma) = value.parse::<f64>() else {
+                        println!("Cannot parse gamma: {value}");
+                        return CliRes::Err;
+                    };
+
+                    options.gamma = gamma;
+                }
+                "--lights" => {
+                    let texlights = std::fs::read_to_string(value)
+                        .map_err(|err| eyre::eyre!(err))
+                        .and_then(|s| parse_lights_rad(&s));
+
+                    match texlights {
+                        Ok(texlights) => options.texlights.extend(texlights),
+                        Err(err) => {
+                            println!("Cannot read lights file: {err}");
+                            return CliRes::Err;
+                        }
+                    }
+                }
+                "--wad" => options.wad_paths.push(PathBuf::from(value)),
+                _ => unreachable!(),
+            }
+        }
+
+        let Some(bsp_path) = bsp_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        match rad_file(&bsp_path, &options) {
+            Ok(warnings) => {
+                warnings.iter().for_each(|warning| println!("{warning}"));
+                println!("Lightmaps baked into {bsp_path}");
+            }
+            Err(err) => {
+                println!("Cannot bake lightmaps: {err}");
+                return CliRes::Err;
+            }
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Bakes lightmaps into a compiled BSP with direct light and bounces.
+
+Texlights are read from --lights and from <.bsp name>.rad next to the BSP.
+The BSP is overwritten.
+
+<.bsp> [--bounces <count>] [--gamma <gamma>] [--lights <lights.rad>] [--wad <.wad>]...
+"
+        )
+    }
+}
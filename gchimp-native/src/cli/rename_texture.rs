@@ -1,4 +1,9 @@
-use gchimp::modules::rename_texture::rename_texture;
+use std::collections::HashMap;
+
+use common::img_stuffs::generate_rgba8_from_image_path;
+use gchimp::modules::rename_texture::{
+    bsp_texture_usage, rename_bsp_textures, rename_texture, replace_bsp_texture,
+};
 
 use crate::cli::{Cli, CliRes};
 
@@ -13,6 +18,10 @@ impl Cli for RenameTexture {
         // Example. Skips "gchimp" "<module name>". Third argument is the input
         let args: Vec<String> = std::env::args().skip(2).collect();
 
+        if args.first().is_some_and(|path| path.ends_with(".bsp")) {
+            return self.bsp_cli(&args);
+        }
+
         if args.len() != 1 {
             self.cli_help();
             return CliRes::Err;
@@ -42,8 +51,99 @@ impl Cli for RenameTexture {
             "\
 rename_texture
 
+Renames textures of a .map with gchimp_renametex entities.
+
+Renames textures of a .bsp in place, or replaces the pixels of an embedded texture with an image
+resized to the texture. Without --rename or --replace it lists the textures with their texinfos.
+
 <path to .map>
+<path to .bsp> [--rename <texture> <new name>]... [--replace <texture> <image>]...
 "
         );
     }
 }
+
+impl RenameTexture {
+    fn bsp_cli(&self, args: &[String]) -> CliRes {
+        let bsp_path = &args[0];
+
+        let mut renames: HashMap<String, String> = HashMap::new();
+        let mut replaces: Vec<(&str, &str)> = vec![];
+
+        for arg in args[1..].chunks(3) {
+            let [flag, texture, value] = arg else {
+                self.cli_help();
+                return CliRes::Err;
+            };
+
+            match flag.as_str() {
+                "--rename" => {
+                    renames.insert(texture.clone(), value.clone());
+                }
+                "--replace" => replaces.push((texture, value)),
+                _ => {
+                    self.cli_help();
+                    return CliRes::Err;
+                }
+            }
+        }
+
+        let mut bsp = match bsp::Bsp::from_file(bsp_path) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open bsp file: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        if renames.is_empty() && replaces.is_empty() {
+            for usage in bsp_texture_usage(&bsp) {
+                println!(
+                    "{:<16} {:>4}x{:<4} {:<8} {:>5} faces  texinfos {:?}",
+                    usage.name,
+                    usage.dimensions.0,
+                    usage.dimensions.1,
+                    if usage.embedded {
+                        "embedded"
+                    } else {
+                        "external"
+                    },
+                    usage.face_count,
+                    usage.texinfos
+                );
+            }
+
+            return CliRes::Ok;
+        }
+
+        // replace first so the old names can be used
+        for (texture, image_path) in replaces {
+            let res = generate_rgba8_from_image_path(image_path)
+                .and_then(|image| replace_bsp_texture(&mut bsp, texture, image));
+
+            if let Err(err) = res {
+                println!("Cannot replace {texture}: {err}");
+                return CliRes::Err;
+            }
+
+            println!("Replaced {texture}");
+        }
+
+        if !renames.is_empty() {
+            match rename_bsp_textures(&mut bsp, &renames) {
+                Ok(count) => println!("Renamed {count} textures"),
+                Err(err) => {
+                    println!("{err}");
+                    return CliRes::Err;
+                }
+            }
+        }
+
+        if let Err(err) = bsp.write_to_file(bsp_path) {
+            println!("Error writing bsp: {err}");
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+}
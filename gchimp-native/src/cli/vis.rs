@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bsp::{Bsp, VisMode, VisOptions, VisStage};
+
+use super::{Cli, CliRes};
+
+pub struct Vis;
+impl Cli for Vis {
+    fn name(&self) -> &'static str {
+        "vis"
+    }
+
+    // .bsp file
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let fast = args.iter().any(|arg| arg == "--fast");
+        let args: Vec<&String> = args.iter().filter(|arg| *arg != "--fast").collect();
+
+        if args.len() != 1 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let mut bsp = match Bsp::from_file(args[0]) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open BSP file: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        // prints every 10%
+        let last_tenth = AtomicUsize::new(0);
+
+        let options = VisOptions {
+            mode: if fast { VisMode::Fast } else { VisMode::Full },
+            progress: Some(Box::new(move |progress| {
+                if progress.stage == VisStage::Portals || progress.total == 0 {
+                    return;
+                }
+
+                let tenth = progress.done * 10 / progress.total;
+
+                if last_tenth.swap(tenth, Ordering::Relaxed) != tenth {
+                    println!("{:?} {}%", progress.stage, tenth * 10);
+                }
+            })),
+            cancel: None,
+        };
+
+        let stats = match bsp.compute_vis(&options) {
+            Ok(stats) => stats,
+            Err(err) => {
+                println!("Cannot compute visibility: {err}");
+                return CliRes::Err;
+            }
+        };
+
+        println!(
+            "{} portals, {} leaves, {:.1} visible leaves on average",
+            stats.portal_count / 2,
+            stats.leaf_count,
+            stats.average_visible_leaves
+        );
+
+        if let Err(err) = bsp.write_to_file(args[0]) {
+            println!("Cannot write BSP file: {err}");
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Computes visibility of a compiled BSP and overwrites it.
+
+--fast only floods through portals without clipping them.
+
+<.bsp> [--fast]
+"
+        )
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use gchimp::modules::wad_embed::{
+    WadEmbedOptions, embed_wad_textures_file, strip_wad_textures_file,
+};
+
+use super::{Cli, CliRes};
+
+pub struct WadEmbed;
+impl Cli for WadEmbed {
+    fn name(&self) -> &'static str {
+        "wad_embed"
+    }
+
+    // embed|strip, .bsp file and options
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let Some(command) = args.first() else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        let mut options = WadEmbedOptions::default();
+        let mut bsp_path = None;
+        let mut args = args.iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+
+            if flag == "--custom" {
+                options.only_custom = true;
+                continue;
+            }
+
+            if !matches!(flag, "--wad" | "--game") {
+                if bsp_path.is_some() {
+                    self.cli_help();
+                    return CliRes::Err;
+                }
+
+                bsp_path = Some(arg.clone());
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                self.cli_help();
+                return CliRes::Err;
+            };
+
+            match flag {
+                "--wad" => options.wad_paths.push(PathBuf::from(value)),
+                "--game" => options.game_dir = Some(PathBuf::from(value)),
+                _ => unreachable!(),
+            }
+        }
+
+        let Some(bsp_path) = bsp_path else {
+            self.cli_help();
+            return CliRes::Err;
+        };
+
+        match command.as_str() {
+            "embed" => match embed_wad_textures_file(&bsp_path, &options) {
+                Ok(res) => {
+                    res.missing
+                        .iter()
+                        .for_each(|texture| println!("Cannot find texture {texture}"));
+
+                    println!(
+                        "Embedded {} textures, skipped {} default textures",
+                        res.embedded.len(),
+                        res.skipped.len()
+                    );
+
+                    CliRes::Ok
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    CliRes::Err
+                }
+            },
+            "strip" => match strip_wad_textures_file(&bsp_path) {
+                Ok(wad_path) => {
+                    println!("Stripped textures to {}", wad_path.display());
+                    CliRes::Ok
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    CliRes::Err
+                }
+            },
+            _ => {
+                self.cli_help();
+                CliRes::Err
+            }
+        }
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Embeds external WAD textures into a BSP or strips embedded textures into <bsp>.wad.
+The BSP is overwritten.
+
+Textures are looked up in the --wad files first and then the WADs of the game folder.
+The game folder defaults to the one the BSP is in.
+
+--custom only embeds textures that are not from the WADs of the base game.
+
+embed <.bsp> [--custom] [--game <game folder>] [--wad <.wad>]...
+strip <.bsp>
+"
+        )
+    }
+}
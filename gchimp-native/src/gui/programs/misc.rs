@@ -8,7 +8,12 @@ use eframe::egui;
 
 use gchimp::{
     modules::{
+        bsp_limits::{BspLimitsOptions, BspLimitsReport, bsp_limits},
         loop_wave::loop_wave,
+        mdl_texture::{
+            MdlTextureEdit, TEXTURE_FLAG_NAMES, describe_textures, edit_mdl_textures_file,
+            extract_textures_file,
+        },
         resmake::{ResMake, ResMakeOptions},
         split_model::split_model,
     },
@@ -37,6 +42,17 @@ pub struct Misc {
     resmake_status: Arc<Mutex<String>>,
     smd_compile_status: Arc<Mutex<String>>,
     studiomdl_compile_status: Arc<Mutex<String>>,
+    bsp_limits_status: Arc<Mutex<String>>,
+    bsp_limits_report: Arc<Mutex<Option<BspLimitsReport>>>,
+    mdl: String,
+    mdl_texture: String,
+    mdl_texture_image: String,
+    mdl_texture_name: String,
+    mdl_texture_flag: usize,
+    mdl_texture_flag_enabled: bool,
+    mdl_texture_family: usize,
+    mdl_texture_status: Arc<Mutex<String>>,
+    mdl_texture_listing: Arc<Mutex<String>>,
 }
 
 impl Misc {
@@ -53,6 +69,17 @@ impl Misc {
             resmake_status: Arc::new(Mutex::new(String::from("Idle"))),
             smd_compile_status: Arc::new(Mutex::new(String::from("Idle"))),
             studiomdl_compile_status: Arc::new(Mutex::new(String::from("Idle"))),
+            bsp_limits_status: Arc::new(Mutex::new(String::from("Idle"))),
+            bsp_limits_report: Arc::new(Mutex::new(None)),
+            mdl: Default::default(),
+            mdl_texture: Default::default(),
+            mdl_texture_image: Default::default(),
+            mdl_texture_name: Default::default(),
+            mdl_texture_flag: 0,
+            mdl_texture_flag_enabled: true,
+            mdl_texture_family: 0,
+            mdl_texture_status: Arc::new(Mutex::new(String::from("Idle"))),
+            mdl_texture_listing: Arc::new(Mutex::new(String::new())),
             loop_wave_loop: true,
             loop_wave_16_bit: true,
         }
@@ -250,6 +277,209 @@ If there are external WADs found, this option will create a new WAD file contain
             });
     }
 
+    fn bsp_limits(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.label("BSP limits")
+            .on_hover_text("Counts what a compiled BSP uses against the engine limits");
+        egui::Grid::new("bsp_limits").num_columns(2).show(ui, |ui| {
+            ui.label("BSP:");
+            ui.add(egui::TextEdit::singleline(&mut self.bsp).hint_text("Choose .bsp file"));
+            if ui.button("Add").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("BSP", &["bsp"])
+                    .pick_file()
+                && path.extension().is_some_and(|ext| ext == "bsp")
+            {
+                self.bsp = path.display().to_string();
+            }
+
+            ui.end_row();
+
+            if ui.button("Run").clicked() {
+                self.run_bsp_limits();
+            }
+
+            let binding = self.bsp_limits_status.lock().unwrap();
+            let mut status_text = binding.as_str();
+            ui.text_edit_singleline(&mut status_text)
+        });
+
+        let binding = self.bsp_limits_report.lock().unwrap();
+        let Some(report) = binding.as_ref() else {
+            return;
+        };
+
+        egui::Grid::new("bsp_limits_report")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for limit in &report.limits {
+                    let percent = limit.percent();
+                    let color = if percent > 100. {
+                        egui::Color32::RED
+                    } else if percent > 90. {
+                        egui::Color32::ORANGE
+                    } else {
+                        ui.visuals().text_color()
+                    };
+
+                    ui.label(limit.name);
+                    ui.label(limit.count.to_string());
+                    ui.label(limit.max.to_string());
+                    ui.colored_label(color, format!("{percent:.1}%"));
+                    ui.end_row();
+                }
+            });
+
+        let [x, y, z] = report.wpoly.max_origin;
+
+        ui.label(format!(
+            "Clipnodes per hull: {} {} {}",
+            report.clipnodes_per_hull[0],
+            report.clipnodes_per_hull[1],
+            report.clipnodes_per_hull[2]
+        ));
+        ui.label(format!(
+            "wpoly: max {} in leaf {} @ ( {x} {y} {z} ), average {:.1}",
+            report.wpoly.max, report.wpoly.max_leaf, report.wpoly.average
+        ));
+
+        if !report.problems.is_empty() {
+            egui::ScrollArea::vertical()
+                .id_salt("bsp_limits_problems")
+                .max_height(200.)
+                .show(ui, |ui| {
+                    for problem in &report.problems {
+                        ui.label(problem.to_string());
+                    }
+                });
+        }
+    }
+
+    fn mdl_texture(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.label("MDL textures").on_hover_text(
+            "Edits textures and skin families of a compiled model and overwrites it.\n\
+Texture is given by index or by name.",
+        );
+        egui::Grid::new("mdl_texture")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("MDL:");
+                ui.add(egui::TextEdit::singleline(&mut self.mdl).hint_text("Choose .mdl file"));
+                if ui.button("Add").clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("MDL", &["mdl"])
+                        .pick_file()
+                    && path.extension().is_some_and(|ext| ext == "mdl")
+                {
+                    self.mdl = path.display().to_string();
+                    self.run_mdl_texture(None);
+                }
+                ui.end_row();
+
+                ui.label("Texture:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.mdl_texture).hint_text("Index or name"),
+                );
+                ui.end_row();
+
+                ui.label("Image:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.mdl_texture_image)
+                        .hint_text("Image to replace with or to add as skin"),
+                );
+                if ui.button("Add").clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_file()
+                {
+                    self.mdl_texture_image = path.display().to_string();
+                }
+                ui.end_row();
+
+                ui.label("New name:");
+                ui.add(egui::TextEdit::singleline(&mut self.mdl_texture_name));
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("Extract").clicked() {
+                let mdl_path = PathBuf::from(&self.mdl);
+                let status = self.mdl_texture_status.clone();
+
+                thread::spawn(move || match extract_textures_file(&mdl_path) {
+                    Ok(out_dir) => format!("Extracted to {}", out_dir.display())
+                        .clone_into(&mut status.lock().unwrap()),
+                    Err(err) => err.to_string().clone_into(&mut status.lock().unwrap()),
+                });
+            }
+
+            if ui.button("Replace").clicked() {
+                self.run_mdl_texture(Some(MdlTextureEdit::Replace {
+                    texture: self.mdl_texture.clone(),
+                    image: PathBuf::from(&self.mdl_texture_image),
+                    resize: None,
+                }));
+            }
+
+            if ui.button("Rename").clicked() {
+                self.run_mdl_texture(Some(MdlTextureEdit::Rename {
+                    texture: self.mdl_texture.clone(),
+                    name: self.mdl_texture_name.clone(),
+                }));
+            }
+
+            if ui
+                .button("Add skin")
+                .on_hover_text("Adds a skin family with the texture swapped with the image")
+                .clicked()
+            {
+                self.run_mdl_texture(Some(MdlTextureEdit::AddSkinFamily {
+                    replacements: vec![(self.mdl_texture.clone(), self.mdl_texture_image.clone())],
+                }));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("mdl_texture_flag")
+                .selected_text(TEXTURE_FLAG_NAMES[self.mdl_texture_flag].0)
+                .show_ui(ui, |ui| {
+                    for (index, (name, _)) in TEXTURE_FLAG_NAMES.iter().enumerate() {
+                        ui.selectable_value(&mut self.mdl_texture_flag, index, *name);
+                    }
+                });
+            ui.checkbox(&mut self.mdl_texture_flag_enabled, "On");
+
+            if ui.button("Set flag").clicked() {
+                self.run_mdl_texture(Some(MdlTextureEdit::SetFlag {
+                    texture: self.mdl_texture.clone(),
+                    flag: TEXTURE_FLAG_NAMES[self.mdl_texture_flag].1.clone(),
+                    enabled: self.mdl_texture_flag_enabled,
+                }));
+            }
+
+            ui.separator();
+
+            ui.add(egui::DragValue::new(&mut self.mdl_texture_family));
+            if ui.button("Remove skin").clicked() {
+                self.run_mdl_texture(Some(MdlTextureEdit::RemoveSkinFamily {
+                    family: self.mdl_texture_family,
+                }));
+            }
+        });
+
+        let binding = self.mdl_texture_status.lock().unwrap();
+        let mut status_text = binding.as_str();
+        ui.text_edit_singleline(&mut status_text);
+
+        let binding = self.mdl_texture_listing.lock().unwrap();
+        if !binding.is_empty() {
+            egui::ScrollArea::vertical()
+                .id_salt("mdl_texture_listing")
+                .max_height(200.)
+                .show(ui, |ui| {
+                    ui.label(binding.as_str());
+                });
+        }
+    }
+
     fn run_split_model(&mut self) {
         let qc = self.qc.clone();
         let status = self.split_model_status.clone();
@@ -332,6 +562,52 @@ If there are external WADs found, this option will create a new WAD file contain
         });
     }
 
+    fn run_bsp_limits(&mut self) {
+        let bsp_path = PathBuf::from(self.bsp.clone());
+        let status = self.bsp_limits_status.clone();
+        let report = self.bsp_limits_report.clone();
+        "Running".clone_into(&mut status.lock().unwrap());
+
+        thread::spawn(move || match bsp::Bsp::from_file(&bsp_path) {
+            Ok(bsp) => {
+                *report.lock().unwrap() = Some(bsp_limits(&bsp, &BspLimitsOptions::default()));
+                "Done".clone_into(&mut status.lock().unwrap());
+            }
+            Err(err) => {
+                *report.lock().unwrap() = None;
+                err.to_string().clone_into(&mut status.lock().unwrap());
+            }
+        });
+    }
+
+    /// Applies the edit then lists textures again
+    fn run_mdl_texture(&mut self, edit: Option<MdlTextureEdit>) {
+        let mdl_path = PathBuf::from(self.mdl.clone());
+        let status = self.mdl_texture_status.clone();
+        let listing = self.mdl_texture_listing.clone();
+        "Running".clone_into(&mut status.lock().unwrap());
+
+        thread::spawn(move || {
+            if let Some(edit) = edit
+                && let Err(err) = edit_mdl_textures_file(&mdl_path, &[edit], None)
+            {
+                err.to_string().clone_into(&mut status.lock().unwrap());
+                return;
+            }
+
+            match mdl::Mdl::open_from_file(&mdl_path) {
+                Ok(mdl) => {
+                    *listing.lock().unwrap() = describe_textures(&mdl);
+                    "Done".clone_into(&mut status.lock().unwrap());
+                }
+                Err(err) => {
+                    listing.lock().unwrap().clear();
+                    err.to_string().clone_into(&mut status.lock().unwrap());
+                }
+            }
+        });
+    }
+
     fn run_studiomdl_compile(&mut self) {
         let qc = self.qc.clone();
         let status = self.studiomdl_compile_status.clone();
@@ -392,6 +668,12 @@ impl TabProgram for Misc {
         self.studiomdl_compile(ui);
         ui.separator();
 
+        self.bsp_limits(ui);
+        ui.separator();
+
+        self.mdl_texture(ui);
+        ui.separator();
+
         let ctx = ui.ctx();
         preview_file_being_dropped(ctx);
 
@@ -410,6 +692,9 @@ impl TabProgram for Misc {
                         self.bsp = item.to_str().unwrap().to_string();
                     } else if ext == "smd" {
                         self.smd = item.to_str().unwrap().to_string();
+                    } else if ext == "mdl" {
+                        self.mdl = item.to_str().unwrap().to_string();
+                        self.run_mdl_texture(None);
                     }
                 };
             }
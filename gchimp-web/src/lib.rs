@@ -82,6 +82,7 @@ pub fn dem2cam(demo_bytes: Vec<u8>, filename: &str, override_fps: f32) -> Result
                 override_fps.into()
             },
             rotation: None,
+            ..Default::default()
         },
     ) {
         Ok(ok) => Ok(ok),
@@ -0,0 +1,824 @@
+//! Exports BSP geometry with its textures and lightmaps to render the map in other programs.
+//!
+//! glTF is written in GoldSrc units and coordinates under one root node that turns Z up into Y up.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bsp::{Bsp, Entity, LUXEL_SIZE, LightmapExtents, Vec3};
+use cgmath::Rotation;
+use common::setup_studio_model_transformations::{
+    origin_posrot, setup_studio_model_transformations,
+};
+use mdl::{Mdl, MeshTriangles, TextureFlag};
+use serde_json::{Value, json};
+use wad::types::MipTex;
+
+use crate::{
+    modules::{
+        lightmap_edit::Atlas,
+        wad_embed::{bsp_game_dir, embed_wad_textures, load_wads},
+    },
+    utils::{
+        bsp_stuffs::is_drawn_texture,
+        gltf_stuffs::{GltfBuilder, GltfPrimitive},
+        misc::COMMON_GAME_MODS,
+    },
+};
+
+mod obj;
+
+pub use obj::write_obj;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bsp2GltfFormat {
+    Gltf,
+    /// No lightmaps or props
+    Obj,
+}
+
+pub struct Bsp2GltfOptions {
+    pub format: Bsp2GltfFormat,
+    /// Lightmap atlas on the second UV set
+    pub lightmap: bool,
+    /// Places MDL props at the origins of entities with a `.mdl` model
+    pub props: bool,
+    /// WADs looked up for external textures before the ones in the game folder
+    pub wad_paths: Vec<PathBuf>,
+    /// Folder with the game mods, defaults to the one the BSP is in
+    pub game_dir: Option<PathBuf>,
+    /// Multiplies every position, 0.0254 turns units into meters
+    pub scale: f32,
+}
+
+impl Default for Bsp2GltfOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bsp2GltfOptions {
+    pub fn new() -> Self {
+        Self {
+            format: Bsp2GltfFormat::Gltf,
+            lightmap: true,
+            props: false,
+            wad_paths: vec![],
+            game_dir: None,
+            scale: 1.,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Bsp2GltfResult {
+    pub path: PathBuf,
+    /// External textures not found in any WAD, they have no image
+    pub missing_textures: Vec<String>,
+    /// Props not found in the game folder
+    pub missing_props: Vec<String>,
+}
+
+/// Triangles of a brush model sharing a texture
+struct TexturePrimitive {
+    texture_idx: usize,
+    /// Has the lightmap atlas as the second UV set
+    lit: bool,
+    primitive: GltfPrimitive,
+}
+
+/// Lightmap atlas position of every face
+struct LightmapAtlas {
+    width: usize,
+    height: usize,
+    faces: HashMap<usize, (LightmapExtents, [usize; 2])>,
+}
+
+impl LightmapAtlas {
+    fn new(atlas: &Atlas) -> Self {
+        Self {
+            width: atlas.width,
+            height: atlas.height,
+            faces: atlas
+                .faces
+                .iter()
+                .map(|&(face_idx, extents, corner)| (face_idx, (extents, corner)))
+                .collect(),
+        }
+    }
+}
+
+fn dot(a: Vec3, b: Vec3) -> f64 {
+    a.x as f64 * b.x as f64 + a.y as f64 * b.y as f64 + a.z as f64 * b.z as f64
+}
+
+/// One primitive per texture, and per lit or not, of the brush model
+fn model_primitives(
+    bsp: &Bsp,
+    model_idx: usize,
+    atlas: Option<&LightmapAtlas>,
+) -> Vec<TexturePrimitive> {
+    let model = &bsp.models[model_idx];
+    let mut res: Vec<TexturePrimitive> = vec![];
+
+    let faces = model.first_face as usize..(model.first_face + model.face_count) as usize;
+
+    for face_idx in faces {
+        let face = &bsp.faces[face_idx];
+        let texinfo = &bsp.texinfo[face.texinfo as usize];
+        let texture_idx = texinfo.texture_index as usize;
+        let texture = &bsp.textures[texture_idx];
+
+        if !is_drawn_texture(&texture.texture_name.get_string()) {
+            continue;
+        }
+
+        let vertices = bsp.face_vertices(face_idx);
+
+        if vertices.len() < 3 {
+            continue;
+        }
+
+        let plane = &bsp.planes[face.plane as usize];
+        let normal = if face.side == 0 {
+            plane.normal
+        } else {
+            -plane.normal
+        };
+
+        let lightmap = atlas.and_then(|atlas| {
+            atlas
+                .faces
+                .get(&face_idx)
+                .map(|&(extents, corner)| (atlas, extents, corner))
+        });
+
+        let lit = lightmap.is_some();
+
+        let primitive_idx = match res
+            .iter()
+            .position(|curr| curr.texture_idx == texture_idx && curr.lit == lit)
+        {
+            Some(primitive_idx) => primitive_idx,
+            None => {
+                res.push(TexturePrimitive {
+                    texture_idx,
+                    lit,
+                    primitive: GltfPrimitive::default(),
+                });
+                res.len() - 1
+            }
+        };
+
+        let primitive = &mut res[primitive_idx].primitive;
+        let (width, height) = (texture.width.max(1) as f64, texture.height.max(1) as f64);
+
+        let indices = vertices
+            .iter()
+            .map(|&vertex| {
+                let s = dot(vertex, texinfo.u) + texinfo.u_offset as f64;
+                let t = dot(vertex, texinfo.v) + texinfo.v_offset as f64;
+
+                let mut uvs = vec![[(s / width) as f32, (t / height) as f32]];
+
+                if let Some((atlas, extents, [x, y])) = lightmap {
+                    // luxels are sampled at their centers
+                    let luxel_s = s / LUXEL_SIZE - extents.mins[0] as f64 + 0.5 + x as f64;
+                    let luxel_t = t / LUXEL_SIZE - extents.mins[1] as f64 + 0.5 + y as f64;
+
+                    uvs.push([
+                        (luxel_s / atlas.width as f64) as f32,
+                        (luxel_t / atlas.height as f64) as f32,
+                    ]);
+                }
+
+                primitive.push_vertex(vertex.to_array(), normal.to_array(), &uvs)
+            })
+            .collect::<Vec<u32>>();
+
+        // faces wind clockwise but glTF front faces are counter-clockwise
+        for i in 1..indices.len() - 1 {
+            primitive
+                .indices
+                .extend([indices[0], indices[i + 1], indices[i]]);
+        }
+    }
+
+    res
+}
+
+fn entity_origin(entity: &Entity) -> Option<[f32; 3]> {
+    let values = entity
+        .get("origin")?
+        .split_whitespace()
+        .map(|value| value.parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    values.try_into().ok()
+}
+
+/// Rotation of a studio model with `angles` or `angle`, as `[x, y, z, w]`
+fn prop_rotation(entity: &Entity) -> [f32; 4] {
+    let [pitch, yaw, roll] = entity
+        .get("angles")
+        .and_then(|angles| {
+            angles
+                .split_whitespace()
+                .map(|value| value.parse::<f32>().ok())
+                .collect::<Option<Vec<f32>>>()?
+                .try_into()
+                .ok()
+        })
+        .or_else(|| {
+            entity
+                .get("angle")
+                .and_then(|angle| angle.parse::<f32>().ok())
+                .map(|yaw| [0., yaw, 0.])
+        })
+        .unwrap_or_default();
+
+    // the engine flips pitch for studio models
+    let rotation = glam::Quat::from_rotation_z(yaw.to_radians())
+        * glam::Quat::from_rotation_y(-pitch.to_radians())
+        * glam::Quat::from_rotation_x(roll.to_radians());
+
+    rotation.to_array()
+}
+
+fn entity_name(entity_idx: usize, entity: &Entity) -> String {
+    let classname = entity.get("classname").map(String::as_str).unwrap_or("");
+
+    match entity.get("targetname") {
+        Some(targetname) => format!("{entity_idx}_{classname}_{targetname}"),
+        None => format!("{entity_idx}_{classname}"),
+    }
+}
+
+fn brush_model_idx(entity: &Entity) -> Option<usize> {
+    entity.get("model")?.strip_prefix('*')?.parse().ok()
+}
+
+fn prop_model(entity: &Entity) -> Option<&str> {
+    entity
+        .get("model")
+        .map(String::as_str)
+        .filter(|model| model.to_lowercase().ends_with(".mdl"))
+}
+
+/// File name for a texture image that works on every file system
+pub(crate) fn texture_file_name(name: &str) -> String {
+    format!(
+        "{}.png",
+        name.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_")
+    )
+}
+
+/// Writes the embedded texture and returns false if it is external
+fn write_miptex_image(texture: &MipTex, path: &Path) -> eyre::Result<bool> {
+    if texture.is_external() {
+        return Ok(false);
+    }
+
+    let (mut image, (width, height)) = texture.to_rgba();
+
+    // last palette color is transparent
+    if texture.texture_name.get_string().starts_with('{') {
+        texture.mip_images[0]
+            .data
+            .get_bytes()
+            .iter()
+            .zip(image.chunks_exact_mut(4))
+            .filter(|(palette_idx, _)| **palette_idx == 255)
+            .for_each(|(_, pixel)| pixel[3] = 0);
+    }
+
+    image::RgbaImage::from_raw(width, height, image)
+        .expect("image matches dimensions")
+        .save(path)?;
+
+    Ok(true)
+}
+
+pub(crate) fn write_mdl_texture_image(texture: &mdl::Texture, path: &Path) -> eyre::Result<()> {
+    let (width, height) = texture.dimensions();
+    let masked = texture.header.flags.contains(TextureFlag::MASKED);
+
+    let image = texture
+        .image
+        .iter()
+        .flat_map(|&palette_idx| {
+            let [r, g, b] = texture.palette[palette_idx as usize];
+            let a = if masked && palette_idx == 255 { 0 } else { 255 };
+
+            [r, g, b, a]
+        })
+        .collect::<Vec<u8>>();
+
+    image::RgbaImage::from_raw(width, height, image)
+        .expect("image matches dimensions")
+        .save(path)?;
+
+    Ok(())
+}
+
+pub(crate) fn material(name: &str, base_color: Option<usize>, masked: bool) -> Value {
+    let mut pbr = json!({
+        "metallicFactor": 0.,
+        "roughnessFactor": 1.,
+    });
+
+    if let Some(texture) = base_color {
+        pbr["baseColorTexture"] = json!({ "index": texture });
+    }
+
+    let mut res = json!({
+        "name": name,
+        "pbrMetallicRoughness": pbr,
+    });
+
+    if masked {
+        res["alphaMode"] = "MASK".into();
+        res["alphaCutoff"] = 0.5.into();
+    }
+
+    res
+}
+
+/// Triangles of the first submodel of every bodypart in the pose of the first frame
+fn mdl_primitives(mdl: &Mdl, materials: &[usize]) -> Vec<GltfPrimitive> {
+    let pose = setup_studio_model_transformations(mdl)
+        .first()
+        .and_then(|sequence| sequence.first())
+        .and_then(|blend| blend.first())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut res: Vec<(i32, GltfPrimitive)> = vec![];
+
+    for model in mdl
+        .bodyparts
+        .iter()
+        .filter_map(|bodypart| bodypart.models.first())
+    {
+        for mesh in &model.meshes {
+            let skin_ref = mesh.header.skin_ref;
+            // textures can be in a separate T.mdl
+            let (width, height) = mdl
+                .textures
+                .get(skin_ref as usize)
+                .map(|texture| texture.dimensions())
+                .unwrap_or((1, 1));
+
+            let primitive_idx = match res.iter().position(|(curr, _)| *curr == skin_ref) {
+                Some(primitive_idx) => primitive_idx,
+                None => {
+                    res.push((
+                        skin_ref,
+                        GltfPrimitive {
+                            material: materials.get(skin_ref as usize).copied(),
+                            ..Default::default()
+                        },
+                    ));
+                    res.len() - 1
+                }
+            };
+
+            let primitive = &mut res[primitive_idx].1;
+
+            for triangles in &mesh.triangles {
+                let indices = triangles
+                    .get_triverts()
+                    .iter()
+                    .map(|trivert| {
+                        let bone_idx = model.vertex_info[trivert.header.vert_index as usize];
+                        let (bone_pos, bone_rot) = pose
+                            .get(bone_idx as usize)
+                            .copied()
+                            .unwrap_or_else(origin_posrot);
+
+                        let rotate =
+                            |v: glam::Vec3| bone_rot.rotate_vector(cgmath::vec3(v.x, v.y, v.z));
+
+                        let position = bone_pos + rotate(trivert.vertex);
+                        let normal = rotate(trivert.normal);
+
+                        primitive.push_vertex(
+                            position.into(),
+                            normal.into(),
+                            &[[
+                                trivert.header.s as f32 / width as f32,
+                                trivert.header.t as f32 / height as f32,
+                            ]],
+                        )
+                    })
+                    .collect::<Vec<u32>>();
+
+                if indices.len() < 3 {
+                    continue;
+                }
+
+                // studio models wind clockwise like the BSP
+                match triangles {
+                    MeshTriangles::Strip(_) => {
+                        for i in 0..indices.len() - 2 {
+                            let [a, b, c] = [indices[i], indices[i + 1], indices[i + 2]];
+
+                            if i % 2 == 0 {
+                                primitive.indices.extend([a, c, b]);
+                            } else {
+                                primitive.indices.extend([b, c, a]);
+                            }
+                        }
+                    }
+                    MeshTriangles::Fan(_) => {
+                        for i in 1..indices.len() - 1 {
+                            primitive
+                                .indices
+                                .extend([indices[0], indices[i + 1], indices[i]]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    res.into_iter().map(|(_, primitive)| primitive).collect()
+}
+
+/// Looks for the MDL in the mod of the BSP and then in the common mods
+fn find_prop(model: &str, bsp_mod_dir: Option<&Path>, game_dir: Option<&Path>) -> Option<PathBuf> {
+    let model = model.replace('\\', "/");
+
+    bsp_mod_dir
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(game_dir.into_iter().flat_map(|game_dir| {
+            COMMON_GAME_MODS
+                .iter()
+                .map(move |game_mod| game_dir.join(game_mod))
+        }))
+        .map(|mod_dir| mod_dir.join(&model))
+        .find(|path| path.exists())
+}
+
+/// Writes `<out>.gltf`, `<out>.bin` and the images into `<out>_textures`.
+///
+/// Props are MDL models keyed by the `model` value of the entities.
+///
+/// There is no lightmap slot in glTF materials.
+/// Lit materials list the atlas of every light style in `extras.lightmaps` keyed by style
+/// and the atlas goes on `TEXCOORD_1`.
+pub fn write_gltf(
+    bsp: &Bsp,
+    props: &HashMap<String, Mdl>,
+    options: &Bsp2GltfOptions,
+    out_path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let out_path = out_path.as_ref();
+    let (textures_dir, textures_dir_name) = textures_dir(out_path);
+
+    std::fs::create_dir_all(&textures_dir)?;
+
+    let mut gltf = GltfBuilder::new();
+
+    // lightmap atlas of every style
+    let atlas = options.lightmap.then(|| Atlas::new(bsp));
+    let mut lightmaps: Vec<(u8, usize)> = vec![];
+
+    if let Some(atlas) = &atlas {
+        for (style, image) in atlas.images(bsp) {
+            let file_name = format!("lightmap_style{style}.png");
+
+            image.save(textures_dir.join(&file_name))?;
+            lightmaps.push((
+                style,
+                gltf.add_texture(&format!("{textures_dir_name}/{file_name}")),
+            ));
+        }
+    }
+
+    let atlas = atlas.as_ref().map(LightmapAtlas::new);
+
+    // texture images are written when used
+    let mut texture_images: HashMap<usize, Option<usize>> = HashMap::new();
+    let mut materials: HashMap<(usize, bool), usize> = HashMap::new();
+
+    let mut model_mesh = |gltf: &mut GltfBuilder, model_idx: usize| -> eyre::Result<usize> {
+        let mut primitives = vec![];
+
+        for TexturePrimitive {
+            texture_idx,
+            lit,
+            mut primitive,
+        } in model_primitives(bsp, model_idx, atlas.as_ref())
+        {
+            let texture = &bsp.textures[texture_idx];
+            let name = texture.texture_name.get_string();
+
+            let image = match texture_images.get(&texture_idx) {
+                Some(image) => *image,
+                None => {
+                    let file_name = texture_file_name(&name);
+                    let image = write_miptex_image(texture, &textures_dir.join(&file_name))?
+                        .then(|| gltf.add_texture(&format!("{textures_dir_name}/{file_name}")));
+
+                    texture_images.insert(texture_idx, image);
+                    image
+                }
+            };
+
+            let material = *materials.entry((texture_idx, lit)).or_insert_with(|| {
+                let mut material = material(&name, image, name.starts_with('{'));
+
+                if lit {
+                    material["extras"] = json!({
+                        "lightmaps": lightmaps
+                            .iter()
+                            .map(|(style, texture)| {
+                                (style.to_string(), json!({ "index": texture, "texCoord": 1 }))
+                            })
+                            .collect::<serde_json::Map<String, Value>>(),
+                    });
+                }
+
+                gltf.add_material(material)
+            });
+
+            primitive.material = Some(material);
+            primitives.push(primitive);
+        }
+
+        Ok(gltf.add_mesh(&format!("*{model_idx}"), &primitives))
+    };
+
+    let map_name = out_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("map");
+
+    let scale = [options.scale; 3];
+
+    // Z up to Y up
+    let root = gltf.add_node(
+        json!({
+            "name": map_name,
+            "rotation": [-std::f32::consts::FRAC_1_SQRT_2, 0., 0., std::f32::consts::FRAC_1_SQRT_2],
+            "scale": scale,
+        }),
+        None,
+    );
+
+    // props share meshes
+    let mut prop_meshes: HashMap<&str, usize> = HashMap::new();
+
+    for (entity_idx, entity) in bsp.entities.iter().enumerate() {
+        let mut node = json!({
+            "name": entity_name(entity_idx, entity),
+            "extras": entity
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect::<serde_json::Map<String, Value>>(),
+        });
+
+        if let Some(origin) = entity_origin(entity) {
+            node["translation"] = origin.into();
+        }
+
+        let model_idx = if entity_idx == 0 {
+            Some(0)
+        } else {
+            brush_model_idx(entity)
+        };
+
+        if let Some(model_idx) = model_idx.filter(|&model_idx| model_idx < bsp.models.len()) {
+            node["mesh"] = model_mesh(&mut gltf, model_idx)?.into();
+        }
+
+        let node_idx = gltf.add_node(node, Some(root));
+
+        let Some((model, mdl)) =
+            prop_model(entity).and_then(|model| props.get(model).map(|mdl| (model, mdl)))
+        else {
+            continue;
+        };
+
+        let mesh = match prop_meshes.get(model) {
+            Some(mesh) => *mesh,
+            None => {
+                let stem = Path::new(&model.replace('\\', "/"))
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("prop")
+                    .to_string();
+
+                let materials = mdl
+                    .textures
+                    .iter()
+                    .map(|texture| {
+                        let name = texture_name(&texture.header.name);
+                        let file_name = texture_file_name(&format!("{stem}_{name}"));
+
+                        write_mdl_texture_image(texture, &textures_dir.join(&file_name))?;
+
+                        let image = gltf.add_texture(&format!("{textures_dir_name}/{file_name}"));
+
+                        Ok(gltf.add_material(material(
+                            &name,
+                            Some(image),
+                            texture.header.flags.contains(TextureFlag::MASKED),
+                        )))
+                    })
+                    .collect::<eyre::Result<Vec<usize>>>()?;
+
+                let mesh = gltf.add_mesh(&stem, &mdl_primitives(mdl, &materials));
+
+                prop_meshes.insert(model, mesh);
+                mesh
+            }
+        };
+
+        gltf.add_node(
+            json!({
+                "name": model,
+                "rotation": prop_rotation(entity),
+                "mesh": mesh,
+            }),
+            Some(node_idx),
+        );
+    }
+
+    gltf.write_to_file(out_path)
+}
+
+fn texture_name(name: &[u8]) -> String {
+    let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+    String::from_utf8_lossy(&name[..end]).to_string()
+}
+
+/// `<out>_textures` and its name
+pub(crate) fn textures_dir(out_path: &Path) -> (PathBuf, String) {
+    let stem = out_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let name = format!("{stem}_textures");
+
+    (out_path.with_file_name(&name), name)
+}
+
+/// Exports the BSP next to it as `.gltf` or `.obj`
+pub fn bsp2gltf(
+    bsp_path: impl AsRef<Path>,
+    options: &Bsp2GltfOptions,
+) -> eyre::Result<Bsp2GltfResult> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let game_dir = options.game_dir.clone().or_else(|| bsp_game_dir(bsp_path));
+
+    let mut res = Bsp2GltfResult::default();
+
+    if bsp.textures.iter().any(|texture| {
+        texture.is_external() && is_drawn_texture(&texture.texture_name.get_string())
+    }) {
+        let wads = load_wads(&bsp, &options.wad_paths, game_dir.as_deref());
+
+        res.missing_textures = embed_wad_textures(&mut bsp, &wads, false)
+            .missing
+            .into_iter()
+            .filter(|texture| is_drawn_texture(texture))
+            .collect();
+    }
+
+    match options.format {
+        Bsp2GltfFormat::Gltf => {
+            let mut props: HashMap<String, Mdl> = HashMap::new();
+
+            if options.props {
+                let bsp_mod_dir = bsp_path.parent().and_then(Path::parent);
+
+                for model in bsp.entities.iter().filter_map(prop_model) {
+                    if props.contains_key(model) || res.missing_props.iter().any(|m| m == model) {
+                        continue;
+                    }
+
+                    match find_prop(model, bsp_mod_dir, game_dir.as_deref())
+                        .and_then(|path| Mdl::open_from_file(path).ok())
+                    {
+                        Some(mdl) => {
+                            props.insert(model.to_string(), mdl);
+                        }
+                        None => res.missing_props.push(model.to_string()),
+                    }
+                }
+            }
+
+            res.path = bsp_path.with_extension("gltf");
+            write_gltf(&bsp, &props, options, &res.path)?;
+        }
+        Bsp2GltfFormat::Obj => {
+            res.path = bsp_path.with_extension("obj");
+            write_obj(&bsp, options, &res.path)?;
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lightmap_uvs_inside_faces() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let atlas = Atlas::new(&bsp);
+        let lookup = LightmapAtlas::new(&atlas);
+
+        for primitive in model_primitives(&bsp, 0, Some(&lookup))
+            .iter()
+            .filter(|primitive| primitive.lit)
+        {
+            let uvs = &primitive.primitive.uvs[1];
+
+            assert_eq!(uvs.len(), primitive.primitive.positions.len());
+            assert!(
+                uvs.iter()
+                    .flatten()
+                    .all(|value| (0.0..=1.0).contains(value))
+            );
+        }
+    }
+
+    #[test]
+    fn counter_clockwise() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+
+        for TexturePrimitive { primitive, .. } in model_primitives(&bsp, 0, None) {
+            for triangle in primitive.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2]
+                    .map(|i| glam::Vec3::from_array(primitive.positions[triangle[i] as usize]));
+                let normal = glam::Vec3::from_array(primitive.normals[triangle[0] as usize]);
+
+                assert!((b - a).cross(c - a).dot(normal) >= -0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn prop_counter_clockwise() {
+        let mdl =
+            Mdl::open_from_bytes(include_bytes!("../../../../mdl/src/tests/orange.mdl")).unwrap();
+
+        let (mut front, mut back) = (0, 0);
+
+        for primitive in mdl_primitives(&mdl, &[]) {
+            for triangle in primitive.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2]
+                    .map(|i| glam::Vec3::from_array(primitive.positions[triangle[i] as usize]));
+                let normal = [0, 1, 2]
+                    .map(|i| glam::Vec3::from_array(primitive.normals[triangle[i] as usize]))
+                    .into_iter()
+                    .sum::<glam::Vec3>();
+
+                if (b - a).cross(c - a).dot(normal) > 0. {
+                    front += 1;
+                } else {
+                    back += 1;
+                }
+            }
+        }
+
+        assert!(front > back * 10, "{front} {back}");
+    }
+
+    #[test]
+    fn write() {
+        let out_dir = std::env::temp_dir().join("gchimp_bsp2gltf");
+        let _ = std::fs::remove_dir_all(&out_dir);
+        std::fs::create_dir_all(&out_dir).unwrap();
+
+        let out_path = out_dir.join("c1a3d.gltf");
+        let bsp = Bsp::from_bytes(include_bytes!("../../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+
+        write_gltf(&bsp, &HashMap::new(), &Bsp2GltfOptions::new(), &out_path).unwrap();
+
+        let gltf: Value =
+            serde_json::from_str(&std::fs::read_to_string(&out_path).unwrap()).unwrap();
+        let bin_len = std::fs::metadata(out_path.with_extension("bin"))
+            .unwrap()
+            .len();
+
+        assert_eq!(gltf["buffers"][0]["byteLength"], bin_len);
+        assert!(out_dir.join("c1a3d_textures/lightmap_style0.png").exists());
+        // root and one node per entity
+        assert_eq!(
+            gltf["nodes"].as_array().unwrap().len(),
+            bsp.entities.len() + 1
+        );
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
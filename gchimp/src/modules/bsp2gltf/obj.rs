@@ -0,0 +1,109 @@
+use std::{collections::HashSet, fmt::Write, path::Path};
+
+use bsp::Bsp;
+
+use super::{
+    Bsp2GltfOptions, TexturePrimitive, brush_model_idx, entity_name, entity_origin,
+    model_primitives, texture_file_name, textures_dir, write_miptex_image,
+};
+
+/// Writes `<out>.obj`, `<out>.mtl` and the images into `<out>_textures`.
+///
+/// Brush entities are objects moved to their origins, in Y up.
+pub fn write_obj(
+    bsp: &Bsp,
+    options: &Bsp2GltfOptions,
+    out_path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let out_path = out_path.as_ref();
+    let (textures_dir, textures_dir_name) = textures_dir(out_path);
+    let mtl_path = out_path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    std::fs::create_dir_all(&textures_dir)?;
+
+    let mut obj = format!("mtllib {mtl_name}\n");
+    let mut mtl = String::new();
+    let mut written_textures: HashSet<usize> = HashSet::new();
+
+    // obj indices start from 1 and are shared by every object
+    let mut vertex_count = 0;
+
+    for (entity_idx, entity) in bsp.entities.iter().enumerate() {
+        let model_idx = if entity_idx == 0 {
+            Some(0)
+        } else {
+            brush_model_idx(entity)
+        };
+
+        let Some(model_idx) = model_idx.filter(|&model_idx| model_idx < bsp.models.len()) else {
+            continue;
+        };
+
+        let origin = entity_origin(entity).unwrap_or_default();
+
+        writeln!(obj, "o {}", entity_name(entity_idx, entity))?;
+
+        for TexturePrimitive {
+            texture_idx,
+            primitive,
+            ..
+        } in model_primitives(bsp, model_idx, None)
+        {
+            let texture = &bsp.textures[texture_idx];
+            let name = texture.texture_name.get_string();
+
+            if written_textures.insert(texture_idx) {
+                let file_name = texture_file_name(&name);
+
+                writeln!(mtl, "newmtl {name}\nKd 1 1 1")?;
+
+                if write_miptex_image(texture, &textures_dir.join(&file_name))? {
+                    writeln!(mtl, "map_Kd {textures_dir_name}/{file_name}")?;
+                }
+
+                mtl.push('\n');
+            }
+
+            // Z up to Y up
+            let convert = |[x, y, z]: [f32; 3]| [x, z, -y];
+
+            for position in &primitive.positions {
+                let [x, y, z] = convert(std::array::from_fn(|i| {
+                    (position[i] + origin[i]) * options.scale
+                }));
+
+                writeln!(obj, "v {x} {y} {z}")?;
+            }
+
+            for normal in &primitive.normals {
+                let [x, y, z] = convert(*normal);
+
+                writeln!(obj, "vn {x} {y} {z}")?;
+            }
+
+            // obj starts at the bottom left
+            for [u, v] in &primitive.uvs[0] {
+                writeln!(obj, "vt {u} {}", 1. - v)?;
+            }
+
+            writeln!(obj, "usemtl {name}")?;
+
+            for triangle in primitive.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize + vertex_count + 1);
+
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+            }
+
+            vertex_count += primitive.positions.len();
+        }
+    }
+
+    std::fs::write(&mtl_path, mtl)?;
+    std::fs::write(out_path, obj)?;
+
+    Ok(())
+}
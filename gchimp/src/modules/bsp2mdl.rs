@@ -0,0 +1,567 @@
+//! Turns brush models of a compiled map into MDL props with the lighting of RAD baked in.
+//!
+//! Every face gets its own spot in atlas textures. Pixels there are its texture multiplied by its
+//! lightmap, so the prop looks the same as the brush did in game.
+use std::path::{Path, PathBuf};
+
+use bsp::{Bsp, LUXEL_SIZE, LightmapExtents, MAX_LIGHTMAPS};
+use common::{
+    constants::{MAX_GOLDSRC_MODEL_TEXTURE_COUNT, MAX_GOLDSRC_TEXTURE_SIZE},
+    img_stuffs::rgba8_to_8bpp,
+};
+use glam::{DVec2, DVec3};
+use image::RgbaImage;
+use mdl::Mdl;
+use smd::{Triangle, Vertex};
+use studiomdl::StudioMdl;
+
+use crate::{
+    err,
+    modules::wad_embed::{bsp_game_dir, embed_wad_textures, load_wads},
+    utils::{
+        bsp_stuffs::{is_drawn_face, is_drawn_texture},
+        smd_stuffs::maybe_split_triangles,
+    },
+};
+
+/// Pixels around every face copied from its edge so neighbours do not bleed in
+const BAKE_PADDING: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bsp2MdlBake {
+    /// Texture multiplied by the lightmap
+    Multiply,
+    /// Only the lightmap, to combine with the textures somewhere else
+    Lightmap,
+}
+
+pub struct Bsp2MdlOptions {
+    pub bake: Bsp2MdlBake,
+    /// Baked pixels per luxel, faces too big for a texture get fewer
+    pub pixels_per_luxel: u32,
+    /// Lighting is already baked so the engine should not shade the prop again
+    pub fullbright: bool,
+    /// Brush models to convert, every brush entity if empty
+    pub models: Vec<usize>,
+    /// WADs looked up for external textures before the ones in the game folder
+    pub wad_paths: Vec<PathBuf>,
+    /// Folder with the game mods, defaults to the one the BSP is in
+    pub game_dir: Option<PathBuf>,
+}
+
+impl Default for Bsp2MdlOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bsp2MdlOptions {
+    pub fn new() -> Self {
+        Self {
+            bake: Bsp2MdlBake::Multiply,
+            pixels_per_luxel: 4,
+            fullbright: true,
+            models: vec![],
+            wad_paths: vec![],
+            game_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Bsp2MdlResult {
+    pub paths: Vec<PathBuf>,
+    /// External textures not found in any WAD, they are baked as white
+    pub missing_textures: Vec<String>,
+}
+
+/// Where a face is baked
+#[derive(Debug, Clone, Copy)]
+struct BakedFace {
+    face_idx: usize,
+    /// Texture coordinates of the top left corner
+    mins: DVec2,
+    /// Pixels per texture unit
+    scale: f64,
+    /// Without padding
+    size: [u32; 2],
+    page: usize,
+    corner: [u32; 2],
+}
+
+impl BakedFace {
+    /// Texture coordinates to pixel position in the page
+    fn pixel(&self, st: DVec2) -> DVec2 {
+        DVec2::new(self.corner[0] as f64, self.corner[1] as f64)
+            + BAKE_PADDING as f64
+            + (st - self.mins) * self.scale
+    }
+}
+
+/// bsp is on another glam
+fn to_dvec3(vector: bsp::Vec3) -> DVec3 {
+    DVec3::from_array(vector.to_array().map(f64::from))
+}
+
+fn face_texture_coordinates(bsp: &Bsp, face_idx: usize) -> Vec<DVec2> {
+    let texinfo = &bsp.texinfo[bsp.faces[face_idx].texinfo as usize];
+    let (u, v) = (to_dvec3(texinfo.u), to_dvec3(texinfo.v));
+
+    bsp.face_vertices(face_idx)
+        .into_iter()
+        .map(|vertex| {
+            let vertex = to_dvec3(vertex);
+
+            DVec2::new(
+                vertex.dot(u) + texinfo.u_offset as f64,
+                vertex.dot(v) + texinfo.v_offset as f64,
+            )
+        })
+        .collect()
+}
+
+/// Shelf packs the faces of the model into pages no bigger than a model texture
+fn layout_faces(
+    bsp: &Bsp,
+    model_idx: usize,
+    pixels_per_luxel: u32,
+) -> (Vec<BakedFace>, Vec<[u32; 2]>) {
+    let model = &bsp.models[model_idx];
+    let faces = model.first_face as usize..(model.first_face + model.face_count) as usize;
+    let max_size = (MAX_GOLDSRC_TEXTURE_SIZE - BAKE_PADDING * 2) as f64;
+
+    let mut baked = faces
+        .filter(|&face_idx| bsp.faces[face_idx].edge_count >= 3 && is_drawn_face(bsp, face_idx))
+        .map(|face_idx| {
+            let coordinates = face_texture_coordinates(bsp, face_idx);
+            let mins = coordinates.iter().fold(DVec2::MAX, |acc, st| acc.min(*st));
+            let maxs = coordinates.iter().fold(DVec2::MIN, |acc, st| acc.max(*st));
+            let extents = maxs - mins;
+
+            let scale = (pixels_per_luxel.max(1) as f64 / LUXEL_SIZE)
+                .min(max_size / extents.max_element().max(1.));
+            let size = (extents * scale).ceil().max(DVec2::ONE);
+
+            BakedFace {
+                face_idx,
+                mins,
+                scale,
+                size: [size.x as u32, size.y as u32],
+                page: 0,
+                corner: [0, 0],
+            }
+        })
+        .collect::<Vec<BakedFace>>();
+
+    baked.sort_by_key(|face| (std::cmp::Reverse(face.size[1]), face.face_idx));
+
+    let mut pages: Vec<[u32; 2]> = vec![];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+    for face in baked.iter_mut() {
+        let [width, height] = face.size.map(|side| side + BAKE_PADDING * 2);
+
+        if pages.is_empty() {
+            pages.push([0, 0]);
+        }
+
+        if x + width > MAX_GOLDSRC_TEXTURE_SIZE {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+
+        if y + height > MAX_GOLDSRC_TEXTURE_SIZE {
+            pages.push([0, 0]);
+            (x, y) = (0, 0);
+        }
+
+        face.page = pages.len() - 1;
+        face.corner = [x, y];
+
+        let page = pages.last_mut().expect("there is a page");
+        page[0] = page[0].max(x + width);
+        page[1] = page[1].max(y + height);
+
+        x += width;
+        shelf_height = shelf_height.max(height);
+    }
+
+    // multiple of 16 like other model textures
+    pages
+        .iter_mut()
+        .for_each(|page| *page = page.map(|side| side.div_ceil(16) * 16));
+
+    (baked, pages)
+}
+
+/// Normal light style of the face at texture coordinates, `None` if it has no lightmap
+fn sample_lightmap(
+    bsp: &Bsp,
+    face_idx: usize,
+    extents: LightmapExtents,
+    st: DVec2,
+) -> Option<[f64; 3]> {
+    let luxels = (0..MAX_LIGHTMAPS)
+        .filter(|&slot| bsp.faces[face_idx].styles[slot] == 0)
+        .filter_map(|slot| bsp.face_lightmap(face_idx, slot))
+        .collect::<Vec<&[[u8; 3]]>>();
+
+    if luxels.is_empty() {
+        return None;
+    }
+
+    // luxels are at multiples of the luxel size
+    let luxel = (st / LUXEL_SIZE - DVec2::new(extents.mins[0] as f64, extents.mins[1] as f64))
+        .clamp(
+            DVec2::ZERO,
+            DVec2::new(extents.width as f64 - 1., extents.height as f64 - 1.),
+        );
+
+    let [x0, y0] = [luxel.x.floor() as usize, luxel.y.floor() as usize];
+    let [x1, y1] = [
+        (x0 + 1).min(extents.width - 1),
+        (y0 + 1).min(extents.height - 1),
+    ];
+    let fract = luxel - luxel.floor();
+
+    let mut res = [0.; 3];
+
+    for luxels in luxels {
+        let get =
+            |x: usize, y: usize| DVec3::from_array(luxels[y * extents.width + x].map(|c| c as f64));
+
+        let top = get(x0, y0).lerp(get(x1, y0), fract.x);
+        let bottom = get(x0, y1).lerp(get(x1, y1), fract.x);
+        let color = top.lerp(bottom, fract.y);
+
+        res = std::array::from_fn(|i| res[i] + color[i]);
+    }
+
+    Some(res.map(|c| c.min(255.)))
+}
+
+fn bake_page(
+    bsp: &Bsp,
+    faces: &[BakedFace],
+    [width, height]: [u32; 2],
+    bake: Bsp2MdlBake,
+) -> RgbaImage {
+    // opaque so the empty space does not become a transparent color
+    let mut image = RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+
+    for face in faces {
+        let texinfo = &bsp.texinfo[bsp.faces[face.face_idx].texinfo as usize];
+        let texture = &bsp.textures[texinfo.texture_index as usize];
+        let extents = bsp.face_lightmap_extents(face.face_idx);
+
+        let [face_width, face_height] = face.size;
+
+        for y in 0..face_height + BAKE_PADDING * 2 {
+            for x in 0..face_width + BAKE_PADDING * 2 {
+                // padding repeats the edge
+                let inside = [
+                    x.saturating_sub(BAKE_PADDING).min(face_width - 1),
+                    y.saturating_sub(BAKE_PADDING).min(face_height - 1),
+                ];
+                let st =
+                    face.mins + (DVec2::new(inside[0] as f64, inside[1] as f64) + 0.5) / face.scale;
+
+                let light = extents
+                    .and_then(|extents| sample_lightmap(bsp, face.face_idx, extents, st))
+                    .unwrap_or([255.; 3]);
+
+                let color = match bake {
+                    Bsp2MdlBake::Lightmap => light,
+                    Bsp2MdlBake::Multiply if texture.is_external() || texture.width == 0 => light,
+                    Bsp2MdlBake::Multiply => {
+                        let texture_x = (st.x.floor() as i64).rem_euclid(texture.width as i64);
+                        let texture_y = (st.y.floor() as i64).rem_euclid(texture.height as i64);
+                        let palette_idx = texture.mip_images[0].data.get_bytes()
+                            [(texture_y * texture.width as i64 + texture_x) as usize];
+                        let texel = texture.palette.get_bytes()[palette_idx as usize];
+
+                        std::array::from_fn(|i| texel[i] as f64 * light[i] / 255.)
+                    }
+                };
+
+                let [r, g, b] = color.map(|c| c.round().clamp(0., 255.) as u8);
+
+                image.put_pixel(
+                    face.corner[0] + x,
+                    face.corner[1] + y,
+                    image::Rgba([r, g, b, 255]),
+                );
+            }
+        }
+    }
+
+    image
+}
+
+fn bake_texture_name(page: usize) -> String {
+    format!("bake{page}.bmp")
+}
+
+/// Converts one brush model, vertices stay relative to the model so the prop goes at the origin of the entity.
+///
+/// External textures should be embedded first.
+pub fn bsp_model_to_mdl(
+    bsp: &Bsp,
+    model_idx: usize,
+    model_name: &str,
+    options: &Bsp2MdlOptions,
+) -> eyre::Result<Mdl> {
+    if model_idx >= bsp.models.len() {
+        return err!(
+            "Model {model_idx} does not exist, the BSP has {} models",
+            bsp.models.len()
+        );
+    }
+
+    let (faces, pages) = layout_faces(bsp, model_idx, options.pixels_per_luxel);
+
+    if faces.is_empty() {
+        return err!("Model {model_idx} has no visible faces");
+    }
+
+    if pages.len() > MAX_GOLDSRC_MODEL_TEXTURE_COUNT {
+        return err!(
+            "Model {model_idx} needs {} baked textures, lower the pixels per luxel",
+            pages.len()
+        );
+    }
+
+    let mut triangles: Vec<Triangle> = vec![];
+
+    for face in &faces {
+        let bsp_face = &bsp.faces[face.face_idx];
+        let plane = &bsp.planes[bsp_face.plane as usize];
+        let normal = if bsp_face.side == 0 {
+            to_dvec3(plane.normal)
+        } else {
+            -to_dvec3(plane.normal)
+        };
+
+        let [page_width, page_height] = pages[face.page].map(|side| side as f64);
+
+        let vertices = bsp
+            .face_vertices(face.face_idx)
+            .into_iter()
+            .zip(face_texture_coordinates(bsp, face.face_idx))
+            .map(|(vertex, st)| {
+                let pixel = face.pixel(st);
+
+                Vertex {
+                    parent: 0,
+                    pos: to_dvec3(vertex),
+                    norm: normal,
+                    // smd starts at the bottom
+                    uv: DVec2::new(pixel.x / page_width, 1. - pixel.y / page_height),
+                    source: None,
+                }
+            })
+            .collect::<Vec<Vertex>>();
+
+        // faces wind clockwise but smd front faces are counter-clockwise
+        for i in 1..vertices.len() - 1 {
+            triangles.push(Triangle {
+                material: bake_texture_name(face.page),
+                vertices: vec![
+                    vertices[0].clone(),
+                    vertices[i + 1].clone(),
+                    vertices[i].clone(),
+                ],
+            });
+        }
+    }
+
+    let mut studiomdl = StudioMdl::new();
+
+    maybe_split_triangles(triangles)
+        .into_iter()
+        .enumerate()
+        .for_each(|(mesh_idx, mesh)| {
+            studiomdl.add_bodypart((format!("gchimp{mesh_idx}"), mesh));
+        });
+
+    let mut flags = mdl::TextureFlag::NOMIPS | mdl::TextureFlag::FLATSHADE;
+    flags.set(mdl::TextureFlag::FULLBRIGHT, options.fullbright);
+
+    for (page_idx, &page) in pages.iter().enumerate() {
+        let page_faces = faces
+            .iter()
+            .filter(|face| face.page == page_idx)
+            .copied()
+            .collect::<Vec<BakedFace>>();
+
+        let image = bake_page(bsp, &page_faces, page, options.bake);
+
+        studiomdl.add_texture((
+            bake_texture_name(page_idx),
+            rgba8_to_8bpp(image)?,
+            flags.clone(),
+        ));
+    }
+
+    studiomdl.set_model_name(model_name);
+
+    Ok(studiomdl.compile()?)
+}
+
+/// Brush models of the entities with something to draw, `*12` is 12
+fn brush_entity_models(bsp: &Bsp) -> Vec<usize> {
+    let mut models = bsp
+        .entities
+        .iter()
+        .filter_map(|entity| entity.get("model"))
+        .filter_map(|model| model.strip_prefix('*'))
+        .filter_map(|model| model.parse::<usize>().ok())
+        .filter(|&model_idx| model_idx != 0 && model_idx < bsp.models.len())
+        .filter(|&model_idx| {
+            let model = &bsp.models[model_idx];
+
+            (model.first_face..model.first_face + model.face_count)
+                .any(|face_idx| is_drawn_face(bsp, face_idx as usize))
+        })
+        .collect::<Vec<usize>>();
+
+    models.sort();
+    models.dedup();
+
+    models
+}
+
+/// Writes `<map>_<model>.mdl` next to the BSP for every model
+pub fn bsp2mdl(
+    bsp_path: impl AsRef<Path>,
+    options: &Bsp2MdlOptions,
+) -> eyre::Result<Bsp2MdlResult> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let mut res = Bsp2MdlResult::default();
+
+    if options.bake == Bsp2MdlBake::Multiply
+        && bsp.textures.iter().any(|texture| texture.is_external())
+    {
+        let game_dir = options.game_dir.clone().or_else(|| bsp_game_dir(bsp_path));
+        let wads = load_wads(&bsp, &options.wad_paths, game_dir.as_deref());
+
+        res.missing_textures = embed_wad_textures(&mut bsp, &wads, false)
+            .missing
+            .into_iter()
+            .filter(|texture| is_drawn_texture(texture))
+            .collect();
+    }
+
+    let models = if options.models.is_empty() {
+        brush_entity_models(&bsp)
+    } else {
+        options.models.clone()
+    };
+
+    if models.is_empty() {
+        return err!("No brush entities to convert");
+    }
+
+    let stem = bsp_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+
+    for model_idx in models {
+        let path = bsp_path.with_file_name(format!("{stem}_{model_idx}.mdl"));
+        let model_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        bsp_model_to_mdl(&bsp, model_idx, &model_name, options)?.write_to_file(&path)?;
+
+        res.paths.push(path);
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn faces_fit_pages() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+
+        for model_idx in brush_entity_models(&bsp) {
+            let (faces, pages) = layout_faces(&bsp, model_idx, 16);
+
+            for face in &faces {
+                let [width, height] = pages[face.page];
+
+                assert!(width <= MAX_GOLDSRC_TEXTURE_SIZE && height <= MAX_GOLDSRC_TEXTURE_SIZE);
+                assert!(face.corner[0] + face.size[0] + BAKE_PADDING * 2 <= width);
+                assert!(face.corner[1] + face.size[1] + BAKE_PADDING * 2 <= height);
+
+                // vertices land inside the face
+                for st in face_texture_coordinates(&bsp, face.face_idx) {
+                    let pixel = face.pixel(st);
+
+                    assert!(pixel.x >= (face.corner[0] + BAKE_PADDING) as f64 - 1e-6);
+                    assert!(pixel.y >= (face.corner[1] + BAKE_PADDING) as f64 - 1e-6);
+                    assert!(
+                        pixel.x <= (face.corner[0] + BAKE_PADDING + face.size[0]) as f64 + 1e-6
+                    );
+                    assert!(
+                        pixel.y <= (face.corner[1] + BAKE_PADDING + face.size[1]) as f64 + 1e-6
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lightmap_matches_luxels() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+
+        let face_idx = (0..bsp.faces.len())
+            .find(|&face_idx| {
+                bsp.faces[face_idx].styles[0] == 0 && bsp.face_lightmap(face_idx, 0).is_some()
+            })
+            .unwrap();
+        let extents = bsp.face_lightmap_extents(face_idx).unwrap();
+        let luxels = bsp.face_lightmap(face_idx, 0).unwrap();
+
+        // at a luxel there is nothing to blend
+        let st = DVec2::new(
+            (extents.mins[0] + 1) as f64 * LUXEL_SIZE,
+            extents.mins[1] as f64 * LUXEL_SIZE,
+        );
+        let light = sample_lightmap(&bsp, face_idx, extents, st).unwrap();
+        let expected = luxels[1.min(extents.width - 1)];
+
+        // other style slots could add to it
+        assert!((0..3).all(|i| light[i] >= expected[i] as f64 - 1e-6));
+    }
+
+    #[test]
+    fn convert() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let model_idx = brush_entity_models(&bsp)[0];
+
+        let mdl = bsp_model_to_mdl(
+            &bsp,
+            model_idx,
+            "c1a3d_test.mdl",
+            &Bsp2MdlOptions::default(),
+        )
+        .unwrap();
+
+        assert!(!mdl.textures.is_empty());
+        assert!(mdl.textures.iter().all(|texture| {
+            let (width, height) = texture.dimensions();
+            width <= MAX_GOLDSRC_TEXTURE_SIZE && height <= MAX_GOLDSRC_TEXTURE_SIZE
+        }));
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use bsp::{Bsp, Entity, entity_lump, parse_entities_exact, replace_entity_lump_bytes};
+
+use crate::err;
+
+/// Entity lump text as is so bytes that are not UTF-8 survive a round trip
+pub fn export_entities_bytes(bsp_bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    Ok(entity_lump(bsp_bytes)?.to_vec())
+}
+
+/// Checks the entities still make sense for the BSP they go into
+pub fn validate_entities(entities: &[Entity], model_count: usize) -> eyre::Result<()> {
+    let Some(world) = entities.first() else {
+        return err!("No entities");
+    };
+
+    if world.get("classname").map(|s| s.as_str()) != Some("worldspawn") {
+        return err!("First entity is not worldspawn");
+    }
+
+    for (entity_idx, entity) in entities.iter().enumerate().skip(1) {
+        let classname = entity.get("classname").map(|s| s.as_str()).unwrap_or("");
+
+        if classname == "worldspawn" {
+            return err!("Entity {entity_idx} is another worldspawn");
+        }
+
+        let Some(model) = entity.get("model") else {
+            continue;
+        };
+
+        let Some(model_idx) = model.strip_prefix('*') else {
+            continue;
+        };
+
+        // model 0 is the world
+        match model_idx.parse::<usize>() {
+            Ok(model_idx) if (1..model_count).contains(&model_idx) => (),
+            _ => {
+                return err!(
+                    "Entity {entity_idx} ({classname}) references brush model `{model}` \
+but the BSP has {model_count} models"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the entity lump with the text as is, other lumps stay the same
+pub fn import_entities_bytes(bsp_bytes: &[u8], ent_bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    let bsp = Bsp::from_bytes(bsp_bytes)?;
+    let entities = parse_entities_exact(ent_bytes)?;
+
+    validate_entities(&entities, bsp.models.len())?;
+
+    let end = ent_bytes
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |idx| idx + 1);
+
+    Ok(replace_entity_lump_bytes(bsp_bytes, &ent_bytes[..end])?)
+}
+
+/// Writes `<bsp>.ent` next to the BSP and returns its path
+pub fn export_entities(bsp_path: impl AsRef<Path>) -> eyre::Result<PathBuf> {
+    let bsp_path = bsp_path.as_ref();
+    let ent_path = bsp_path.with_extension("ent");
+
+    let ent = export_entities_bytes(&std::fs::read(bsp_path)?)?;
+
+    std::fs::write(&ent_path, ent)?;
+
+    Ok(ent_path)
+}
+
+/// Writes the entities from the `.ent` file into the BSP in place
+pub fn import_entities(bsp_path: impl AsRef<Path>, ent_path: impl AsRef<Path>) -> eyre::Result<()> {
+    let bsp_path = bsp_path.as_ref();
+
+    let res = import_entities_bytes(&std::fs::read(bsp_path)?, &std::fs::read(ent_path)?)?;
+
+    std::fs::write(bsp_path, res)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BSP: &[u8] = include_bytes!("../../../bsp/src/tests/c1a3d.bsp");
+
+    fn export_text(bsp: &[u8]) -> String {
+        String::from_utf8(export_entities_bytes(bsp).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn export_import() {
+        const BSP: &[u8] = include_bytes!("../../../bsp/src/tests/datacore.bsp");
+
+        let ent = export_text(BSP);
+        let ent = ent.replacen(
+            "\"classname\" \"worldspawn\"",
+            "\"classname\" \"worldspawn\"\n\"_note\" \"edited\"",
+            1,
+        );
+
+        let res = import_entities_bytes(BSP, ent.as_bytes()).unwrap();
+
+        assert_eq!(export_text(&res), ent);
+        assert_eq!(
+            Bsp::from_bytes(&res).unwrap().entities[0]["_note"],
+            "edited"
+        );
+    }
+
+    #[test]
+    fn non_utf8() {
+        let ent = export_entities_bytes(BSP).unwrap();
+
+        assert!(std::str::from_utf8(&ent).is_err());
+
+        let header = |offset: usize| {
+            i32::from_le_bytes(BSP[offset..offset + 4].try_into().unwrap()) as usize
+        };
+
+        // entity lump header is right after the version
+        let lump = &BSP[header(4)..header(4) + header(8)];
+
+        assert_eq!(ent, lump.strip_suffix(&[0]).unwrap_or(lump));
+
+        let res = import_entities_bytes(BSP, &ent).unwrap();
+
+        assert_eq!(export_entities_bytes(&res).unwrap(), ent);
+    }
+
+    #[test]
+    fn trailing_text() {
+        let mut ent = export_entities_bytes(BSP).unwrap();
+        ent.extend_from_slice(b"{\n\"classname\" \"info_null\"\n");
+
+        assert!(import_entities_bytes(BSP, &ent).is_err());
+
+        // released maps with junk after the last entity still load
+        let res = replace_entity_lump_bytes(BSP, &ent).unwrap();
+
+        assert!(Bsp::from_bytes(&res).is_ok());
+    }
+
+    #[test]
+    fn bad_model() {
+        let ent = String::from_utf8_lossy(&export_entities_bytes(BSP).unwrap()).to_string();
+        let ent = format!("{ent}{{\n\"classname\" \"func_wall\"\n\"model\" \"*9999\"\n}}\n");
+
+        assert!(import_entities_bytes(BSP, ent.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn worldspawn_first() {
+        let ent = String::from_utf8_lossy(&export_entities_bytes(BSP).unwrap()).to_string();
+        let ent = format!("{{\n\"classname\" \"info_null\"\n}}\n{ent}");
+
+        assert!(import_entities_bytes(BSP, ent.as_bytes()).is_err());
+    }
+}
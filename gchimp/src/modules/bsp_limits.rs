@@ -0,0 +1,595 @@
+//! Counts what a compiled BSP uses against the limits of the compilers and the engine.
+use std::fmt::Display;
+
+use bsp::{Bsp, LUXEL_SIZE, constants::*, write_entities};
+use serde::Serialize;
+
+use crate::utils::bsp_stuffs::is_drawn_face;
+
+/// Lightmap blocks of the GL renderer are 128x128 luxels
+const ALLOCBLOCK_SIZE: usize = 128;
+/// "AllocBlock: full" after this many blocks
+const MAX_ALLOCBLOCKS: usize = 64;
+/// Engine refuses faces spanning more texture units between the first and last luxel
+const MAX_SURFACE_EXTENTS: usize = 256;
+
+pub struct BspLimitsOptions {
+    /// Leaves that can see more world polygons than this are reported
+    pub wpoly_threshold: usize,
+}
+
+impl Default for BspLimitsOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BspLimitsOptions {
+    pub fn new() -> Self {
+        Self {
+            wpoly_threshold: 800,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitUsage {
+    pub name: &'static str,
+    pub count: usize,
+    pub max: usize,
+}
+
+impl LimitUsage {
+    pub fn percent(&self) -> f64 {
+        self.count as f64 / self.max as f64 * 100.
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LimitIssue {
+    OverLimit {
+        name: &'static str,
+        count: usize,
+        max: usize,
+    },
+    /// The engine errors with "Bad surface extents"
+    BadSurfaceExtents {
+        face: usize,
+        texture: String,
+        /// In texture units
+        extents: [usize; 2],
+    },
+    /// More polygons can be drawn from the leaf than the threshold
+    HighWpoly { leaf: usize, wpoly: usize },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitProblem {
+    pub issue: LimitIssue,
+    /// Where to look in the map
+    pub origin: Option<[f64; 3]>,
+}
+
+impl Display for LimitProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.issue {
+            LimitIssue::OverLimit { name, count, max } => {
+                write!(f, "{name} is over the limit: {count}/{max}")?
+            }
+            LimitIssue::BadSurfaceExtents {
+                face,
+                texture,
+                extents,
+            } => write!(
+                f,
+                "Face {face} ({texture}) has bad surface extents {}x{}",
+                extents[0], extents[1]
+            )?,
+            LimitIssue::HighWpoly { leaf, wpoly } => {
+                write!(f, "Leaf {leaf} can see {wpoly} world polygons")?
+            }
+        }
+
+        if let Some([x, y, z]) = self.origin {
+            write!(f, " @ ( {x} {y} {z} )")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WpolyStats {
+    pub max: usize,
+    pub average: f64,
+    /// Leaf with the most polygons
+    pub max_leaf: usize,
+    pub max_origin: [f64; 3],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BspLimitsReport {
+    pub limits: Vec<LimitUsage>,
+    /// Clipnodes of hull 1, 2 and 3
+    pub clipnodes_per_hull: [usize; 3],
+    /// Worst case world polygons per leaf, from the PVS
+    pub wpoly: WpolyStats,
+    /// Highest wpoly first
+    pub problems: Vec<LimitProblem>,
+}
+
+impl Display for BspLimitsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<16}{:>10}{:>10}{:>8}", "", "count", "max", "%")?;
+
+        for limit in &self.limits {
+            writeln!(
+                f,
+                "{:<16}{:>10}{:>10}{:>7.1}%",
+                limit.name,
+                limit.count,
+                limit.max,
+                limit.percent()
+            )?;
+        }
+
+        writeln!(
+            f,
+            "\nclipnodes per hull: {} {} {}",
+            self.clipnodes_per_hull[0], self.clipnodes_per_hull[1], self.clipnodes_per_hull[2]
+        )?;
+
+        let [x, y, z] = self.wpoly.max_origin;
+
+        writeln!(
+            f,
+            "wpoly: max {} in leaf {} @ ( {x} {y} {z} ), average {:.1}",
+            self.wpoly.max, self.wpoly.max_leaf, self.wpoly.average
+        )?;
+
+        if !self.problems.is_empty() {
+            writeln!(f)?;
+        }
+
+        for problem in &self.problems {
+            writeln!(f, "{problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lump lengths in bytes as the BSP would be written
+fn lump_lengths(bsp: &Bsp) -> [usize; HEADER_LUMPS] {
+    let bytes = bsp.write_to_bytes();
+
+    std::array::from_fn(|lump| {
+        // after the version, offset and length of every lump
+        let offset = 4 + lump * HEADER_LUMP_SIZE + 4;
+
+        i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    })
+}
+
+/// Same as `AllocBlock` of the GL renderer, every face in order
+fn allocblock_count(bsp: &Bsp) -> usize {
+    let mut blocks: Vec<[usize; ALLOCBLOCK_SIZE]> = vec![];
+
+    for face_idx in 0..bsp.faces.len() {
+        let Some(extents) = bsp.face_lightmap_extents(face_idx) else {
+            continue;
+        };
+
+        // cannot fit anyway
+        if extents.width >= ALLOCBLOCK_SIZE || extents.height > ALLOCBLOCK_SIZE {
+            continue;
+        }
+
+        let fits = |allocated: &[usize; ALLOCBLOCK_SIZE]| {
+            let mut best = ALLOCBLOCK_SIZE;
+            let mut x = 0;
+
+            for i in 0..ALLOCBLOCK_SIZE - extents.width {
+                let span = &allocated[i..i + extents.width];
+
+                if span.iter().all(|&height| height < best) {
+                    x = i;
+                    best = span.iter().copied().max().unwrap_or(0);
+                }
+            }
+
+            (best + extents.height <= ALLOCBLOCK_SIZE).then_some((x, best))
+        };
+
+        let (block_idx, (x, y)) = match blocks
+            .iter()
+            .enumerate()
+            .find_map(|(block_idx, block)| fits(block).map(|pos| (block_idx, pos)))
+        {
+            Some(res) => res,
+            None => {
+                blocks.push([0; ALLOCBLOCK_SIZE]);
+                (blocks.len() - 1, (0, 0))
+            }
+        };
+
+        blocks[block_idx][x..x + extents.width].fill(y + extents.height);
+    }
+
+    blocks.len()
+}
+
+/// Clipnodes reachable from the head nodes of every model, per hull
+fn clipnodes_per_hull(bsp: &Bsp) -> [usize; 3] {
+    std::array::from_fn(|hull| {
+        let mut visited = vec![false; bsp.clipnodes.len()];
+        let mut stack = bsp
+            .models
+            .iter()
+            .map(|model| model.head_nodes[hull + 1])
+            .collect::<Vec<i32>>();
+
+        while let Some(node) = stack.pop() {
+            // negative is contents
+            let Some(seen) = usize::try_from(node)
+                .ok()
+                .and_then(|node| visited.get_mut(node))
+            else {
+                continue;
+            };
+
+            if *seen {
+                continue;
+            }
+
+            *seen = true;
+            stack.extend(
+                bsp.clipnodes[node as usize]
+                    .children
+                    .iter()
+                    .map(|&child| child as i32),
+            );
+        }
+
+        visited.into_iter().filter(|&seen| seen).count()
+    })
+}
+
+/// Leaves of the world touching the box
+fn leaves_in_box(bsp: &Bsp, mins: [f64; 3], maxs: [f64; 3]) -> Vec<usize> {
+    let mut res = vec![];
+    let mut stack = vec![bsp.models[0].head_nodes[0] as i16];
+
+    while let Some(node) = stack.pop() {
+        if node < 0 {
+            let leaf = (-(node as i32) - 1) as usize;
+
+            // leaf 0 is the solid outside
+            if leaf != 0 {
+                res.push(leaf);
+            }
+
+            continue;
+        }
+
+        let node = &bsp.nodes[node as usize];
+        let plane = &bsp.planes[node.plane as usize];
+        let normal = plane.normal.to_array().map(|e| e as f64);
+
+        let (mut front, mut back) = (-plane.distance as f64, -plane.distance as f64);
+
+        for axis in 0..3 {
+            let [a, b] = [normal[axis] * mins[axis], normal[axis] * maxs[axis]];
+
+            front += a.max(b);
+            back += a.min(b);
+        }
+
+        if front >= 0. {
+            stack.push(node.children[0]);
+        }
+
+        if back <= 0. {
+            stack.push(node.children[1]);
+        }
+    }
+
+    res
+}
+
+fn leaf_origin(bsp: &Bsp, leaf: usize) -> [f64; 3] {
+    let leaf = &bsp.leaves[leaf];
+
+    std::array::from_fn(|axis| (leaf.mins[axis] as f64 + leaf.maxs[axis] as f64) / 2.)
+}
+
+/// Polygons that can be drawn from every leaf, world and brush entities.
+///
+/// Index 0 is leaf 1. Every leaf sees everything without visibility data.
+fn wpoly_per_leaf(bsp: &Bsp) -> Vec<usize> {
+    let leaf_count = bsp.models[0].vis_leaves_count.max(0) as usize;
+
+    // brush entities are drawn when any of their leaves is visible
+    let submodels = bsp
+        .models
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(model_idx, model)| {
+            let origin = bsp
+                .entities
+                .iter()
+                .find(|entity| entity.get("model") == Some(&format!("*{model_idx}")))
+                .and_then(|entity| entity.get("origin"))
+                .map(|origin| {
+                    let mut values = origin
+                        .split_whitespace()
+                        .map(|value| value.parse::<f64>().unwrap_or(0.));
+
+                    [0; 3].map(|_| values.next().unwrap_or(0.))
+                })
+                .unwrap_or_default();
+
+            let mins = std::array::from_fn(|axis| model.mins[axis] as f64 + origin[axis]);
+            let maxs = std::array::from_fn(|axis| model.maxs[axis] as f64 + origin[axis]);
+
+            let polys = (model.first_face..model.first_face + model.face_count)
+                .filter(|&face_idx| is_drawn_face(bsp, face_idx as usize))
+                .count();
+
+            (leaves_in_box(bsp, mins, maxs), polys)
+        })
+        .collect::<Vec<(Vec<usize>, usize)>>();
+
+    // faces are shared between leaves, only counted once per leaf
+    let mut face_stamp = vec![usize::MAX; bsp.faces.len()];
+
+    (1..=leaf_count)
+        .map(|leaf| {
+            let mut visible = bsp.leaf_pvs(leaf).unwrap_or(vec![true; leaf_count]);
+
+            visible.resize(leaf_count, false);
+            visible[leaf - 1] = true;
+
+            let world = visible
+                .iter()
+                .enumerate()
+                .filter(|(_, visible)| **visible)
+                .flat_map(|(other, _)| {
+                    let other = &bsp.leaves[other + 1];
+                    let first = other.first_mark_surface as usize;
+
+                    bsp.mark_surfaces[first..first + other.mark_surface_count as usize].iter()
+                })
+                .filter(|&&face_idx| {
+                    let face_idx = face_idx as usize;
+                    let counted = face_stamp[face_idx] == leaf;
+
+                    face_stamp[face_idx] = leaf;
+
+                    !counted && is_drawn_face(bsp, face_idx)
+                })
+                .count();
+
+            let entities = submodels
+                .iter()
+                .filter(|(leaves, _)| {
+                    leaves
+                        .iter()
+                        .any(|&other| visible.get(other - 1).copied().unwrap_or(false))
+                })
+                .map(|(_, polys)| polys)
+                .sum::<usize>();
+
+            world + entities
+        })
+        .collect()
+}
+
+fn face_center(bsp: &Bsp, face_idx: usize) -> [f64; 3] {
+    let vertices = bsp.face_vertices(face_idx);
+    let count = vertices.len().max(1) as f64;
+
+    std::array::from_fn(|axis| vertices.iter().map(|v| v[axis] as f64).sum::<f64>() / count)
+}
+
+pub fn bsp_limits(bsp: &Bsp, options: &BspLimitsOptions) -> BspLimitsReport {
+    let lumps = lump_lengths(bsp);
+    let clipnodes_per_hull = clipnodes_per_hull(bsp);
+
+    let limits = vec![
+        ("models", bsp.models.len(), MAX_MAP_MODELS),
+        ("entities", bsp.entities.len(), MAX_MAP_ENTITIES),
+        (
+            "entstring",
+            // null terminated
+            write_entities(&bsp.entities).len() + 1,
+            MAX_MAP_ENTSTRING,
+        ),
+        ("planes", bsp.planes.len(), MAX_MAP_PLANES),
+        ("nodes", bsp.nodes.len(), MAX_MAP_NODES),
+        ("clipnodes", bsp.clipnodes.len(), MAX_MAP_CLIPNODES),
+        ("leaves", bsp.leaves.len(), MAX_MAP_LEAFS),
+        ("vertices", bsp.vertices.len(), MAX_MAP_VERTS),
+        ("faces", bsp.faces.len(), MAX_MAP_FACES),
+        (
+            "marksurfaces",
+            bsp.mark_surfaces.len(),
+            MAX_MAP_MARKSURFACES,
+        ),
+        ("texinfo", bsp.texinfo.len(), MAX_MAP_TEXINFO),
+        ("edges", bsp.edges.len(), MAX_MAP_EDGES),
+        ("surfedges", bsp.surf_edges.len(), MAX_MAP_SURFEDGES),
+        ("textures", bsp.textures.len(), MAX_MAP_TEXTURES),
+        ("miptex", lumps[LUMP_TEXTURES], MAX_MAP_MIPTEX),
+        ("lightdata", lumps[LUMP_LIGHTING], MAX_MAP_LIGHTING),
+        ("visdata", lumps[LUMP_VISIBILITY], MAX_MAP_VISIBILITY),
+        ("allocblock", allocblock_count(bsp), MAX_ALLOCBLOCKS),
+    ]
+    .into_iter()
+    .map(|(name, count, max)| LimitUsage { name, count, max })
+    .collect::<Vec<LimitUsage>>();
+
+    let mut problems = limits
+        .iter()
+        .filter(|limit| limit.count > limit.max)
+        .map(|limit| LimitProblem {
+            issue: LimitIssue::OverLimit {
+                name: limit.name,
+                count: limit.count,
+                max: limit.max,
+            },
+            origin: None,
+        })
+        .collect::<Vec<LimitProblem>>();
+
+    for face_idx in 0..bsp.faces.len() {
+        let Some(extents) = bsp.face_lightmap_extents(face_idx) else {
+            continue;
+        };
+
+        let extents =
+            [extents.width, extents.height].map(|luxels| (luxels - 1) * LUXEL_SIZE as usize);
+
+        if extents.iter().any(|&extent| extent > MAX_SURFACE_EXTENTS) {
+            let texinfo = &bsp.texinfo[bsp.faces[face_idx].texinfo as usize];
+
+            problems.push(LimitProblem {
+                issue: LimitIssue::BadSurfaceExtents {
+                    face: face_idx,
+                    texture: bsp.textures[texinfo.texture_index as usize]
+                        .texture_name
+                        .get_string(),
+                    extents,
+                },
+                origin: Some(face_center(bsp, face_idx)),
+            });
+        }
+    }
+
+    let wpoly_per_leaf = wpoly_per_leaf(bsp);
+    let mut wpoly = WpolyStats {
+        average: wpoly_per_leaf.iter().sum::<usize>() as f64 / wpoly_per_leaf.len().max(1) as f64,
+        ..Default::default()
+    };
+
+    if let Some((leaf_idx, &max)) = wpoly_per_leaf
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, wpoly)| wpoly)
+    {
+        wpoly.max = max;
+        wpoly.max_leaf = leaf_idx + 1;
+        wpoly.max_origin = leaf_origin(bsp, leaf_idx + 1);
+    }
+
+    let mut high_wpoly = wpoly_per_leaf
+        .iter()
+        .enumerate()
+        .filter(|&(_, &wpoly)| wpoly > options.wpoly_threshold)
+        .map(|(leaf_idx, &wpoly)| (leaf_idx + 1, wpoly))
+        .collect::<Vec<(usize, usize)>>();
+
+    high_wpoly.sort_by_key(|&(leaf, wpoly)| (std::cmp::Reverse(wpoly), leaf));
+
+    problems.extend(high_wpoly.into_iter().map(|(leaf, wpoly)| LimitProblem {
+        issue: LimitIssue::HighWpoly { leaf, wpoly },
+        origin: Some(leaf_origin(bsp, leaf)),
+    }));
+
+    BspLimitsReport {
+        limits,
+        clipnodes_per_hull,
+        wpoly,
+        problems,
+    }
+}
+
+pub fn bsp_limits_to_json(report: &BspLimitsReport) -> eyre::Result<String> {
+    Ok(serde_json::to_string_pretty(report)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let report = bsp_limits(&bsp, &BspLimitsOptions::new());
+
+        let count = |name: &str| {
+            report
+                .limits
+                .iter()
+                .find(|limit| limit.name == name)
+                .unwrap()
+                .count
+        };
+
+        assert_eq!(count("faces"), bsp.faces.len());
+        assert_eq!(count("lightdata"), bsp.lightmap.len() * 3);
+        assert!((1..=MAX_ALLOCBLOCKS).contains(&count("allocblock")));
+
+        // shipped map so it has to be fine
+        assert!(
+            report
+                .problems
+                .iter()
+                .all(|problem| matches!(problem.issue, LimitIssue::HighWpoly { .. }))
+        );
+
+        // hulls have their own clipnodes
+        assert_eq!(
+            report.clipnodes_per_hull.iter().sum::<usize>(),
+            bsp.clipnodes.len()
+        );
+    }
+
+    #[test]
+    fn wpoly() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let report = bsp_limits(&bsp, &BspLimitsOptions { wpoly_threshold: 0 });
+
+        assert!(report.wpoly.max > 0);
+        assert!(report.wpoly.average <= report.wpoly.max as f64);
+
+        // sorted by wpoly
+        let LimitIssue::HighWpoly { wpoly, leaf } = report.problems[0].issue else {
+            panic!("first problem is not wpoly");
+        };
+
+        assert_eq!(wpoly, report.wpoly.max);
+        assert_eq!(leaf, report.wpoly.max_leaf);
+    }
+
+    #[test]
+    fn allocblock_full() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let faces = bsp.faces.len();
+
+        // every face again is twice the luxels
+        let single = allocblock_count(&bsp);
+
+        let copies = (0..faces)
+            .map(|face_idx| {
+                let face = &bsp.faces[face_idx];
+
+                bsp::Face {
+                    plane: face.plane,
+                    side: face.side,
+                    first_edge: face.first_edge,
+                    edge_count: face.edge_count,
+                    texinfo: face.texinfo,
+                    styles: face.styles,
+                    lightmap_offset: face.lightmap_offset,
+                }
+            })
+            .collect::<Vec<bsp::Face>>();
+
+        bsp.faces.extend(copies);
+
+        assert!(allocblock_count(&bsp) > single);
+    }
+}
@@ -1,22 +1,499 @@
-use map::Map;
+use std::fmt::Display;
 
-pub fn check_illegal_brush(map: &Map) {
-    map.entities
+use glam::{DVec3, Vec4Swizzles};
+use map::{Brush, Map};
+use serde::Serialize;
+
+use crate::utils::{
+    map_stuffs::brush_to_solid3d,
+    simple_calculs::{Point3D, Polygon3D},
+};
+
+/// Size of the polygon that gets clipped down into a brush face
+const BASE_WINDING_SIZE: f64 = 131072.;
+/// How far a point can be from a plane and still be considered on it
+const ON_EPSILON: f64 = 0.01;
+/// Vertices closer than this to a grid point are considered on grid
+const GRID_EPSILON: f64 = 0.001;
+
+pub struct CheckIllegalBrushOptions {
+    /// Vertices should snap to multiples of this value
+    pub grid: f64,
+    /// Vertices beyond this absolute coordinate are out of bounds
+    pub max_coord: f64,
+    /// Brushes with any dimension smaller than this are microbrushes
+    pub microbrush_size: f64,
+    /// Faces with smaller area than this are considered degenerate
+    pub min_face_area: f64,
+    /// Brushes with this many planes or more are reported
+    pub max_planes: usize,
+}
+
+impl Default for CheckIllegalBrushOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CheckIllegalBrushOptions {
+    pub fn new() -> Self {
+        Self {
+            grid: 1.,
+            max_coord: 4096.,
+            microbrush_size: 1.,
+            min_face_area: 0.1,
+            max_planes: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BrushIssue {
+    TooManyPlanes {
+        count: usize,
+    },
+    /// The three points defining the plane are collinear
+    DegeneratePlane,
+    /// Same plane appears twice in the brush
+    DuplicatePlane {
+        other: usize,
+    },
+    /// Plane does not end up as a face, usually a flipped plane or a concave brush
+    NonConvexPlane,
+    ZeroAreaFace {
+        area: f64,
+    },
+    NoVolume,
+    /// Brush is open on one side, usually a flipped plane
+    Unbounded,
+    OffGridVertex {
+        count: usize,
+    },
+    OutOfBounds {
+        count: usize,
+    },
+    Microbrush {
+        size: [f64; 3],
+    },
+    ZeroTextureScale {
+        u_scale: f64,
+        v_scale: f64,
+    },
+    /// Texture axis is zero, parallel to the face normal, or parallel to the other axis
+    InvalidTextureAxis,
+}
+
+impl Display for BrushIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BrushIssue::TooManyPlanes { count } => write!(f, "might be illegal: {count} faces"),
+            BrushIssue::DegeneratePlane => write!(f, "plane points are collinear"),
+            BrushIssue::DuplicatePlane { other } => {
+                write!(f, "plane is duplicated by plane {other}")
+            }
+            BrushIssue::NonConvexPlane => {
+                write!(f, "plane does not form a face, brush is likely non-convex")
+            }
+            BrushIssue::ZeroAreaFace { area } => write!(f, "face has near zero area: {area}"),
+            BrushIssue::NoVolume => write!(f, "brush has no volume"),
+            BrushIssue::Unbounded => write!(f, "brush is not closed, a plane might be flipped"),
+            BrushIssue::OffGridVertex { count } => write!(f, "{count} vertices are off grid"),
+            BrushIssue::OutOfBounds { count } => write!(f, "{count} vertices are out of bounds"),
+            BrushIssue::Microbrush { size } => write!(
+                f,
+                "microbrush with size {} {} {}",
+                size[0], size[1], size[2]
+            ),
+            BrushIssue::ZeroTextureScale { u_scale, v_scale } => {
+                write!(f, "texture has zero scale: {u_scale} {v_scale}")
+            }
+            BrushIssue::InvalidTextureAxis => {
+                write!(f, "texture axis is zero or parallel to face normal")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BrushDiagnostic {
+    pub entity: usize,
+    pub brush: usize,
+    /// Plane index inside the brush if the issue is about one plane
+    pub plane: Option<usize>,
+    /// Where to look at in the editor
+    pub position: [f64; 3],
+    pub issue: BrushIssue,
+    pub message: String,
+}
+
+impl BrushDiagnostic {
+    fn new(
+        entity: usize,
+        brush: usize,
+        plane: Option<usize>,
+        position: [f64; 3],
+        issue: BrushIssue,
+    ) -> Self {
+        let message = issue.to_string();
+
+        Self {
+            entity,
+            brush,
+            plane,
+            position,
+            issue,
+            message,
+        }
+    }
+}
+
+impl Display for BrushDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Entity {} Brush {}", self.entity, self.brush)?;
+
+        if let Some(plane) = self.plane {
+            write!(f, " Plane {plane}")?;
+        }
+
+        write!(
+            f,
+            " ( {} {} {} ) {}",
+            self.position[0], self.position[1], self.position[2], self.message
+        )
+    }
+}
+
+/// Checks one brush and returns the issues along with where they are
+pub fn check_brush(
+    brush: &Brush,
+    options: &CheckIllegalBrushOptions,
+) -> Vec<(Option<usize>, [f64; 3], BrushIssue)> {
+    let mut res = vec![];
+
+    let Some(first_plane) = brush.planes.first() else {
+        return res;
+    };
+
+    let brush_position = first_plane.p1.to_array();
+
+    if brush.planes.len() >= options.max_planes {
+        res.push((
+            None,
+            brush_position,
+            BrushIssue::TooManyPlanes {
+                count: brush.planes.len(),
+            },
+        ));
+    }
+
+    let solid = brush_to_solid3d(brush);
+    let planes = solid
+        .faces()
+        .iter()
+        .map(|plane| plane.normalized())
+        .collect::<Vec<_>>();
+
+    // plane definition and texture checks
+    brush
+        .planes
         .iter()
         .enumerate()
-        .for_each(|(entity_idx, entity)| {
-            if let Some(brushes) = &entity.brushes {
-                brushes.iter().enumerate().for_each(|(brush_idx, brush)| {
-                    if brush.planes.len() >= 32 {
-                        println!(
-                            "Entity {entity_idx} Brush {brush_idx} ( {} {} {} ) ( {} {} {} ) ( {} {} {} ) might be illegal: {} faces",
-                            brush.planes[0].p1.x, brush.planes[0].p1.y, brush.planes[0].p1.z,
-                            brush.planes[0].p2.x, brush.planes[0].p2.y, brush.planes[0].p2.z,
-                            brush.planes[0].p3.x, brush.planes[0].p3.y, brush.planes[0].p3.z,
-                            brush.planes.len()
-                        );
-                    }
-                });
+        .for_each(|(plane_idx, brush_plane)| {
+            let position = brush_plane.p1.to_array();
+            let normal = brush_plane.normal();
+
+            if !normal.is_finite() {
+                res.push((Some(plane_idx), position, BrushIssue::DegeneratePlane));
+                return;
+            }
+
+            if let Some(other) = planes[..plane_idx].iter().position(|other| {
+                (other.normal() - planes[plane_idx].normal()).length() < ON_EPSILON
+                    && (other.distance() - planes[plane_idx].distance()).abs() < ON_EPSILON
+            }) {
+                res.push((
+                    Some(plane_idx),
+                    position,
+                    BrushIssue::DuplicatePlane { other },
+                ));
+            }
+
+            if brush_plane.u_scale == 0. || brush_plane.v_scale == 0. {
+                res.push((
+                    Some(plane_idx),
+                    position,
+                    BrushIssue::ZeroTextureScale {
+                        u_scale: brush_plane.u_scale,
+                        v_scale: brush_plane.v_scale,
+                    },
+                ));
+            }
+
+            if is_texture_axis_invalid(brush_plane.u.xyz(), brush_plane.v.xyz(), normal) {
+                res.push((Some(plane_idx), position, BrushIssue::InvalidTextureAxis));
             }
         });
+
+    // if the planes are already broken, the geometry checks are just noise
+    if res.iter().any(|(_, _, issue)| {
+        matches!(
+            issue,
+            BrushIssue::DegeneratePlane | BrushIssue::DuplicatePlane { .. }
+        )
+    }) {
+        return res;
+    }
+
+    let polygons = solid.face_polygons(BASE_WINDING_SIZE, ON_EPSILON);
+
+    // vertices of the base winding survived the clipping
+    if polygons
+        .iter()
+        .flat_map(|polygon| polygon.vertices())
+        .any(|vertex| {
+            vertex
+                .as_array()
+                .iter()
+                .any(|coord| coord.abs() > BASE_WINDING_SIZE / 2.)
+        })
+    {
+        res.push((None, brush_position, BrushIssue::Unbounded));
+        return res;
+    }
+    let face_count = polygons
+        .iter()
+        .filter(|polygon| !polygon.vertices().is_empty())
+        .count();
+
+    if face_count < 4 || solid.volume(BASE_WINDING_SIZE, ON_EPSILON) < ON_EPSILON {
+        res.push((None, brush_position, BrushIssue::NoVolume));
+        return res;
+    }
+
+    polygons.iter().zip(&brush.planes).enumerate().for_each(
+        |(plane_idx, (polygon, brush_plane))| {
+            if polygon.vertices().is_empty() {
+                res.push((
+                    Some(plane_idx),
+                    brush_plane.p1.to_array(),
+                    BrushIssue::NonConvexPlane,
+                ));
+                return;
+            }
+
+            let area = polygon.area();
+
+            if area < options.min_face_area {
+                res.push((
+                    Some(plane_idx),
+                    polygon.vertices()[0].as_array(),
+                    BrushIssue::ZeroAreaFace { area },
+                ));
+            }
+        },
+    );
+
+    let vertices = unique_vertices(&polygons);
+
+    let off_grid = vertices
+        .iter()
+        .filter(|vertex| !is_on_grid(vertex, options.grid))
+        .collect::<Vec<_>>();
+
+    if let Some(first) = off_grid.first() {
+        res.push((
+            None,
+            first.as_array(),
+            BrushIssue::OffGridVertex {
+                count: off_grid.len(),
+            },
+        ));
+    }
+
+    let out_of_bounds = vertices
+        .iter()
+        .filter(|vertex| {
+            vertex
+                .as_array()
+                .iter()
+                .any(|coord| coord.abs() > options.max_coord)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(first) = out_of_bounds.first() {
+        res.push((
+            None,
+            first.as_array(),
+            BrushIssue::OutOfBounds {
+                count: out_of_bounds.len(),
+            },
+        ));
+    }
+
+    let mins = vertices
+        .iter()
+        .fold(DVec3::MAX, |acc, e| acc.min(e.to_dvec3()));
+    let maxs = vertices
+        .iter()
+        .fold(DVec3::MIN, |acc, e| acc.max(e.to_dvec3()));
+    let size = maxs - mins;
+
+    if size.min_element() < options.microbrush_size {
+        res.push((
+            None,
+            mins.to_array(),
+            BrushIssue::Microbrush {
+                size: size.to_array(),
+            },
+        ));
+    }
+
+    res
+}
+
+/// Checks every brush in the map
+pub fn check_illegal_brush(map: &Map, options: &CheckIllegalBrushOptions) -> Vec<BrushDiagnostic> {
+    map.entities
+        .iter()
+        .enumerate()
+        .filter_map(|(entity_idx, entity)| {
+            entity.brushes.as_ref().map(|brushes| (entity_idx, brushes))
+        })
+        .flat_map(|(entity_idx, brushes)| {
+            brushes
+                .iter()
+                .enumerate()
+                .flat_map(move |(brush_idx, brush)| {
+                    check_brush(brush, options)
+                        .into_iter()
+                        .map(move |(plane, position, issue)| {
+                            BrushDiagnostic::new(entity_idx, brush_idx, plane, position, issue)
+                        })
+                })
+        })
+        .collect()
+}
+
+pub fn brush_diagnostics_to_json(diagnostics: &[BrushDiagnostic]) -> eyre::Result<String> {
+    Ok(serde_json::to_string_pretty(diagnostics)?)
+}
+
+fn is_texture_axis_invalid(u: DVec3, v: DVec3, normal: DVec3) -> bool {
+    const PARALLEL_THRESHOLD: f64 = 0.999;
+
+    if u.length_squared() == 0. || v.length_squared() == 0. {
+        return true;
+    }
+
+    let u = u.normalize();
+    let v = v.normalize();
+
+    u.dot(normal).abs() > PARALLEL_THRESHOLD
+        || v.dot(normal).abs() > PARALLEL_THRESHOLD
+        || u.dot(v).abs() > PARALLEL_THRESHOLD
+}
+
+fn is_on_grid(vertex: &Point3D, grid: f64) -> bool {
+    vertex.as_array().iter().all(|coord| {
+        let snapped = (coord / grid).round() * grid;
+
+        (coord - snapped).abs() < GRID_EPSILON
+    })
+}
+
+fn unique_vertices(polygons: &[Polygon3D]) -> Vec<Point3D> {
+    // Polygon3D already checks for duplication
+    polygons
+        .iter()
+        .flat_map(|polygon| polygon.vertices())
+        .fold(Polygon3D::default(), |mut acc, vertex| {
+            acc.add_vertex(*vertex);
+            acc
+        })
+        .vertices()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::map_stuffs::brush_from_mins_maxs;
+
+    use super::*;
+
+    #[test]
+    fn good_brush() {
+        let brush = brush_from_mins_maxs([-16., -16., -16.].into(), [16., 16., 16.].into(), "AAA");
+
+        assert!(check_brush(&brush, &CheckIllegalBrushOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn microbrush_off_grid() {
+        let brush = brush_from_mins_maxs([0., 0., 0.].into(), [16., 16., 0.5].into(), "AAA");
+        let issues = check_brush(&brush, &CheckIllegalBrushOptions::default());
+
+        assert!(
+            issues
+                .iter()
+                .any(|(_, _, issue)| matches!(issue, BrushIssue::Microbrush { .. }))
+        );
+        assert!(
+            issues
+                .iter()
+                .any(|(_, _, issue)| matches!(issue, BrushIssue::OffGridVertex { count: 4 }))
+        );
+    }
+
+    #[test]
+    fn flipped_plane() {
+        let mut brush =
+            brush_from_mins_maxs([-16., -16., -16.].into(), [16., 16., 16.].into(), "AAA");
+        let plane = &mut brush.planes[0];
+        std::mem::swap(&mut plane.p2, &mut plane.p3);
+
+        let issues = check_brush(&brush, &CheckIllegalBrushOptions::default());
+
+        assert!(
+            issues
+                .iter()
+                .any(|(_, _, issue)| matches!(issue, BrushIssue::Unbounded))
+        );
+    }
+
+    #[test]
+    fn redundant_plane() {
+        let mut brush =
+            brush_from_mins_maxs([-16., -16., -16.].into(), [16., 16., 16.].into(), "AAA");
+        let mut extra = brush.planes[0].clone();
+        extra.p1.x -= 32.;
+        extra.p2.x -= 32.;
+        extra.p3.x -= 32.;
+        brush.planes.push(extra);
+
+        let issues = check_brush(&brush, &CheckIllegalBrushOptions::default());
+
+        assert_eq!(
+            issues,
+            vec![(Some(6), [-48., -16., -16.], BrushIssue::NonConvexPlane)]
+        );
+    }
+
+    #[test]
+    fn bad_texture_axis() {
+        let mut brush =
+            brush_from_mins_maxs([-16., -16., -16.].into(), [16., 16., 16.].into(), "AAA");
+        brush.planes[2].u = [0., 0., 1., 0.].into();
+        brush.planes[3].u_scale = 0.;
+
+        let issues = check_brush(&brush, &CheckIllegalBrushOptions::default());
+
+        assert!(issues.contains(&(
+            Some(2),
+            brush.planes[2].p1.to_array(),
+            BrushIssue::InvalidTextureAxis
+        )));
+        assert!(issues.iter().any(|(plane, _, issue)| *plane == Some(3)
+            && matches!(issue, BrushIssue::ZeroTextureScale { .. })));
+    }
 }
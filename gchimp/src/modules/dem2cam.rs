@@ -5,11 +5,16 @@ use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use std::array::from_fn;
+use std::str::FromStr;
+
 use dem::open_demo;
 use dem::types::Demo;
-use glam::Vec3Swizzles;
+use glam::{Vec3, Vec3Swizzles};
+use serde::Serialize;
 
-use crate::utils::dem_stuffs::get_ghost::get_ghost_demo;
+use crate::err;
+use crate::utils::dem_stuffs::get_ghost::{GhostInfo, angle_diff, get_ghost_demo};
 
 #[derive(Clone, Copy)]
 pub struct ViewInfo {
@@ -59,6 +64,7 @@ DATA
         .to_string()
     }
 
+    /// Viewangles are pitch, yaw and roll
     pub fn append_entry(
         &mut self,
         time: f32,
@@ -79,15 +85,18 @@ DATA
 
     fn entry_to_string(&self, idx: usize) -> String {
         let curr = self.data.campaths[idx];
+        // roll pitch yaw
+        let rotation = curr.viewinfo.viewangles.zxy();
+
         format!(
             "{} {} {} {} {} {} {} {}\n",
             curr.time,
             curr.viewinfo.vieworg[0],
             curr.viewinfo.vieworg[1],
             curr.viewinfo.vieworg[2],
-            curr.viewinfo.viewangles[0],
-            curr.viewinfo.viewangles[1],
-            curr.viewinfo.viewangles[2],
+            rotation[0],
+            rotation[1],
+            rotation[2],
             curr.fov
         )
     }
@@ -107,9 +116,199 @@ DATA
 
 // end portion copied from bxt-rs
 
+// keyframes of exporters without their own time are placed at this framerate
+const DEFAULT_EXPORT_FPS: f32 = 100.;
+// zoom of the After Effects camera is for a comp this wide
+const AFTER_EFFECTS_COMP_WIDTH: f32 = 1920.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CamFormat {
+    /// HLAE `advancedfx Cam` v2
+    Hlae,
+    Csv,
+    /// Python script to run in Blender, makes an animated camera
+    Blender,
+    /// Keyframe JSON for a camera layer
+    AfterEffects,
+}
+
+impl CamFormat {
+    /// End of the file name, including the extension
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            CamFormat::Hlae => ".cam",
+            CamFormat::Csv => ".csv",
+            CamFormat::Blender => ".blender.py",
+            CamFormat::AfterEffects => ".ae.json",
+        }
+    }
+}
+
+impl FromStr for CamFormat {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cam" | "hlae" => Ok(CamFormat::Hlae),
+            "csv" => Ok(CamFormat::Csv),
+            "blender" | "py" => Ok(CamFormat::Blender),
+            "ae" | "after_effects" => Ok(CamFormat::AfterEffects),
+            _ => err!("Unknown camera format {s}"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AfterEffectsCamera {
+    fps: f32,
+    comp_width: f32,
+    keyframes: Vec<AfterEffectsKeyframe>,
+}
+
+/// Y is down and Z is forward
+#[derive(Serialize)]
+struct AfterEffectsKeyframe {
+    time: f32,
+    position: [f32; 3],
+    /// X, Y and Z Rotation
+    rotation: [f32; 3],
+    zoom: f32,
+}
+
+impl Exporter {
+    pub fn from_campaths(campaths: Vec<ViewInfoCamIO>) -> Self {
+        Self {
+            data: CamIO { campaths },
+        }
+    }
+
+    pub fn write_to_csv(&self) -> String {
+        let mut res = "time,x,y,z,pitch,yaw,roll,fov\n".to_string();
+
+        for curr in &self.data.campaths {
+            let [x, y, z] = curr.viewinfo.vieworg.to_array();
+            let [pitch, yaw, roll] = curr.viewinfo.viewangles.to_array();
+
+            res += &format!(
+                "{},{x},{y},{z},{pitch},{yaw},{roll},{}\n",
+                curr.time, curr.fov
+            );
+        }
+
+        res
+    }
+
+    /// Blender is also Z up, the camera looks down its -Z so it is turned up first
+    pub fn write_to_blender_py(&self, name: &str, fps: f32) -> String {
+        let keyframes: String = self
+            .data
+            .campaths
+            .iter()
+            .map(|curr| {
+                let [x, y, z] = curr.viewinfo.vieworg.to_array();
+                let [pitch, yaw, roll] = curr.viewinfo.viewangles.to_array();
+
+                format!(
+                    "    ({}, {x}, {y}, {z}, {pitch}, {yaw}, {roll}, {}),\n",
+                    curr.time, curr.fov
+                )
+            })
+            .collect();
+
+        format!(
+            "\
+import math
+
+import bpy
+from mathutils import Euler, Matrix
+
+FPS = {fps}
+# time x y z pitch yaw roll fov
+KEYFRAMES = [
+{keyframes}]
+
+scene = bpy.context.scene
+scene.render.fps = round(FPS)
+
+camera_data = bpy.data.cameras.new({name:?})
+camera_data.sensor_fit = \"HORIZONTAL\"
+camera = bpy.data.objects.new({name:?}, camera_data)
+scene.collection.objects.link(camera)
+scene.camera = camera
+
+for time, x, y, z, pitch, yaw, roll, fov in KEYFRAMES:
+    frame = scene.frame_start + time * scene.render.fps
+
+    rotation = Euler((math.radians(90 - pitch), 0, math.radians(yaw - 90)), \"XYZ\").to_matrix()
+    rotation = rotation @ Matrix.Rotation(math.radians(-roll), 3, \"Z\")
+
+    camera.location = (x, y, z)
+    # keeps the angles continuous
+    camera.rotation_euler = rotation.to_euler(\"XYZ\", camera.rotation_euler)
+    camera_data.angle = math.radians(fov)
+
+    camera.keyframe_insert(\"location\", frame=frame)
+    camera.keyframe_insert(\"rotation_euler\", frame=frame)
+    camera_data.keyframe_insert(\"lens\", frame=frame)
+
+if KEYFRAMES:
+    scene.frame_end = math.ceil(scene.frame_start + KEYFRAMES[-1][0] * scene.render.fps)
+"
+        )
+    }
+
+    pub fn write_to_after_effects_json(&self, fps: f32) -> eyre::Result<String> {
+        let keyframes = self
+            .data
+            .campaths
+            .iter()
+            .map(|curr| {
+                let [x, y, z] = curr.viewinfo.vieworg.to_array();
+                let [pitch, yaw, roll] = curr.viewinfo.viewangles.to_array();
+
+                AfterEffectsKeyframe {
+                    time: curr.time,
+                    position: [-y, -z, x],
+                    rotation: [-pitch, -yaw, roll],
+                    zoom: AFTER_EFFECTS_COMP_WIDTH / (2. * (curr.fov.to_radians() / 2.).tan()),
+                }
+            })
+            .collect();
+
+        let camera = AfterEffectsCamera {
+            fps,
+            comp_width: AFTER_EFFECTS_COMP_WIDTH,
+            keyframes,
+        };
+
+        Ok(serde_json::to_string_pretty(&camera)?)
+    }
+
+    pub fn write_to_format(&self, format: CamFormat, name: &str, fps: f32) -> eyre::Result<String> {
+        match format {
+            CamFormat::Hlae => Ok(self.write_to_string()),
+            CamFormat::Csv => Ok(self.write_to_csv()),
+            CamFormat::Blender => Ok(self.write_to_blender_py(name, fps)),
+            CamFormat::AfterEffects => self.write_to_after_effects_json(fps),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CamSmoothing {
+    /// Curves through every frame when resampling instead of going straight
+    CatmullRom,
+    /// Averages frames within about this many seconds
+    LowPass { time_constant: f32 },
+}
+
 pub struct Dem2CamOptions {
     pub frametime: Option<f32>,
     pub rotation: Option<f32>,
+    pub format: CamFormat,
+    pub smoothing: Option<CamSmoothing>,
+    /// Output time is demo time multiplied by this, 2 is half speed
+    pub time_scale: f32,
 }
 
 impl Default for Dem2CamOptions {
@@ -123,8 +322,199 @@ impl Dem2CamOptions {
         Self {
             frametime: None,
             rotation: None,
+            format: CamFormat::Hlae,
+            smoothing: None,
+            time_scale: 1.,
+        }
+    }
+}
+
+/// Camera of every ghost frame, angles do not wrap around
+pub fn ghost_campaths(ghost: &GhostInfo) -> Vec<ViewInfoCamIO> {
+    let mut campaths: Vec<ViewInfoCamIO> = vec![];
+    let mut time = 0.;
+
+    for frame in &ghost.frames {
+        let viewangles = match campaths.last() {
+            Some(prev) => {
+                let prev = prev.viewinfo.viewangles;
+
+                prev + Vec3::from_array(from_fn(|i| angle_diff(prev[i], frame.viewangles[i])))
+            }
+            None => frame.viewangles,
+        };
+
+        campaths.push(ViewInfoCamIO {
+            viewinfo: ViewInfo {
+                vieworg: frame.origin,
+                viewangles,
+            },
+            time,
+            fov: frame.fov.unwrap_or(90.),
+        });
+
+        time += frame.frametime.unwrap_or_default() as f32;
+    }
+
+    campaths
+}
+
+fn lerp_campath(from: &ViewInfoCamIO, to: &ViewInfoCamIO, time: f32, t: f32) -> ViewInfoCamIO {
+    ViewInfoCamIO {
+        viewinfo: ViewInfo {
+            vieworg: from.viewinfo.vieworg.lerp(to.viewinfo.vieworg, t),
+            viewangles: from.viewinfo.viewangles.lerp(to.viewinfo.viewangles, t),
+        },
+        time,
+        fov: from.fov + (to.fov - from.fov) * t,
+    }
+}
+
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f32, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.
+        + (p2 - p0) * t
+        + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t2
+        + (p1 * 3. - p0 - p2 * 3. + p3) * t3)
+        * 0.5
+}
+
+fn catmull_rom_campath([p0, p1, p2, p3]: [&ViewInfoCamIO; 4], time: f32, t: f32) -> ViewInfoCamIO {
+    ViewInfoCamIO {
+        viewinfo: ViewInfo {
+            vieworg: catmull_rom(
+                p0.viewinfo.vieworg,
+                p1.viewinfo.vieworg,
+                p2.viewinfo.vieworg,
+                p3.viewinfo.vieworg,
+                t,
+            ),
+            viewangles: catmull_rom(
+                p0.viewinfo.viewangles,
+                p1.viewinfo.viewangles,
+                p2.viewinfo.viewangles,
+                p3.viewinfo.viewangles,
+                t,
+            ),
+        },
+        time,
+        fov: catmull_rom(p0.fov, p1.fov, p2.fov, p3.fov, t),
+    }
+}
+
+/// Camera every `frametime` seconds, straight or curved between frames
+pub fn resample_campaths(
+    campaths: &[ViewInfoCamIO],
+    frametime: f32,
+    curved: bool,
+) -> Vec<ViewInfoCamIO> {
+    let Some(last) = campaths.last() else {
+        return vec![];
+    };
+
+    let mut res = vec![];
+    let mut segment = 0usize;
+
+    loop {
+        let time = res.len() as f32 * frametime;
+
+        if time > last.time {
+            break;
+        }
+
+        while segment + 2 < campaths.len() && campaths[segment + 1].time <= time {
+            segment += 1;
+        }
+
+        let from = &campaths[segment];
+        let to = &campaths[(segment + 1).min(campaths.len() - 1)];
+
+        let duration = to.time - from.time;
+        let t = if duration > 0. {
+            ((time - from.time) / duration).clamp(0., 1.)
+        } else {
+            0.
+        };
+
+        let campath = if curved {
+            let before = &campaths[segment.saturating_sub(1)];
+            let after = &campaths[(segment + 2).min(campaths.len() - 1)];
+
+            catmull_rom_campath([before, from, to, after], time, t)
+        } else {
+            lerp_campath(from, to, time, t)
+        };
+
+        res.push(campath);
+    }
+
+    res
+}
+
+/// Exponential moving average both ways so the camera does not lag behind
+pub fn low_pass_campaths(campaths: &mut [ViewInfoCamIO], time_constant: f32) {
+    if time_constant <= 0. {
+        return;
+    }
+
+    let len = campaths.len();
+
+    let mut pass = |indices: &mut dyn Iterator<Item = usize>| {
+        let mut prev: Option<usize> = None;
+
+        for idx in indices {
+            if let Some(prev) = prev {
+                let dt = (campaths[idx].time - campaths[prev].time).abs();
+                let alpha = dt / (time_constant + dt);
+                let (prev, curr) = (campaths[prev], campaths[idx]);
+
+                campaths[idx] = lerp_campath(&prev, &curr, curr.time, alpha);
+            }
+
+            prev = Some(idx);
+        }
+    };
+
+    pass(&mut (0..len));
+    pass(&mut (0..len).rev());
+}
+
+/// Camera path of the demo with the options applied
+pub fn dem2cam_campaths(
+    demo: &Demo,
+    filename: &str,
+    options: &Dem2CamOptions,
+) -> eyre::Result<Vec<ViewInfoCamIO>> {
+    let ghost = get_ghost_demo(filename, demo)?;
+    let mut campaths = ghost_campaths(&ghost);
+
+    if let Some(CamSmoothing::LowPass { time_constant }) = options.smoothing {
+        low_pass_campaths(&mut campaths, time_constant);
+    }
+
+    // if no frametime specified, will use frametime from the demo
+    if let Some(frametime) = options.frametime {
+        if frametime <= 0. {
+            return err!("Frametime must be positive");
         }
+
+        let curved = options.smoothing == Some(CamSmoothing::CatmullRom);
+        campaths = resample_campaths(&campaths, frametime, curved);
     }
+
+    campaths
+        .iter_mut()
+        .for_each(|campath| campath.time *= options.time_scale);
+
+    Ok(campaths)
 }
 
 // for wasm stuffs
@@ -134,62 +524,53 @@ pub fn _dem2cam_string(
     options: &Dem2CamOptions,
 ) -> eyre::Result<String> {
     let filename = demo_path.as_ref().file_stem().unwrap().to_str().unwrap();
-    let ghost = get_ghost_demo(filename, demo)?;
-    let mut exporter = Exporter::new();
+    let campaths = dem2cam_campaths(demo, filename, options)?;
 
-    let Dem2CamOptions {
-        frametime,
-        rotation: _,
-    } = options;
+    let fps = options
+        .frametime
+        .map(|frametime| 1. / frametime)
+        .unwrap_or(DEFAULT_EXPORT_FPS);
 
-    // if no frametime specified, will use frametime from the demo
-    if let Some(frametime) = frametime {
-        let mut cum_time: f32 = 0.;
-
-        while let Some(frame) = ghost.get_frame(cum_time as f64, None) {
-            exporter.append_entry(
-                cum_time,
-                frame.origin,
-                frame.viewangles.zxy(),
-                frame.fov.unwrap(),
-            );
-            cum_time += *frametime;
-        }
-    } else {
-        let mut cum_time = 0.;
+    Exporter::from_campaths(campaths).write_to_format(options.format, filename, fps)
+}
 
-        for frame in ghost.frames {
-            exporter.append_entry(cum_time, frame.origin, frame.viewangles, frame.fov.unwrap());
-            cum_time += frame.frametime.unwrap() as f32;
-        }
-    };
+/// `<folder>/<demo name><format suffix>` next to the demo
+pub fn dem2cam_output_path(demo_path: &Path, format: CamFormat) -> PathBuf {
+    let stem = demo_path.file_stem().unwrap_or_default().to_string_lossy();
 
-    Ok(exporter.write_to_string())
+    demo_path.with_file_name(format!("{stem}{}", format.suffix()))
 }
 
+/// Writes the camera path next to the demo.
+///
+/// Returns the path of the camera path.
 pub fn dem2cam(
     demo_path: impl AsRef<Path> + Into<PathBuf>,
     options: &Dem2CamOptions,
-) -> eyre::Result<()> {
+) -> eyre::Result<PathBuf> {
     let demo = open_demo(demo_path.as_ref())?;
     let res = _dem2cam_string(&demo, demo_path.as_ref(), options)?;
 
+    let out_path = dem2cam_output_path(demo_path.as_ref(), options.format);
+
     let mut file = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open(demo_path.as_ref().with_extension("cam"))?;
+        .open(&out_path)?;
     file.write_all(res.as_bytes())?;
     file.flush()?;
 
-    Ok(())
+    Ok(out_path)
 }
 
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
 
-    use super::{Dem2CamOptions, dem2cam};
+    use crate::utils::dem_stuffs::get_ghost::GhostFrame;
+
+    use super::*;
 
     #[test]
     fn run() {
@@ -201,8 +582,118 @@ mod test {
                 frametime: Some(1.0),
                 // frametime: None,
                 rotation: None,
+                ..Dem2CamOptions::new()
             },
         )
         .unwrap();
     }
+
+    /// Moves 10 units along x and turns by the yaws every 0.1 seconds
+    fn test_ghost(yaws: &[f32]) -> GhostInfo {
+        GhostInfo {
+            ghost_name: "test".to_string(),
+            frames: yaws
+                .iter()
+                .enumerate()
+                .map(|(i, &yaw)| GhostFrame {
+                    origin: Vec3::new(i as f32 * 10., 0., 0.),
+                    viewangles: Vec3::new(0., yaw, 0.),
+                    frametime: Some(0.1),
+                    buttons: None,
+                    anim: None,
+                    fov: Some(90.),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn unwrap_angles() {
+        let campaths = ghost_campaths(&test_ghost(&[170., -170., -150.]));
+        let yaws: Vec<f32> = campaths
+            .iter()
+            .map(|campath| campath.viewinfo.viewangles.y.round())
+            .collect();
+
+        assert_eq!(yaws, vec![170., 190., 210.]);
+        assert!((campaths[2].time - 0.2).abs() < 0.0001);
+    }
+
+    #[test]
+    fn catmull_rom_resample() {
+        let campaths = ghost_campaths(&test_ghost(&[0., 10., 40., 90.]));
+
+        let curved = resample_campaths(&campaths, 0.05, true);
+        let straight = resample_campaths(&campaths, 0.05, false);
+
+        assert_eq!(curved.len(), 7);
+        assert_eq!(straight.len(), 7);
+
+        // both go through every frame
+        for (idx, campath) in campaths.iter().enumerate() {
+            let yaw = campath.viewinfo.viewangles.y;
+
+            assert!((curved[idx * 2].viewinfo.viewangles.y - yaw).abs() < 0.001);
+            assert!((straight[idx * 2].viewinfo.viewangles.y - yaw).abs() < 0.001);
+        }
+
+        // yaw speeds up so the curve is below the straight line in between
+        assert!(curved[3].viewinfo.viewangles.y < straight[3].viewinfo.viewangles.y);
+    }
+
+    #[test]
+    fn low_pass() {
+        let mut campaths = ghost_campaths(&test_ghost(&[0., 0., 0., 90., 90., 90.]));
+
+        low_pass_campaths(&mut campaths, 0.1);
+
+        let yaws: Vec<f32> = campaths
+            .iter()
+            .map(|campath| campath.viewinfo.viewangles.y)
+            .collect();
+
+        // the turn is spread out both ways
+        assert!(yaws[2] > 0. && yaws[2] < 45.);
+        assert!(yaws[3] > 45. && yaws[3] < 90.);
+        assert!(yaws.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn formats() {
+        let exporter = Exporter::from_campaths(ghost_campaths(&test_ghost(&[0., 90., 180.])));
+
+        let cam = exporter
+            .write_to_format(CamFormat::Hlae, "test", 10.)
+            .unwrap();
+        assert!(cam.starts_with("advancedfx Cam"));
+        // roll pitch yaw
+        assert!(cam.ends_with("0.2 20 0 0 0 0 180 90\n"));
+
+        let csv = exporter
+            .write_to_format(CamFormat::Csv, "test", 10.)
+            .unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert_eq!(csv.lines().nth(2), Some("0.1,10,0,0,0,90,0,90"));
+
+        let py = exporter
+            .write_to_format(CamFormat::Blender, "test", 10.)
+            .unwrap();
+        assert!(py.contains("FPS = 10"));
+        assert!(py.contains("    (0.1, 10, 0, 0, 0, 90, 0, 90),"));
+
+        let json = exporter
+            .write_to_format(CamFormat::AfterEffects, "test", 10.)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let keyframes = json["keyframes"].as_array().unwrap();
+
+        assert_eq!(keyframes.len(), 3);
+        // 10 units forward is 10 units into the screen
+        assert_eq!(keyframes[1]["position"][2], 10.);
+        assert_eq!(keyframes[1]["rotation"][1], -90.);
+        // 90 degrees is half the comp width away
+        assert_eq!(keyframes[1]["zoom"].as_f64().unwrap().round(), 960.);
+
+        assert_eq!("ae".parse::<CamFormat>().unwrap(), CamFormat::AfterEffects);
+    }
 }
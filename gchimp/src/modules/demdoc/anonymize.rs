@@ -0,0 +1,402 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use dem::{
+    open_demo,
+    types::{ByteString, Demo, EngineMessage, FrameData, MessageData, NetMessage},
+};
+
+use super::{
+    ANONYMIZE_SUFFIX, demdoc_output_path,
+    utils::{message_name, parse_user_info},
+};
+
+// user messages players talk with
+const CHAT_USER_MESSAGES: &[&str] = &["SayText"];
+// console commands players talk with
+const CHAT_COMMANDS: &[&str] = &["say", "say_team"];
+// userinfo keys that point to the person
+const STEAM_ID_KEYS: &[&str] = &["*sid"];
+const IP_KEYS: &[&str] = &["ip", "*ip"];
+// names shorter than this are too likely to be part of other words
+const MIN_REPLACED_NAME_LENGTH: usize = 3;
+
+pub struct AnonymizeOptions {
+    /// Players are renamed to this followed by their slot
+    pub name: String,
+    /// Removes every console command frame instead of only chat commands
+    pub strip_console_commands: bool,
+}
+
+impl Default for AnonymizeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnonymizeOptions {
+    pub fn new() -> Self {
+        Self {
+            name: "Player".to_string(),
+            strip_console_commands: false,
+        }
+    }
+}
+
+fn is_chat_message(message: &NetMessage) -> bool {
+    match message {
+        NetMessage::UserMessage(user_message) => {
+            CHAT_USER_MESSAGES.contains(&message_name(&user_message.name).as_str())
+        }
+        NetMessage::EngineMessage(engine_message) => {
+            matches!(engine_message.as_ref(), EngineMessage::SvcVoiceData(_))
+        }
+    }
+}
+
+fn is_chat_command(command: &ByteString) -> bool {
+    let command = message_name(&command.0);
+
+    command
+        .split_whitespace()
+        .next()
+        .is_some_and(|command| CHAT_COMMANDS.contains(&command))
+}
+
+fn write_user_info(fields: &[(String, String)]) -> ByteString {
+    let mut user_info: String = fields
+        .iter()
+        .map(|(key, value)| format!("\\{key}\\{value}"))
+        .collect();
+
+    user_info.push('\0');
+
+    ByteString(user_info.into_bytes())
+}
+
+fn replace_bytes(haystack: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut res = vec![];
+    let mut rest = haystack;
+
+    while !rest.is_empty() {
+        if rest.starts_with(from) {
+            res.extend_from_slice(to);
+            rest = &rest[from.len()..];
+        } else {
+            res.push(rest[0]);
+            rest = &rest[1..];
+        }
+    }
+
+    res
+}
+
+/// Replaces every player name in the text, longest names first
+fn replace_names(text: &[u8], names: &[(String, String)]) -> Vec<u8> {
+    names.iter().fold(text.to_vec(), |text, (from, to)| {
+        replace_bytes(&text, from.as_bytes(), to.as_bytes())
+    })
+}
+
+/// Removes who is playing from the demo.
+///
+/// Players are renamed in their userinfo and in server prints, steam IDs and IPs are zeroed,
+/// chat and voice are removed along with say commands.
+pub fn anonymize_demo(demo: &mut Demo, options: &AnonymizeOptions) {
+    let anonymous_name = |slot: u8| format!("{} {}", options.name, slot as u32 + 1);
+
+    // names are known before they show up in prints
+    let mut names: HashMap<String, String> = HashMap::new();
+    // user messages without a fixed size can change length
+    let mut dynamic_user_messages: HashSet<u8> = HashSet::new();
+
+    demo.directory
+        .entries
+        .iter()
+        .flat_map(|entry| &entry.frames)
+        .filter_map(|frame| match &frame.frame_data {
+            FrameData::NetworkMessage(box_type) => match &box_type.1.messages {
+                MessageData::Parsed(messages) => Some(messages),
+                _ => None,
+            },
+            _ => None,
+        })
+        .flatten()
+        .for_each(|message| {
+            let NetMessage::EngineMessage(engine_message) = message else {
+                return;
+            };
+
+            match engine_message.as_ref() {
+                EngineMessage::SvcUpdateUserInfo(user_info) => {
+                    parse_user_info(&user_info.user_info.0)
+                        .into_iter()
+                        .filter(|(key, value)| {
+                            key == "name" && value.len() >= MIN_REPLACED_NAME_LENGTH
+                        })
+                        .for_each(|(_, value)| {
+                            names.insert(value, anonymous_name(user_info.index));
+                        });
+                }
+                EngineMessage::SvcNewUserMsg(new_user_msg) if new_user_msg.size == -1 => {
+                    dynamic_user_messages.insert(new_user_msg.index);
+                }
+                _ => (),
+            }
+        });
+
+    // so a name containing another name is replaced whole
+    let mut names: Vec<(String, String)> = names.into_iter().collect();
+    names.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+    for entry in &mut demo.directory.entries {
+        entry.frames.retain(|frame| match &frame.frame_data {
+            FrameData::ConsoleCommand(command) => {
+                !options.strip_console_commands && !is_chat_command(&command.command)
+            }
+            _ => true,
+        });
+
+        entry.frame_count = entry.frames.len() as i32;
+
+        for frame in &mut entry.frames {
+            let FrameData::NetworkMessage(box_type) = &mut frame.frame_data else {
+                continue;
+            };
+
+            let MessageData::Parsed(messages) = &mut box_type.as_mut().1.messages else {
+                continue;
+            };
+
+            messages.retain(|message| !is_chat_message(message));
+
+            for message in messages.iter_mut() {
+                match message {
+                    NetMessage::EngineMessage(engine_message) => match engine_message.as_mut() {
+                        EngineMessage::SvcUpdateUserInfo(user_info) => {
+                            let fields = parse_user_info(&user_info.user_info.0)
+                                .into_iter()
+                                .map(|(key, value)| {
+                                    let value = if key == "name" {
+                                        anonymous_name(user_info.index)
+                                    } else if STEAM_ID_KEYS.contains(&key.as_str()) {
+                                        "0".to_string()
+                                    } else if IP_KEYS.contains(&key.as_str()) {
+                                        "0.0.0.0".to_string()
+                                    } else {
+                                        value
+                                    };
+
+                                    (key, value)
+                                })
+                                .collect::<Vec<(String, String)>>();
+
+                            user_info.user_info = write_user_info(&fields);
+                            user_info.cd_key_hash = ByteString(vec![0u8; 16]);
+                        }
+                        EngineMessage::SvcPrint(print) => {
+                            print.message = ByteString(replace_names(&print.message.0, &names));
+                        }
+                        EngineMessage::SvcCenterPrint(print) => {
+                            print.message = replace_names(&print.message, &names);
+                        }
+                        _ => (),
+                    },
+                    NetMessage::UserMessage(user_message) => {
+                        if dynamic_user_messages.contains(&user_message.id) {
+                            user_message.data = replace_names(&user_message.data, &names);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Anonymizes the demo and writes it next to the demo.
+///
+/// Returns the path of the new demo.
+pub fn anonymize_demo_file(demo_path: &Path, options: &AnonymizeOptions) -> eyre::Result<PathBuf> {
+    let mut demo = open_demo(demo_path)?;
+
+    anonymize_demo(&mut demo, options);
+
+    let out_path = demdoc_output_path(demo_path, ANONYMIZE_SUFFIX);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use dem::{
+        open_demo_from_bytes,
+        types::{ConsoleCommand, Frame, SvcNewUserMsg, SvcPrint, SvcUpdateUserInfo, UserMessage},
+    };
+
+    use crate::wrap_message;
+
+    use super::super::ghost2dem::test_demo;
+    use super::*;
+
+    const SAY_TEXT_INDEX: u8 = 100;
+
+    /// Test demo with a player joining, talking and typing in console
+    fn talking_demo() -> Demo {
+        let mut demo = test_demo("anonymize", 10);
+
+        let new_user_msg = SvcNewUserMsg {
+            index: SAY_TEXT_INDEX,
+            size: -1,
+            name: ByteString(b"SayText\0".to_vec()).padded(16),
+        };
+
+        // the writer only knows the size of user messages registered while parsing
+        demo._aux
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .custom_messages
+            .insert(SAY_TEXT_INDEX, new_user_msg.clone());
+
+        let frames = &mut demo.directory.entries[1].frames;
+
+        let netmsg_idx = frames
+            .iter()
+            .position(|frame| matches!(frame.frame_data, FrameData::NetworkMessage(_)))
+            .unwrap();
+
+        let FrameData::NetworkMessage(box_type) = &mut frames[netmsg_idx].frame_data else {
+            unreachable!()
+        };
+
+        let MessageData::Parsed(messages) = &mut box_type.as_mut().1.messages else {
+            unreachable!()
+        };
+
+        let user_info = SvcUpdateUserInfo {
+            index: 0,
+            id: 1,
+            user_info: ByteString(
+                b"\\name\\khang\\*sid\\76561198000000000\\model\\gign\0".to_vec(),
+            ),
+            cd_key_hash: ByteString(vec![1u8; 16]),
+        };
+        let print = SvcPrint {
+            message: ByteString(b"khang has joined the game\n\0".to_vec()),
+        };
+
+        messages.push(wrap_message!(SvcNewUserMsg, new_user_msg));
+        messages.push(wrap_message!(SvcUpdateUserInfo, user_info));
+        messages.push(wrap_message!(SvcPrint, print));
+        messages.push(NetMessage::UserMessage(UserMessage {
+            id: SAY_TEXT_INDEX,
+            name: b"SayText\0".to_vec(),
+            data: b"\x01khang: hello\n\0".to_vec(),
+        }));
+
+        let commands = ["say hello", "+attack"].map(|command| Frame {
+            time: 0.,
+            frame: 1,
+            frame_data: FrameData::ConsoleCommand(ConsoleCommand {
+                command: ByteString(format!("{command}\0").into_bytes()),
+            }),
+        });
+
+        frames.splice(netmsg_idx + 1..netmsg_idx + 1, commands);
+        demo.directory.entries[1].frame_count = frames.len() as i32;
+
+        // makes sure our messages are written and read like any other demo
+        open_demo_from_bytes(&demo.write_to_bytes()).unwrap()
+    }
+
+    fn messages(demo: &Demo) -> Vec<&NetMessage> {
+        demo.directory
+            .entries
+            .iter()
+            .flat_map(|entry| &entry.frames)
+            .filter_map(|frame| match &frame.frame_data {
+                FrameData::NetworkMessage(box_type) => match &box_type.1.messages {
+                    MessageData::Parsed(messages) => Some(messages),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn console_commands(demo: &Demo) -> Vec<String> {
+        demo.directory
+            .entries
+            .iter()
+            .flat_map(|entry| &entry.frames)
+            .filter_map(|frame| match &frame.frame_data {
+                FrameData::ConsoleCommand(command) => Some(message_name(&command.command.0)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn anonymize() {
+        let mut demo = talking_demo();
+        assert_eq!(console_commands(&demo), vec!["say hello", "+attack"]);
+
+        anonymize_demo(&mut demo, &AnonymizeOptions::new());
+
+        let demo = open_demo_from_bytes(&demo.write_to_bytes()).unwrap();
+        let messages = messages(&demo);
+
+        let user_info = messages
+            .iter()
+            .find_map(|message| match message {
+                NetMessage::EngineMessage(engine_message) => match engine_message.as_ref() {
+                    EngineMessage::SvcUpdateUserInfo(user_info) => Some(user_info),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            parse_user_info(&user_info.user_info.0),
+            vec![
+                ("name".to_string(), "Player 1".to_string()),
+                ("*sid".to_string(), "0".to_string()),
+                ("model".to_string(), "gign".to_string()),
+            ]
+        );
+        assert_eq!(user_info.cd_key_hash.0, vec![0u8; 16]);
+
+        let print = messages
+            .iter()
+            .find_map(|message| match message {
+                NetMessage::EngineMessage(engine_message) => match engine_message.as_ref() {
+                    EngineMessage::SvcPrint(print) => Some(message_name(&print.message.0)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .unwrap();
+
+        assert_eq!(print, "Player 1 has joined the game\n");
+
+        assert!(!messages.iter().any(|message| is_chat_message(message)));
+        assert_eq!(console_commands(&demo), vec!["+attack"]);
+    }
+
+    #[test]
+    fn strip_console_commands() {
+        let mut demo = talking_demo();
+
+        let mut options = AnonymizeOptions::new();
+        options.strip_console_commands = true;
+
+        anonymize_demo(&mut demo, &options);
+
+        assert!(console_commands(&demo).is_empty());
+    }
+}
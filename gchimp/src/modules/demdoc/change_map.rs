@@ -1,12 +1,34 @@
+use std::path::{Path, PathBuf};
+
 use bsp::Bsp;
 use dem::{
-    bitslice_to_string,
-    hldemo::{Demo, FrameData},
-    nbit_str, parse_netmsg,
-    types::EngineMessage,
-    write_netmsg, Aux,
+    bitslice_to_string, nbit_str, open_demo,
+    types::{ByteString, Demo, EngineMessage, FrameData, MessageData, NetMessage},
 };
 
+use super::{CHANGE_MAP_SUFFIX, demdoc_output_path};
+
+/// Changes the map of the demo to the .bsp and writes it next to the demo.
+///
+/// Returns the path of the new demo.
+pub fn change_map_file(demo_path: &Path, bsp_path: &Path) -> eyre::Result<PathBuf> {
+    let bsp = Bsp::from_file(bsp_path)?;
+    let new_name = bsp_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    let mut demo = open_demo(demo_path)?;
+
+    change_map(&mut demo, &bsp, &new_name);
+
+    let out_path = demdoc_output_path(demo_path, CHANGE_MAP_SUFFIX);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
+
 pub fn change_map(demo: &mut Demo, bsp: &Bsp, new_name: &str) {
     // new demo should request at least bsp_modelnum embedded models
     let bsp_modelnum = bsp.models.len();
@@ -18,22 +40,19 @@ pub fn change_map(demo: &mut Demo, bsp: &Bsp, new_name: &str) {
         new_name.to_owned() + ".bsp"
     };
 
-    let mut header_new_name = vec![0u8; 260];
-
     // no .bsp in header
     let new_header_name = new_name.replace(".bsp", "");
-    header_new_name[..new_header_name.len()].copy_from_slice(new_header_name.as_bytes());
-
-    demo.header.map_name = header_new_name.leak();
-
-    let aux = Aux::new();
+    demo.header.map_name = ByteString(new_header_name.into_bytes()).padded(260);
 
     for entry in &mut demo.directory.entries {
         for frame in &mut entry.frames {
-            if let FrameData::NetMsg((_, data)) = &mut frame.data {
-                let (_, mut netmsg) = parse_netmsg(data.msg, &aux).unwrap();
-                for netmsg in &mut netmsg {
-                    if let dem::types::NetMessage::EngineMessage(engine_message) = netmsg {
+            if let FrameData::NetworkMessage(box_type) = &mut frame.frame_data {
+                let MessageData::Parsed(netmsg) = &mut box_type.as_mut().1.messages else {
+                    continue;
+                };
+
+                for netmsg in netmsg {
+                    if let NetMessage::EngineMessage(engine_message) = netmsg {
                         match engine_message.as_mut() {
                             EngineMessage::SvcServerInfo(server_info) => {
                                 server_info.map_checksum = 0;
@@ -148,9 +167,6 @@ pub fn change_map(demo: &mut Demo, bsp: &Bsp, new_name: &str) {
                         }
                     }
                 }
-
-                let bytes = write_netmsg(netmsg, &aux);
-                data.msg = bytes.leak(); // hldemo does not own any data. Remember to free.
             }
         }
     }
@@ -1,14 +1,15 @@
 use std::{
-    fs::{self, OpenOptions},
+    fs::OpenOptions,
     io::Read,
     path::{Path, PathBuf},
 };
 
-use dem::{demo_writer::DemoWriter, open_demo_from_bytes};
+use dem::open_demo_from_bytes;
 
-use eyre::eyre;
 use rayon::prelude::*;
 
+use super::demos_in_folder;
+
 // yes mean yes doctored, no mean not doctored
 pub fn check_doctored(
     demo_path: impl AsRef<Path> + Into<PathBuf>,
@@ -20,8 +21,7 @@ pub fn check_doctored(
 
     let demo = open_demo_from_bytes(&in_bytes)?;
 
-    let demo_writer = DemoWriter::new("");
-    let out_bytes = demo_writer.write_to_bytes(demo);
+    let out_bytes = demo.write_to_bytes();
 
     Ok((demo_path.into(), in_bytes == out_bytes))
 }
@@ -30,17 +30,7 @@ pub fn check_doctored(
 pub fn check_doctored_folder(
     folder_path: impl AsRef<Path> + Into<PathBuf>,
 ) -> eyre::Result<Vec<PathBuf>> {
-    if !folder_path.as_ref().is_dir() {
-        return Err(eyre!("is not a folder"));
-    }
-
-    let demo_paths = fs::read_dir(folder_path.as_ref())?
-        .filter_map(|read| read.ok())
-        .map(|read| read.path())
-        .filter(|path| path.is_file())
-        .filter(|path| path.extension().is_some())
-        .filter(|path| path.extension().unwrap() == "dem")
-        .collect::<Vec<PathBuf>>();
+    let demo_paths = demos_in_folder(folder_path.as_ref())?;
 
     Ok(demo_paths
         .par_iter()
@@ -54,6 +44,7 @@ mod test {
     use super::*;
 
     #[test]
+    #[ignore]
     fn not_doctored() {
         let res = check_doctored(
             "/home/khang/bxt/_game_native/cstrike/cg_coldbhop_final_average_benis_0031.20.dem",
@@ -62,18 +53,23 @@ mod test {
     }
 
     #[test]
+    #[ignore]
     fn yes_doctored() {
-        let res = check_doctored("/home/khang/bxt/_game_native/cstrike/cg_coldbhop_final_average_benis_0031.20_demdoc.dem");
+        let res = check_doctored(
+            "/home/khang/bxt/_game_native/cstrike/cg_coldbhop_final_average_benis_0031.20_demdoc.dem",
+        );
         println!("res is {:?}", res.unwrap());
     }
 
     #[test]
+    #[ignore]
     fn yes_doctored2() {
         let res = check_doctored("/home/khang/bxt/game_isolated/cstrike/crossfire_demdoc.dem");
         println!("res is {:?}", res.unwrap());
     }
 
     #[test]
+    #[ignore]
     fn folder() {
         let res = check_doctored_folder("/home/khang/bxt/game_isolated/cstrike/cc1036").unwrap();
 
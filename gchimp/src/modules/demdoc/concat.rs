@@ -0,0 +1,282 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use dem::{
+    open_demo,
+    types::{Demo, EngineMessage, Frame, FrameData, MessageData, NetMessage},
+};
+
+use crate::err;
+
+use super::{
+    CONCAT_SUFFIX, demdoc_output_path,
+    utils::{incoming_sequence, message_name, shift_frame, shift_sequence},
+};
+
+// time between the end of a demo and the start of the next one
+const CONCAT_GAP: f32 = 0.01;
+
+fn map_name(demo: &Demo) -> String {
+    demo.header
+        .map_name
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Messages setting up the connection that the first demo already has
+fn is_connection_message(message: &NetMessage) -> bool {
+    let NetMessage::EngineMessage(engine_message) = message else {
+        return false;
+    };
+
+    matches!(
+        engine_message.as_ref(),
+        EngineMessage::SvcServerInfo(_)
+            | EngineMessage::SvcDeltaDescription(_)
+            | EngineMessage::SvcResourceList(_)
+            | EngineMessage::SvcSpawnBaseline(_)
+            | EngineMessage::SvcNewUserMsg(_)
+            | EngineMessage::SvcSignOnNum(_)
+            | EngineMessage::SvcSendExtraInfo(_)
+            | EngineMessage::SvcSpawnStatic(_)
+            | EngineMessage::SvcSpawnStaticSound(_)
+            | EngineMessage::SvcDecalName(_)
+            | EngineMessage::SvcResourceLocation(_)
+            | EngineMessage::SvcVoiceInit(_)
+            | EngineMessage::SvcVersion(_)
+            | EngineMessage::SvcCdTrack(_)
+    )
+}
+
+/// User message names of the demo with their index
+fn user_message_indices(demo: &Demo) -> HashMap<String, u8> {
+    demo._aux
+        .as_ref()
+        .map(|aux| {
+            aux.borrow()
+                .custom_messages
+                .values()
+                .map(|message| (message_name(message.name.as_slice()), message.index))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Appends the playback of the other demos to the first one.
+///
+/// The demos must be recorded on the same map with the same game.
+/// The connection of the other demos is skipped, their entity updates are kept.
+pub fn concat_demos(demos: Vec<Demo>) -> eyre::Result<Demo> {
+    let mut demos = demos.into_iter();
+
+    let Some(mut res) = demos.next() else {
+        return err!("No demo to concatenate");
+    };
+
+    if res.directory.entries.len() < 2 {
+        return err!("Demo has no playback section");
+    }
+
+    let map = map_name(&res);
+    let user_messages = user_message_indices(&res);
+
+    for (demo_idx, demo) in demos.enumerate() {
+        let demo_idx = demo_idx + 1;
+
+        if map_name(&demo) != map {
+            return err!(
+                "Demo #{} is on {} instead of {}",
+                demo_idx,
+                map_name(&demo),
+                map
+            );
+        }
+
+        if demo.directory.entries.len() < 2 {
+            return err!("Demo #{} has no playback section", demo_idx);
+        }
+
+        let playback = res.directory.entries.last_mut().unwrap();
+
+        // writer adds it back at the end
+        while playback
+            .frames
+            .last()
+            .is_some_and(|frame| matches!(frame.frame_data, FrameData::NextSection))
+        {
+            playback.frames.pop();
+        }
+
+        let (last_time, last_frame) = playback
+            .frames
+            .last()
+            .map(|frame| (frame.time, frame.frame))
+            .unwrap_or_default();
+
+        let last_sequence = res.directory.entries[1..]
+            .iter()
+            .flat_map(|entry| &entry.frames)
+            .filter_map(incoming_sequence)
+            .max()
+            .unwrap_or_default();
+
+        let Some(first_sequence) = demo
+            .directory
+            .entries
+            .iter()
+            .flat_map(|entry| &entry.frames)
+            .find_map(incoming_sequence)
+        else {
+            return err!("Demo #{} has no net message", demo_idx);
+        };
+
+        let demo_start_time = demo.directory.entries[1]
+            .frames
+            .first()
+            .map(|frame| frame.time)
+            .unwrap_or_default();
+
+        let sequence_offset = last_sequence + 1 - first_sequence;
+        let time_offset = last_time + CONCAT_GAP - demo_start_time;
+        let frame_offset = last_frame + 1;
+
+        let mut frames: Vec<Frame> = vec![];
+
+        for (entry_idx, entry) in demo.directory.entries.into_iter().enumerate() {
+            for mut frame in entry.frames {
+                if matches!(
+                    frame.frame_data,
+                    FrameData::DemoStart | FrameData::NextSection
+                ) {
+                    continue;
+                }
+
+                if let FrameData::NetworkMessage(box_type) = &mut frame.frame_data {
+                    let MessageData::Parsed(messages) = &mut box_type.as_mut().1.messages else {
+                        return err!("Demo #{} has unparsed net messages", demo_idx);
+                    };
+
+                    if entry_idx == 0 {
+                        messages.retain(|message| !is_connection_message(message));
+                    }
+
+                    for message in messages.iter_mut() {
+                        let NetMessage::UserMessage(user_message) = message else {
+                            continue;
+                        };
+
+                        let name = message_name(&user_message.name);
+
+                        let Some(&index) = user_messages.get(&name) else {
+                            return err!(
+                                "User message {} of demo #{} is not in the first demo",
+                                name,
+                                demo_idx
+                            );
+                        };
+
+                        user_message.id = index;
+                    }
+                } else if entry_idx == 0 {
+                    continue;
+                }
+
+                if entry_idx == 0 {
+                    frame.time = demo_start_time;
+                    frame.frame = 0;
+                }
+
+                // negative shift moves it forward
+                shift_frame(&mut frame, -time_offset, -frame_offset);
+                shift_sequence(&mut frame, sequence_offset);
+
+                frames.push(frame);
+            }
+        }
+
+        let playback = res.directory.entries.last_mut().unwrap();
+        playback.frames.extend(frames);
+        playback.frame_count = playback.frames.len() as i32;
+    }
+
+    Ok(res)
+}
+
+/// Concatenates the demos in order and writes it next to the first demo.
+///
+/// Returns the path of the new demo.
+pub fn concat_demos_file(demo_paths: &[PathBuf]) -> eyre::Result<PathBuf> {
+    let Some(first_path) = demo_paths.first() else {
+        return err!("No demo to concatenate");
+    };
+
+    let demos = demo_paths
+        .iter()
+        .map(|path| {
+            open_demo(path).map_err(|err| eyre::eyre!("Cannot open {}: {}", path.display(), err))
+        })
+        .collect::<eyre::Result<Vec<Demo>>>()?;
+
+    let demo = concat_demos(demos)?;
+
+    let out_path = demdoc_output_path(first_path, CONCAT_SUFFIX);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use dem::{open_demo_from_bytes, types::ByteString};
+
+    use super::super::ghost2dem::test_demo;
+    use super::*;
+
+    #[test]
+    fn concat() {
+        let first = test_demo("concat_first", 30);
+        let second = test_demo("concat_second", 20);
+
+        let demo = concat_demos(vec![first, second]).unwrap();
+        let demo = open_demo_from_bytes(&demo.write_to_bytes()).unwrap();
+
+        let frames = demo.directory.entries[1..]
+            .iter()
+            .flat_map(|entry| &entry.frames)
+            .collect::<Vec<_>>();
+
+        let client_data = frames
+            .iter()
+            .filter(|frame| matches!(frame.frame_data, FrameData::ClientData(_)))
+            .count();
+
+        assert_eq!(client_data, 50);
+
+        // writer puts the next section at 0
+        assert!(
+            frames
+                .iter()
+                .filter(|frame| !matches!(frame.frame_data, FrameData::NextSection))
+                .collect::<Vec<_>>()
+                .windows(2)
+                .all(|pair| pair[0].time <= pair[1].time)
+        );
+
+        let sequences = frames
+            .iter()
+            .filter_map(|frame| incoming_sequence(frame))
+            .collect::<Vec<_>>();
+
+        assert!(sequences.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn concat_other_map() {
+        let first = test_demo("concat_map_first", 10);
+        let mut second = test_demo("concat_map_second", 10);
+
+        second.header.map_name = ByteString(b"c1a0".to_vec()).padded(260);
+
+        assert!(concat_demos(vec![first, second]).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use dem::open_demo;
+
+use crate::{
+    err,
+    utils::dem_stuffs::get_ghost::{GhostFormat, get_ghost, get_ghost_demo, write_ghost},
+};
+
+use super::ghost2dem::ghost_info_to_demo;
+
+pub struct ConvertGhostOptions {
+    /// Resamples the ghost to this framerate
+    pub fps: Option<f64>,
+    /// Framerate of ghosts without frametime like Surf Gateway
+    ///
+    /// Simen ghosts spread the time in their header over the frames unless this is set.
+    pub ghost_fps: Option<f64>,
+    /// Map the ghost runs on, needed to make a demo
+    pub bsp: Option<PathBuf>,
+}
+
+impl Default for ConvertGhostOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConvertGhostOptions {
+    pub fn new() -> Self {
+        Self {
+            fps: None,
+            ghost_fps: None,
+            bsp: None,
+        }
+    }
+}
+
+/// `<folder>/<name><format suffix>` next to the ghost.
+///
+/// `_demdoc` is added to the name when it would write over the ghost.
+pub fn converted_ghost_path(ghost_path: &Path, format: GhostFormat) -> PathBuf {
+    let name = GhostFormat::from_path(ghost_path)
+        .map(|ghost_format| ghost_format.file_stem(ghost_path))
+        .unwrap_or_else(|| {
+            ghost_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        });
+
+    let out_path = ghost_path.with_file_name(format!("{name}{}", format.suffix()));
+
+    if out_path == ghost_path {
+        ghost_path.with_file_name(format!("{name}_demdoc{}", format.suffix()))
+    } else {
+        out_path
+    }
+}
+
+/// Converts the ghost into another format and writes it next to the ghost.
+///
+/// Returns the path of the new ghost.
+pub fn convert_ghost_file(
+    ghost_path: &Path,
+    format: GhostFormat,
+    options: &ConvertGhostOptions,
+) -> eyre::Result<PathBuf> {
+    let Some(ghost_format) = GhostFormat::from_path(ghost_path) else {
+        return err!("Unknown ghost file extension.");
+    };
+
+    let ghost_file_name = ghost_path.to_string_lossy();
+
+    // the map name of the demo goes to the new ghost
+    let (mut ghost, demo_map_name) = if ghost_format == GhostFormat::Demo {
+        let demo = open_demo(ghost_path)?;
+        let map_name = demo.header.map_name.to_str().unwrap_or_default().to_owned();
+
+        (get_ghost_demo(&ghost_file_name, &demo)?, Some(map_name))
+    } else {
+        (get_ghost(&ghost_file_name)?, None)
+    };
+
+    if ghost.frames.is_empty() {
+        return err!("Ghost has no frames");
+    }
+
+    // other formats name the ghost after the file
+    if ghost_format != GhostFormat::Gchimp {
+        ghost.ghost_name = ghost_format.file_stem(ghost_path);
+    }
+
+    let ghost_frametime = options.ghost_fps.map(|fps| 1. / fps);
+
+    if let Some(fps) = options.fps {
+        ghost = ghost.resample(1. / fps, ghost_frametime)?;
+    } else if let Some(ghost_frametime) = ghost_frametime {
+        ghost
+            .frames
+            .iter_mut()
+            .for_each(|frame| frame.frametime = Some(ghost_frametime));
+    }
+
+    let out_path = converted_ghost_path(ghost_path, format);
+
+    if format == GhostFormat::Demo {
+        let Some(bsp_path) = &options.bsp else {
+            return err!("Converting to a demo needs the .bsp");
+        };
+
+        let demo = ghost_info_to_demo(&ghost, bsp_path)?;
+        demo.write_to_file(&out_path)?;
+
+        return Ok(out_path);
+    }
+
+    let map_name = options
+        .bsp
+        .as_ref()
+        .and_then(|bsp_path| bsp_path.file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .or(demo_map_name)
+        .unwrap_or_default();
+
+    fs::write(&out_path, write_ghost(&ghost, format, &map_name)?)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::ghost2dem::write_test_ghost;
+    use super::*;
+
+    fn out_dir(name: &str) -> PathBuf {
+        let out_dir = std::env::temp_dir().join(format!("gchimp_convert_ghost_{name}"));
+        fs::create_dir_all(&out_dir).unwrap();
+
+        out_dir
+    }
+
+    #[test]
+    fn round_trip() {
+        let out_dir = out_dir("round_trip");
+        let ghost_path = write_test_ghost(&out_dir, "round_trip", 20);
+
+        let original = get_ghost(ghost_path.to_str().unwrap()).unwrap();
+
+        for format in [
+            GhostFormat::Simen,
+            GhostFormat::SurfGateway,
+            GhostFormat::RomanianJumpers,
+            GhostFormat::Gchimp,
+        ] {
+            let out_path =
+                convert_ghost_file(&ghost_path, format, &ConvertGhostOptions::new()).unwrap();
+
+            assert_eq!(GhostFormat::from_path(&out_path), Some(format));
+
+            let ghost = get_ghost(out_path.to_str().unwrap()).unwrap();
+
+            assert_eq!(ghost.frames.len(), original.frames.len());
+
+            for (frame, original) in ghost.frames.iter().zip(&original.frames) {
+                assert_eq!(frame.origin, original.origin);
+                assert_eq!(frame.viewangles, original.viewangles);
+                assert_eq!(frame.buttons, original.buttons);
+            }
+        }
+
+        // same format gets a new name
+        let out_path = convert_ghost_file(
+            &ghost_path,
+            GhostFormat::RomanianJumpers,
+            &ConvertGhostOptions::new(),
+        )
+        .unwrap();
+
+        assert_eq!(out_path, out_dir.join("round_trip_demdoc.rj.json"));
+    }
+
+    #[test]
+    fn simen_frametime() {
+        let out_dir = out_dir("simen_frametime");
+        let ghost_path = write_test_ghost(&out_dir, "simen_frametime", 20);
+
+        let simen_path =
+            convert_ghost_file(&ghost_path, GhostFormat::Simen, &ConvertGhostOptions::new())
+                .unwrap();
+        let simen = get_ghost(simen_path.to_str().unwrap()).unwrap();
+
+        assert!(simen.frames.iter().all(|frame| {
+            frame
+                .frametime
+                .is_some_and(|frametime| (frametime - 0.01).abs() < 0.0001)
+        }));
+        assert!(simen.resample(1. / 50., None).is_ok());
+
+        // goes on to formats that need frametime
+        let rj_path = convert_ghost_file(
+            &simen_path,
+            GhostFormat::RomanianJumpers,
+            &ConvertGhostOptions::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_ghost(rj_path.to_str().unwrap()).unwrap().frames.len(),
+            20
+        );
+    }
+
+    #[test]
+    fn surf_gateway_frametime() {
+        let out_dir = out_dir("surf_gateway_frametime");
+        let ghost_path = write_test_ghost(&out_dir, "surf_gateway_frametime", 20);
+
+        let sg_path = convert_ghost_file(
+            &ghost_path,
+            GhostFormat::SurfGateway,
+            &ConvertGhostOptions::new(),
+        )
+        .unwrap();
+        let sg: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(sg_path).unwrap()).unwrap();
+
+        assert_eq!(sg["frames"][0]["frametime"], 10);
+        // 2 units every 10 ms
+        assert!((sg["startvel"][0].as_f64().unwrap() - 200.).abs() < 0.1);
+        assert!(sg["timestamp"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn gchimp_minimal() {
+        let ghost_path = out_dir("gchimp_minimal").join("minimal.gchimp.json");
+        fs::write(
+            &ghost_path,
+            r#"{"frames": [{"origin": [0, 0, 36], "viewangles": [0, 90, 0]}]}"#,
+        )
+        .unwrap();
+
+        let ghost = get_ghost(ghost_path.to_str().unwrap()).unwrap();
+
+        assert_eq!(ghost.ghost_name, ghost_path.to_str().unwrap());
+        assert_eq!(ghost.frames.len(), 1);
+        assert_eq!(ghost.frames[0].frametime, None);
+    }
+
+    #[test]
+    fn resample() {
+        let out_dir = out_dir("resample");
+        // 100 fps moving 2 units every frame
+        let ghost_path = write_test_ghost(&out_dir, "resample", 101);
+
+        let ghost = get_ghost(ghost_path.to_str().unwrap()).unwrap();
+        let resampled = ghost.resample(1. / 250., None).unwrap();
+
+        // same as going through the ghost every time
+        let mut frame_count = 0;
+
+        while let Some(frame) = ghost.get_frame(frame_count as f64 / 250., None) {
+            assert_eq!(resampled.frames[frame_count].origin, frame.origin);
+            frame_count += 1;
+        }
+
+        assert_eq!(resampled.frames.len(), frame_count);
+        assert!(
+            resampled
+                .frames
+                .iter()
+                .all(|frame| frame.frametime == Some(1. / 250.))
+        );
+
+        // 0.8 units every frame, the first one is the ghost as it is
+        let step = resampled.frames[11].origin.x - resampled.frames[10].origin.x;
+        assert!((step - 0.8).abs() < 0.01);
+
+        assert!(ghost.resample(0., None).is_err());
+    }
+
+    #[test]
+    fn to_demo() {
+        let out_dir = out_dir("to_demo");
+        let ghost_path = write_test_ghost(&out_dir, "to_demo", 20);
+
+        assert!(
+            convert_ghost_file(&ghost_path, GhostFormat::Demo, &ConvertGhostOptions::new())
+                .is_err()
+        );
+
+        let mut options = ConvertGhostOptions::new();
+        options.bsp = Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("test/datacore.bsp"));
+
+        let demo_path = convert_ghost_file(&ghost_path, GhostFormat::Demo, &options).unwrap();
+        assert_eq!(demo_path, out_dir.join("to_demo.dem"));
+
+        // ghosts that say their map take it from the .bsp
+        let sg_path = convert_ghost_file(&ghost_path, GhostFormat::SurfGateway, &options).unwrap();
+        let sg = fs::read_to_string(sg_path).unwrap();
+
+        assert!(sg.contains(r#""map":"datacore""#));
+    }
+}
@@ -1,92 +1,107 @@
-use std::cell::RefCell;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use bsp::Bsp;
 
-use dem::hldemo::{
-    ClientDataData, Demo, DemoBufferData, Directory, DirectoryEntry, Frame, FrameData, Header,
-    NetMsgData, NetMsgFrameType,
-};
 use dem::parse_netmsg;
 use dem::types::{
-    Delta, EntityS, EntityState, EntityStateDelta, OriginCoord, Resource, SvcDeltaPacketEntities,
-    SvcNewMovevars, SvcPacketEntities, SvcResourceList, SvcServerInfo, SvcSetView, SvcSignOnNum,
-    SvcSound, SvcSpawnBaseline,
+    Aux, AuxRefCell, ByteString, ClientData, Delta, Demo, DemoBuffer, Directory, DirectoryEntry,
+    EntityS, EntityState, EntityStateDelta, Frame, FrameData, Header, MessageData, NetworkMessage,
+    NetworkMessageType, OriginCoord, Resource, SvcDeltaPacketEntities, SvcNewMovevars,
+    SvcPacketEntities, SvcResourceList, SvcServerInfo, SvcSetView, SvcSignOnNum, SvcSound,
+    SvcSpawnBaseline,
 };
 use dem::{
     bitvec::{bitvec, order::Lsb0},
     nbit_num, nbit_str,
     netmsg_doer::Doer,
-    Aux,
 };
 use nom::{number::complete::float, sequence::tuple};
 
-use crate::utils::dem_stuffs::get_ghost::get_ghost;
+use crate::utils::dem_stuffs::get_ghost::{GhostFormat, GhostInfo, get_ghost};
 use crate::{
-    get_cs_delta_msg, insert_packet_entity_state_delta_with_index,
+    err, get_cs_delta_msg, insert_packet_entity_state_delta_with_index,
     insert_packet_entity_state_with_index, modules::demdoc::ResourceType, rand_int_range,
 };
 
-use super::{Buttons, NetMsgDataMethods};
+use super::{Buttons, GHOST_SUFFIX, NetworkMessageMethods, demdoc_output_path, is_demdoc_output};
 
 const DEMO_BUFFER_SIZE: [u8; 8] = [1, 0, 0, 0, 0, 0, 180, 66];
 const DEFAULT_IN_SEQ: i32 = 143791;
 const STEP_TIME: f32 = 0.3;
+// cl_forwardspeed and cl_sidespeed clamped by the knife speed
+const GHOST_MOVE_SPEED: f32 = 250.;
 
 const MAX_PLAYERS: i32 = 1;
 
 // Eh, maybe someone can spot this and use for different mod.
 const GAME_DIR: &str = "cstrike";
 
-pub fn ghost_to_demo<'a>(ghost_file_name: &'a Path, map_file_name: &'a Path) -> Demo<'a> {
+pub fn ghost_to_demo(ghost_file_name: &Path, map_file_name: &Path) -> eyre::Result<Demo> {
+    let ghost_info = get_ghost(ghost_file_name.to_str().unwrap())?;
+
+    ghost_info_to_demo(&ghost_info, map_file_name)
+}
+
+/// Same as [`ghost_to_demo`] with the ghost already read
+pub fn ghost_info_to_demo(ghost_info: &GhostInfo, map_file_name: &Path) -> eyre::Result<Demo> {
+    let (mut demo, aux) = empty_demo(map_file_name);
+
+    // final steps
+    let base = insert_base_netmsg(&mut demo, map_file_name, &aux, &[], 0)?;
+    insert_ghost(
+        &mut demo,
+        ghost_info,
+        None,
+        None,
+        base.game_resource_index_start,
+        base.packet_entities,
+        base.delta_packet_entities,
+        &[],
+        &aux,
+    )?;
+
+    Ok(demo)
+}
+
+/// Demo with the header and empty directory entries for the map
+pub(super) fn empty_demo(map_file_name: &Path) -> (Demo, AuxRefCell) {
     // need to mutate the aux data or we won't be able to write anything with delta
-    let aux = Aux::new();
+    let aux = Aux::new2();
 
-    let mut map_name = vec![0u8; 260];
     let map_file_name_stem = map_file_name.file_stem().unwrap().to_str().unwrap();
-    map_name[..map_file_name_stem.len()].copy_from_slice(map_file_name_stem.as_bytes());
-
-    let mut game_dir = vec![0u8; 260];
-    game_dir[..GAME_DIR.len()].copy_from_slice(GAME_DIR.as_bytes());
 
     let header = Header {
+        magic: b"HLDEMO\0\0".to_vec(),
         demo_protocol: 5,
-        net_protocol: 48,
-        map_name: map_name.leak(),
-        game_dir: game_dir.leak(),
-        map_crc: 0,          // doesnt matter
+        network_protocol: 48,
+        map_name: ByteString(map_file_name_stem.as_bytes().to_vec()).padded(260),
+        game_directory: ByteString(GAME_DIR.as_bytes().to_vec()).padded(260),
+        map_checksum: 0,     // doesnt matter
         directory_offset: 1, // will be corrected when written
     };
 
-    let mut entry0_desc = vec![0u8; 64];
-    let entry0_name = "LOADING";
-    entry0_desc[..entry0_name.len()].copy_from_slice(entry0_name.as_bytes());
-
     let entry0 = DirectoryEntry {
-        entry_type: 0, // 0 for LOADING
-        description: entry0_desc.leak(),
+        type_: 0, // 0 for LOADING
+        description: ByteString(b"LOADING".to_vec()).padded(64),
         flags: 0,
         cd_track: -1,
         track_time: 0.0, // doesnt matter
         frame_count: 0,
-        offset: 0,      // will be corrected when written
-        file_length: 1, // doesnt matter
+        frame_offset: 0, // will be corrected when written
+        file_length: 1,  // doesnt matter
         frames: vec![],
     };
 
-    let mut entry1_desc = vec![0u8; 64];
-    let entry1_name = "Normal";
-    entry1_desc[..entry1_name.len()].copy_from_slice(entry1_name.as_bytes());
-
     let entry1 = DirectoryEntry {
-        entry_type: 1, // 1 for Normal
-        description: entry1_desc.leak(),
+        type_: 1, // 1 for Normal
+        description: ByteString(b"Normal".to_vec()).padded(64),
         flags: 0,
         cd_track: -1,
         track_time: 0.0, // doesnt matter
         frame_count: 0,
-        offset: 0,      // will be corrected when written
-        file_length: 1, // doesnt matter
+        frame_offset: 0, // will be corrected when written
+        file_length: 1,  // doesnt matter
         frames: vec![],
     };
 
@@ -94,23 +109,62 @@ pub fn ghost_to_demo<'a>(ghost_file_name: &'a Path, map_file_name: &'a Path) ->
         entries: vec![entry0, entry1],
     };
 
-    let mut demo = Demo { header, directory };
+    let demo = Demo {
+        header,
+        directory,
+        _aux: Some(aux.clone()),
+    };
 
-    // final steps
-    let (game_resource_index_start, packet_entities, delta_packet_entities) =
-        insert_base_netmsg(&mut demo, map_file_name, &aux);
-    insert_ghost(
-        &mut demo,
-        ghost_file_name.to_str().unwrap(),
-        None,
-        None,
-        game_resource_index_start,
-        packet_entities,
-        delta_packet_entities,
-        &aux,
-    );
+    (demo, aux)
+}
+
+/// Every ghost file inside the folder except demos written by demdoc, sorted by name
+pub fn ghosts_in_folder(folder: &Path) -> eyre::Result<Vec<PathBuf>> {
+    if !folder.is_dir() {
+        return err!("{} is not a folder", folder.display());
+    }
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && !is_demdoc_output(path))
+        .filter(|path| GhostFormat::from_path(path).is_some())
+        .collect();
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Converts the ghost into a demo on the .bsp and writes it next to the ghost.
+///
+/// Returns the path of the new demo.
+pub fn ghost_to_demo_file(ghost_path: &Path, bsp_path: &Path) -> eyre::Result<PathBuf> {
+    let demo = ghost_to_demo(ghost_path, bsp_path)?;
+
+    let out_path = demdoc_output_path(ghost_path, GHOST_SUFFIX);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
 
-    demo
+/// What the ghost frames need from the base net message
+pub(super) struct BaseNetMsg {
+    pub game_resource_index_start: usize,
+    pub packet_entities: Vec<u8>,
+    pub delta_packet_entities: SvcDeltaPacketEntities,
+    /// Model index of the first extra model
+    pub extra_model_index_start: usize,
+    /// Entity index of the first extra entity
+    pub extra_entity_index_start: usize,
+}
+
+/// Entity deltas and raw messages added to a ghost frame
+#[derive(Debug, Clone, Default)]
+pub struct FrameExtras {
+    /// Entity index and its delta, the player is entity 1
+    pub entities: Vec<(u16, Delta)>,
+    pub messages: Vec<u8>,
 }
 
 #[derive(Debug)]
@@ -125,9 +179,9 @@ struct BaselineEntity<'a> {
 const BASELINE_ENTITIES_BRUSH: &[&str] = &["func_door", "func_illusionary"];
 const BASELINE_ENTITIES_CYCLER: &[&str] = &["cycler_sprite", "cycler"];
 
+use nom::IResult;
 use nom::character::complete::space0;
 use nom::combinator::map;
-use nom::IResult;
 fn parse_3_f32(i: &str) -> IResult<&str, (f32, f32, f32)> {
     map(
         tuple((float, space0, float, space0, float)),
@@ -151,18 +205,21 @@ fn parse_3_f32(i: &str) -> IResult<&str, (f32, f32, f32)> {
 ///
 /// frame 4: svcpackent entity
 ///
-/// returns the index of game resources for ghost to generate footstep
-fn insert_base_netmsg(
+/// `extra_models` are precached after game resources
+/// and `extra_entity_count` entities are spawned after map entities.
+pub(super) fn insert_base_netmsg(
     demo: &mut Demo,
     map_file_name: &Path,
-    aux: &RefCell<Aux>,
-) -> (usize, Vec<u8>, SvcDeltaPacketEntities) {
+    aux: &AuxRefCell,
+    extra_models: &[String],
+    extra_entity_count: usize,
+) -> eyre::Result<BaseNetMsg> {
     // add maps entities first with its models, named "*{number}" and so on until we are done
     // by then we can insert our own custom files
     // bsp is still cached first as 0
     // each baseline_entities will have `model` key. To insert that into baseline, we have to
     // translate that into `modelindex` instead.
-    let bsp_file = Bsp::from_file(map_file_name).unwrap();
+    let bsp_file = Bsp::from_file(map_file_name)?;
     let bsp_entities = bsp_file.entities;
     // println!("{:?}", bsp_entities);
 
@@ -231,6 +288,9 @@ fn insert_base_netmsg(
         })
         .collect();
 
+    // map entities are offset by the players
+    let extra_entity_index_start = bsp_entities.len() + MAX_PLAYERS as usize;
+
     let game_dir = format!("{}\0", GAME_DIR);
     let map_file_name = format!(
         "maps/{}\0",
@@ -241,7 +301,7 @@ fn insert_base_netmsg(
         protocol: 48,
         spawn_count: 5, // ?
         map_checksum: 0,
-        client_dll_hash: vec![0u8; 16],
+        client_dll_hash: ByteString(vec![0u8; 16]),
         max_players: MAX_PLAYERS as u8,
         player_index: 0,
         is_deathmatch: 0,
@@ -251,18 +311,18 @@ fn insert_base_netmsg(
         map_cycle: b"a\0".to_vec(), // must be null string
         unknown: 0u8,
     };
-    let server_info = server_info.write(aux);
+    let server_info = server_info.write(aux.clone());
 
     let dds: Vec<u8> = get_cs_delta_msg!()
         .iter()
-        .flat_map(|dd| dd.write(aux))
+        .flat_map(|dd| dd.write(aux.clone()))
         .collect();
 
     // parse delta again just so that we mutate our Aux
-    parse_netmsg(dds.as_slice(), aux).unwrap();
+    parse_netmsg(dds.as_slice(), aux.clone()).unwrap();
 
     let set_view = SvcSetView { entity_index: 1 }; // always 1
-    let set_view = set_view.write(aux);
+    let set_view = set_view.write(aux.clone());
 
     let new_movevars = SvcNewMovevars {
         gravity: 800.,
@@ -288,7 +348,7 @@ fn insert_base_netmsg(
         sky_vec: vec![-0.0, 2.68e-43, 2.7721908e20],
         sky_name: [0].to_vec(),
     };
-    let new_movevars = new_movevars.write(aux);
+    let new_movevars = new_movevars.write(aux.clone());
 
     // bsp is always 1, then func_door and illusionary and whatever renders
     // maps resources first
@@ -347,16 +407,39 @@ fn insert_base_netmsg(
         })
         .collect();
 
+    let extra_model_index_start = game_resource_index_start + pl_steps.len() + 1;
+
+    let extra_models_resource: Vec<Resource> = extra_models
+        .iter()
+        .enumerate()
+        .map(|(i, model)| Resource {
+            type_: nbit_num!(ResourceType::Model, 4),
+            name: nbit_str!(format!("{}\0", model)),
+            index: nbit_num!(extra_model_index_start + i, 12),
+            size: nbit_num!(0, 3 * 8),
+            flags: nbit_num!(0, 3),
+            md5_hash: None,
+            has_extra_info: false,
+            extra_info: None,
+        })
+        .collect();
+
     // add resources here
     // the order doesn't matter because we already specify the resource index
-    let resources = [vec![bsp, v_usp], pl_steps, bsp_entities_resource].concat();
+    let resources = [
+        vec![bsp, v_usp],
+        pl_steps,
+        bsp_entities_resource,
+        extra_models_resource,
+    ]
+    .concat();
 
     let resource_list = SvcResourceList {
         resource_count: nbit_num!(resources.len(), 12),
         resources,
         consistencies: vec![],
     };
-    let resource_list = resource_list.write(aux);
+    let resource_list = resource_list.write(aux.clone());
 
     let worldspawn = EntityS {
         entity_index: 0, // worldspawn is index 0
@@ -379,7 +462,22 @@ fn insert_base_netmsg(
         })
         .collect();
 
-    let spawn_baseline_entities = [vec![worldspawn], bsp_entities_baseline].concat();
+    // extra entities get their model from their deltas
+    let extra_entities_baseline: Vec<EntityS> = (0..extra_entity_count)
+        .map(|i| EntityS {
+            entity_index: (extra_entity_index_start + i) as u16,
+            index: nbit_num!(extra_entity_index_start + i, 11),
+            type_: nbit_num!(1, 2),
+            delta: Delta::new(),
+        })
+        .collect();
+
+    let spawn_baseline_entities = [
+        vec![worldspawn],
+        bsp_entities_baseline,
+        extra_entities_baseline,
+    ]
+    .concat();
 
     // max_client should be 1 because we are playing demo and it is OK.
     let spawn_baseline = SvcSpawnBaseline {
@@ -387,10 +485,10 @@ fn insert_base_netmsg(
         total_extra_data: nbit_num!(0, 6),
         extra_data: vec![],
     };
-    let spawn_baseline = spawn_baseline.write(aux);
+    let spawn_baseline = spawn_baseline.write(aux.clone());
 
     let sign_on_num = SvcSignOnNum { sign: 1 };
-    let sign_on_num = sign_on_num.write(aux);
+    let sign_on_num = sign_on_num.write(aux.clone());
 
     // making entities appearing
     // packet entities is not enough
@@ -416,13 +514,21 @@ fn insert_base_netmsg(
         insert_packet_entity_state_with_index!(entity_states, Delta::new(), ent.index as u16);
     });
 
+    (0..extra_entity_count).for_each(|i| {
+        insert_packet_entity_state_with_index!(
+            entity_states,
+            Delta::new(),
+            (extra_entity_index_start + i) as u16
+        );
+    });
+
     // println!("{:?}", entity_states);
 
     let packet_entities = SvcPacketEntities {
         entity_count: nbit_num!(entity_states.len(), 16), // has to match the length, of EntityState
         entity_states,
     };
-    let packet_entities = packet_entities.write(aux);
+    let packet_entities = packet_entities.write(aux.clone());
 
     let player_entity_state_delta = EntityStateDelta {
         entity_index: 1,
@@ -461,8 +567,7 @@ fn insert_base_netmsg(
 
     // println!("{}", baseline_entities.len());
 
-    let mut new_netmsg_data = NetMsgData::new(2);
-    new_netmsg_data.msg = [
+    let msg = [
         server_info,
         dds,
         set_view,
@@ -473,40 +578,44 @@ fn insert_base_netmsg(
         packet_entities.to_owned(),
         // delta_packet_entities,
     ]
-    .concat()
-    .leak();
+    .concat();
+
+    let mut new_netmsg_data = NetworkMessage::new(2);
+    new_netmsg_data.message_length = msg.len() as u32;
+    new_netmsg_data.messages = MessageData::Raw(msg);
 
-    let netmsg_framedata = FrameData::NetMsg((NetMsgFrameType::Start, new_netmsg_data));
+    let netmsg_framedata =
+        FrameData::NetworkMessage(Box::new((NetworkMessageType::Start, new_netmsg_data)));
     let netmsg_frame = Frame {
         time: 0.,
         frame: 0,
-        data: netmsg_framedata,
+        frame_data: netmsg_framedata,
     };
 
     demo.directory.entries[0].frames.push(netmsg_frame);
     demo.directory.entries[0].frame_count += 1;
 
-    (
+    Ok(BaseNetMsg {
         game_resource_index_start,
         packet_entities,
         delta_packet_entities,
-    )
+        extra_model_index_start,
+        extra_entity_index_start,
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn insert_ghost(
     demo: &mut Demo,
-    ghost_file_name: &str,
+    ghost_info: &GhostInfo,
     override_frametime: Option<f32>,
     override_fov: Option<f32>,
     game_resource_index_start: usize,
     packet_entities: Vec<u8>,
-    mut delta_packet_entities: SvcDeltaPacketEntities,
-    aux: &RefCell<Aux>,
-) {
-    // setup
-    let ghost_info = get_ghost(ghost_file_name).unwrap();
-
+    delta_packet_entities: SvcDeltaPacketEntities,
+    frame_extras: &[FrameExtras],
+    aux: &AuxRefCell,
+) -> eyre::Result<()> {
     // set directory entry info
     let entry1 = &mut demo.directory.entries[1];
 
@@ -522,7 +631,7 @@ pub fn insert_ghost(
     let start_frame = Frame {
         time,
         frame: 0,
-        data: start_framedata,
+        frame_data: start_framedata,
     };
     entry1.frames.push(start_frame);
 
@@ -530,13 +639,11 @@ pub fn insert_ghost(
 
     // insert :DDD
     for (frame_idx, frame) in ghost_info.frames.iter().enumerate() {
-        let frametime = override_frametime.unwrap_or({
-            if let Some(frametime) = frame.frametime {
-                frametime as f32
-            } else {
-                unreachable!("No frametime");
-            }
-        });
+        let Some(frametime) =
+            override_frametime.or(frame.frametime.map(|frametime| frametime as f32))
+        else {
+            return err!("Ghost frame {frame_idx} has no frametime");
+        };
 
         let fov = override_fov.unwrap_or(90.);
         let mut vieworigin = frame.origin;
@@ -552,39 +659,58 @@ pub fn insert_ghost(
         }
 
         // buffer because it does so.... not sure the number for now :DDD
-        let buffer_framedata = FrameData::DemoBuffer(DemoBufferData {
-            buffer: &DEMO_BUFFER_SIZE,
+        let buffer_framedata = FrameData::DemoBuffer(DemoBuffer {
+            buffer: DEMO_BUFFER_SIZE.to_vec(),
         });
         let buffer_frame = Frame {
             time,
             frame: (frame_idx + 1) as i32,
-            data: buffer_framedata,
+            frame_data: buffer_framedata,
         };
 
         // client data
-        let clientdata_framedata = FrameData::ClientData(ClientDataData {
-            origin: frame.origin.into(),
-            viewangles: frame.viewangles.into(),
+        let clientdata_framedata = FrameData::ClientData(ClientData {
+            origin: frame.origin.to_array().to_vec(),
+            viewangles: frame.viewangles.to_array().to_vec(),
             weapon_bits: 0,
             fov,
         });
         let clientdata_frame = Frame {
             time,
             frame: (frame_idx + 1) as i32,
-            data: clientdata_framedata,
+            frame_data: clientdata_framedata,
         };
 
         // netmsg
-        let mut new_netmsg_data = NetMsgData::new(DEFAULT_IN_SEQ + frame_idx as i32);
-        new_netmsg_data.info.ref_params.vieworg = vieworigin.into();
-        new_netmsg_data.info.ref_params.viewangles = frame.viewangles.into();
-        new_netmsg_data.info.ref_params.frametime = frametime;
-        new_netmsg_data.info.ref_params.time = time;
-        new_netmsg_data.info.ref_params.simorg = frame.origin.into();
-        new_netmsg_data.info.ref_params.cl_viewangles = frame.viewangles.into();
-        new_netmsg_data.info.usercmd.viewangles = frame.viewangles.into();
-        // new_netmsg_data.info.movevars.sky_name = hehe; // TODO... DO NOT ASSIGN to &[]
-        new_netmsg_data.info.view = vieworigin.into();
+        let mut new_netmsg_data = NetworkMessage::new(DEFAULT_IN_SEQ + frame_idx as i32);
+        new_netmsg_data.info.refparams.view_origin = vieworigin.to_array().to_vec();
+        new_netmsg_data.info.refparams.view_angles = frame.viewangles.to_array().to_vec();
+        new_netmsg_data.info.refparams.frame_time = frametime;
+        new_netmsg_data.info.refparams.time = time;
+        new_netmsg_data.info.refparams.sim_org = frame.origin.to_array().to_vec();
+        new_netmsg_data.info.refparams.cl_viewangles = frame.viewangles.to_array().to_vec();
+        new_netmsg_data.info.usercmd.view_angles = frame.viewangles.to_array().to_vec();
+        new_netmsg_data.info.view = vieworigin.to_array().to_vec();
+
+        // ghosts only have keys so moves are at full speed
+        if let Some(buttons) = frame.buttons {
+            let usercmd = &mut new_netmsg_data.info.usercmd;
+            let pressed = |button: Buttons| buttons & button as u32 != 0;
+
+            usercmd.buttons = buttons as u16;
+            usercmd.forward_move = match (pressed(Buttons::Forward), pressed(Buttons::Back)) {
+                (true, false) => GHOST_MOVE_SPEED,
+                (false, true) => -GHOST_MOVE_SPEED,
+                _ => 0.,
+            };
+            usercmd.side_move = match (pressed(Buttons::MoveRight), pressed(Buttons::MoveLeft)) {
+                (true, false) => GHOST_MOVE_SPEED,
+                (false, true) => -GHOST_MOVE_SPEED,
+                _ => 0.,
+            };
+        }
+
+        let mut msg: Vec<u8> = vec![];
 
         let speed = ((frame.origin[0] - last_pos[0]).powi(2)
             + (frame.origin[1] - last_pos[1]).powi(2))
@@ -600,53 +726,53 @@ pub fn insert_ghost(
         let footstep_sound_index_start = game_resource_index_start + 1;
 
         // play jump sound
-        if let Some(buttons) = frame.buttons {
-            if buttons & Buttons::Jump as u32 != 0 && curr_z_vel > last_z_vel && speed > 150. {
-                let svcsound = SvcSound {
-                    flags: bitvec![u8, Lsb0; 1, 1, 1, 0, 0, 0, 0, 0, 0],
-                    volume: nbit_num!(128, 8).into(),
-                    attenuation: nbit_num!(204, 8).into(),
-                    channel: nbit_num!(5, 3),
-                    entity_index: nbit_num!(1, 11),
-                    sound_index_long: nbit_num!(
-                        rand_int_range!(footstep_sound_index_start, footstep_sound_index_start + 3),
-                        16
-                    )
-                    .into(),
-                    sound_index_short: None,
-                    has_x: true,
-                    has_y: true,
-                    has_z: true,
-                    origin_x: Some(OriginCoord {
-                        int_flag: true,
-                        fraction_flag: false,
-                        is_negative: frame.origin[0].is_sign_negative().into(),
-                        int_value: nbit_num!(frame.origin[0].round().abs() as i32, 12).into(),
-                        fraction_value: None,
-                    }),
-                    origin_y: Some(OriginCoord {
-                        int_flag: true,
-                        fraction_flag: false,
-                        is_negative: frame.origin[1].is_sign_negative().into(),
-                        int_value: nbit_num!(frame.origin[1].round().abs() as i32, 12).into(),
-                        fraction_value: None,
-                    }),
-                    origin_z: Some(OriginCoord {
-                        int_flag: true,
-                        fraction_flag: false,
-                        is_negative: frame.origin[2].is_sign_negative().into(),
-                        int_value: nbit_num!(frame.origin[2].round().abs() as i32, 12).into(),
-                        fraction_value: None,
-                    }),
-                    pitch: bitvec![u8, Lsb0; 1, 0, 0, 0, 0, 0, 0, 0],
-                };
-
-                let svcsound_msg = svcsound.write(aux);
-
-                new_netmsg_data.msg = [new_netmsg_data.msg.to_owned(), svcsound_msg]
-                    .concat()
-                    .leak();
-            }
+        if let Some(buttons) = frame.buttons
+            && buttons & Buttons::Jump as u32 != 0
+            && curr_z_vel > last_z_vel
+            && speed > 150.
+        {
+            let svcsound = SvcSound {
+                flags: bitvec![u8, Lsb0; 1, 1, 1, 0, 0, 0, 0, 0, 0],
+                volume: nbit_num!(128, 8).into(),
+                attenuation: nbit_num!(204, 8).into(),
+                channel: nbit_num!(5, 3),
+                entity_index: nbit_num!(1, 11),
+                sound_index_long: nbit_num!(
+                    rand_int_range!(footstep_sound_index_start, footstep_sound_index_start + 3),
+                    16
+                )
+                .into(),
+                sound_index_short: None,
+                has_x: true,
+                has_y: true,
+                has_z: true,
+                origin_x: Some(OriginCoord {
+                    int_flag: true,
+                    fraction_flag: false,
+                    is_negative: frame.origin[0].is_sign_negative().into(),
+                    int_value: nbit_num!(frame.origin[0].round().abs() as i32, 12).into(),
+                    fraction_value: None,
+                }),
+                origin_y: Some(OriginCoord {
+                    int_flag: true,
+                    fraction_flag: false,
+                    is_negative: frame.origin[1].is_sign_negative().into(),
+                    int_value: nbit_num!(frame.origin[1].round().abs() as i32, 12).into(),
+                    fraction_value: None,
+                }),
+                origin_z: Some(OriginCoord {
+                    int_flag: true,
+                    fraction_flag: false,
+                    is_negative: frame.origin[2].is_sign_negative().into(),
+                    int_value: nbit_num!(frame.origin[2].round().abs() as i32, 12).into(),
+                    fraction_value: None,
+                }),
+                pitch: bitvec![u8, Lsb0; 1, 0, 0, 0, 0, 0, 0, 0],
+            };
+
+            let svcsound_msg = svcsound.write(aux.clone());
+
+            msg.extend(svcsound_msg);
         }
         // play step sound every 0.3 on ground
         if time_step <= 0. && last_pos[2] == frame.origin[2] {
@@ -692,47 +818,71 @@ pub fn insert_ghost(
                 pitch: nbit_num!(1, 8),
             };
 
-            let svcsound_msg = svcsound.write(aux);
+            let svcsound_msg = svcsound.write(aux.clone());
 
-            new_netmsg_data.msg = [new_netmsg_data.msg.to_owned(), svcsound_msg]
-                .concat()
-                .leak();
+            msg.extend(svcsound_msg);
         }
 
         if packet_entity_msg {
-            new_netmsg_data.msg = [
+            msg = [
                 packet_entities.to_owned(),
                 // delta_packet_entities_byte,
-                new_netmsg_data.msg.to_owned(),
+                msg,
             ]
-            .concat()
-            .leak();
+            .concat();
 
             packet_entity_msg = false;
         }
 
-        if frame_idx % 100 == 0 {
-            // let mut delta_packet_entities = delta_packet_entities;
-            // println!("{} {}", delta_packet_entities.entity_states.len(), delta_packet_entities.entity_count.to_u32());
-            // delta_sequence: nbit_num!(DEFAULT_IN_SEQ & 0xff - 1, 8), // otherwise entity flush happens
-            delta_packet_entities.delta_sequence =
-                nbit_num!((DEFAULT_IN_SEQ + frame_idx as i32 - 1) & 0xff, 8);
-            let delta_packet_entities_byte = delta_packet_entities.write(aux);
-
-            new_netmsg_data.msg = [
-                // packet_entities.to_owned(),
-                delta_packet_entities_byte,
-                new_netmsg_data.msg.to_owned(),
-            ]
-            .concat()
-            .leak();
+        let extras = frame_extras.get(frame_idx);
+        let extra_entities = extras
+            .map(|extras| extras.entities.as_slice())
+            .unwrap_or_default();
+
+        // every 100 frames has every entity, otherwise only the extra entities
+        if frame_idx % 100 == 0 || !extra_entities.is_empty() {
+            let mut entity_states = if frame_idx % 100 == 0 {
+                delta_packet_entities.entity_states.clone()
+            } else {
+                delta_packet_entities.entity_states[..1].to_vec()
+            };
+
+            for (index, delta) in extra_entities {
+                if *index == 1 {
+                    entity_states[0].delta = Some(delta.clone());
+                } else {
+                    insert_packet_entity_state_delta_with_index!(
+                        entity_states,
+                        delta.clone(),
+                        *index
+                    );
+                }
+            }
+
+            let frame_delta_packet_entities = SvcDeltaPacketEntities {
+                entity_count: nbit_num!(entity_states.len(), 16),
+                // otherwise entity flush happens
+                delta_sequence: nbit_num!((DEFAULT_IN_SEQ + frame_idx as i32 - 1) & 0xff, 8),
+                entity_states,
+            };
+            let delta_packet_entities_byte = frame_delta_packet_entities.write(aux.clone());
+
+            msg = [delta_packet_entities_byte, msg].concat();
+        }
+
+        if let Some(extras) = extras {
+            msg.extend(&extras.messages);
         }
 
-        let netmsg_framedata = FrameData::NetMsg((NetMsgFrameType::Normal, new_netmsg_data));
+        new_netmsg_data.message_length = msg.len() as u32;
+        new_netmsg_data.messages = MessageData::Raw(msg);
+
+        let netmsg_framedata =
+            FrameData::NetworkMessage(Box::new((NetworkMessageType::Normal, new_netmsg_data)));
         let netmsg_frame = Frame {
             time,
             frame: (frame_idx + 1) as i32,
-            data: netmsg_framedata,
+            frame_data: netmsg_framedata,
         };
 
         // insert
@@ -752,26 +902,120 @@ pub fn insert_ghost(
     let end_frame = Frame {
         time,
         frame: ghost_info.frames.len() as i32,
-        data: end_framedata,
+        frame_data: end_framedata,
     };
 
     entry1.frames.push(end_frame);
     entry1.frame_count = ghost_info.frames.len() as i32;
+
+    Ok(())
 }
 
+/// Origin, yaw and buttons of a test ghost frame in GoldSrc coordinates
 #[cfg(test)]
-mod test {
-    use dem::write_demo;
+pub(super) type TestGhostFrame = ([f32; 3], f32, u32);
+
+/// Romanian-Jumpers ghost at 100 fps, their Y is up
+#[cfg(test)]
+pub(super) fn write_test_ghost_frames(
+    folder: &Path,
+    name: &str,
+    frames: &[TestGhostFrame],
+) -> PathBuf {
+    let frames = frames
+        .iter()
+        .enumerate()
+        .map(|(i, ([x, y, z], yaw, buttons))| {
+            format!(
+                r#"{{"position": [{x}, {z}, {}], "orientation": [0, {yaw}], "length": 0.01, "time": {}, "buttons": {buttons}}}"#,
+                -y,
+                i as f32 * 0.01
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let ghost_path = folder.join(format!("{name}.rj.json"));
+    fs::write(&ghost_path, format!(r#"{{"frames": [{frames}]}}"#)).unwrap();
+
+    ghost_path
+}
+
+/// Romanian-Jumpers ghost moving along x at 100 fps
+#[cfg(test)]
+pub(super) fn write_test_ghost(folder: &Path, name: &str, frame_count: usize) -> PathBuf {
+    let frames = (0..frame_count)
+        .map(|i| ([(i * 2) as f32, 0., 64.], 90., 0))
+        .collect::<Vec<TestGhostFrame>>();
+
+    write_test_ghost_frames(folder, name, &frames)
+}
 
+/// Parsed demo of the test ghost frames on datacore
+#[cfg(test)]
+pub(super) fn test_demo_from_frames(name: &str, frames: &[TestGhostFrame]) -> Demo {
+    let out_dir = std::env::temp_dir().join(format!("gchimp_demdoc_{name}"));
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let ghost_path = write_test_ghost_frames(&out_dir, name, frames);
+    let bsp_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/datacore.bsp");
+
+    let demo = ghost_to_demo(&ghost_path, &bsp_path).unwrap();
+
+    // net messages are only parsed when read back
+    dem::open_demo_from_bytes(&demo.write_to_bytes()).unwrap()
+}
+
+/// Parsed demo of a test ghost on datacore
+#[cfg(test)]
+pub(super) fn test_demo(name: &str, frame_count: usize) -> Demo {
+    let frames = (0..frame_count)
+        .map(|i| ([(i * 2) as f32, 0., 64.], 90., 0))
+        .collect::<Vec<TestGhostFrame>>();
+
+    test_demo_from_frames(name, &frames)
+}
+
+#[cfg(test)]
+mod test {
     use super::*;
 
     #[test]
+    #[ignore]
     fn run() {
         let demo = ghost_to_demo(
             Path::new("/home/khang/gchimp/examples/ghost2dem/rvp.rj.json"),
             Path::new("/home/khang/gchimp/examples/ghost2dem/rvp_tundra-bhop.bsp"),
+        )
+        .unwrap();
+
+        demo.write_to_file("/home/khang/gchimp/examples/ghost2dem/out.dem")
+            .unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let out_dir = std::env::temp_dir().join("gchimp_ghost2dem");
+        fs::create_dir_all(&out_dir).unwrap();
+
+        let ghost_path = write_test_ghost(&out_dir, "round_trip", 20);
+        let bsp_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/datacore.bsp");
+
+        assert_eq!(
+            ghosts_in_folder(&out_dir).unwrap(),
+            vec![ghost_path.clone()]
         );
 
-        write_demo("/home/khang/gchimp/examples/ghost2dem/out.dem", demo).unwrap();
+        let out_path = ghost_to_demo_file(&ghost_path, &bsp_path).unwrap();
+        assert_eq!(out_path, out_dir.join("round_trip.rj_ghost.dem"));
+
+        // output is not picked up again
+        assert_eq!(ghosts_in_folder(&out_dir).unwrap(), vec![ghost_path]);
+
+        let demo = dem::open_demo(&out_path).unwrap();
+        assert_eq!(demo.header.map_name.to_str().unwrap(), "datacore");
+        // buffer, client data and net message for every ghost frame
+        // between start, end and the next section added by the writer
+        assert_eq!(demo.directory.entries[1].frames.len(), 20 * 3 + 3);
     }
 }
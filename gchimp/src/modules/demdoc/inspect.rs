@@ -0,0 +1,811 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use dem::{
+    bitslice_to_string, open_demo,
+    prelude::BitSliceCast,
+    types::{Demo, EngineMessage, Frame, FrameData, MessageData, NetMessage, NetworkMessageType},
+};
+use serde::Serialize;
+
+use super::utils::{incoming_sequence, message_name, parse_user_info};
+
+pub struct InspectOptions {
+    /// Only frames and messages of these types like `ClientData` or `SvcSound`, case insensitive
+    pub types: Vec<String>,
+    /// Only frames between these demo times
+    pub start: f32,
+    pub end: f32,
+    /// Only messages mentioning this entity
+    pub entity: Option<u16>,
+    /// Lists the frames, otherwise there is only the summary
+    pub frames: bool,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InspectOptions {
+    pub fn new() -> Self {
+        Self {
+            types: vec![],
+            start: 0.,
+            end: f32::INFINITY,
+            entity: None,
+            frames: true,
+        }
+    }
+
+    fn has_type(&self, name: &str) -> bool {
+        self.types.iter().any(|t| t.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryInspection {
+    pub description: String,
+    /// What the directory says
+    pub frame_count: i32,
+    /// What is actually there
+    pub frames: usize,
+    pub track_time: f32,
+    pub start_time: f32,
+    pub end_time: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceInspection {
+    pub index: u16,
+    pub type_: &'static str,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerInspection {
+    pub slot: u8,
+    pub id: u32,
+    pub name: String,
+    pub steam_id: Option<String>,
+}
+
+/// Something a recorded demo does not do, usually from being edited
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Anomaly {
+    TimeBackwards {
+        entry: usize,
+        frame_index: usize,
+        from: f32,
+        to: f32,
+    },
+    FrameNumberBackwards {
+        entry: usize,
+        frame_index: usize,
+        from: i32,
+        to: i32,
+    },
+    /// Frame numbers skipping ahead
+    MissingFrames {
+        entry: usize,
+        frame_index: usize,
+        from: i32,
+        to: i32,
+    },
+    /// Net message sequences skipping ahead or going back
+    SequenceGap {
+        entry: usize,
+        frame_index: usize,
+        from: i32,
+        to: i32,
+    },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::TimeBackwards {
+                entry,
+                frame_index,
+                from,
+                to,
+            } => write!(
+                f,
+                "[{entry}:{frame_index}] time goes back from {from:.3} to {to:.3}"
+            ),
+            Anomaly::FrameNumberBackwards {
+                entry,
+                frame_index,
+                from,
+                to,
+            } => write!(
+                f,
+                "[{entry}:{frame_index}] frame number goes back from {from} to {to}"
+            ),
+            Anomaly::MissingFrames {
+                entry,
+                frame_index,
+                from,
+                to,
+            } => write!(
+                f,
+                "[{entry}:{frame_index}] frame number skips from {from} to {to}"
+            ),
+            Anomaly::SequenceGap {
+                entry,
+                frame_index,
+                from,
+                to,
+            } => write!(
+                f,
+                "[{entry}:{frame_index}] net message sequence jumps from {from} to {to}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DemoSummary {
+    pub map: String,
+    pub game_dir: String,
+    pub demo_protocol: i32,
+    pub network_protocol: i32,
+    pub entries: Vec<EntryInspection>,
+    pub resources: Vec<ResourceInspection>,
+    /// Last userinfo of every slot
+    pub players: Vec<PlayerInspection>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageInspection {
+    pub name: String,
+    pub entities: Vec<u16>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameInspection {
+    pub entry: usize,
+    pub index: usize,
+    pub time: f32,
+    pub frame: i32,
+    pub frame_type: String,
+    /// Content of frames other than net messages
+    pub detail: Option<String>,
+    pub messages: Vec<MessageInspection>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DemoInspection {
+    pub summary: DemoSummary,
+    pub frames: Vec<FrameInspection>,
+}
+
+impl Display for DemoSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Map: {}", self.map)?;
+        writeln!(f, "Game dir: {}", self.game_dir)?;
+        writeln!(
+            f,
+            "Protocol: demo {}, network {}",
+            self.demo_protocol, self.network_protocol
+        )?;
+
+        writeln!(f, "Entries:")?;
+        for (index, entry) in self.entries.iter().enumerate() {
+            writeln!(
+                f,
+                "  {index} {}: {} frames ({} in directory), {:.3}s to {:.3}s",
+                entry.description,
+                entry.frames,
+                entry.frame_count,
+                entry.start_time,
+                entry.end_time
+            )?;
+        }
+
+        writeln!(f, "Resources: {}", self.resources.len())?;
+        for resource in &self.resources {
+            writeln!(
+                f,
+                "  {} {} {}",
+                resource.index, resource.type_, resource.name
+            )?;
+        }
+
+        writeln!(f, "Players: {}", self.players.len())?;
+        for player in &self.players {
+            write!(f, "  {} {}", player.slot, player.name)?;
+
+            if let Some(steam_id) = &player.steam_id {
+                write!(f, " ({steam_id})")?;
+            }
+
+            writeln!(f)?;
+        }
+
+        writeln!(f, "Anomalies: {}", self.anomalies.len())?;
+        for anomaly in &self.anomalies {
+            writeln!(f, "  {anomaly}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for FrameInspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}:{}] {:.3}s #{} {}",
+            self.entry, self.index, self.time, self.frame, self.frame_type
+        )?;
+
+        if let Some(detail) = &self.detail {
+            write!(f, " {detail}")?;
+        }
+
+        for message in &self.messages {
+            write!(f, "\n    {} {}", message.name, message.detail)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for DemoInspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.summary)?;
+
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f, "Frames: {}", self.frames.len())?;
+        for frame in &self.frames {
+            writeln!(f, "{frame}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn resource_type_name(type_: u8) -> &'static str {
+    match type_ {
+        0 => "sound",
+        1 => "skin",
+        2 => "model",
+        3 => "decal",
+        4 => "generic",
+        5 => "eventscript",
+        6 => "world",
+        _ => "unknown",
+    }
+}
+
+/// `Name(inner)` from the debug print into `Name` and `inner`
+fn split_variant(debug: &str) -> (String, String) {
+    match debug.split_once('(') {
+        Some((name, rest)) => (
+            name.to_string(),
+            rest.strip_suffix(')').unwrap_or(rest).to_string(),
+        ),
+        None => (debug.to_string(), String::new()),
+    }
+}
+
+/// Debug prints of bit vectors come with their address, only the bits are kept
+fn strip_bitvec_header(debug: &str) -> String {
+    const HEADER: &str = "BitVec<u8, bitvec::order::Lsb0> { ";
+
+    let mut res = String::new();
+    let mut rest = debug;
+
+    while let Some(start) = rest.find(HEADER) {
+        res.push_str(&rest[..start]);
+        rest = &rest[start + HEADER.len()..];
+
+        if let Some(end) = rest.find("} ") {
+            rest = &rest[end + 2..];
+        }
+    }
+
+    res.push_str(rest);
+    res
+}
+
+fn engine_message_entities(message: &EngineMessage) -> Vec<u16> {
+    match message {
+        EngineMessage::SvcSetView(set_view) => vec![set_view.entity_index as u16],
+        EngineMessage::SvcSound(sound) => vec![sound.entity_index.to_u16()],
+        EngineMessage::SvcSpawnBaseline(baseline) => baseline
+            .entities
+            .iter()
+            .map(|entity| entity.entity_index)
+            .collect(),
+        EngineMessage::SvcPacketEntities(packet_entities) => packet_entities
+            .entity_states
+            .iter()
+            .map(|state| state.entity_index)
+            .collect(),
+        EngineMessage::SvcDeltaPacketEntities(delta_packet_entities) => delta_packet_entities
+            .entity_states
+            .iter()
+            .map(|state| state.entity_index)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Entity lists only show the filtered entity
+fn engine_message_detail(message: &EngineMessage, entity: Option<u16>) -> String {
+    let Some(entity) = entity else {
+        return split_variant(&format!("{message:?}")).1;
+    };
+
+    match message {
+        EngineMessage::SvcSpawnBaseline(baseline) => format!(
+            "{:?}",
+            baseline
+                .entities
+                .iter()
+                .filter(|state| state.entity_index == entity)
+                .collect::<Vec<_>>()
+        ),
+        EngineMessage::SvcPacketEntities(packet_entities) => format!(
+            "{:?}",
+            packet_entities
+                .entity_states
+                .iter()
+                .filter(|state| state.entity_index == entity)
+                .collect::<Vec<_>>()
+        ),
+        EngineMessage::SvcDeltaPacketEntities(delta_packet_entities) => format!(
+            "delta_sequence: {}, {:?}",
+            delta_packet_entities.delta_sequence.to_u8(),
+            delta_packet_entities
+                .entity_states
+                .iter()
+                .filter(|state| state.entity_index == entity)
+                .collect::<Vec<_>>()
+        ),
+        _ => split_variant(&format!("{message:?}")).1,
+    }
+}
+
+fn inspect_message(message: &NetMessage, entity: Option<u16>) -> MessageInspection {
+    match message {
+        NetMessage::UserMessage(user_message) => MessageInspection {
+            name: message_name(&user_message.name),
+            entities: vec![],
+            detail: format!("{:?}", user_message.data),
+        },
+        NetMessage::EngineMessage(engine_message) => {
+            let (name, _) = split_variant(&format!("{engine_message:?}"));
+
+            MessageInspection {
+                name,
+                entities: engine_message_entities(engine_message),
+                detail: strip_bitvec_header(&engine_message_detail(engine_message, entity)),
+            }
+        }
+    }
+}
+
+fn frame_type_name(frame_data: &FrameData) -> String {
+    match frame_data {
+        FrameData::NetworkMessage(_) => "NetworkMessage",
+        FrameData::DemoStart => "DemoStart",
+        FrameData::ConsoleCommand(_) => "ConsoleCommand",
+        FrameData::ClientData(_) => "ClientData",
+        FrameData::NextSection => "NextSection",
+        FrameData::Event(_) => "Event",
+        FrameData::WeaponAnimation(_) => "WeaponAnimation",
+        FrameData::Sound(_) => "Sound",
+        FrameData::DemoBuffer(_) => "DemoBuffer",
+    }
+    .to_string()
+}
+
+fn frame_detail(frame_data: &FrameData) -> Option<String> {
+    match frame_data {
+        FrameData::NetworkMessage(box_type) => {
+            let (type_, netmsg) = box_type.as_ref();
+            let type_ = match type_ {
+                NetworkMessageType::Start => "start".to_string(),
+                NetworkMessageType::Normal => "normal".to_string(),
+                NetworkMessageType::Unknown(type_) => format!("type {type_}"),
+            };
+
+            let messages = match &netmsg.messages {
+                MessageData::Parsed(_) => String::new(),
+                MessageData::Raw(bytes) => format!(", {} bytes unparsed", bytes.len()),
+                MessageData::None => ", no messages".to_string(),
+            };
+
+            Some(format!(
+                "{type_}, sequence {}{messages}",
+                netmsg.sequence_info.incoming_sequence
+            ))
+        }
+        FrameData::DemoStart | FrameData::NextSection => None,
+        FrameData::ConsoleCommand(command) => Some(message_name(&command.command.0)),
+        FrameData::ClientData(client_data) => Some(format!("{client_data:?}")),
+        FrameData::Event(event) => Some(format!("{event:?}")),
+        FrameData::WeaponAnimation(weapon_animation) => Some(format!("{weapon_animation:?}")),
+        FrameData::Sound(sound) => Some(format!(
+            "{} channel {} volume {:.2}",
+            message_name(&sound.sample),
+            sound.channel,
+            sound.volume
+        )),
+        FrameData::DemoBuffer(buffer) => Some(format!("{} bytes", buffer.buffer.len())),
+    }
+}
+
+/// `None` when the filters leave nothing of the frame
+fn inspect_frame(
+    frame: &Frame,
+    entry: usize,
+    index: usize,
+    options: &InspectOptions,
+) -> Option<FrameInspection> {
+    if frame.time < options.start || frame.time > options.end {
+        return None;
+    }
+
+    let frame_type = frame_type_name(&frame.frame_data);
+    let type_matches = options.types.is_empty() || options.has_type(&frame_type);
+
+    let messages = match &frame.frame_data {
+        FrameData::NetworkMessage(box_type) => match &box_type.as_ref().1.messages {
+            MessageData::Parsed(messages) => messages
+                .iter()
+                .map(|message| inspect_message(message, options.entity))
+                .filter(|message| type_matches || options.has_type(&message.name))
+                .filter(|message| {
+                    options
+                        .entity
+                        .is_none_or(|entity| message.entities.contains(&entity))
+                })
+                .collect(),
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+
+    // only frames with what is looked for are kept
+    let filtered_by_messages =
+        options.entity.is_some() || (!options.types.is_empty() && !type_matches);
+
+    if filtered_by_messages && messages.is_empty() {
+        return None;
+    }
+
+    Some(FrameInspection {
+        entry,
+        index,
+        time: frame.time,
+        frame: frame.frame,
+        frame_type,
+        detail: frame_detail(&frame.frame_data),
+        messages,
+    })
+}
+
+/// Anomalies between consecutive frames of every entry
+pub fn find_anomalies(demo: &Demo) -> Vec<Anomaly> {
+    let mut res = vec![];
+
+    for (entry, directory_entry) in demo.directory.entries.iter().enumerate() {
+        let mut last_sequence: Option<i32> = None;
+
+        // the closing frame is not on the timeline, the writer adds it with time 0
+        let frames = directory_entry
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| !matches!(frame.frame_data, FrameData::NextSection))
+            .collect::<Vec<_>>();
+
+        for pair in frames.windows(2) {
+            let ((_, prev), (frame_index, curr)) = (pair[0], pair[1]);
+
+            if curr.time < prev.time {
+                res.push(Anomaly::TimeBackwards {
+                    entry,
+                    frame_index,
+                    from: prev.time,
+                    to: curr.time,
+                });
+            }
+
+            if curr.frame < prev.frame {
+                res.push(Anomaly::FrameNumberBackwards {
+                    entry,
+                    frame_index,
+                    from: prev.frame,
+                    to: curr.frame,
+                });
+            } else if curr.frame > prev.frame + 1 {
+                res.push(Anomaly::MissingFrames {
+                    entry,
+                    frame_index,
+                    from: prev.frame,
+                    to: curr.frame,
+                });
+            }
+
+            let Some(sequence) = incoming_sequence(curr) else {
+                continue;
+            };
+
+            // the same sequence is written again when no packet came that frame
+            if let Some(last) = last_sequence
+                && (sequence < last || sequence > last + 1)
+            {
+                res.push(Anomaly::SequenceGap {
+                    entry,
+                    frame_index,
+                    from: last,
+                    to: sequence,
+                });
+            }
+
+            last_sequence = Some(sequence);
+        }
+    }
+
+    res
+}
+
+pub fn demo_summary(demo: &Demo) -> DemoSummary {
+    let entries = demo
+        .directory
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut times = entry
+                .frames
+                .iter()
+                .filter(|frame| !matches!(frame.frame_data, FrameData::NextSection))
+                .map(|frame| frame.time);
+            let start_time = times.next().unwrap_or(0.);
+            let end_time = times.next_back().unwrap_or(start_time);
+
+            EntryInspection {
+                description: message_name(&entry.description.0),
+                frame_count: entry.frame_count,
+                frames: entry.frames.len(),
+                track_time: entry.track_time,
+                start_time,
+                end_time,
+            }
+        })
+        .collect();
+
+    let mut resources = vec![];
+    let mut players = BTreeMap::new();
+
+    let messages = demo
+        .directory
+        .entries
+        .iter()
+        .flat_map(|entry| &entry.frames)
+        .filter_map(|frame| match &frame.frame_data {
+            FrameData::NetworkMessage(box_type) => match &box_type.as_ref().1.messages {
+                MessageData::Parsed(messages) => Some(messages),
+                _ => None,
+            },
+            _ => None,
+        })
+        .flatten();
+
+    for message in messages {
+        let NetMessage::EngineMessage(engine_message) = message else {
+            continue;
+        };
+
+        match engine_message.as_ref() {
+            EngineMessage::SvcResourceList(resource_list) => {
+                resources.extend(resource_list.resources.iter().map(|resource| {
+                    ResourceInspection {
+                        index: resource.index.to_u16(),
+                        type_: resource_type_name(resource.type_.to_u8()),
+                        name: bitslice_to_string(&resource.name)
+                            .trim_end_matches('\0')
+                            .to_string(),
+                    }
+                }));
+            }
+            EngineMessage::SvcUpdateUserInfo(user_info) => {
+                let fields = parse_user_info(&user_info.user_info.0);
+
+                // empty userinfo is someone leaving
+                if fields.is_empty() {
+                    players.remove(&user_info.index);
+                    continue;
+                }
+
+                let field = |key: &str| {
+                    fields
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, value)| value.clone())
+                };
+
+                players.insert(
+                    user_info.index,
+                    PlayerInspection {
+                        slot: user_info.index,
+                        id: user_info.id,
+                        name: field("name").unwrap_or_default(),
+                        steam_id: field("*sid"),
+                    },
+                );
+            }
+            _ => (),
+        }
+    }
+
+    DemoSummary {
+        map: message_name(&demo.header.map_name.0),
+        game_dir: message_name(&demo.header.game_directory.0),
+        demo_protocol: demo.header.demo_protocol,
+        network_protocol: demo.header.network_protocol,
+        entries,
+        resources,
+        players: players.into_values().collect(),
+        anomalies: find_anomalies(demo),
+    }
+}
+
+pub fn inspect_demo(demo: &Demo, options: &InspectOptions) -> DemoInspection {
+    let frames = if options.frames {
+        demo.directory
+            .entries
+            .iter()
+            .enumerate()
+            .flat_map(|(entry, directory_entry)| {
+                directory_entry
+                    .frames
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(index, frame)| inspect_frame(frame, entry, index, options))
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    DemoInspection {
+        summary: demo_summary(demo),
+        frames,
+    }
+}
+
+pub fn inspect_demo_file(
+    demo_path: &Path,
+    options: &InspectOptions,
+) -> eyre::Result<DemoInspection> {
+    let demo = open_demo(demo_path)?;
+
+    Ok(inspect_demo(&demo, options))
+}
+
+pub fn inspection_to_json(inspection: &DemoInspection) -> eyre::Result<String> {
+    Ok(serde_json::to_string_pretty(inspection)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::ghost2dem::test_demo;
+    use super::*;
+
+    #[test]
+    fn bitvec_header() {
+        let debug = "SvcSound { entity_index: BitVec<u8, bitvec::order::Lsb0> { addr: 0x1, head: 000, bits: 2, capacity: 8 } [1, 0], pitch: 1 }";
+
+        assert_eq!(
+            strip_bitvec_header(debug),
+            "SvcSound { entity_index: [1, 0], pitch: 1 }"
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let demo = test_demo("inspect_summary", 20);
+        let summary = demo_summary(&demo);
+
+        assert_eq!(summary.map, "datacore");
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.entries[0].description, "LOADING");
+        assert!((summary.entries[1].end_time - 0.2).abs() < 0.001);
+        assert!(
+            summary
+                .resources
+                .iter()
+                .any(|resource| resource.type_ == "model" && resource.name == "maps/datacore.bsp")
+        );
+        assert!(summary.anomalies.is_empty(), "{:?}", summary.anomalies);
+    }
+
+    #[test]
+    fn anomalies() {
+        let mut demo = test_demo("inspect_anomalies", 20);
+        let frames = &mut demo.directory.entries[1].frames;
+
+        frames[10].time = 0.;
+        frames.drain(20..26);
+
+        let anomalies = find_anomalies(&demo);
+
+        assert!(anomalies.iter().any(|anomaly| matches!(
+            anomaly,
+            Anomaly::TimeBackwards {
+                entry: 1,
+                frame_index: 10,
+                ..
+            }
+        )));
+        assert!(
+            anomalies
+                .iter()
+                .any(|anomaly| matches!(anomaly, Anomaly::MissingFrames { entry: 1, .. }))
+        );
+        assert!(
+            anomalies
+                .iter()
+                .any(|anomaly| matches!(anomaly, Anomaly::SequenceGap { entry: 1, .. }))
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let demo = test_demo("inspect_filters", 20);
+
+        let options = InspectOptions {
+            types: vec!["svcpacketentities".to_string()],
+            ..Default::default()
+        };
+        let inspection = inspect_demo(&demo, &options);
+
+        assert!(!inspection.frames.is_empty());
+        assert!(inspection.frames.iter().all(|frame| {
+            frame.messages.len() == 1 && frame.messages[0].name == "SvcPacketEntities"
+        }));
+
+        let options = InspectOptions {
+            types: vec!["ClientData".to_string()],
+            start: 0.05,
+            end: 0.1,
+            ..Default::default()
+        };
+        let inspection = inspect_demo(&demo, &options);
+
+        assert_eq!(inspection.frames.len(), 5);
+        assert!(inspection.frames.iter().all(|frame| {
+            frame.frame_type == "ClientData" && frame.time >= 0.05 && frame.time <= 0.1
+        }));
+
+        let options = InspectOptions {
+            entity: Some(1),
+            ..Default::default()
+        };
+        let inspection = inspect_demo(&demo, &options);
+
+        assert!(!inspection.frames.is_empty());
+        assert!(
+            inspection
+                .frames
+                .iter()
+                .flat_map(|frame| &frame.messages)
+                .all(|message| message.entities.contains(&1))
+        );
+
+        let json = inspection_to_json(&inspection).unwrap();
+        assert!(json.contains("\"anomalies\""));
+    }
+}
@@ -0,0 +1,652 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bsp::Bsp;
+use dem::{
+    open_demo,
+    types::{Demo, FrameData},
+};
+use glam::{Vec2, Vec3, Vec3Swizzles};
+use serde::Serialize;
+
+use super::Buttons;
+
+/// Width of the player hull, added to the distance like KZ plugins do
+const PLAYER_WIDTH: f32 = 32.;
+const STANDING_FEET: f32 = -36.;
+const DUCKING_FEET: f32 = -18.;
+/// Moving less than this vertically in a frame is standing on something
+const GROUND_EPSILON: f32 = 0.01;
+/// Frames on the ground between two jumps to still be a bhop, including the landing
+const BHOP_MAX_GROUND_FRAMES: usize = 2;
+/// Frames without gravity before jumping off a ladder
+const LADDER_MIN_FRAMES: usize = 3;
+/// Duck taps this long before the takeoff make a count jump
+const COUNT_JUMP_WINDOW: f32 = 0.5;
+const MAX_EDGE: f32 = 32.;
+const EDGE_STEP: f32 = 1. / 16.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum JumpType {
+    #[serde(rename = "LJ")]
+    LongJump,
+    /// First bhop after a jump
+    #[serde(rename = "BJ")]
+    BhopJump,
+    #[serde(rename = "CJ")]
+    CountJump,
+    #[serde(rename = "LAJ")]
+    LadderJump,
+    /// Later bhops of a chain
+    #[serde(rename = "Bhop")]
+    Bhop,
+}
+
+impl Display for JumpType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            JumpType::LongJump => "LJ",
+            JumpType::BhopJump => "BJ",
+            JumpType::CountJump => "CJ",
+            JumpType::LadderJump => "LAJ",
+            JumpType::Bhop => "Bhop",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrafeDirection {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Strafe {
+    pub direction: StrafeDirection,
+    pub frames: usize,
+    /// Percentage of the frames gaining speed
+    pub sync: f32,
+    pub gain: f32,
+    pub loss: f32,
+    pub max_speed: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JumpStats {
+    pub jump_type: JumpType,
+    /// Jumps before this one in the bhop chain, 0 when it does not follow a jump
+    pub chain: usize,
+    /// Demo frame numbers of the last frame on the ground and the first one back
+    pub takeoff_frame: i32,
+    pub landing_frame: i32,
+    pub takeoff_time: f32,
+    pub takeoff: [f32; 3],
+    pub landing: [f32; 3],
+    /// Horizontal distance between the takeoff and landing plus the player width
+    pub distance: f32,
+    /// Landing feet height minus takeoff feet height
+    pub height_difference: f32,
+    /// Horizontal speed at the takeoff
+    pub prestrafe: f32,
+    pub max_speed: f32,
+    pub airtime: f32,
+    /// Percentage of the strafing frames gaining speed
+    pub sync: f32,
+    pub strafes: Vec<Strafe>,
+    /// From the takeoff origin to where the ground ends, only known with the map
+    pub edge: Option<f32>,
+}
+
+impl Display for JumpStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:.2} units, pre {:.2}, max {:.2}, {} strafes, {:.0}% sync, {:.2}s airtime",
+            self.jump_type,
+            self.distance,
+            self.prestrafe,
+            self.max_speed,
+            self.strafes.len(),
+            self.sync,
+            self.airtime
+        )?;
+
+        if let Some(edge) = self.edge {
+            write!(f, ", {edge:.2} edge")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Player state of one net message frame
+#[derive(Debug, Clone, Copy)]
+struct Tick {
+    frame: i32,
+    time: f32,
+    origin: Vec3,
+    velocity: Vec3,
+    on_ground: bool,
+    gravity: f32,
+    buttons: u16,
+    side_move: f32,
+}
+
+impl Tick {
+    fn pressed(&self, button: Buttons) -> bool {
+        self.buttons & button as u16 != 0
+    }
+
+    fn speed(&self) -> f32 {
+        self.velocity.xy().length()
+    }
+
+    fn feet(&self) -> Vec3 {
+        let feet = if self.pressed(Buttons::Duck) {
+            DUCKING_FEET
+        } else {
+            STANDING_FEET
+        };
+
+        self.origin + Vec3::Z * feet
+    }
+
+    fn strafe_direction(&self) -> Option<StrafeDirection> {
+        if self.side_move < 0. {
+            return Some(StrafeDirection::Left);
+        }
+
+        if self.side_move > 0. {
+            return Some(StrafeDirection::Right);
+        }
+
+        match (
+            self.pressed(Buttons::MoveLeft),
+            self.pressed(Buttons::MoveRight),
+        ) {
+            (true, false) => Some(StrafeDirection::Left),
+            (false, true) => Some(StrafeDirection::Right),
+            _ => None,
+        }
+    }
+}
+
+fn to_vec3(v: &[f32]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+/// Player states of the playback in order.
+///
+/// Velocity and ground come from the demo when it has them.
+/// Otherwise, like with ghost demos, they are worked out from the origins.
+fn ticks(demo: &Demo) -> Vec<Tick> {
+    let mut res: Vec<Tick> = vec![];
+    let mut has_velocity = false;
+    let mut has_ground = false;
+
+    for frame in demo
+        .directory
+        .entries
+        .iter()
+        .skip(1)
+        .flat_map(|entry| &entry.frames)
+    {
+        let FrameData::NetworkMessage(box_type) = &frame.frame_data else {
+            continue;
+        };
+
+        let info = &box_type.as_ref().1.info;
+
+        // later one is what the player ends up with
+        match res.last() {
+            Some(prev) if frame.time < prev.time => continue,
+            Some(prev) if frame.time == prev.time => {
+                res.pop();
+            }
+            _ => (),
+        }
+
+        let velocity = to_vec3(&info.refparams.sim_vel);
+
+        has_velocity |= velocity != Vec3::ZERO;
+        has_ground |= info.refparams.on_ground != 0;
+
+        res.push(Tick {
+            frame: frame.frame,
+            time: frame.time,
+            origin: to_vec3(&info.refparams.sim_org),
+            velocity,
+            on_ground: info.refparams.on_ground != 0,
+            gravity: info.movevars.gravity,
+            buttons: info.usercmd.buttons,
+            side_move: info.usercmd.side_move,
+        });
+    }
+
+    if !has_velocity {
+        for i in 1..res.len() {
+            let frametime = res[i].time - res[i - 1].time;
+            res[i].velocity = (res[i].origin - res[i - 1].origin) / frametime;
+        }
+    }
+
+    if !has_ground {
+        for i in 1..res.len() {
+            res[i].on_ground = infer_ground(&res[i - 1], &res[i]);
+        }
+    }
+
+    res
+}
+
+/// Vertical velocity of the tick if only gravity was pulling since the previous one
+fn falling_velocity(prev: &Tick, tick: &Tick) -> f32 {
+    prev.velocity.z - tick.gravity * (tick.time - prev.time)
+}
+
+fn is_falling(prev: &Tick, tick: &Tick) -> bool {
+    let pull = prev.velocity.z - falling_velocity(prev, tick);
+
+    (tick.velocity.z - falling_velocity(prev, tick)).abs() < pull * 0.5
+}
+
+/// Standing still vertically, or stopped by something while going down
+fn infer_ground(prev: &Tick, tick: &Tick) -> bool {
+    if is_falling(prev, tick) {
+        return false;
+    }
+
+    let dz = tick.origin.z - prev.origin.z;
+    let is_stopped = tick.velocity.z > falling_velocity(prev, tick);
+
+    dz.abs() < GROUND_EPSILON || (!prev.on_ground && tick.velocity.z <= 0. && is_stopped)
+}
+
+/// In the air without gravity for a while before `idx`, on a ladder or in water
+fn is_off_ladder(ticks: &[Tick], idx: usize) -> bool {
+    if idx < LADDER_MIN_FRAMES {
+        return false;
+    }
+
+    (idx + 1 - LADDER_MIN_FRAMES..=idx)
+        .all(|i| !ticks[i].on_ground && !is_falling(&ticks[i - 1], &ticks[i]))
+}
+
+/// Ground ends under the feet moving from the takeoff towards the landing
+fn find_edge(bsp: &Bsp, feet: Vec3, direction: Vec2) -> Option<f32> {
+    let mut distance = 0.;
+
+    while distance <= MAX_EDGE {
+        let point = feet + direction.extend(0.) * distance;
+        let start = bsp::Vec3::from_array((point + Vec3::Z).to_array());
+        let end = bsp::Vec3::from_array((point - Vec3::Z * 2.).to_array());
+
+        let trace = bsp.trace(0, start, end);
+
+        if !trace.start_solid && trace.fraction == 1. {
+            return Some(distance);
+        }
+
+        distance += EDGE_STEP;
+    }
+
+    None
+}
+
+fn strafes(ticks: &[Tick]) -> Vec<Strafe> {
+    let mut res: Vec<Strafe> = vec![];
+    let mut sync_frames = 0;
+
+    for pair in ticks.windows(2) {
+        let (prev, tick) = (&pair[0], &pair[1]);
+
+        if let Some(direction) = tick.strafe_direction()
+            && res
+                .last()
+                .is_none_or(|strafe| strafe.direction != direction)
+        {
+            if let Some(strafe) = res.last_mut() {
+                strafe.sync = sync_frames as f32 / strafe.frames as f32 * 100.;
+            }
+
+            sync_frames = 0;
+
+            res.push(Strafe {
+                direction,
+                frames: 0,
+                sync: 0.,
+                gain: 0.,
+                loss: 0.,
+                max_speed: 0.,
+            });
+        }
+
+        // not strafing yet
+        let Some(strafe) = res.last_mut() else {
+            continue;
+        };
+
+        let delta = tick.speed() - prev.speed();
+
+        strafe.frames += 1;
+        strafe.max_speed = strafe.max_speed.max(tick.speed());
+
+        if delta > 0. {
+            strafe.gain += delta;
+            sync_frames += 1;
+        } else {
+            strafe.loss -= delta;
+        }
+    }
+
+    if let Some(strafe) = res.last_mut() {
+        strafe.sync = sync_frames as f32 / strafe.frames as f32 * 100.;
+    }
+
+    res
+}
+
+/// Jumps of the demo player with their KZ stats.
+///
+/// Edges are found with the map when it is given.
+pub fn jump_stats(demo: &Demo, bsp: Option<&Bsp>) -> Vec<JumpStats> {
+    let ticks = ticks(demo);
+    let mut res: Vec<JumpStats> = vec![];
+    // tick index where the last jump landed
+    let mut last_landing: Option<usize> = None;
+
+    let mut takeoff_idx = 0;
+
+    while takeoff_idx + 1 < ticks.len() {
+        let takeoff = &ticks[takeoff_idx];
+        let first_air = &ticks[takeoff_idx + 1];
+
+        let jump_pressed = takeoff.pressed(Buttons::Jump) || first_air.pressed(Buttons::Jump);
+        let from_ground = takeoff.on_ground && !first_air.on_ground && first_air.velocity.z > 0.;
+        let from_ladder = is_off_ladder(&ticks, takeoff_idx)
+            && !first_air.on_ground
+            && is_falling(takeoff, first_air);
+
+        if !jump_pressed || !(from_ground || from_ladder) {
+            takeoff_idx += 1;
+            continue;
+        }
+
+        // the first frame is where the jump changes the velocity
+        let mut landing_idx = takeoff_idx + 2;
+
+        while landing_idx < ticks.len()
+            && !ticks[landing_idx].on_ground
+            && is_falling(&ticks[landing_idx - 1], &ticks[landing_idx])
+        {
+            landing_idx += 1;
+        }
+
+        // grabbing a ladder, teleporting or the demo ends
+        if landing_idx == ticks.len() || !ticks[landing_idx].on_ground {
+            takeoff_idx = landing_idx;
+            continue;
+        }
+
+        let landing = &ticks[landing_idx];
+
+        let previous = last_landing
+            .filter(|&idx| from_ground && takeoff_idx + 1 - idx <= BHOP_MAX_GROUND_FRAMES)
+            .and_then(|_| res.last());
+
+        let duck_tapped = (last_landing.unwrap_or(0).max(1)..=takeoff_idx).any(|i| {
+            ticks[i].time >= takeoff.time - COUNT_JUMP_WINDOW
+                && ticks[i - 1].pressed(Buttons::Duck)
+                && !ticks[i].pressed(Buttons::Duck)
+        });
+
+        let (jump_type, chain) = match previous {
+            Some(previous) if previous.chain == 0 => (JumpType::BhopJump, 1),
+            Some(previous) => (JumpType::Bhop, previous.chain + 1),
+            None if from_ladder => (JumpType::LadderJump, 0),
+            None if duck_tapped => (JumpType::CountJump, 0),
+            None => (JumpType::LongJump, 0),
+        };
+
+        let air = &ticks[takeoff_idx..=landing_idx];
+        let strafes = strafes(&ticks[takeoff_idx..landing_idx]);
+
+        let strafe_frames: usize = strafes.iter().map(|strafe| strafe.frames).sum();
+        let sync = if strafe_frames == 0 {
+            0.
+        } else {
+            strafes
+                .iter()
+                .map(|strafe| strafe.sync * strafe.frames as f32)
+                .sum::<f32>()
+                / strafe_frames as f32
+        };
+
+        let displacement = (landing.origin - takeoff.origin).xy();
+
+        let edge =
+            bsp.and_then(|bsp| find_edge(bsp, takeoff.feet(), displacement.normalize_or_zero()));
+
+        res.push(JumpStats {
+            jump_type,
+            chain,
+            takeoff_frame: takeoff.frame,
+            landing_frame: landing.frame,
+            takeoff_time: takeoff.time,
+            takeoff: takeoff.origin.to_array(),
+            landing: landing.origin.to_array(),
+            distance: displacement.length() + PLAYER_WIDTH,
+            height_difference: landing.feet().z - takeoff.feet().z,
+            prestrafe: takeoff.speed(),
+            max_speed: air.iter().map(Tick::speed).fold(0., f32::max),
+            airtime: landing.time - takeoff.time,
+            sync,
+            strafes,
+            edge,
+        });
+
+        last_landing = Some(landing_idx);
+        // bhops take off from the landing
+        takeoff_idx = landing_idx;
+    }
+
+    res
+}
+
+pub fn jump_stats_to_json(jumps: &[JumpStats]) -> eyre::Result<String> {
+    Ok(serde_json::to_string_pretty(jumps)?)
+}
+
+/// `<folder>/<file stem>_jumps.json` next to the demo
+pub fn jump_stats_output_path(demo_path: &Path) -> PathBuf {
+    let stem = demo_path.file_stem().unwrap_or_default().to_string_lossy();
+
+    demo_path.with_file_name(format!("{stem}_jumps.json"))
+}
+
+/// Writes the jump stats of the demo next to it as JSON.
+///
+/// Returns the path of the JSON file.
+pub fn jump_stats_file(demo_path: &Path, bsp_path: Option<&Path>) -> eyre::Result<PathBuf> {
+    let demo = open_demo(demo_path)?;
+    let bsp = bsp_path.map(Bsp::from_file).transpose()?;
+
+    let jumps = jump_stats(&demo, bsp.as_ref());
+
+    let out_path = jump_stats_output_path(demo_path);
+    fs::write(&out_path, jump_stats_to_json(&jumps)?)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::ghost2dem::{TestGhostFrame, test_demo_from_frames};
+    use super::*;
+
+    const FRAMETIME: f32 = 0.01;
+    const GRAVITY: f32 = 800.;
+    // sqrt(2 * 800 * 45)
+    const JUMP_SPEED: f32 = 268.3;
+
+    #[derive(Default)]
+    struct Player {
+        origin: Vec3,
+        velocity: Vec3,
+        frames: Vec<TestGhostFrame>,
+    }
+
+    impl Player {
+        fn push(&mut self, buttons: u32) {
+            self.frames.push((self.origin.to_array(), 0., buttons));
+        }
+
+        fn run(&mut self, frame_count: usize, buttons: u32) {
+            for _ in 0..frame_count {
+                self.origin += self.velocity * FRAMETIME;
+                self.push(buttons);
+            }
+        }
+
+        /// Strafes left then right gaining 1 unit every frame until landing on the floor
+        fn jump(&mut self, speed: f32, floor: f32) {
+            self.velocity.z = speed;
+
+            for frame_idx in 0.. {
+                self.velocity.x += 1.;
+                self.velocity.z -= GRAVITY * FRAMETIME;
+                self.origin += self.velocity * FRAMETIME;
+
+                if self.origin.z <= floor {
+                    self.origin.z = floor;
+                    self.velocity.z = 0.;
+                    self.push(0);
+                    break;
+                }
+
+                let strafe = if frame_idx < 30 {
+                    Buttons::MoveLeft
+                } else {
+                    Buttons::MoveRight
+                };
+
+                let jump = if frame_idx == 0 {
+                    Buttons::Jump as u32
+                } else {
+                    0
+                };
+
+                self.push(strafe as u32 | jump);
+            }
+        }
+    }
+
+    fn player_demo(name: &str) -> Demo {
+        let mut player = Player {
+            origin: Vec3::new(0., 0., 36.),
+            velocity: Vec3::new(250., 0., 0.),
+            ..Default::default()
+        };
+
+        player.run(50, 0);
+        player.jump(JUMP_SPEED, 36.);
+        player.jump(JUMP_SPEED, 36.);
+        player.jump(JUMP_SPEED, 36.);
+
+        player.velocity = Vec3::new(250., 0., 0.);
+        player.run(50, 0);
+        player.run(5, Buttons::Duck as u32);
+        player.run(5, 0);
+        player.jump(JUMP_SPEED, 36.);
+
+        // climbing a ladder then jumping off of it
+        player.velocity = Vec3::new(0., 0., 0.);
+        player.run(20, 0);
+        player.velocity = Vec3::new(0., 0., 200.);
+        player.run(20, 0);
+        player.velocity = Vec3::new(270., 0., 0.);
+        player.jump(0., 36.);
+
+        player.velocity = Vec3::ZERO;
+        player.run(20, 0);
+
+        test_demo_from_frames(name, &player.frames)
+    }
+
+    #[test]
+    fn jump_types() {
+        let demo = player_demo("jump_types");
+        let jumps = jump_stats(&demo, None);
+
+        assert_eq!(
+            jumps
+                .iter()
+                .map(|jump| (jump.jump_type, jump.chain))
+                .collect::<Vec<_>>(),
+            vec![
+                (JumpType::LongJump, 0),
+                (JumpType::BhopJump, 1),
+                (JumpType::Bhop, 2),
+                (JumpType::CountJump, 0),
+                (JumpType::LadderJump, 0),
+            ]
+        );
+
+        let ladder_jump = &jumps[4];
+        assert!((ladder_jump.height_difference + 40.).abs() < 0.1);
+        // pushed off the ladder
+        assert!(ladder_jump.prestrafe > 260.);
+    }
+
+    #[test]
+    fn long_jump() {
+        let demo = player_demo("long_jump");
+        let jump = jump_stats(&demo, None).remove(0);
+
+        let displacement = Vec3::from(jump.landing) - Vec3::from(jump.takeoff);
+
+        assert!((jump.distance - displacement.xy().length() - 32.).abs() < 0.001);
+        assert!(jump.distance > 200.);
+        assert!(jump.height_difference.abs() < 0.001);
+        assert!((jump.prestrafe - 250.).abs() < 0.1);
+        assert!(jump.max_speed > jump.prestrafe);
+        assert!((jump.airtime - 0.67).abs() < 0.02);
+
+        assert_eq!(
+            jump.strafes
+                .iter()
+                .map(|strafe| strafe.direction)
+                .collect::<Vec<_>>(),
+            vec![StrafeDirection::Left, StrafeDirection::Right]
+        );
+        assert_eq!(jump.strafes[0].frames, 30);
+        assert!(jump.strafes.iter().all(|strafe| strafe.loss < 0.1));
+        assert!((jump.sync - 100.).abs() < 0.001);
+
+        assert!(jump.edge.is_none());
+
+        let json = jump_stats_to_json(&[jump]).unwrap();
+        assert!(json.contains(r#""jump_type": "LJ""#));
+        assert!(json.contains(r#""direction": "left""#));
+    }
+
+    #[test]
+    fn edge() {
+        let bsp = Bsp::from_file(Path::new(env!("CARGO_MANIFEST_DIR")).join("test/datacore.bsp"))
+            .unwrap();
+
+        // floor under a spawn ends 936 units along x
+        let spawn_feet = Vec3::new(-3576., 1576., 780.03125);
+
+        assert_eq!(find_edge(&bsp, spawn_feet, Vec2::X), None);
+
+        let edge = find_edge(&bsp, spawn_feet + Vec3::X * 926., Vec2::X).unwrap();
+        assert!((edge - 10.).abs() < 1.);
+    }
+}
@@ -0,0 +1,46 @@
+use dem::types::{ByteString, SvcTempEntity, TeTextMessage, TempEntity};
+
+use crate::modules::demdoc::jump_stats::JumpStats;
+
+use super::*;
+
+// about 3 seconds in 1/256 of a second
+const JUMP_STATS_HOLD_TIME: i16 = 768;
+
+pub fn add_jump_stats(jump: &JumpStats) -> SvcTempEntity {
+    let mut message = format!(
+        "{}: {:.2}\nPre: {:.2}\nMax: {:.2}\nStrafes: {}\nSync: {:.0}%\n",
+        jump.jump_type,
+        jump.distance,
+        jump.prestrafe,
+        jump.max_speed,
+        jump.strafes.len(),
+        jump.sync
+    );
+
+    if let Some(edge) = jump.edge {
+        message += &format!("Edge: {edge:.2}\n");
+    }
+
+    message.push('\0');
+
+    let text = TeTextMessage {
+        channel: 3,
+        // (0, 0) is top left
+        x: 0.05f32.coord_conversion(),
+        y: 0.4f32.coord_conversion(),
+        effect: 0,
+        text_color: [255, 255, 255, 0].to_vec(),
+        effect_color: [255, 255, 255, 0].to_vec(),
+        fade_in_time: 25,
+        fade_out_time: 76,
+        hold_time: JUMP_STATS_HOLD_TIME,
+        effect_time: None,
+        message: ByteString(message.into_bytes()),
+    };
+
+    SvcTempEntity {
+        entity_type: 29,
+        entity: TempEntity::TeTextMessage(text),
+    }
+}
@@ -1,10 +1,10 @@
-use dem::types::{SvcTempEntity, TeTextMessage, TempEntity};
+use dem::types::{ByteString, SvcTempEntity, TeTextMessage, TempEntity};
 
 use crate::modules::demdoc::Buttons;
 
 use super::*;
 
-pub fn add_keys(curr: Option<&KzInfo<'_>>) -> Option<SvcTempEntity> {
+pub fn add_keys(curr: Option<&KzInfo>) -> Option<SvcTempEntity> {
     curr?;
 
     let curr = curr.unwrap();
@@ -78,7 +78,7 @@ pub fn add_keys(curr: Option<&KzInfo<'_>>) -> Option<SvcTempEntity> {
         fade_out_time: 76,
         hold_time: 60,
         effect_time: None,
-        message: message.to_vec(),
+        message: ByteString(message.to_vec()),
     };
 
     let temp_entity = SvcTempEntity {
@@ -1,11 +1,8 @@
-use dem::types::{SvcTempEntity, TeTextMessage, TempEntity};
+use dem::types::{ByteString, SvcTempEntity, TeTextMessage, TempEntity};
 
 use super::*;
 
-pub fn add_speedometer<'a>(
-    prev: Option<&KzInfo<'a>>,
-    curr: Option<&KzInfo<'a>>,
-) -> Option<SvcTempEntity> {
+pub fn add_speedometer(prev: Option<&KzInfo>, curr: Option<&KzInfo>) -> Option<SvcTempEntity> {
     if prev.is_none() || curr.is_none() {
         return None;
     }
@@ -41,7 +38,7 @@ pub fn add_speedometer<'a>(
         fade_out_time: 76,
         hold_time: 60,
         effect_time: None,
-        message: message.to_vec(),
+        message: ByteString(message.to_vec()),
     };
 
     let temp_entity = SvcTempEntity {
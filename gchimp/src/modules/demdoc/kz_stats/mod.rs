@@ -1,18 +1,25 @@
-use dem::hldemo::{Demo, FrameData};
-use dem::types::{EngineMessage, NetMessage, SvcTime};
-use dem::{parse_netmsg, write_netmsg, Aux};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use dem::open_demo;
+use dem::types::{Demo, EngineMessage, FrameData, MessageData, NetMessage, SvcTime};
 
 use crate::wrap_message;
 
+use super::{KZ_STATS_SUFFIX, demdoc_output_path, jump_stats::jump_stats};
+
+use self::add_jump_stats::add_jump_stats;
 use self::add_keys::add_keys;
 use self::add_speedometer::add_speedometer;
 
+pub mod add_jump_stats;
 pub mod add_keys;
 pub mod add_speedometer;
 
 pub struct KzAddOns {
     keys: bool,
     speedometer: bool,
+    jump_stats: bool,
 }
 
 impl Default for KzAddOns {
@@ -26,6 +33,7 @@ impl KzAddOns {
         Self {
             keys: false,
             speedometer: false,
+            jump_stats: false,
         }
     }
 
@@ -38,10 +46,16 @@ impl KzAddOns {
         self.speedometer = true;
         self
     }
+
+    /// Shows the stats of every jump when landing
+    pub fn add_jump_stats(&mut self) -> &mut Self {
+        self.jump_stats = true;
+        self
+    }
 }
 
 #[derive(Debug)]
-pub struct KzInfo<'a> {
+pub struct KzInfo {
     // First 3 members could only be found in netmessage.
     // Frame 0 0 is netmessage.
     // Frame 1 0 is not netmessage.
@@ -55,11 +69,11 @@ pub struct KzInfo<'a> {
     _movetype: i32,
     // weapon: i32,
     _flags: u32,
-    commands: &'a [u8],
+    commands: Vec<u8>,
     frametime: f32,
 }
 
-impl<'a> KzInfo<'a> {
+impl KzInfo {
     fn new(origin: [f32; 3], viewangles: [f32; 3], frametime: f32) -> Self {
         Self {
             forward: 0.,
@@ -72,7 +86,7 @@ impl<'a> KzInfo<'a> {
             _movetype: 0,
             // weapon,
             _flags: 0,
-            commands: &[],
+            commands: vec![],
             // accumulative
             frametime,
         }
@@ -80,12 +94,21 @@ impl<'a> KzInfo<'a> {
 }
 
 pub fn add_kz_stats(demo: &mut Demo, builder: impl FnOnce(&mut KzAddOns)) {
-    let aux = Aux::new();
-
     let mut addons = KzAddOns::new();
     builder(&mut addons);
 
-    for entry in demo.directory.entries.iter_mut() {
+    let jumps = if addons.jump_stats {
+        jump_stats(demo, None)
+    } else {
+        vec![]
+    };
+
+    let mut landings = jumps
+        .iter()
+        .map(|jump| (jump.landing_frame, jump))
+        .collect::<HashMap<_, _>>();
+
+    for (entry_idx, entry) in demo.directory.entries.iter_mut().enumerate() {
         let mut curr: Option<KzInfo> = None;
         let mut prev: Option<KzInfo> = None;
         let mut should_push = false;
@@ -191,29 +214,33 @@ pub fn add_kz_stats(demo: &mut Demo, builder: impl FnOnce(&mut KzAddOns)) {
         }
 
         for frame in &mut entry.frames {
-            match &mut frame.data {
-                FrameData::NetMsg((_, netmsg)) => {
-                    let (_, mut messages) = parse_netmsg(netmsg.msg, &aux).unwrap();
-
-                    for message in &messages {
-                        if let NetMessage::EngineMessage(x) = &message {
-                            if let EngineMessage::SvcTime(SvcTime { time }) = x.as_ref() {
-                                prev = curr;
-                                curr = Some(KzInfo::new(
-                                    netmsg.info.ref_params.vieworg,
-                                    netmsg.info.ref_params.viewangles,
-                                    *time,
-                                ));
-
-                                should_push = true;
-                            }
+            match &mut frame.frame_data {
+                FrameData::NetworkMessage(box_type) => {
+                    let netmsg = &mut box_type.as_mut().1;
+
+                    let MessageData::Parsed(messages) = &mut netmsg.messages else {
+                        continue;
+                    };
+
+                    for message in messages.iter() {
+                        if let NetMessage::EngineMessage(x) = &message
+                            && let EngineMessage::SvcTime(SvcTime { time }) = x.as_ref()
+                        {
+                            prev = curr;
+                            curr = Some(KzInfo::new(
+                                to_vec3(&netmsg.info.refparams.view_origin),
+                                to_vec3(&netmsg.info.refparams.view_angles),
+                                *time,
+                            ));
+
+                            should_push = true;
                         }
                     }
 
                     if let Some(ref mut curr) = curr {
-                        curr.forward = netmsg.info.usercmd.forwardmove;
-                        curr.side = netmsg.info.usercmd.sidemove;
-                        curr.up = netmsg.info.usercmd.upmove;
+                        curr.forward = netmsg.info.usercmd.forward_move;
+                        curr.side = netmsg.info.usercmd.side_move;
+                        curr.up = netmsg.info.usercmd.up_move;
                         curr.buttons = netmsg.info.usercmd.buttons;
                         // movetype?
                         // weapon?
@@ -221,23 +248,27 @@ pub fn add_kz_stats(demo: &mut Demo, builder: impl FnOnce(&mut KzAddOns)) {
                     }
 
                     if should_push {
-                        if addons.speedometer {
-                            if let Some(temp_entity) = add_speedometer(prev.as_ref(), curr.as_ref())
-                            {
-                                messages.push(wrap_message!(SvcTempEntity, temp_entity));
-                            }
+                        if addons.speedometer
+                            && let Some(temp_entity) = add_speedometer(prev.as_ref(), curr.as_ref())
+                        {
+                            messages.push(wrap_message!(SvcTempEntity, temp_entity));
                         }
 
-                        if addons.keys {
-                            if let Some(temp_entity) = add_keys(curr.as_ref()) {
-                                messages.push(wrap_message!(SvcTempEntity, temp_entity));
-                            }
+                        if addons.keys
+                            && let Some(temp_entity) = add_keys(curr.as_ref())
+                        {
+                            messages.push(wrap_message!(SvcTempEntity, temp_entity));
                         }
                         should_push = false;
                     }
 
-                    let write = write_netmsg(messages, &aux);
-                    netmsg.msg = write.leak();
+                    // jumps are only in the playback
+                    if entry_idx > 0
+                        && let Some(jump) = landings.remove(&frame.frame)
+                    {
+                        let temp_entity = add_jump_stats(jump);
+                        messages.push(wrap_message!(SvcTempEntity, temp_entity));
+                    }
                 }
                 // FrameData::ClientData(client_data) => {
                 //     // prev = curr;
@@ -245,7 +276,7 @@ pub fn add_kz_stats(demo: &mut Demo, builder: impl FnOnce(&mut KzAddOns)) {
                 // }
                 FrameData::ConsoleCommand(command) => {
                     if let Some(ref mut curr) = curr {
-                        curr.commands = command.command;
+                        curr.commands = command.command.0.clone();
                     }
                 }
                 _ => (),
@@ -254,7 +285,28 @@ pub fn add_kz_stats(demo: &mut Demo, builder: impl FnOnce(&mut KzAddOns)) {
     }
 }
 
-trait CoordConversion {
+fn to_vec3(v: &[f32]) -> [f32; 3] {
+    [v[0], v[1], v[2]]
+}
+
+/// Adds KZ stats to the demo and writes it next to the demo.
+///
+/// Returns the path of the new demo.
+pub fn add_kz_stats_file(
+    demo_path: &Path,
+    builder: impl FnOnce(&mut KzAddOns),
+) -> eyre::Result<PathBuf> {
+    let mut demo = open_demo(demo_path)?;
+
+    add_kz_stats(&mut demo, builder);
+
+    let out_path = demdoc_output_path(demo_path, KZ_STATS_SUFFIX);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
+
+pub(super) trait CoordConversion {
     fn coord_conversion(&self) -> i16;
 }
 
@@ -1,9 +1,24 @@
+pub mod anonymize;
 pub mod change_map;
 pub mod check_doctored;
+pub mod concat;
+pub mod convert_ghost;
 pub mod ghost2dem;
+pub mod inspect;
+pub mod jump_stats;
 pub mod kz_stats;
+pub mod race;
+pub mod trim;
 mod utils;
 
+use std::path::{Path, PathBuf};
+
+use dem::types::{
+    ByteString, DemoInfo, MessageData, MoveVars, NetworkMessage, RefParams, SequenceInfo, UserCmd,
+};
+
+use crate::{err, utils::misc::find_files_with_ext_in_folder};
+
 #[macro_export]
 macro_rules! wrap_message {
     ($svc:ident, $msg:ident) => {{
@@ -36,6 +51,57 @@ pub enum Buttons {
     Score = 1 << 15,
 }
 
+// Demos written by demdoc end with the suffix of the operation before the extension.
+// Operations can be chained, trim then anonymize writes `<name>_trim_anon.dem`.
+pub const TRIM_SUFFIX: &str = "_trim";
+pub const CONCAT_SUFFIX: &str = "_concat";
+pub const ANONYMIZE_SUFFIX: &str = "_anon";
+pub const CHANGE_MAP_SUFFIX: &str = "_changemap";
+pub const KZ_STATS_SUFFIX: &str = "_kzstats";
+pub const GHOST_SUFFIX: &str = "_ghost";
+pub const RACE_SUFFIX: &str = "_race";
+
+const OUTPUT_SUFFIXES: &[&str] = &[
+    TRIM_SUFFIX,
+    CONCAT_SUFFIX,
+    ANONYMIZE_SUFFIX,
+    CHANGE_MAP_SUFFIX,
+    KZ_STATS_SUFFIX,
+    GHOST_SUFFIX,
+    RACE_SUFFIX,
+    // written before every operation had its own suffix
+    "_demdoc",
+];
+
+/// `<folder>/<file stem><suffix>.dem` next to the input
+pub fn demdoc_output_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    path.with_file_name(format!("{stem}{suffix}.dem"))
+}
+
+/// Whether the demo is written by demdoc, going by its name
+pub fn is_demdoc_output(path: &Path) -> bool {
+    path.file_stem().is_some_and(|stem| {
+        let stem = stem.to_string_lossy();
+
+        OUTPUT_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+    })
+}
+
+/// Every .dem file inside the folder, sorted by name
+pub fn demos_in_folder(folder: &Path) -> eyre::Result<Vec<PathBuf>> {
+    if !folder.is_dir() {
+        return err!("{} is not a folder", folder.display());
+    }
+
+    let mut paths = find_files_with_ext_in_folder(folder, "dem")?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+
+    Ok(paths)
+}
+
 pub enum ResourceType {
     Sound = 0,
     Skin = 1,
@@ -49,9 +115,8 @@ pub enum ResourceType {
 const VEC_0: [f32; 3] = [0., 0., 0.];
 const VIEWHEIGHT: [f32; 3] = [0.0, 0.0, 17.0];
 const VIEWPORT: [i32; 4] = [0, 0, 1024, 768];
-const SKYNAME: [u8; 32] = [0u8; 32];
 
-pub trait NetMsgDataMethods {
+pub trait NetworkMessageMethods {
     /// Creates semi-default net message data for CS 1.6
     ///
     /// Recommended to change fields after this. Or just add new method :DDD
@@ -61,59 +126,63 @@ pub trait NetMsgDataMethods {
     fn new(seq: i32) -> Self;
 }
 
-impl<'a> NetMsgDataMethods for NetMsgData<'a> {
+impl NetworkMessageMethods for NetworkMessage {
     fn new(seq: i32) -> Self {
         Self {
-            info: NetMsgInfo {
+            info: DemoInfo {
                 timestamp: 0.0,
-                ref_params: RefParams {
-                    vieworg: VEC_0,
-                    viewangles: VEC_0,
-                    forward: VEC_0,
-                    right: VEC_0,
-                    up: VEC_0,
-                    frametime: 0.,
+                refparams: RefParams {
+                    view_origin: VEC_0.to_vec(),
+                    view_angles: VEC_0.to_vec(),
+                    forward: VEC_0.to_vec(),
+                    right: VEC_0.to_vec(),
+                    up: VEC_0.to_vec(),
+                    frame_time: 0.,
                     time: 0.,
                     intermission: 0,
                     paused: 0,
                     spectator: 0,
-                    onground: 0,
-                    waterlevel: 0,
-                    simvel: VEC_0,
-                    simorg: VEC_0,
-                    viewheight: VIEWHEIGHT,
-                    idealpitch: 0.,
-                    cl_viewangles: VEC_0,
+                    on_ground: 0,
+                    water_level: 0,
+                    sim_vel: VEC_0.to_vec(),
+                    sim_org: VEC_0.to_vec(),
+                    view_height: VIEWHEIGHT.to_vec(),
+                    ideal_pitch: 0.,
+                    cl_viewangles: VEC_0.to_vec(),
                     health: 100,
-                    crosshairangle: VEC_0,
-                    viewsize: 120.,
-                    punchangle: VEC_0,
-                    maxclients: 32,
-                    viewentity: 1,
-                    playernum: 0,
+                    crosshair_angle: VEC_0.to_vec(),
+                    view_size: 120.,
+                    punch_angle: VEC_0.to_vec(),
+                    max_clients: 32,
+                    view_entity: 1,
+                    player_num: 0,
                     max_entities: 6969,
-                    demoplayback: 0,
+                    demo_playback: 0,
                     hardware: 1,
                     smoothing: 1,
                     ptr_cmd: 0,
-                    ptr_movevars: 0,
-                    viewport: VIEWPORT,
+                    ptr_move_vars: 0,
+                    view_port: VIEWPORT.to_vec(),
                     next_view: 0,
                     only_client_draw: 0,
                 },
                 usercmd: UserCmd {
                     lerp_msec: 9,
                     msec: 10,
-                    viewangles: VEC_0,
-                    forwardmove: 0.,
-                    sidemove: 0.,
-                    upmove: 0.,
-                    lightlevel: 68,
+                    unknown1: 0,
+                    view_angles: VEC_0.to_vec(),
+                    forward_move: 0.,
+                    side_move: 0.,
+                    up_move: 0.,
+                    light_level: 68,
+                    unknonwn2: 0,
                     buttons: 0,
                     impulse: 0,
-                    weaponselect: 0,
+                    weapon_select: 0,
+                    unknown3: 0,
+                    unknown4: 0,
                     impact_index: 0,
-                    impact_position: VEC_0,
+                    impact_position: VEC_0.to_vec(),
                 },
                 movevars: MoveVars {
                     gravity: 800.0,
@@ -133,28 +202,27 @@ impl<'a> NetMsgDataMethods for NetMsgData<'a> {
                     zmax: 409600.,
                     wave_height: 0.,
                     footsteps: 1,
-                    sky_name: &SKYNAME, // TODO
+                    sky_name: ByteString(vec![0u8; 32]), // TODO
                     rollangle: 0.,
                     rollspeed: 0.,
-                    skycolor_r: 0.,
-                    skycolor_g: 0.,
-                    skycolor_b: 0.,
-                    skyvec_x: 0.,
-                    skyvec_y: 0.,
-                    skyvec_z: 0.,
+                    skycolor: VEC_0.to_vec(),
+                    skyvec: VEC_0.to_vec(),
                 },
-                view: VEC_0,
+                view: VEC_0.to_vec(),
                 viewmodel: 0,
             },
             // To make sure that game doesn't crash, change it like this.
-            incoming_sequence: seq,
-            incoming_acknowledged: seq - 1,
-            incoming_reliable_acknowledged: 1,
-            incoming_reliable_sequence: 0,
-            outgoing_sequence: seq,
-            reliable_sequence: 1,
-            last_reliable_sequence: seq - 1,
-            msg: &[],
+            sequence_info: SequenceInfo {
+                incoming_sequence: seq,
+                incoming_acknowledged: seq - 1,
+                incoming_reliable_acknowledged: 1,
+                incoming_reliable_sequence: 0,
+                outgoing_sequence: seq,
+                reliable_sequence: 1,
+                last_reliable_sequence: seq - 1,
+            },
+            message_length: 0,
+            messages: MessageData::Parsed(vec![]),
         }
     }
 }
@@ -0,0 +1,523 @@
+use std::path::{Path, PathBuf};
+
+use dem::{
+    netmsg_doer::Doer,
+    types::{ByteString, Delta, Demo, SvcTempEntity, TeTextMessage, TempEntity},
+};
+
+use crate::{
+    err,
+    utils::dem_stuffs::get_ghost::{GhostFormat, GhostFrame, GhostInfo, get_ghost},
+};
+
+use super::{
+    Buttons, RACE_SUFFIX,
+    ghost2dem::{FrameExtras, empty_demo, insert_base_netmsg, insert_ghost},
+    kz_stats::CoordConversion,
+};
+
+/// Counter-Strike player models, given out in order
+const RACE_MODELS: &[&str] = &[
+    "gign", "sas", "gsg9", "urban", "arctic", "leet", "guerilla", "terror",
+];
+const RACE_COLORS: &[[u8; 3]] = &[
+    [255, 64, 64],
+    [64, 128, 255],
+    [64, 255, 64],
+    [255, 255, 64],
+    [255, 64, 255],
+    [64, 255, 255],
+    [255, 160, 32],
+    [255, 255, 255],
+];
+
+// kRenderFxGlowShell, renderamt is the thickness of the shell
+const RENDER_FX_GLOW_SHELL: i32 = 19;
+const GLOW_SHELL_THICKNESS: i32 = 8;
+
+// player.mdl sequences
+const SEQUENCE_IDLE: i32 = 1;
+const SEQUENCE_CROUCH_IDLE: i32 = 2;
+const SEQUENCE_WALK: i32 = 3;
+const SEQUENCE_RUN: i32 = 4;
+const SEQUENCE_CROUCH_RUN: i32 = 5;
+const SEQUENCE_JUMP: i32 = 6;
+
+// kz_stats uses channels 3 to 5
+const LABEL_CHANNELS: &[i8] = &[0, 1, 2];
+// labels are sent again before they fade out, hold time is in 1/256 of a second
+const LABEL_INTERVAL: f64 = 60.;
+const LABEL_HOLD_TIME: i16 = 64 * 256;
+const LABEL_LINE_HEIGHT: f32 = 0.03;
+
+pub struct RaceGhost {
+    pub ghost: GhostInfo,
+    /// Shown in the label
+    pub name: String,
+    /// Player model like `gign`
+    pub model: String,
+    /// Colour of the glow shell and the label
+    pub color: [u8; 3],
+    /// Seconds before the ghost starts, can be negative
+    pub offset: f64,
+}
+
+impl RaceGhost {
+    /// Model and colour are picked from the place of the ghost in the race
+    pub fn new(ghost: GhostInfo, index: usize) -> Self {
+        let ghost_path = Path::new(&ghost.ghost_name);
+        let name = GhostFormat::from_path(ghost_path)
+            .map(|format| format.file_stem(ghost_path))
+            .unwrap_or_else(|| ghost.ghost_name.clone());
+
+        Self {
+            ghost,
+            name,
+            model: RACE_MODELS[index % RACE_MODELS.len()].to_string(),
+            color: RACE_COLORS[index % RACE_COLORS.len()],
+            offset: 0.,
+        }
+    }
+}
+
+pub struct RaceOptions {
+    /// Framerate of the demo
+    pub fps: f64,
+    /// Names of the ghosts in the top left corner
+    pub labels: bool,
+    /// Per ghost overrides, in the same order as the ghosts
+    pub names: Vec<String>,
+    pub models: Vec<String>,
+    pub colors: Vec<[u8; 3]>,
+    pub offsets: Vec<f64>,
+}
+
+impl Default for RaceOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RaceOptions {
+    pub fn new() -> Self {
+        Self {
+            fps: 100.,
+            labels: true,
+            names: vec![],
+            models: vec![],
+            colors: vec![],
+            offsets: vec![],
+        }
+    }
+}
+
+/// Ghost frames on the race timeline, `offset` is in frames
+struct RaceTrack {
+    frames: Vec<GhostFrame>,
+    offset: i64,
+}
+
+impl RaceTrack {
+    /// Ghosts wait on their first frame and stay on their last frame
+    fn frame(&self, frame_idx: usize) -> &GhostFrame {
+        let idx = (frame_idx as i64 - self.offset).clamp(0, self.frames.len() as i64 - 1);
+
+        &self.frames[idx as usize]
+    }
+
+    fn end(&self) -> i64 {
+        self.offset + self.frames.len() as i64
+    }
+}
+
+/// Demo of the ghosts racing each other on the map.
+///
+/// The first ghost is the point of view, the others are player models.
+pub fn race_demo(
+    ghosts: &[RaceGhost],
+    map_file_name: &Path,
+    options: &RaceOptions,
+) -> eyre::Result<Demo> {
+    if ghosts.is_empty() {
+        return err!("No ghost to race");
+    }
+
+    if options.fps <= 0. {
+        return err!("Framerate must be positive");
+    }
+
+    let frametime = 1. / options.fps;
+
+    let tracks = ghosts
+        .iter()
+        .map(|race_ghost| {
+            if race_ghost.ghost.frames.is_empty() {
+                return err!("Ghost {} has no frame", race_ghost.name);
+            }
+
+            Ok(RaceTrack {
+                frames: race_ghost.ghost.resample(frametime, None)?.frames,
+                offset: (race_ghost.offset * options.fps).round() as i64,
+            })
+        })
+        .collect::<eyre::Result<Vec<RaceTrack>>>()?;
+
+    let frame_count = tracks.iter().map(RaceTrack::end).max().unwrap_or(0);
+
+    if frame_count <= 0 {
+        return err!("Every ghost ends before the race starts");
+    }
+
+    let frame_count = frame_count as usize;
+
+    // the same model is only precached once
+    let mut models: Vec<String> = vec![];
+    let model_indices = ghosts
+        .iter()
+        .map(|race_ghost| {
+            let model = format!(
+                "models/player/{}/{}.mdl",
+                race_ghost.model, race_ghost.model
+            );
+
+            models.iter().position(|m| *m == model).unwrap_or_else(|| {
+                models.push(model);
+                models.len() - 1
+            })
+        })
+        .collect::<Vec<usize>>();
+
+    let (mut demo, aux) = empty_demo(map_file_name);
+    let base = insert_base_netmsg(&mut demo, map_file_name, &aux, &models, ghosts.len() - 1)?;
+
+    let labels = race_labels(ghosts)
+        .iter()
+        .flat_map(|label| label.write(aux.clone()))
+        .collect::<Vec<u8>>();
+    let label_interval = ((LABEL_INTERVAL * options.fps) as usize).max(1);
+
+    let frame_extras = (0..frame_count)
+        .map(|frame_idx| {
+            let entities = ghosts
+                .iter()
+                .zip(&tracks)
+                .zip(&model_indices)
+                .enumerate()
+                .map(|(ghost_idx, ((race_ghost, track), model_idx))| {
+                    let is_player = ghost_idx == 0;
+                    // the player is entity 1
+                    let entity_index = if is_player {
+                        1
+                    } else {
+                        (base.extra_entity_index_start + ghost_idx - 1) as u16
+                    };
+
+                    let delta = race_entity_delta(
+                        track.frame(frame_idx.saturating_sub(1)),
+                        track.frame(frame_idx),
+                        frametime,
+                        base.extra_model_index_start + model_idx,
+                        race_ghost.color,
+                        is_player,
+                    );
+
+                    (entity_index, delta)
+                })
+                .collect();
+
+            let messages = if options.labels && frame_idx % label_interval == 0 {
+                labels.clone()
+            } else {
+                vec![]
+            };
+
+            FrameExtras { entities, messages }
+        })
+        .collect::<Vec<FrameExtras>>();
+
+    // point of view
+    let player = GhostInfo {
+        ghost_name: ghosts[0].ghost.ghost_name.clone(),
+        frames: (0..frame_count)
+            .map(|frame_idx| tracks[0].frame(frame_idx).clone())
+            .collect(),
+    };
+
+    insert_ghost(
+        &mut demo,
+        &player,
+        None,
+        None,
+        base.game_resource_index_start,
+        base.packet_entities,
+        base.delta_packet_entities,
+        &frame_extras,
+        &aux,
+    )?;
+
+    Ok(demo)
+}
+
+/// Whole body sequence from the ghost animation or from how it moves
+fn race_sequence(prev: &GhostFrame, curr: &GhostFrame, frametime: f64) -> i32 {
+    if let Some(gaitsequence) = curr.anim.as_ref().and_then(|anim| anim.gaitsequence) {
+        return gaitsequence;
+    }
+
+    let ducking = curr
+        .buttons
+        .is_some_and(|buttons| buttons & Buttons::Duck as u32 != 0);
+    let velocity = (curr.origin - prev.origin) / frametime as f32;
+    let speed = velocity.truncate().length();
+
+    match (ducking, speed) {
+        _ if velocity.z.abs() > 100. => SEQUENCE_JUMP,
+        (true, speed) if speed > 0. => SEQUENCE_CROUCH_RUN,
+        (true, _) => SEQUENCE_CROUCH_IDLE,
+        (false, speed) if speed > 150. => SEQUENCE_RUN,
+        (false, speed) if speed > 0. => SEQUENCE_WALK,
+        (false, _) => SEQUENCE_IDLE,
+    }
+}
+
+fn race_entity_delta(
+    prev: &GhostFrame,
+    curr: &GhostFrame,
+    frametime: f64,
+    modelindex: usize,
+    color: [u8; 3],
+    is_player: bool,
+) -> Delta {
+    let sequence = race_sequence(prev, curr, frametime);
+
+    let mut delta = Delta::from([
+        (
+            "origin[0]\0".to_owned(),
+            curr.origin.x.to_le_bytes().to_vec(),
+        ),
+        (
+            "origin[1]\0".to_owned(),
+            curr.origin.y.to_le_bytes().to_vec(),
+        ),
+        (
+            "origin[2]\0".to_owned(),
+            curr.origin.z.to_le_bytes().to_vec(),
+        ),
+        // models only turn around
+        (
+            "angles[1]\0".to_owned(),
+            curr.viewangles.y.to_le_bytes().to_vec(),
+        ),
+        (
+            "modelindex\0".to_owned(),
+            (modelindex as i32).to_le_bytes().to_vec(),
+        ),
+        ("sequence\0".to_owned(), sequence.to_le_bytes().to_vec()),
+        ("framerate\0".to_owned(), 1f32.to_le_bytes().to_vec()),
+        (
+            "renderfx\0".to_owned(),
+            RENDER_FX_GLOW_SHELL.to_le_bytes().to_vec(),
+        ),
+        (
+            "renderamt\0".to_owned(),
+            GLOW_SHELL_THICKNESS.to_le_bytes().to_vec(),
+        ),
+        ("rendercolor.r\0".to_owned(), vec![color[0], 0, 0, 0]),
+        ("rendercolor.g\0".to_owned(), vec![color[1], 0, 0, 0]),
+        ("rendercolor.b\0".to_owned(), vec![color[2], 0, 0, 0]),
+    ]);
+
+    // players are animated with both sequences
+    if is_player {
+        delta.insert("gaitsequence\0".to_owned(), sequence.to_le_bytes().to_vec());
+    }
+
+    delta
+}
+
+/// One label per channel in the ghost colour, the last channel has every ghost left
+fn race_labels(ghosts: &[RaceGhost]) -> Vec<SvcTempEntity> {
+    let last_channel = LABEL_CHANNELS.len() - 1;
+
+    LABEL_CHANNELS
+        .iter()
+        .enumerate()
+        .filter_map(|(line, &channel)| {
+            let to = if line == last_channel {
+                ghosts.len()
+            } else {
+                line + 1
+            };
+            let label_ghosts = ghosts.get(line..to).filter(|ghosts| !ghosts.is_empty())?;
+
+            let mut message = label_ghosts
+                .iter()
+                .map(|race_ghost| {
+                    if race_ghost.offset == 0. {
+                        race_ghost.name.clone()
+                    } else {
+                        format!("{} ({:+.2}s)", race_ghost.name, race_ghost.offset)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            message.push('\0');
+
+            let [r, g, b] = label_ghosts[0].color;
+
+            let text = TeTextMessage {
+                channel,
+                // (0, 0) is top left
+                x: 0.02f32.coord_conversion(),
+                y: (0.1 + line as f32 * LABEL_LINE_HEIGHT).coord_conversion(),
+                effect: 0,
+                text_color: [r, g, b, 0].to_vec(),
+                effect_color: [r, g, b, 0].to_vec(),
+                fade_in_time: 0,
+                fade_out_time: 76,
+                hold_time: LABEL_HOLD_TIME,
+                effect_time: None,
+                message: ByteString(message.into_bytes()),
+            };
+
+            Some(SvcTempEntity {
+                entity_type: 29,
+                entity: TempEntity::TeTextMessage(text),
+            })
+        })
+        .collect()
+}
+
+/// `<folder>/<first ghost>_race.dem` next to the first ghost
+pub fn race_output_path(ghost_path: &Path) -> PathBuf {
+    let name = GhostFormat::from_path(ghost_path)
+        .map(|format| format.file_stem(ghost_path))
+        .unwrap_or_default();
+
+    ghost_path.with_file_name(format!("{name}{RACE_SUFFIX}.dem"))
+}
+
+/// Races the ghosts on the .bsp and writes the demo next to the first ghost.
+///
+/// Returns the path of the new demo.
+pub fn race_demo_file(
+    ghost_paths: &[PathBuf],
+    bsp_path: &Path,
+    options: &RaceOptions,
+) -> eyre::Result<PathBuf> {
+    let Some(first_ghost) = ghost_paths.first() else {
+        return err!("No ghost to race");
+    };
+
+    let ghosts = ghost_paths
+        .iter()
+        .enumerate()
+        .map(|(idx, ghost_path)| {
+            let mut race_ghost = RaceGhost::new(get_ghost(&ghost_path.to_string_lossy())?, idx);
+
+            if let Some(name) = options.names.get(idx) {
+                race_ghost.name = name.clone();
+            }
+
+            if let Some(model) = options.models.get(idx) {
+                race_ghost.model = model.clone();
+            }
+
+            if let Some(&color) = options.colors.get(idx) {
+                race_ghost.color = color;
+            }
+
+            if let Some(&offset) = options.offsets.get(idx) {
+                race_ghost.offset = offset;
+            }
+
+            Ok(race_ghost)
+        })
+        .collect::<eyre::Result<Vec<RaceGhost>>>()?;
+
+    let demo = race_demo(&ghosts, bsp_path, options)?;
+
+    let out_path = race_output_path(first_ghost);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use bsp::Bsp;
+    use dem::types::{EngineMessage, FrameData, MessageData, NetMessage};
+
+    use super::super::ghost2dem::{TestGhostFrame, write_test_ghost_frames};
+    use super::*;
+
+    #[test]
+    fn race() {
+        let out_dir = std::env::temp_dir().join("gchimp_demdoc_race");
+        fs::create_dir_all(&out_dir).unwrap();
+
+        let frames = |y: f32| {
+            (0..50)
+                .map(|i| ([(i * 2) as f32, y, 64.], 90., 0))
+                .collect::<Vec<TestGhostFrame>>()
+        };
+
+        let ghost_paths = vec![
+            write_test_ghost_frames(&out_dir, "race_a", &frames(0.)),
+            write_test_ghost_frames(&out_dir, "race_b", &frames(64.)),
+        ];
+        let bsp_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/datacore.bsp");
+
+        let mut options = RaceOptions::new();
+        options.offsets = vec![0., 0.2];
+
+        let out_path = race_demo_file(&ghost_paths, &bsp_path, &options).unwrap();
+        assert_eq!(out_path, out_dir.join("race_a_race.dem"));
+
+        let demo = dem::open_demo(&out_path).unwrap();
+        // the second ghost starts 20 frames later
+        assert_eq!(demo.directory.entries[1].frames.len(), 70 * 3 + 3);
+
+        // the second ghost is the first entity after the map entities
+        let entity_index = Bsp::from_file(&bsp_path).unwrap().entities.len() + 1;
+
+        let origins = demo.directory.entries[1]
+            .frames
+            .iter()
+            .filter_map(|frame| match &frame.frame_data {
+                FrameData::NetworkMessage(box_type) => match &box_type.1.messages {
+                    MessageData::Parsed(messages) => Some(messages),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .filter_map(|messages| {
+                messages.iter().find_map(|message| match message {
+                    NetMessage::EngineMessage(engine_message) => match &**engine_message {
+                        EngineMessage::SvcDeltaPacketEntities(packet) => {
+                            packet.entity_states.iter().find_map(|state| {
+                                (state.entity_index as usize == entity_index)
+                                    .then(|| state.delta.as_ref()?.get("origin[0]\0").cloned())
+                                    .flatten()
+                            })
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+            })
+            .map(|bytes| f32::from_le_bytes(bytes[..4].try_into().unwrap()))
+            .collect::<Vec<f32>>();
+
+        assert_eq!(origins.len(), 70);
+        // waiting at the start
+        assert_eq!(origins[10], 0.);
+        // 0.1 seconds in is the end of the tenth frame
+        assert_eq!(origins[30], 18.);
+        // and it stays at its last sample
+        assert_eq!(origins[69], 96.);
+    }
+}
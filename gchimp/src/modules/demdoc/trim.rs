@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+
+use dem::{
+    open_demo,
+    types::{Demo, EngineMessage, Frame, FrameData, MessageData, NetMessage},
+};
+
+use crate::err;
+
+use super::{TRIM_SUFFIX, demdoc_output_path, utils::shift_frame};
+
+/// Part of the demo playback to keep, both ends are included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DemoRange {
+    /// Demo time in seconds
+    Time { start: f32, end: f32 },
+    /// Frame number of the demo
+    Frame { start: i32, end: i32 },
+}
+
+impl DemoRange {
+    fn is_before(&self, frame: &Frame) -> bool {
+        match *self {
+            DemoRange::Time { start, .. } => frame.time < start,
+            DemoRange::Frame { start, .. } => frame.frame < start,
+        }
+    }
+
+    fn is_after(&self, frame: &Frame) -> bool {
+        match *self {
+            DemoRange::Time { end, .. } => frame.time > end,
+            DemoRange::Frame { end, .. } => frame.frame > end,
+        }
+    }
+}
+
+// user messages that only show something for a moment
+const TRANSIENT_USER_MESSAGES: &[&str] = &[
+    "SayText",
+    "TextMsg",
+    "HudText",
+    "HudTextPro",
+    "HudTextArgs",
+    "DeathMsg",
+    "Damage",
+    "ScreenShake",
+    "ScreenFade",
+    "SendAudio",
+];
+
+/// Messages that do not change what the client knows about the game.
+///
+/// These are dropped from the skipped part so they do not all play at once.
+pub(super) fn is_transient_message(message: &NetMessage) -> bool {
+    match message {
+        NetMessage::UserMessage(user_message) => {
+            let name = String::from_utf8_lossy(&user_message.name);
+            let name = name.trim_end_matches('\0');
+
+            TRANSIENT_USER_MESSAGES.contains(&name)
+        }
+        NetMessage::EngineMessage(engine_message) => matches!(
+            engine_message.as_ref(),
+            EngineMessage::SvcSound(_)
+                | EngineMessage::SvcTempEntity(_)
+                | EngineMessage::SvcPrint(_)
+                | EngineMessage::SvcCenterPrint(_)
+                | EngineMessage::SvcEvent(_)
+                | EngineMessage::SvcEventReliable(_)
+                | EngineMessage::SvcParticle(_)
+                | EngineMessage::SvcDamage
+                | EngineMessage::SvcStopSound(_)
+                | EngineMessage::SvcSoundFade(_)
+                | EngineMessage::SvcWeaponAnim(_)
+                | EngineMessage::SvcAddAngle(_)
+                | EngineMessage::SvcVoiceData(_)
+        ),
+    }
+}
+
+/// Keeps only the range of the demo playback and moves it to the start.
+///
+/// Entity updates and other net messages before the range are kept at the start
+/// so the demo still plays. Sounds, effects, client data and commands there are removed.
+pub fn trim_demo(demo: &mut Demo, range: DemoRange) -> eyre::Result<()> {
+    if demo.directory.entries.len() < 2 {
+        return err!("Demo has no playback section");
+    }
+
+    let Some(first) = demo.directory.entries[1..]
+        .iter()
+        .flat_map(|entry| &entry.frames)
+        .find(|frame| {
+            !matches!(frame.frame_data, FrameData::DemoStart)
+                && !range.is_before(frame)
+                && !range.is_after(frame)
+        })
+    else {
+        return err!("No frame in {:?}", range);
+    };
+
+    let (start_time, start_frame) = (first.time, first.frame);
+
+    for entry in &mut demo.directory.entries[1..] {
+        let frames = std::mem::take(&mut entry.frames);
+
+        entry.frames = frames
+            .into_iter()
+            .filter_map(|mut frame| {
+                if range.is_after(&frame) {
+                    return None;
+                }
+
+                if !range.is_before(&frame) {
+                    shift_frame(&mut frame, start_time, start_frame);
+                    return Some(frame);
+                }
+
+                // skipped part plays instantly at the start
+                match &mut frame.frame_data {
+                    FrameData::DemoStart => (),
+                    FrameData::NetworkMessage(box_type) => {
+                        if let MessageData::Parsed(messages) = &mut box_type.as_mut().1.messages {
+                            messages.retain(|message| !is_transient_message(message));
+                        }
+                    }
+                    _ => return None,
+                }
+
+                frame.time = 0.;
+                frame.frame = 0;
+
+                if let FrameData::NetworkMessage(box_type) = &mut frame.frame_data {
+                    box_type.as_mut().1.info.timestamp = 0.;
+                }
+
+                Some(frame)
+            })
+            .collect();
+
+        entry.frame_count = entry.frames.len() as i32;
+    }
+
+    // sections past the range are left with nothing
+    let rest = demo.directory.entries.split_off(2);
+    demo.directory
+        .entries
+        .extend(rest.into_iter().filter(|entry| !entry.frames.is_empty()));
+
+    Ok(())
+}
+
+/// Trims the demo and writes it next to the demo.
+///
+/// Returns the path of the new demo.
+pub fn trim_demo_file(demo_path: &Path, range: DemoRange) -> eyre::Result<PathBuf> {
+    let mut demo = open_demo(demo_path)?;
+
+    trim_demo(&mut demo, range)?;
+
+    let out_path = demdoc_output_path(demo_path, TRIM_SUFFIX);
+    demo.write_to_file(&out_path)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use dem::open_demo_from_bytes;
+
+    use super::super::ghost2dem::test_demo;
+    use super::*;
+
+    fn playback_frames(demo: &Demo) -> Vec<&Frame> {
+        demo.directory.entries[1..]
+            .iter()
+            .flat_map(|entry| &entry.frames)
+            .collect()
+    }
+
+    #[test]
+    fn trim_time() {
+        let mut demo = test_demo("trim_time", 100);
+
+        trim_demo(
+            &mut demo,
+            DemoRange::Time {
+                start: 0.495,
+                end: 0.795,
+            },
+        )
+        .unwrap();
+
+        let demo = open_demo_from_bytes(&demo.write_to_bytes()).unwrap();
+        let frames = playback_frames(&demo);
+
+        // skipped net messages are still there at the start
+        let skipped = frames.iter().filter(|frame| frame.time == 0.).count();
+        assert!(skipped > 50);
+
+        let client_data = frames
+            .iter()
+            .filter(|frame| matches!(frame.frame_data, FrameData::ClientData(_)))
+            .collect::<Vec<_>>();
+
+        // 0.50 to 0.79
+        assert_eq!(client_data.len(), 30);
+        assert!(client_data[0].time.abs() < 0.001);
+        assert!((client_data[29].time - 0.29).abs() < 0.001);
+
+        let FrameData::ClientData(first) = &client_data[0].frame_data else {
+            unreachable!()
+        };
+
+        // ghost moves 2 units every frame
+        assert_eq!(first.origin[0], 100.);
+    }
+
+    #[test]
+    fn trim_frame() {
+        let mut demo = test_demo("trim_frame", 100);
+
+        trim_demo(&mut demo, DemoRange::Frame { start: 11, end: 20 }).unwrap();
+
+        let client_data = playback_frames(&demo)
+            .into_iter()
+            .filter(|frame| matches!(frame.frame_data, FrameData::ClientData(_)))
+            .count();
+
+        assert_eq!(client_data, 10);
+    }
+
+    #[test]
+    fn trim_nothing() {
+        let mut demo = test_demo("trim_nothing", 10);
+
+        assert!(
+            trim_demo(
+                &mut demo,
+                DemoRange::Time {
+                    start: 100.,
+                    end: 200.,
+                },
+            )
+            .is_err()
+        );
+    }
+}
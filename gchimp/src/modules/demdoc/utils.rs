@@ -1,3 +1,9 @@
+use dem::{
+    nbit_num,
+    prelude::BitSliceCast,
+    types::{EngineMessage, Frame, FrameData, MessageData, NetMessage},
+};
+
 #[macro_export]
 macro_rules! insert_packet_entity_state_with_index {
     ($entity_states:ident,$delta:expr,$index:expr) => {{
@@ -506,3 +512,77 @@ macro_rules! get_cs_delta_msg {
         [d1, d2, d3, d4, d5, d6, d7]
     }};
 }
+
+/// Shifts the frame and its net message timestamp back by `time`
+pub fn shift_frame(frame: &mut Frame, time: f32, frame_number: i32) {
+    frame.time = (frame.time - time).max(0.);
+    frame.frame = (frame.frame - frame_number).max(0);
+
+    if let FrameData::NetworkMessage(box_type) = &mut frame.frame_data {
+        let info = &mut box_type.as_mut().1.info;
+        info.timestamp = (info.timestamp - time).max(0.);
+    }
+}
+
+/// Text up to the null terminator
+pub fn message_name(name: &[u8]) -> String {
+    String::from_utf8_lossy(name)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// `\key\value\key\value` pairs, the null terminator is left out
+pub fn parse_user_info(user_info: &[u8]) -> Vec<(String, String)> {
+    let user_info = message_name(user_info);
+    let mut fields = user_info.split('\\').skip(1);
+    let mut res = vec![];
+
+    while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+        res.push((key.to_string(), value.to_string()));
+    }
+
+    res
+}
+
+pub fn incoming_sequence(frame: &Frame) -> Option<i32> {
+    match &frame.frame_data {
+        FrameData::NetworkMessage(box_type) => {
+            Some(box_type.as_ref().1.sequence_info.incoming_sequence)
+        }
+        _ => None,
+    }
+}
+
+/// Moves the sequence of the net message and everything delta'd against an older one
+pub fn shift_sequence(frame: &mut Frame, offset: i32) {
+    let FrameData::NetworkMessage(box_type) = &mut frame.frame_data else {
+        return;
+    };
+
+    let netmsg = &mut box_type.as_mut().1;
+    netmsg.sequence_info.incoming_sequence += offset;
+
+    let MessageData::Parsed(messages) = &mut netmsg.messages else {
+        return;
+    };
+
+    for message in messages {
+        let NetMessage::EngineMessage(engine_message) = message else {
+            continue;
+        };
+
+        match engine_message.as_mut() {
+            EngineMessage::SvcDeltaPacketEntities(delta_packet_entities) => {
+                let sequence = delta_packet_entities.delta_sequence.to_u8() as i32;
+                delta_packet_entities.delta_sequence = nbit_num!((sequence + offset) & 0xff, 8);
+            }
+            EngineMessage::SvcClientData(client_data) => {
+                if let Some(mask) = &mut client_data.delta_update_mask {
+                    let sequence = mask.to_u8() as i32;
+                    *mask = nbit_num!((sequence + offset) & 0xff, 8);
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::{fmt::Display, path::Path};
+
+use map::Map;
+use serde::Serialize;
+
+use crate::utils::{
+    brush_bsp::{BrushTree, BspBrush, PlaneSet, map_brush_contents},
+    map_stuffs::is_world_entity,
+    simple_calculs::Point3D,
+};
+
+pub struct LeakCheckOptions {
+    /// Distance between two points in the .pts file
+    pub pointfile_step: f64,
+}
+
+impl Default for LeakCheckOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LeakCheckOptions {
+    pub fn new() -> Self {
+        Self { pointfile_step: 2. }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Leak {
+    /// Index of the entity in the map
+    pub entity: usize,
+    pub classname: String,
+    pub origin: [f64; 3],
+    /// Path from the entity to the outside of the map
+    pub path: Vec<[f64; 3]>,
+}
+
+impl Display for Leak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Entity {} @ ( {} {} {} ) is leaking",
+            self.classname, self.origin[0], self.origin[1], self.origin[2]
+        )
+    }
+}
+
+impl Leak {
+    /// Line file, one line per vertex of the leak path
+    pub fn to_lin(&self) -> String {
+        self.path
+            .iter()
+            .map(|[x, y, z]| format!("{x} {y} {z}\n"))
+            .collect()
+    }
+
+    /// Point file, the leak path with points every `step` units
+    pub fn to_pts(&self, step: f64) -> String {
+        let step = step.max(0.1);
+        let mut res = String::new();
+
+        for window in self.path.windows(2) {
+            let from: Point3D = window[0].into();
+            let to: Point3D = window[1].into();
+            let length = (to - from).length();
+            let count = (length / step).ceil().max(1.) as usize;
+
+            (0..count).for_each(|idx| {
+                let point = from + (to - from) * (idx as f64 / count as f64);
+                res += &format!("{} {} {}\n", point.x, point.y, point.z);
+            });
+        }
+
+        if let Some([x, y, z]) = self.path.last() {
+            res += &format!("{x} {y} {z}\n");
+        }
+
+        res
+    }
+
+    /// Writes .pts and .lin next to the map file
+    pub fn write_pointfile(
+        &self,
+        map_path: impl AsRef<Path>,
+        options: &LeakCheckOptions,
+    ) -> eyre::Result<()> {
+        let map_path = map_path.as_ref();
+
+        std::fs::write(
+            map_path.with_extension("pts"),
+            self.to_pts(options.pointfile_step),
+        )?;
+        std::fs::write(map_path.with_extension("lin"), self.to_lin())?;
+
+        Ok(())
+    }
+}
+
+/// Builds the world hull out of world brushes, no clipping hulls.
+pub fn build_world_tree(map: &Map) -> BrushTree {
+    let mut planes = PlaneSet::new();
+
+    let brushes = map
+        .entities
+        .iter()
+        .enumerate()
+        .filter(|(_, entity)| is_world_entity(entity))
+        .filter_map(|(entity_idx, entity)| {
+            entity.brushes.as_ref().map(|brushes| (entity_idx, brushes))
+        })
+        .flat_map(|(entity_idx, brushes)| {
+            brushes
+                .iter()
+                .enumerate()
+                .map(move |(brush_idx, brush)| (entity_idx, brush_idx, brush))
+        })
+        .filter_map(|(entity_idx, brush_idx, brush)| {
+            let contents = map_brush_contents(brush)?;
+
+            // these brushes only exist in clipping hulls
+            if matches!(
+                contents,
+                bsp::LeafContent::ContentsOrigin | bsp::LeafContent::ContentsClip
+            ) {
+                return None;
+            }
+
+            BspBrush::from_map_brush(&mut planes, brush, contents, entity_idx, brush_idx, |_| {
+                None
+            })
+        })
+        .collect::<Vec<BspBrush>>();
+
+    let mut tree = BrushTree::build(planes, brushes);
+    tree.make_portals();
+
+    tree
+}
+
+/// Point entities that the map should be flooded from, as (entity index, origin)
+pub fn point_entity_origins(map: &Map) -> Vec<(usize, Point3D)> {
+    map.entities
+        .iter()
+        .enumerate()
+        .filter(|(_, entity)| entity.brushes.is_none() && !is_world_entity(entity))
+        .filter_map(|(entity_idx, entity)| {
+            entity
+                .origin()
+                .map(|origin| (entity_idx, Point3D::from(origin)))
+        })
+        .collect()
+}
+
+/// Floods the map from the void and returns every point entity that can be reached.
+pub fn leak_check(map: &Map) -> Vec<Leak> {
+    find_leaks(map, &build_world_tree(map))
+}
+
+/// Same as [`leak_check`] but with an already built tree that has portals.
+pub fn find_leaks(map: &Map, tree: &BrushTree) -> Vec<Leak> {
+    let Some(outside) = tree.outside else {
+        return vec![];
+    };
+
+    let flood = tree.flood(outside);
+
+    point_entity_origins(map)
+        .into_iter()
+        .filter_map(|(entity_idx, origin)| {
+            let leaf = tree.point_in_leaf(origin);
+
+            flood[leaf]?;
+
+            let path = std::iter::once(origin.as_array())
+                .chain(
+                    tree.flood_path(&flood, leaf)
+                        .into_iter()
+                        // portals to the outside leaf are on the padded bounding box
+                        .filter(|&portal| tree.portals[portal].on_node.is_some())
+                        .filter_map(|portal| {
+                            tree.portals[portal]
+                                .winding
+                                .centroid()
+                                .ok()
+                                .map(|point| point.as_array())
+                        }),
+                )
+                .collect();
+
+            Some(Leak {
+                entity: entity_idx,
+                classname: map.entities[entity_idx]
+                    .classname()
+                    .cloned()
+                    .unwrap_or_default(),
+                origin: origin.as_array(),
+                path,
+            })
+        })
+        .collect()
+}
+
+pub fn leaks_to_json(leaks: &[Leak]) -> eyre::Result<String> {
+    Ok(serde_json::to_string_pretty(leaks)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::map_stuffs::{hollow_box, map_with};
+
+    use super::*;
+
+    #[test]
+    fn sealed() {
+        let map = map_with(hollow_box(128., 16., Some("A")), "0 0 0");
+
+        assert!(leak_check(&map).is_empty());
+    }
+
+    #[test]
+    fn inside_solid() {
+        let map = map_with(hollow_box(128., 16., Some("A")), "0 0 -136");
+
+        assert!(leak_check(&map).is_empty());
+    }
+
+    #[test]
+    fn leaking() {
+        let map = map_with(hollow_box(128., 16., None), "0 0 0");
+        let leaks = leak_check(&map);
+
+        assert_eq!(leaks.len(), 1);
+        assert_eq!(leaks[0].entity, 1);
+        assert_eq!(leaks[0].path[0], [0., 0., 0.]);
+        // the path has to go out through the top
+        assert!(leaks[0].path.iter().any(|point| point[2] >= 128.));
+    }
+}
@@ -0,0 +1,407 @@
+//! Exports lightmaps of a compiled BSP as PNG and puts edited ones back.
+use std::path::{Path, PathBuf};
+
+use bsp::{Bsp, LightmapExtents, MAX_LIGHTMAPS};
+use image::RgbImage;
+
+use crate::err;
+
+/// Gap between faces in the atlas so filtering in image editors does not bleed
+const ATLAS_PADDING: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightmapLayout {
+    /// `face<index>_style<style>.png` for every face
+    PerFace,
+    /// `style<style>.png` with every face packed in one image
+    Atlas,
+}
+
+/// Applied in order: gamma, scale and then tint
+#[derive(Debug, Clone, Copy)]
+pub struct LightmapAdjust {
+    /// Same as RAD gamma, lower is brighter
+    pub gamma: f64,
+    pub scale: f64,
+    pub tint: [f64; 3],
+}
+
+impl Default for LightmapAdjust {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LightmapAdjust {
+    pub fn new() -> Self {
+        Self {
+            gamma: 1.,
+            scale: 1.,
+            tint: [1.; 3],
+        }
+    }
+
+    fn apply(&self, luxel: [u8; 3]) -> [u8; 3] {
+        std::array::from_fn(|channel| {
+            let value = (luxel[channel] as f64 / 255.).powf(self.gamma)
+                * 255.
+                * self.scale
+                * self.tint[channel];
+
+            value.round().clamp(0., 255.) as u8
+        })
+    }
+}
+
+pub fn adjust_lightmap(bsp: &mut Bsp, adjust: &LightmapAdjust) {
+    bsp.lightmap
+        .iter_mut()
+        .for_each(|luxel| *luxel = adjust.apply(*luxel));
+}
+
+/// Where every lightmapped face is in the atlas
+pub(crate) struct Atlas {
+    pub width: usize,
+    pub height: usize,
+    /// Face index, extents and top left corner
+    pub faces: Vec<(usize, LightmapExtents, [usize; 2])>,
+}
+
+impl Atlas {
+    /// Shelf packing tallest faces first, the same BSP always gives the same atlas
+    pub fn new(bsp: &Bsp) -> Self {
+        let mut faces = (0..bsp.faces.len())
+            .filter(|&face_idx| bsp.face_lightmap_range(face_idx, 0).is_some())
+            .filter_map(|face_idx| {
+                bsp.face_lightmap_extents(face_idx)
+                    .map(|extents| (face_idx, extents))
+            })
+            .collect::<Vec<(usize, LightmapExtents)>>();
+
+        faces.sort_by_key(|(face_idx, extents)| (std::cmp::Reverse(extents.height), *face_idx));
+
+        let area = faces
+            .iter()
+            .map(|(_, extents)| (extents.width + ATLAS_PADDING) * (extents.height + ATLAS_PADDING))
+            .sum::<usize>();
+
+        let width = faces
+            .iter()
+            .map(|(_, extents)| extents.width)
+            .max()
+            .unwrap_or(1)
+            .max((area as f64).sqrt().ceil() as usize);
+
+        let mut res = Self {
+            width,
+            height: 0,
+            faces: vec![],
+        };
+
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+        for (face_idx, extents) in faces {
+            if x + extents.width > width {
+                x = 0;
+                y += shelf_height + ATLAS_PADDING;
+                shelf_height = 0;
+            }
+
+            res.faces.push((face_idx, extents, [x, y]));
+
+            x += extents.width + ATLAS_PADDING;
+            shelf_height = shelf_height.max(extents.height);
+            res.height = res.height.max(y + extents.height);
+        }
+
+        res
+    }
+
+    /// One image per light style
+    pub fn images(&self, bsp: &Bsp) -> Vec<(u8, RgbImage)> {
+        let mut images: Vec<(u8, RgbImage)> = vec![];
+
+        for &(face_idx, extents, [x, y]) in &self.faces {
+            for (slot, style) in face_styles(bsp, face_idx) {
+                let image_idx = match images.iter().position(|(curr, _)| *curr == style) {
+                    Some(image_idx) => image_idx,
+                    None => {
+                        images.push((style, RgbImage::new(self.width as u32, self.height as u32)));
+                        images.len() - 1
+                    }
+                };
+
+                let luxels = bsp.face_lightmap(face_idx, slot).expect("slot is used");
+
+                for (luxel_idx, luxel) in luxels.iter().enumerate() {
+                    let (luxel_x, luxel_y) = (luxel_idx % extents.width, luxel_idx / extents.width);
+
+                    images[image_idx].1.put_pixel(
+                        (x + luxel_x) as u32,
+                        (y + luxel_y) as u32,
+                        image::Rgb(*luxel),
+                    );
+                }
+            }
+        }
+
+        images
+    }
+}
+
+/// Style slots of the face with their styles
+fn face_styles(bsp: &Bsp, face_idx: usize) -> impl Iterator<Item = (usize, u8)> + '_ {
+    (0..MAX_LIGHTMAPS)
+        .filter(move |&slot| bsp.face_lightmap_range(face_idx, slot).is_some())
+        .map(move |slot| (slot, bsp.faces[face_idx].styles[slot]))
+}
+
+fn luxels_to_image(luxels: &[[u8; 3]], width: usize, height: usize) -> RgbImage {
+    RgbImage::from_raw(
+        width as u32,
+        height as u32,
+        luxels.iter().flatten().copied().collect(),
+    )
+    .expect("luxel count matches dimensions")
+}
+
+/// Writes the images into the folder and returns their paths
+pub fn export_lightmaps(
+    bsp: &Bsp,
+    layout: LightmapLayout,
+    out_dir: impl AsRef<Path>,
+) -> eyre::Result<Vec<PathBuf>> {
+    let out_dir = out_dir.as_ref();
+    let mut res = vec![];
+
+    std::fs::create_dir_all(out_dir)?;
+
+    match layout {
+        LightmapLayout::PerFace => {
+            for face_idx in 0..bsp.faces.len() {
+                let Some(extents) = bsp.face_lightmap_extents(face_idx) else {
+                    continue;
+                };
+
+                for (slot, style) in face_styles(bsp, face_idx) {
+                    let luxels = bsp.face_lightmap(face_idx, slot).expect("slot is used");
+                    let path = out_dir.join(format!("face{face_idx}_style{style}.png"));
+
+                    luxels_to_image(luxels, extents.width, extents.height).save(&path)?;
+                    res.push(path);
+                }
+            }
+        }
+        LightmapLayout::Atlas => {
+            let images = Atlas::new(bsp).images(bsp);
+
+            for (style, image) in images {
+                let path = out_dir.join(format!("style{style}.png"));
+
+                image.save(&path)?;
+                res.push(path);
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Reads back the images in the folder that are there and returns how many are read.
+///
+/// The images must have the same dimensions as the exported ones.
+pub fn import_lightmaps(
+    bsp: &mut Bsp,
+    layout: LightmapLayout,
+    in_dir: impl AsRef<Path>,
+) -> eyre::Result<usize> {
+    let in_dir = in_dir.as_ref();
+
+    let open = |path: &Path, width: usize, height: usize| -> eyre::Result<Option<RgbImage>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let image = image::open(path)?.to_rgb8();
+
+        if image.dimensions() != (width as u32, height as u32) {
+            return err!(
+                "{} is {}x{} but the lightmap is {width}x{height}",
+                path.display(),
+                image.width(),
+                image.height()
+            );
+        }
+
+        Ok(Some(image))
+    };
+
+    let mut count = 0;
+
+    match layout {
+        LightmapLayout::PerFace => {
+            for face_idx in 0..bsp.faces.len() {
+                let Some(extents) = bsp.face_lightmap_extents(face_idx) else {
+                    continue;
+                };
+
+                let styles = face_styles(bsp, face_idx).collect::<Vec<(usize, u8)>>();
+
+                for (slot, style) in styles {
+                    let path = in_dir.join(format!("face{face_idx}_style{style}.png"));
+
+                    let Some(image) = open(&path, extents.width, extents.height)? else {
+                        continue;
+                    };
+
+                    bsp.face_lightmap_mut(face_idx, slot)
+                        .expect("slot is used")
+                        .iter_mut()
+                        .zip(image.pixels())
+                        .for_each(|(luxel, pixel)| *luxel = pixel.0);
+
+                    count += 1;
+                }
+            }
+        }
+        LightmapLayout::Atlas => {
+            let atlas = Atlas::new(bsp);
+            let mut images: Vec<(u8, Option<RgbImage>)> = vec![];
+
+            for &(face_idx, extents, [x, y]) in &atlas.faces {
+                let styles = face_styles(bsp, face_idx).collect::<Vec<(usize, u8)>>();
+
+                for (slot, style) in styles {
+                    let image_idx = match images.iter().position(|(curr, _)| *curr == style) {
+                        Some(image_idx) => image_idx,
+                        None => {
+                            let path = in_dir.join(format!("style{style}.png"));
+                            let image = open(&path, atlas.width, atlas.height)?;
+
+                            count += image.is_some() as usize;
+                            images.push((style, image));
+                            images.len() - 1
+                        }
+                    };
+
+                    let Some(image) = &images[image_idx].1 else {
+                        continue;
+                    };
+
+                    bsp.face_lightmap_mut(face_idx, slot)
+                        .expect("slot is used")
+                        .iter_mut()
+                        .enumerate()
+                        .for_each(|(luxel_idx, luxel)| {
+                            let (luxel_x, luxel_y) =
+                                (luxel_idx % extents.width, luxel_idx / extents.width);
+
+                            *luxel = image
+                                .get_pixel((x + luxel_x) as u32, (y + luxel_y) as u32)
+                                .0;
+                        });
+                }
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Exports the lightmaps into `<bsp name>_lightmaps` next to the BSP
+pub fn export_lightmaps_file(
+    bsp_path: impl AsRef<Path>,
+    layout: LightmapLayout,
+) -> eyre::Result<PathBuf> {
+    let bsp_path = bsp_path.as_ref();
+    let bsp = Bsp::from_file(bsp_path)?;
+    let out_dir = lightmaps_dir(bsp_path);
+
+    export_lightmaps(&bsp, layout, &out_dir)?;
+
+    Ok(out_dir)
+}
+
+/// Imports the lightmaps from `<bsp name>_lightmaps` and overwrites the BSP
+pub fn import_lightmaps_file(
+    bsp_path: impl AsRef<Path>,
+    layout: LightmapLayout,
+) -> eyre::Result<usize> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let count = import_lightmaps(&mut bsp, layout, lightmaps_dir(bsp_path))?;
+
+    if count == 0 {
+        return err!("No lightmap images found");
+    }
+
+    bsp.write_to_file(bsp_path)?;
+
+    Ok(count)
+}
+
+pub fn adjust_lightmap_file(
+    bsp_path: impl AsRef<Path>,
+    adjust: &LightmapAdjust,
+) -> eyre::Result<()> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    adjust_lightmap(&mut bsp, adjust);
+    bsp.write_to_file(bsp_path)?;
+
+    Ok(())
+}
+
+fn lightmaps_dir(bsp_path: &Path) -> PathBuf {
+    let stem = bsp_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+
+    bsp_path.with_file_name(format!("{stem}_lightmaps"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(layout: LightmapLayout, name: &str) {
+        let out_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        let original = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        export_lightmaps(&original, layout, &out_dir).unwrap();
+
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        bsp.lightmap.iter_mut().for_each(|luxel| *luxel = [0; 3]);
+
+        assert!(import_lightmaps(&mut bsp, layout, &out_dir).unwrap() > 0);
+        assert_eq!(bsp.lightmap, original.lightmap);
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn per_face() {
+        round_trip(LightmapLayout::PerFace, "gchimp_lightmap_per_face");
+    }
+
+    #[test]
+    fn atlas() {
+        round_trip(LightmapLayout::Atlas, "gchimp_lightmap_atlas");
+    }
+
+    #[test]
+    fn adjust() {
+        let adjust = LightmapAdjust {
+            scale: 0.5,
+            tint: [1., 1., 0.],
+            ..Default::default()
+        };
+
+        assert_eq!(adjust.apply([200, 100, 50]), [100, 50, 0]);
+        assert_eq!(LightmapAdjust::new().apply([1, 2, 3]), [1, 2, 3]);
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+
+use bsp::{
+    Bsp, ClipNode, Face, Leaf, LeafContent, Model, Node, Plane, PlaneType, TexInfo, Texture, Vec3,
+};
+use glam::DVec3;
+
+use crate::{
+    err,
+    utils::{
+        brush_bsp::{BrushTree, PlaneSet},
+        simple_calculs::{Point3D, newell_normal},
+    },
+};
+
+use super::face::FaceDraft;
+
+/// Vertices closer than this are welded
+const VERTEX_PRECISION: f64 = 100.;
+
+fn vec3(v: DVec3) -> Vec3 {
+    Vec3::new(v.x as f32, v.y as f32, v.z as f32)
+}
+
+fn to_i16_bounds(mins: DVec3, maxs: DVec3) -> ([i16; 3], [i16; 3]) {
+    if mins.cmpgt(maxs).any() {
+        return ([0; 3], [0; 3]);
+    }
+
+    let clamp = |e: f64| e.clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+
+    (
+        mins.floor().to_array().map(clamp),
+        maxs.ceil().to_array().map(clamp),
+    )
+}
+
+/// Index of the plane in the BSP, only the positive facing one of each pair is written
+fn bsp_plane(plane: usize) -> usize {
+    plane / 2
+}
+
+/// Collects lumps model by model
+pub struct BspBuilder {
+    bsp: Bsp,
+    vertex_lookup: HashMap<[i64; 3], u16>,
+    /// Edges used by only one face so far, (from vertex, to vertex)
+    open_edges: HashMap<(u16, u16), usize>,
+}
+
+/// Leaves written for the current model, (BSP leaf, tree leaf)
+type WrittenLeaves = Vec<(usize, usize)>;
+
+impl BspBuilder {
+    pub fn new() -> Self {
+        Self {
+            bsp: Bsp {
+                entities: vec![],
+                planes: vec![],
+                textures: vec![],
+                vertices: vec![],
+                visibility: vec![],
+                nodes: vec![],
+                texinfo: vec![],
+                faces: vec![],
+                lightmap: vec![],
+                clipnodes: vec![],
+                // leaf 0 is shared by all solid leaves
+                leaves: vec![Leaf {
+                    contents: LeafContent::ContentsSolid,
+                    vis_offset: -1,
+                    mins: [0; 3],
+                    maxs: [0; 3],
+                    first_mark_surface: 0,
+                    mark_surface_count: 0,
+                    ambient_levels: [0; 4],
+                }],
+                mark_surfaces: vec![],
+                // edge 0 cannot be referenced because it has no sign
+                edges: vec![[0, 0]],
+                surf_edges: vec![],
+                models: vec![],
+            },
+            vertex_lookup: HashMap::new(),
+            open_edges: HashMap::new(),
+        }
+    }
+
+    /// Writes nodes, leaves and faces.
+    ///
+    /// Returns (head node, first face, face count, leaf count).
+    pub fn emit_hull0(
+        &mut self,
+        tree: &BrushTree,
+        faces: &[FaceDraft],
+    ) -> eyre::Result<(i32, i32, i32, i32)> {
+        let first_face = self.bsp.faces.len();
+
+        let mut faces_by_node: HashMap<usize, Vec<&FaceDraft>> = HashMap::new();
+        faces
+            .iter()
+            .for_each(|face| faces_by_node.entry(face.node).or_default().push(face));
+
+        let mut solid = vec![false; tree.nodes.len()];
+        let mut bounds = vec![(DVec3::MAX, DVec3::MIN); tree.nodes.len()];
+        solid_and_bounds_r(tree, tree.head, &faces_by_node, &mut solid, &mut bounds);
+
+        let mut leaves: WrittenLeaves = vec![];
+        let mut leaf_faces: HashMap<usize, Vec<u16>> = HashMap::new();
+
+        let head = if tree.nodes[tree.head].is_leaf() {
+            // a model must have at least one node
+            let child = self.emit_node_r(
+                tree,
+                tree.head,
+                &faces_by_node,
+                &solid,
+                &bounds,
+                &mut leaves,
+                &mut leaf_faces,
+            )?;
+
+            let (mins, maxs) = to_i16_bounds(tree.mins, tree.maxs);
+
+            self.bsp.nodes.push(Node {
+                plane: 0,
+                children: [child, child],
+                mins,
+                maxs,
+                first_face: 0,
+                face_count: 0,
+            });
+
+            self.bsp.nodes.len() as i32 - 1
+        } else {
+            self.emit_node_r(
+                tree,
+                tree.head,
+                &faces_by_node,
+                &solid,
+                &bounds,
+                &mut leaves,
+                &mut leaf_faces,
+            )? as i32
+        };
+
+        for &(bsp_leaf, tree_leaf) in &leaves {
+            let marks = leaf_faces.remove(&tree_leaf).unwrap_or_default();
+            let leaf = &mut self.bsp.leaves[bsp_leaf];
+
+            leaf.first_mark_surface = self.bsp.mark_surfaces.len().try_into()?;
+            leaf.mark_surface_count = marks.len().try_into()?;
+
+            self.bsp.mark_surfaces.extend(marks);
+        }
+
+        if self.bsp.mark_surfaces.len() > u16::MAX as usize {
+            return err!("Too many mark surfaces");
+        }
+
+        Ok((
+            head,
+            first_face as i32,
+            (self.bsp.faces.len() - first_face) as i32,
+            leaves.len() as i32,
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn emit_node_r(
+        &mut self,
+        tree: &BrushTree,
+        node: usize,
+        faces_by_node: &HashMap<usize, Vec<&FaceDraft>>,
+        solid: &[bool],
+        bounds: &[(DVec3, DVec3)],
+        leaves: &mut WrittenLeaves,
+        leaf_faces: &mut HashMap<usize, Vec<u16>>,
+    ) -> eyre::Result<i16> {
+        if solid[node] {
+            return Ok(-1);
+        }
+
+        let (mins, maxs) = to_i16_bounds(bounds[node].0, bounds[node].1);
+        let tree_node = &tree.nodes[node];
+
+        let Some(plane) = tree_node.plane else {
+            let leaf_idx = self.bsp.leaves.len();
+
+            if leaf_idx > i16::MAX as usize {
+                return err!("Too many leaves");
+            }
+
+            self.bsp.leaves.push(Leaf {
+                contents: tree_node.contents,
+                vis_offset: -1,
+                mins,
+                maxs,
+                first_mark_surface: 0,
+                mark_surface_count: 0,
+                ambient_levels: [0; 4],
+            });
+
+            leaves.push((leaf_idx, node));
+
+            return Ok(-(leaf_idx as i16) - 1);
+        };
+
+        let node_idx = self.bsp.nodes.len();
+
+        if node_idx > i16::MAX as usize {
+            return err!("Too many nodes");
+        }
+
+        let first_face = self.bsp.faces.len();
+
+        for face in faces_by_node.get(&node).into_iter().flatten() {
+            let face_idx = self.emit_face(tree, plane, face)?;
+
+            leaf_faces.entry(face.leaf).or_default().push(face_idx);
+        }
+
+        self.bsp.nodes.push(Node {
+            plane: bsp_plane(plane) as u32,
+            children: [0; 2],
+            mins,
+            maxs,
+            first_face: first_face.try_into()?,
+            face_count: (self.bsp.faces.len() - first_face).try_into()?,
+        });
+
+        let [front, back] = tree_node.children;
+
+        let front = self.emit_node_r(
+            tree,
+            front,
+            faces_by_node,
+            solid,
+            bounds,
+            leaves,
+            leaf_faces,
+        )?;
+        let back =
+            self.emit_node_r(tree, back, faces_by_node, solid, bounds, leaves, leaf_faces)?;
+
+        self.bsp.nodes[node_idx].children = [front, back];
+
+        Ok(node_idx as i16)
+    }
+
+    fn emit_vertex(&mut self, vertex: &Point3D) -> eyre::Result<u16> {
+        let key = vertex
+            .as_array()
+            .map(|e| (e * VERTEX_PRECISION).round() as i64);
+
+        if let Some(&idx) = self.vertex_lookup.get(&key) {
+            return Ok(idx);
+        }
+
+        let idx: u16 = self
+            .bsp
+            .vertices
+            .len()
+            .try_into()
+            .map_err(|_| eyre::eyre!("Too many vertices"))?;
+
+        self.bsp.vertices.push(vec3(vertex.to_dvec3()));
+        self.vertex_lookup.insert(key, idx);
+
+        Ok(idx)
+    }
+
+    fn emit_edge(&mut self, from: u16, to: u16) -> eyre::Result<i32> {
+        // the other face already made this edge going the other way
+        if let Some(idx) = self.open_edges.remove(&(to, from)) {
+            return Ok(-(idx as i32));
+        }
+
+        let idx = self.bsp.edges.len();
+
+        self.bsp.edges.push([from, to]);
+        self.open_edges.insert((from, to), idx);
+
+        Ok(idx as i32)
+    }
+
+    fn emit_face(&mut self, tree: &BrushTree, plane: usize, face: &FaceDraft) -> eyre::Result<u16> {
+        let mut normal = tree.planes.get(plane).normal().to_dvec3();
+
+        if face.flipped {
+            normal = -normal;
+        }
+
+        let mut vertices = face.winding.vertices().clone();
+
+        // faces are clockwise when looking at the front
+        if newell_normal(&vertices).dot(normal) > 0. {
+            vertices.reverse();
+        }
+
+        let vertices = vertices
+            .iter()
+            .map(|vertex| self.emit_vertex(vertex))
+            .collect::<eyre::Result<Vec<u16>>>()?;
+
+        // welding might collapse some vertices
+        let mut vertices = vertices;
+        vertices.dedup();
+
+        while vertices.len() > 1 && vertices.first() == vertices.last() {
+            vertices.pop();
+        }
+
+        let first_edge = self.bsp.surf_edges.len();
+
+        for idx in 0..vertices.len() {
+            let edge = self.emit_edge(vertices[idx], vertices[(idx + 1) % vertices.len()])?;
+
+            self.bsp.surf_edges.push(edge);
+        }
+
+        let face_idx: u16 = self
+            .bsp
+            .faces
+            .len()
+            .try_into()
+            .map_err(|_| eyre::eyre!("Too many faces"))?;
+
+        self.bsp.faces.push(Face {
+            plane: bsp_plane(plane).try_into()?,
+            side: face.flipped as u16,
+            first_edge: first_edge as i32,
+            edge_count: vertices.len().try_into()?,
+            texinfo: face.texinfo.try_into()?,
+            styles: [0, 255, 255, 255],
+            lightmap_offset: -1,
+        });
+
+        Ok(face_idx)
+    }
+
+    /// Writes clipnodes of a hull, returns the head clipnode.
+    pub fn emit_clip_hull(&mut self, tree: &BrushTree) -> eyre::Result<i32> {
+        let mut uniform = vec![None; tree.nodes.len()];
+        clip_contents_r(tree, tree.head, &mut uniform);
+
+        if let Some(contents) = uniform[tree.head] {
+            self.bsp.clipnodes.push(ClipNode {
+                plane: 0,
+                children: [contents; 2],
+            });
+
+            return Ok(self.bsp.clipnodes.len() as i32 - 1);
+        }
+
+        Ok(self.emit_clipnode_r(tree, tree.head, &uniform)? as i32)
+    }
+
+    fn emit_clipnode_r(
+        &mut self,
+        tree: &BrushTree,
+        node: usize,
+        uniform: &[Option<i16>],
+    ) -> eyre::Result<i16> {
+        if let Some(contents) = uniform[node] {
+            return Ok(contents);
+        }
+
+        let clipnode_idx = self.bsp.clipnodes.len();
+
+        if clipnode_idx > i16::MAX as usize {
+            return err!("Too many clipnodes");
+        }
+
+        let tree_node = &tree.nodes[node];
+        let plane = tree_node.plane.expect("mixed contents node is not a leaf");
+
+        self.bsp.clipnodes.push(ClipNode {
+            plane: bsp_plane(plane) as i32,
+            children: [0; 2],
+        });
+
+        let [front, back] = tree_node.children;
+
+        let front = self.emit_clipnode_r(tree, front, uniform)?;
+        let back = self.emit_clipnode_r(tree, back, uniform)?;
+
+        self.bsp.clipnodes[clipnode_idx].children = [front, back];
+
+        Ok(clipnode_idx as i16)
+    }
+
+    pub fn add_model(
+        &mut self,
+        mins: DVec3,
+        maxs: DVec3,
+        head_nodes: [i32; 4],
+        vis_leaves: i32,
+        (first_face, face_count): (i32, i32),
+    ) {
+        self.bsp.models.push(Model {
+            mins: vec3(mins),
+            maxs: vec3(maxs),
+            origin: Vec3::ZERO,
+            head_nodes,
+            vis_leaves_count: vis_leaves,
+            first_face,
+            face_count,
+        });
+    }
+
+    pub fn emit_planes(&mut self, planes: &PlaneSet) {
+        self.bsp.planes = planes
+            .iter()
+            .step_by(2)
+            .map(|plane| {
+                let normal = plane.normal().to_dvec3();
+                let abs = normal.abs();
+
+                let type_ = if abs.x == 1. {
+                    PlaneType::X
+                } else if abs.y == 1. {
+                    PlaneType::Y
+                } else if abs.z == 1. {
+                    PlaneType::Z
+                } else if abs.x >= abs.y && abs.x >= abs.z {
+                    PlaneType::AnyX
+                } else if abs.y >= abs.z {
+                    PlaneType::AnyY
+                } else {
+                    PlaneType::AnyZ
+                };
+
+                Plane {
+                    normal: vec3(normal),
+                    distance: plane.distance() as f32,
+                    type_,
+                }
+            })
+            .collect();
+    }
+
+    pub fn finish(self, (texinfo, textures): (Vec<TexInfo>, Vec<Texture>)) -> Bsp {
+        Bsp {
+            texinfo,
+            textures,
+            ..self.bsp
+        }
+    }
+}
+
+/// Marks subtrees that are entirely solid without faces and computes node bounds from leaves
+fn solid_and_bounds_r(
+    tree: &BrushTree,
+    node: usize,
+    faces_by_node: &HashMap<usize, Vec<&FaceDraft>>,
+    solid: &mut [bool],
+    bounds: &mut [(DVec3, DVec3)],
+) {
+    let tree_node = &tree.nodes[node];
+
+    if tree_node.is_leaf() {
+        solid[node] = tree_node.contents == LeafContent::ContentsSolid;
+        bounds[node] = (tree_node.mins, tree_node.maxs);
+
+        return;
+    }
+
+    let [front, back] = tree_node.children;
+
+    solid_and_bounds_r(tree, front, faces_by_node, solid, bounds);
+    solid_and_bounds_r(tree, back, faces_by_node, solid, bounds);
+
+    solid[node] = solid[front] && solid[back] && !faces_by_node.contains_key(&node);
+    bounds[node] = (
+        bounds[front].0.min(bounds[back].0),
+        bounds[front].1.max(bounds[back].1),
+    );
+}
+
+/// Contents of the whole subtree if it is all the same
+fn clip_contents_r(tree: &BrushTree, node: usize, uniform: &mut [Option<i16>]) {
+    let tree_node = &tree.nodes[node];
+
+    if tree_node.is_leaf() {
+        uniform[node] = Some(if tree_node.contents == LeafContent::ContentsSolid {
+            LeafContent::ContentsSolid as i16
+        } else {
+            LeafContent::ContentsEmpty as i16
+        });
+
+        return;
+    }
+
+    let [front, back] = tree_node.children;
+
+    clip_contents_r(tree, front, uniform);
+    clip_contents_r(tree, back, uniform);
+
+    uniform[node] = match (uniform[front], uniform[back]) {
+        (Some(front), Some(back)) if front == back => Some(front),
+        _ => None,
+    };
+}
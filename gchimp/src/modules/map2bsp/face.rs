@@ -0,0 +1,154 @@
+use bsp::{LeafContent, TEX_SPECIAL, TexInfo};
+use glam::DVec3;
+
+use crate::utils::{
+    brush_bsp::{BrushTree, contents_priority, is_opaque},
+    simple_calculs::{Plane3D, Polygon3D},
+};
+
+/// A face before it is written into the BSP
+pub struct FaceDraft {
+    /// Tree node the face lies on
+    pub node: usize,
+    /// Face normal is opposite of the node plane
+    pub flipped: bool,
+    pub texinfo: usize,
+    pub winding: Polygon3D,
+    /// Leaf the face can be seen from
+    pub leaf: usize,
+}
+
+fn is_liquid(contents: LeafContent) -> bool {
+    matches!(
+        contents,
+        LeafContent::ContentsWater | LeafContent::ContentsSlime | LeafContent::ContentsLava
+    )
+}
+
+/// Makes faces out of portals between leaves with different contents.
+///
+/// Only the visible parts of brush sides end up as faces, so hidden faces are removed for free.
+pub fn make_faces(tree: &BrushTree, texinfo: &[TexInfo], subdivide_size: f64) -> Vec<FaceDraft> {
+    let mut res = vec![];
+
+    for portal in &tree.portals {
+        let Some(node) = portal.on_node else {
+            continue;
+        };
+
+        let plane = tree.nodes[node].plane.expect("portal node is not a leaf");
+        let [front, back] = portal.nodes;
+
+        let (front_contents, back_contents) =
+            (tree.nodes[front].contents, tree.nodes[back].contents);
+
+        if front_contents == back_contents {
+            continue;
+        }
+
+        // node plane points to the front leaf
+        let (strong, weak, strong_is_front) =
+            if contents_priority(front_contents) >= contents_priority(back_contents) {
+                (front, back, true)
+            } else {
+                (back, front, false)
+            };
+
+        let strong_contents = tree.nodes[strong].contents;
+
+        // the brush side facing from the strong leaf into the weak leaf
+        let side_plane = if strong_is_front { plane ^ 1 } else { plane };
+
+        let Some(texinfo_idx) = find_side_texinfo(tree, strong, side_plane, strong_contents) else {
+            continue;
+        };
+
+        let mut add_face = |flipped: bool, leaf: usize| {
+            let windings = if texinfo[texinfo_idx].flags & TEX_SPECIAL != 0 {
+                vec![portal.winding.clone()]
+            } else {
+                subdivide(
+                    portal.winding.clone(),
+                    &texinfo[texinfo_idx],
+                    subdivide_size,
+                )
+            };
+
+            res.extend(windings.into_iter().map(|winding| FaceDraft {
+                node,
+                flipped,
+                texinfo: texinfo_idx,
+                winding,
+                leaf,
+            }));
+        };
+
+        if !is_opaque(tree.nodes[weak].contents) {
+            add_face(strong_is_front, weak);
+        }
+
+        // liquid surface is also seen from below
+        if is_liquid(strong_contents) {
+            add_face(!strong_is_front, strong);
+        }
+    }
+
+    res
+}
+
+fn find_side_texinfo(
+    tree: &BrushTree,
+    leaf: usize,
+    side_plane: usize,
+    contents: LeafContent,
+) -> Option<usize> {
+    let brushes = &tree.nodes[leaf].brushes;
+
+    // brushes deciding the leaf contents come first
+    brushes
+        .iter()
+        .filter(|brush| brush.contents == contents)
+        .chain(brushes.iter().filter(|brush| brush.contents != contents))
+        .flat_map(|brush| brush.sides.iter())
+        .find(|side| side.plane == side_plane && side.texinfo.is_some())
+        .and_then(|side| side.texinfo)
+}
+
+/// Splits the face so it does not span more than `size` texels on either axis
+fn subdivide(winding: Polygon3D, texinfo: &TexInfo, size: f64) -> Vec<Polygon3D> {
+    for axis in [texinfo.u, texinfo.v] {
+        let axis = DVec3::new(axis.x as f64, axis.y as f64, axis.z as f64);
+        let length = axis.length();
+
+        if length == 0. {
+            continue;
+        }
+
+        let (mins, maxs) = winding
+            .vertices()
+            .iter()
+            .map(|vertex| vertex.to_dvec3().dot(axis))
+            .fold((f64::MAX, f64::MIN), |(mins, maxs), e| {
+                (mins.min(e), maxs.max(e))
+            });
+
+        if maxs - mins <= size {
+            continue;
+        }
+
+        let normal = axis / length;
+        let plane = Plane3D::new(normal.x, normal.y, normal.z, (mins + size - 16.) / length);
+        let (front, back) = winding.chop(&plane, 0.);
+
+        if front.vertices().is_empty() || back.vertices().is_empty() {
+            break;
+        }
+
+        let mut res = subdivide(back, texinfo, size);
+        res.extend(subdivide(front, texinfo, size));
+
+        return res;
+    }
+
+    vec![winding]
+}
@@ -0,0 +1,453 @@
+//! Compiles a .map into a .bsp, the same job as hlcsg and hlbsp.
+//!
+//! Visibility and lighting are left empty. Faces are not merged and T-junctions are not fixed.
+use std::path::{Path, PathBuf};
+
+use bsp::{Bsp, LeafContent};
+use glam::DVec3;
+use map::{Brush, Entity, Map};
+
+use crate::{
+    err,
+    modules::leak_check::{Leak, find_leaks, point_entity_origins},
+    utils::{
+        brush_bsp::{BrushTree, BspBrush, PlaneSet, map_brush_contents},
+        map_stuffs::{brush_to_solid3d, is_world_entity},
+        simple_calculs::{Plane3D, Point3D, Solid3D},
+    },
+};
+
+mod emit;
+mod face;
+mod texture;
+
+use emit::BspBuilder;
+use face::make_faces;
+use texture::TextureBuilder;
+
+/// Half extents of the hulls, hull 0 is a point
+const HULL_SIZES: [[f64; 3]; 4] = [
+    [0., 0., 0.],
+    [16., 16., 36.],
+    [32., 32., 32.],
+    [16., 16., 18.],
+];
+
+const BRUSH_WINDING_SIZE: f64 = 65536.;
+
+pub struct Map2BspOptions {
+    /// Embeds used textures into the BSP instead of referencing WAD files
+    pub embed_textures: bool,
+    /// WAD files searched after the ones in worldspawn "wad" key
+    pub wad_paths: Vec<PathBuf>,
+    /// Faces spanning more texels than this are subdivided
+    pub subdivide_size: f64,
+}
+
+impl Default for Map2BspOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Map2BspOptions {
+    pub fn new() -> Self {
+        Self {
+            embed_textures: false,
+            wad_paths: vec![],
+            subdivide_size: 240.,
+        }
+    }
+}
+
+pub struct Map2BspResult {
+    pub bsp: Bsp,
+    /// Outside of the world is not filled if there is any leak
+    pub leaks: Vec<Leak>,
+    pub warnings: Vec<String>,
+}
+
+/// Brushes of one model, world or brush entity
+struct ModelSource<'a> {
+    /// Index of the entity in the map, world is always 0
+    entity: usize,
+    brushes: Vec<(usize, usize, &'a Brush)>,
+    /// Center of the ORIGIN brush, subtracted from every brush
+    origin: Option<DVec3>,
+}
+
+fn brush_bounds(solid: &Solid3D) -> Option<(DVec3, DVec3)> {
+    let (mins, maxs) = solid
+        .face_polygons(BRUSH_WINDING_SIZE, 0.)
+        .iter()
+        .flat_map(|polygon| polygon.vertices().clone())
+        .fold((DVec3::MAX, DVec3::MIN), |(mins, maxs), vertex| {
+            (mins.min(vertex.to_dvec3()), maxs.max(vertex.to_dvec3()))
+        });
+
+    mins.cmple(maxs).all().then_some((mins, maxs))
+}
+
+/// Outward facing planes of the brush, moved by `offset` and expanded by the hull size.
+///
+/// Expanded brushes also get axial bevel planes so they do not stick out too far at sharp corners.
+fn hull_planes(solid: &Solid3D, offset: DVec3, hull: usize) -> Vec<Plane3D> {
+    let half = DVec3::from(HULL_SIZES[hull]);
+
+    let mut res = solid
+        .faces()
+        .iter()
+        .map(|face| {
+            // solid3d normals point inward
+            let plane = face.get_backplane().normalized();
+            let normal = plane.normal().to_dvec3();
+
+            let distance = plane.distance() - normal.dot(offset) + normal.abs().dot(half);
+
+            Plane3D::new(normal.x, normal.y, normal.z, distance)
+        })
+        .collect::<Vec<Plane3D>>();
+
+    if hull != 0
+        && let Some((mins, maxs)) = brush_bounds(solid)
+    {
+        let (mins, maxs) = (mins - offset - half, maxs - offset + half);
+
+        (0..3).for_each(|axis| {
+            let mut normal = [0.; 3];
+
+            normal[axis] = 1.;
+            res.push(Plane3D::new(normal[0], normal[1], normal[2], maxs[axis]));
+
+            normal[axis] = -1.;
+            res.push(Plane3D::new(normal[0], normal[1], normal[2], -mins[axis]));
+        });
+    }
+
+    res
+}
+
+fn hull_contents(contents: LeafContent, hull: usize) -> Option<LeafContent> {
+    match (contents, hull) {
+        (LeafContent::ContentsOrigin, _) => None,
+        (LeafContent::ContentsClip, 0) => None,
+        (contents, 0) => Some(contents),
+        // only solid matters for clipping hulls
+        (LeafContent::ContentsSolid | LeafContent::ContentsSky | LeafContent::ContentsClip, _) => {
+            Some(LeafContent::ContentsSolid)
+        }
+        _ => None,
+    }
+}
+
+fn model_sources(map: &Map) -> Vec<ModelSource<'_>> {
+    fn entity_brushes(entity_idx: usize, entity: &Entity) -> Vec<(usize, usize, &Brush)> {
+        entity
+            .brushes
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(brush_idx, brush)| (entity_idx, brush_idx, brush))
+            .collect()
+    }
+
+    let world = ModelSource {
+        entity: 0,
+        brushes: map
+            .entities
+            .iter()
+            .enumerate()
+            .filter(|(_, entity)| is_world_entity(entity))
+            .flat_map(|(entity_idx, entity)| entity_brushes(entity_idx, entity))
+            .collect(),
+        origin: None,
+    };
+
+    let brush_entities = map
+        .entities
+        .iter()
+        .enumerate()
+        .filter(|(_, entity)| {
+            !is_world_entity(entity) && entity.brushes.as_ref().is_some_and(|b| !b.is_empty())
+        })
+        .map(|(entity_idx, entity)| {
+            let brushes = entity_brushes(entity_idx, entity);
+
+            let origin = brushes
+                .iter()
+                .find(|(_, _, brush)| {
+                    map_brush_contents(brush) == Some(LeafContent::ContentsOrigin)
+                })
+                .and_then(|(_, _, brush)| brush_bounds(&brush_to_solid3d(brush)))
+                .map(|(mins, maxs)| (mins + maxs) / 2.);
+
+            ModelSource {
+                entity: entity_idx,
+                brushes,
+                origin,
+            }
+        });
+
+    std::iter::once(world).chain(brush_entities).collect()
+}
+
+/// Compiles the map.
+///
+/// Leaks are not errors, the outside of the world is just not filled.
+pub fn map2bsp(map: &Map, options: &Map2BspOptions) -> eyre::Result<Map2BspResult> {
+    if map
+        .entities
+        .first()
+        .and_then(|entity| entity.classname())
+        .is_none_or(|classname| classname != "worldspawn")
+    {
+        return err!("First entity is not worldspawn");
+    }
+
+    let mut warnings = vec![];
+
+    let mut textures = TextureBuilder::new(map, options, &mut warnings);
+    let mut builder = BspBuilder::new();
+    let mut planes = PlaneSet::new();
+    let mut leaks = vec![];
+
+    let fill_points = point_entity_origins(map)
+        .into_iter()
+        .map(|(_, origin)| origin)
+        .collect::<Vec<Point3D>>();
+
+    let sources = model_sources(map);
+
+    for (model_idx, source) in sources.iter().enumerate() {
+        let offset = source.origin.unwrap_or_default();
+        let is_world = model_idx == 0;
+        let mut head_nodes = [0i32; 4];
+        let mut model_faces = (0, 0);
+        let mut vis_leaves = 0;
+        let (mut model_mins, mut model_maxs) = (DVec3::MAX, DVec3::MIN);
+
+        for hull in 0..4 {
+            let brushes = source
+                .brushes
+                .iter()
+                .filter_map(|&(entity_idx, brush_idx, brush)| {
+                    let contents = hull_contents(map_brush_contents(brush)?, hull)?;
+                    let solid = brush_to_solid3d(brush);
+                    let sides = hull_planes(&solid, offset, hull);
+
+                    let sides = sides
+                        .into_iter()
+                        .enumerate()
+                        .map(|(plane_idx, plane)| {
+                            let texinfo = if hull == 0 {
+                                brush
+                                    .planes
+                                    .get(plane_idx)
+                                    .and_then(|brush_plane| textures.texinfo(brush_plane, offset))
+                            } else {
+                                None
+                            };
+
+                            (plane, texinfo)
+                        })
+                        // degenerate planes from illegal brushes
+                        .filter(|(plane, _)| plane.normal().to_dvec3().is_finite())
+                        .collect::<Vec<_>>();
+
+                    let res =
+                        BspBrush::from_planes(&mut planes, &sides, contents, entity_idx, brush_idx);
+
+                    if res.is_none() && hull == 0 {
+                        warnings.push(format!(
+                            "Entity {entity_idx} Brush {brush_idx} is invalid and ignored"
+                        ));
+                    }
+
+                    res
+                })
+                .collect::<Vec<BspBrush>>();
+
+            if hull == 0 {
+                brushes.iter().for_each(|brush| {
+                    model_mins = model_mins.min(brush.mins);
+                    model_maxs = model_maxs.max(brush.maxs);
+                });
+            }
+
+            let mut tree = BrushTree::build(std::mem::take(&mut planes), brushes);
+
+            if hull == 0 || is_world {
+                tree.make_portals();
+            }
+
+            if is_world {
+                if hull == 0 {
+                    leaks = find_leaks(map, &tree);
+                }
+
+                if leaks.is_empty() {
+                    tree.fill_outside(&fill_points);
+                }
+            }
+
+            if hull == 0 {
+                let faces = make_faces(&tree, textures.texinfo_list(), options.subdivide_size);
+                let (head, first_face, face_count, leaf_count) =
+                    builder.emit_hull0(&tree, &faces)?;
+
+                head_nodes[0] = head;
+                model_faces = (first_face, face_count);
+                vis_leaves = leaf_count;
+            } else {
+                head_nodes[hull] = builder.emit_clip_hull(&tree)?;
+            }
+
+            planes = tree.planes;
+        }
+
+        if model_mins.cmpgt(model_maxs).any() {
+            (model_mins, model_maxs) = (DVec3::ZERO, DVec3::ZERO);
+        }
+
+        if is_world && vis_leaves == 0 {
+            return err!("World has no visible brushes");
+        }
+
+        builder.add_model(model_mins, model_maxs, head_nodes, vis_leaves, model_faces);
+    }
+
+    if !leaks.is_empty() {
+        warnings.push(format!(
+            "Map is leaking from {} entities, outside is not filled",
+            leaks.len()
+        ));
+    }
+
+    builder.emit_planes(&planes);
+
+    let entities = map
+        .entities
+        .iter()
+        .enumerate()
+        // world entities other than worldspawn are merged
+        .filter(|(entity_idx, entity)| *entity_idx == 0 || !is_world_entity(entity))
+        .map(|(entity_idx, entity)| {
+            let mut attributes = entity.attributes.clone();
+
+            if let Some((model_idx, source)) = sources
+                .iter()
+                .enumerate()
+                .skip(1)
+                .find(|(_, source)| source.entity == entity_idx)
+            {
+                attributes.insert("model".to_string(), format!("*{model_idx}"));
+
+                if let Some(origin) = source.origin {
+                    attributes.insert(
+                        "origin".to_string(),
+                        format!("{} {} {}", origin.x, origin.y, origin.z),
+                    );
+                }
+            }
+
+            attributes.into_iter().collect()
+        })
+        .collect();
+
+    let mut bsp = builder.finish(textures.finish(&mut warnings));
+    bsp.entities = entities;
+
+    Ok(Map2BspResult {
+        bsp,
+        leaks,
+        warnings,
+    })
+}
+
+/// Compiles the map and writes the .bsp next to it.
+pub fn map2bsp_file(
+    map_path: impl AsRef<Path>,
+    options: &Map2BspOptions,
+) -> eyre::Result<Map2BspResult> {
+    let map_path = map_path.as_ref();
+    let map = Map::from_file(map_path)?;
+
+    let res = map2bsp(&map, options)?;
+
+    res.bsp.write_to_file(map_path.with_extension("bsp"))?;
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use map::Attributes;
+
+    use crate::utils::map_stuffs::{brush_from_mins_maxs, hollow_box, map_with};
+
+    use super::*;
+
+    #[test]
+    fn sealed_box() {
+        let map = map_with(hollow_box(64., 16., Some("A")), "0 0 0");
+        let res = map2bsp(&map, &Map2BspOptions::default()).unwrap();
+
+        assert!(res.leaks.is_empty());
+        assert_eq!(res.bsp.models.len(), 1);
+        // only the inside is visible
+        assert_eq!(res.bsp.faces.len(), 6);
+        assert_eq!(res.bsp.textures.len(), 1);
+        assert!(res.bsp.textures[0].is_external());
+
+        let bytes = res.bsp.write_to_bytes();
+        let bsp = Bsp::from_bytes(&bytes).unwrap();
+
+        assert_eq!(bsp.faces.len(), res.bsp.faces.len());
+        assert_eq!(bsp.leaves.len(), res.bsp.leaves.len());
+        assert_eq!(bsp.clipnodes.len(), res.bsp.clipnodes.len());
+    }
+
+    #[test]
+    fn subdivide_big_faces() {
+        let map = map_with(hollow_box(256., 16., Some("A")), "0 0 0");
+        let res = map2bsp(&map, &Map2BspOptions::default()).unwrap();
+
+        assert!(res.bsp.faces.len() > 6);
+    }
+
+    #[test]
+    fn leaking_box() {
+        let map = map_with(hollow_box(64., 16., None), "0 0 0");
+        let res = map2bsp(&map, &Map2BspOptions::default()).unwrap();
+
+        assert_eq!(res.leaks.len(), 1);
+        // outside faces are kept
+        assert!(res.bsp.faces.len() > 6);
+    }
+
+    #[test]
+    fn brush_entity() {
+        let mut map = map_with(hollow_box(128., 16., Some("A")), "0 0 0");
+
+        map.entities.push(Entity {
+            attributes: Attributes::from([("classname".to_string(), "func_wall".to_string())]),
+            brushes: Some(vec![brush_from_mins_maxs(
+                [-16.; 3].into(),
+                [16.; 3].into(),
+                "B",
+            )]),
+        });
+
+        let res = map2bsp(&map, &Map2BspOptions::default()).unwrap();
+
+        assert_eq!(res.bsp.models.len(), 2);
+        assert_eq!(res.bsp.models[1].face_count, 6);
+        assert_eq!(res.bsp.textures.len(), 2);
+        assert!(
+            res.bsp
+                .entities
+                .iter()
+                .any(|entity| entity.get("model").is_some_and(|model| model == "*1"))
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bsp::{TEX_SPECIAL, TexInfo, Vec3};
+use glam::{DVec3, Vec4Swizzles};
+use map::{BrushPlane, Map};
+use wad::types::{MipTex, TextureName, Wad};
+
+use common::constants::{CLIP_TEXTURE, CONTENTWATER_TEXTURE, ORIGIN_TEXTURE};
+
+use crate::utils::wad_stuffs::{SimpleWad, external_miptex};
+
+use super::Map2BspOptions;
+
+/// Brush sides with these textures never become faces
+const NO_FACE_TEXTURES: &[&str] = &[
+    "NULL",
+    "SKIP",
+    "HINT",
+    "BEVEL",
+    "SOLIDHINT",
+    "BEVELHINT",
+    ORIGIN_TEXTURE,
+    CLIP_TEXTURE,
+    CONTENTWATER_TEXTURE,
+];
+
+fn is_special(texture: &str) -> bool {
+    texture.starts_with("SKY") || texture.starts_with('!') || texture == "AAATRIGGER"
+}
+
+pub struct TextureBuilder {
+    wads: Vec<Wad>,
+    simple_wad: SimpleWad,
+    embed: bool,
+    /// Uppercase texture names in the order they are in the BSP
+    textures: Vec<String>,
+    texinfo: Vec<TexInfo>,
+    texinfo_lookup: HashMap<[u32; 10], usize>,
+}
+
+impl TextureBuilder {
+    pub fn new(map: &Map, options: &Map2BspOptions, warnings: &mut Vec<String>) -> Self {
+        let wad_paths = map
+            .entities
+            .first()
+            .and_then(|entity| entity.attributes.get("wad"))
+            .map(|wad| {
+                wad.split_terminator(';')
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+
+        let wads = wad_paths
+            .iter()
+            .chain(options.wad_paths.iter())
+            .filter_map(|path| match Wad::from_file(path) {
+                Ok(wad) => Some(wad),
+                Err(err) => {
+                    warnings.push(format!("Cannot open WAD {}: {err}", path.display()));
+                    None
+                }
+            })
+            .collect::<Vec<Wad>>();
+
+        // wads later in the list should not override earlier ones
+        let simple_wad = wads
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(wad_idx, wad)| {
+                wad.entries
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(entry_idx, entry)| {
+                        entry
+                            .file_entry
+                            .get_mip_tex()
+                            .map(|miptex| (entry.texture_name(), (wad_idx, entry_idx), miptex))
+                    })
+            })
+            .fold(SimpleWad::new(), |mut acc, (name, index, miptex)| {
+                acc.insert(name, index, (miptex.width, miptex.height));
+                acc
+            })
+            .uppercase();
+
+        Self {
+            wads,
+            simple_wad,
+            embed: options.embed_textures,
+            textures: vec![],
+            texinfo: vec![],
+            texinfo_lookup: HashMap::new(),
+        }
+    }
+
+    fn texture_index(&mut self, texture: &str) -> usize {
+        self.textures
+            .iter()
+            .position(|name| name == texture)
+            .unwrap_or_else(|| {
+                self.textures.push(texture.to_string());
+                self.textures.len() - 1
+            })
+    }
+
+    /// Returns the texture info index of the brush plane, moved by `offset`.
+    ///
+    /// Returns `None` for tool textures that do not make faces.
+    pub fn texinfo(&mut self, plane: &BrushPlane, offset: DVec3) -> Option<usize> {
+        let texture = plane.texture_name.get_string_standard();
+
+        if NO_FACE_TEXTURES.contains(&texture.as_str()) {
+            return None;
+        }
+
+        let texture_index = self.texture_index(&texture);
+
+        let scale = |scale: f64| if scale == 0. { 1. } else { scale };
+        let u = plane.u.xyz() / scale(plane.u_scale);
+        let v = plane.v.xyz() / scale(plane.v_scale);
+
+        let texinfo = TexInfo {
+            u: Vec3::new(u.x as f32, u.y as f32, u.z as f32),
+            u_offset: (plane.u.w + u.dot(offset)) as f32,
+            v: Vec3::new(v.x as f32, v.y as f32, v.z as f32),
+            v_offset: (plane.v.w + v.dot(offset)) as f32,
+            texture_index: texture_index as u32,
+            flags: if is_special(&texture) { TEX_SPECIAL } else { 0 },
+        };
+
+        let key = [
+            texinfo.u.x.to_bits(),
+            texinfo.u.y.to_bits(),
+            texinfo.u.z.to_bits(),
+            texinfo.u_offset.to_bits(),
+            texinfo.v.x.to_bits(),
+            texinfo.v.y.to_bits(),
+            texinfo.v.z.to_bits(),
+            texinfo.v_offset.to_bits(),
+            texinfo.texture_index,
+            texinfo.flags,
+        ];
+
+        let idx = *self.texinfo_lookup.entry(key).or_insert_with(|| {
+            self.texinfo.push(texinfo);
+            self.texinfo.len() - 1
+        });
+
+        Some(idx)
+    }
+
+    pub fn texinfo_list(&self) -> &[TexInfo] {
+        &self.texinfo
+    }
+
+    /// Returns texture infos and textures
+    pub fn finish(self, warnings: &mut Vec<String>) -> (Vec<TexInfo>, Vec<MipTex>) {
+        let textures = self
+            .textures
+            .iter()
+            .map(|texture| {
+                let Some(entry) = self.simple_wad.get(texture) else {
+                    warnings.push(format!("Cannot find texture {texture}"));
+
+                    return external_miptex(wad::utils::create_blue_miptex(16, 16, texture));
+                };
+
+                let (wad_idx, entry_idx) = entry.index();
+                let entry = &self.wads[wad_idx].entries[entry_idx];
+
+                let mut miptex = entry
+                    .file_entry
+                    .get_mip_tex()
+                    .expect("entry is a miptex")
+                    .clone();
+
+                // directory name is more reliable
+                miptex.texture_name = TextureName::from_string(entry.texture_name());
+
+                if self.embed {
+                    miptex
+                } else {
+                    external_miptex(miptex)
+                }
+            })
+            .collect();
+
+        (self.texinfo, textures)
+    }
+}
@@ -0,0 +1,567 @@
+//! Exports a studio model to glTF with its skeleton, animations and textures.
+//!
+//! Same layout as bsp2gltf, GoldSrc units under one root node that turns Z up into Y up.
+//! Every submodel of every bodypart is a separate mesh skinned to the bones.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use common::setup_studio_model_transformations::setup_studio_model_transformations;
+use glam::{Mat4, Quat, Vec3};
+use mdl::{Mdl, Model, TextureFlag, name_to_string};
+use serde_json::json;
+
+use crate::{
+    err,
+    modules::bsp2gltf::{material, texture_file_name, textures_dir, write_mdl_texture_image},
+    utils::gltf_stuffs::{GltfBuilder, GltfPrimitive},
+};
+
+/// Used when the sequence says 0 fps
+const DEFAULT_FPS: f32 = 30.;
+
+pub struct Mdl2GltfOptions {
+    /// Every sequence as an animation
+    pub animations: bool,
+    /// Multiplies every position, 0.0254 turns units into meters
+    pub scale: f32,
+}
+
+impl Default for Mdl2GltfOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mdl2GltfOptions {
+    pub fn new() -> Self {
+        Self {
+            animations: true,
+            scale: 1.,
+        }
+    }
+}
+
+/// Position and rotation of a bone
+type BoneTransform = (Vec3, Quat);
+
+/// Bones with their parents before them
+fn bone_order(mdl: &Mdl) -> Vec<usize> {
+    fn visit(bone_idx: usize, mdl: &Mdl, order: &mut Vec<usize>, visited: &mut [bool]) {
+        if visited[bone_idx] {
+            return;
+        }
+
+        visited[bone_idx] = true;
+
+        let parent = mdl.bones[bone_idx].parent;
+
+        if parent >= 0 && (parent as usize) < mdl.bones.len() {
+            visit(parent as usize, mdl, order, visited);
+        }
+
+        order.push(bone_idx);
+    }
+
+    let mut order = Vec::with_capacity(mdl.bones.len());
+    let mut visited = vec![false; mdl.bones.len()];
+
+    (0..mdl.bones.len()).for_each(|bone_idx| visit(bone_idx, mdl, &mut order, &mut visited));
+
+    order
+}
+
+fn parent_bone(mdl: &Mdl, bone_idx: usize) -> Option<usize> {
+    let parent = mdl.bones[bone_idx].parent;
+
+    (parent >= 0 && (parent as usize) < mdl.bones.len() && parent as usize != bone_idx)
+        .then_some(parent as usize)
+}
+
+/// Local transformation of the bones from their default values
+fn rest_pose(mdl: &Mdl) -> Vec<BoneTransform> {
+    mdl.bones
+        .iter()
+        .map(|bone| {
+            let [x, y, z, rx, ry, rz] = bone.value;
+            let rotation =
+                Quat::from_rotation_z(rz) * Quat::from_rotation_y(ry) * Quat::from_rotation_x(rx);
+
+            (Vec3::new(x, y, z), rotation)
+        })
+        .collect()
+}
+
+/// Model space transformation of the bones from their local ones
+fn model_pose(mdl: &Mdl, local: &[BoneTransform]) -> Vec<BoneTransform> {
+    let mut res = vec![(Vec3::ZERO, Quat::IDENTITY); mdl.bones.len()];
+
+    for bone_idx in bone_order(mdl) {
+        let (pos, rot) = local[bone_idx];
+
+        res[bone_idx] = match parent_bone(mdl, bone_idx) {
+            Some(parent) => {
+                let (parent_pos, parent_rot) = res[parent];
+
+                (parent_pos + parent_rot * pos, parent_rot * rot)
+            }
+            None => (pos, rot),
+        };
+    }
+
+    res
+}
+
+/// Local transformation of the bones from their model space ones
+fn local_pose(mdl: &Mdl, model: &[BoneTransform]) -> Vec<BoneTransform> {
+    (0..mdl.bones.len())
+        .map(|bone_idx| {
+            let (pos, rot) = model[bone_idx];
+
+            match parent_bone(mdl, bone_idx) {
+                Some(parent) => {
+                    let (parent_pos, parent_rot) = model[parent];
+                    let inverse = parent_rot.inverse();
+
+                    (inverse * (pos - parent_pos), (inverse * rot).normalize())
+                }
+                None => (pos, rot),
+            }
+        })
+        .collect()
+}
+
+/// Triangles of the submodel in the rest pose, one primitive per texture
+fn submodel_primitives(
+    model: &Model,
+    textures: &[mdl::Texture],
+    pose: &[BoneTransform],
+    materials: &HashMap<String, usize>,
+) -> Vec<GltfPrimitive> {
+    let mut model = model.clone();
+
+    if model.agnostic_mesh.is_none() {
+        model.build_agnostic_data(textures);
+    }
+
+    let mut res: Vec<(String, GltfPrimitive)> = vec![];
+    // same vertex in the same primitive is shared
+    let mut vertex_lookup: HashMap<(usize, [u64; 8], i32), u32> = HashMap::new();
+
+    for triangle in model.agnostic_mesh.iter().flatten() {
+        let primitive_idx = match res.iter().position(|(curr, _)| *curr == triangle.material) {
+            Some(primitive_idx) => primitive_idx,
+            None => {
+                res.push((
+                    triangle.material.clone(),
+                    GltfPrimitive {
+                        material: materials.get(&triangle.material).copied(),
+                        ..Default::default()
+                    },
+                ));
+                res.len() - 1
+            }
+        };
+
+        let primitive = &mut res[primitive_idx].1;
+
+        let indices = triangle
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let key = (
+                    primitive_idx,
+                    [
+                        vertex.pos.x.to_bits(),
+                        vertex.pos.y.to_bits(),
+                        vertex.pos.z.to_bits(),
+                        vertex.norm.x.to_bits(),
+                        vertex.norm.y.to_bits(),
+                        vertex.norm.z.to_bits(),
+                        vertex.uv.x.to_bits(),
+                        vertex.uv.y.to_bits(),
+                    ],
+                    vertex.parent,
+                );
+
+                *vertex_lookup.entry(key).or_insert_with(|| {
+                    let bone_idx = vertex.parent.max(0) as usize;
+                    let (bone_pos, bone_rot) = pose
+                        .get(bone_idx)
+                        .copied()
+                        .unwrap_or((Vec3::ZERO, Quat::IDENTITY));
+
+                    let position = bone_pos + bone_rot * vertex.pos.as_vec3();
+                    let normal = (bone_rot * vertex.norm.as_vec3()).normalize_or_zero();
+
+                    primitive.push_skinned_vertex(
+                        position.to_array(),
+                        normal.to_array(),
+                        &[[vertex.uv.x as f32, vertex.uv.y as f32]],
+                        bone_idx as u16,
+                    )
+                })
+            })
+            .collect::<Vec<u32>>();
+
+        // studio models wind clockwise like the BSP
+        primitive
+            .indices
+            .extend([indices[0], indices[2], indices[1]]);
+    }
+
+    res.into_iter().map(|(_, primitive)| primitive).collect()
+}
+
+/// Writes `<out>.gltf`, `<out>.bin` and the images into `<out>_textures`.
+///
+/// Animations only use the first blend of the sequences.
+pub fn write_mdl_gltf(
+    mdl: &Mdl,
+    options: &Mdl2GltfOptions,
+    out_path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let out_path = out_path.as_ref();
+
+    if mdl.textures.is_empty() {
+        return err!("Model textures are stored in an external file");
+    }
+
+    if mdl.bones.is_empty() {
+        return err!("Model has no bones");
+    }
+
+    let (textures_dir, textures_dir_name) = textures_dir(out_path);
+
+    std::fs::create_dir_all(&textures_dir)?;
+
+    let model_name = out_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("model");
+
+    let mut gltf = GltfBuilder::new();
+
+    let mut materials: HashMap<String, usize> = HashMap::new();
+
+    for texture in &mdl.textures {
+        let name = texture.name();
+        let file_name = texture_file_name(&format!("{model_name}_{name}"));
+
+        write_mdl_texture_image(texture, &textures_dir.join(&file_name))?;
+
+        let image = gltf.add_texture(&format!("{textures_dir_name}/{file_name}"));
+        let material = gltf.add_material(material(
+            &name,
+            Some(image),
+            texture.header.flags.contains(TextureFlag::MASKED),
+        ));
+
+        materials.insert(name, material);
+    }
+
+    // Z up to Y up
+    let root = gltf.add_node(
+        json!({
+            "name": model_name,
+            "rotation": [-std::f32::consts::FRAC_1_SQRT_2, 0., 0., std::f32::consts::FRAC_1_SQRT_2],
+            "scale": [options.scale, options.scale, options.scale],
+        }),
+        None,
+    );
+
+    // joints
+    let rest = rest_pose(mdl);
+    let bind = model_pose(mdl, &rest);
+    let mut joints = vec![0; mdl.bones.len()];
+
+    for bone_idx in bone_order(mdl) {
+        let (translation, rotation) = rest[bone_idx];
+        let parent = parent_bone(mdl, bone_idx).map_or(root, |parent| joints[parent]);
+
+        joints[bone_idx] = gltf.add_node(
+            json!({
+                "name": name_to_string(&mdl.bones[bone_idx].name),
+                "translation": translation.to_array(),
+                "rotation": rotation.to_array(),
+            }),
+            Some(parent),
+        );
+    }
+
+    let inverse_bind_matrices = bind
+        .iter()
+        .map(|&(pos, rot)| {
+            Mat4::from_rotation_translation(rot, pos)
+                .inverse()
+                .to_cols_array()
+        })
+        .collect::<Vec<[f32; 16]>>();
+    let inverse_bind_matrices = gltf.add_matrices(&inverse_bind_matrices);
+
+    let skin = gltf.add_skin(json!({
+        "name": model_name,
+        "skeleton": root,
+        "joints": joints,
+        "inverseBindMatrices": inverse_bind_matrices,
+    }));
+
+    // bodygroups
+    for (bodypart_idx, bodypart) in mdl.bodyparts.iter().enumerate() {
+        let bodypart_name = name_to_string(&bodypart.header.name);
+
+        for (submodel_idx, model) in bodypart.models.iter().enumerate() {
+            let primitives = submodel_primitives(model, &mdl.textures, &bind, &materials);
+
+            // blank submodels
+            if primitives
+                .iter()
+                .all(|primitive| primitive.indices.is_empty())
+            {
+                continue;
+            }
+
+            let name = name_to_string(&model.header.name);
+            let mesh = gltf.add_mesh(&name, &primitives);
+
+            // skinned meshes ignore their own transformation so they stay out of the root
+            gltf.add_node(
+                json!({
+                    "name": name,
+                    "mesh": mesh,
+                    "skin": skin,
+                    "extras": {
+                        "bodypart": bodypart_name,
+                        "bodypart_index": bodypart_idx,
+                        "submodel_index": submodel_idx,
+                    },
+                }),
+                None,
+            );
+        }
+    }
+
+    if options.animations {
+        let transformations = setup_studio_model_transformations(mdl);
+
+        for (sequence, blends) in mdl.sequences.iter().zip(&transformations) {
+            let Some(frames) = blends.first().filter(|frames| !frames.is_empty()) else {
+                continue;
+            };
+
+            let fps = if sequence.header.fps > 0. {
+                sequence.header.fps
+            } else {
+                DEFAULT_FPS
+            };
+
+            let times = (0..frames.len())
+                .map(|frame_idx| frame_idx as f32 / fps)
+                .collect::<Vec<f32>>();
+            let input = gltf.add_scalars(&times);
+
+            let locals = frames
+                .iter()
+                .map(|frame| {
+                    let model = frame
+                        .iter()
+                        .map(|(pos, rot)| {
+                            (
+                                Vec3::new(pos.x, pos.y, pos.z),
+                                Quat::from_xyzw(rot.v.x, rot.v.y, rot.v.z, rot.s),
+                            )
+                        })
+                        .collect::<Vec<BoneTransform>>();
+
+                    local_pose(mdl, &model)
+                })
+                .collect::<Vec<Vec<BoneTransform>>>();
+
+            let mut samplers = vec![];
+            let mut channels = vec![];
+
+            for (bone_idx, &joint) in joints.iter().enumerate() {
+                let translations = locals
+                    .iter()
+                    .map(|frame| frame[bone_idx].0.to_array())
+                    .collect::<Vec<[f32; 3]>>();
+
+                // keeps neighbouring keyframes on the same side so interpolation takes the short way
+                let mut previous = Quat::IDENTITY;
+                let rotations = locals
+                    .iter()
+                    .map(|frame| {
+                        let rotation = frame[bone_idx].1;
+                        let rotation = if previous.dot(rotation) < 0. {
+                            -rotation
+                        } else {
+                            rotation
+                        };
+
+                        previous = rotation;
+                        rotation.to_array()
+                    })
+                    .collect::<Vec<[f32; 4]>>();
+
+                for (path, output) in [
+                    ("translation", gltf.add_animation_vectors(&translations)),
+                    ("rotation", gltf.add_animation_vectors(&rotations)),
+                ] {
+                    samplers.push(json!({
+                        "input": input,
+                        "output": output,
+                        "interpolation": "LINEAR",
+                    }));
+                    channels.push(json!({
+                        "sampler": samplers.len() - 1,
+                        "target": { "node": joint, "path": path },
+                    }));
+                }
+            }
+
+            gltf.add_animation(json!({
+                "name": name_to_string(&sequence.header.label),
+                "samplers": samplers,
+                "channels": channels,
+            }));
+        }
+    }
+
+    gltf.write_to_file(out_path)
+}
+
+/// Exports the model next to it as `.gltf`.
+///
+/// Textures in a separate `<name>T.mdl` are picked up.
+pub fn mdl2gltf(mdl_path: impl AsRef<Path>, options: &Mdl2GltfOptions) -> eyre::Result<PathBuf> {
+    let mdl_path = mdl_path.as_ref();
+    let mut mdl = Mdl::open_from_file(mdl_path)?;
+
+    if mdl.textures.is_empty() {
+        let stem = mdl_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let texture_mdl_path = mdl_path.with_file_name(format!("{stem}T.mdl"));
+
+        if texture_mdl_path.exists() {
+            let texture_mdl = Mdl::open_from_file(&texture_mdl_path)?;
+
+            mdl.textures = texture_mdl.textures;
+            mdl.skin_families = texture_mdl.skin_families;
+        }
+    }
+
+    let out_path = mdl_path.with_extension("gltf");
+
+    write_mdl_gltf(&mdl, options, &out_path)?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn close(a: BoneTransform, b: BoneTransform) -> bool {
+        a.0.abs_diff_eq(b.0, 0.001) && a.1.dot(b.1).abs() > 0.9999
+    }
+
+    #[test]
+    fn local_model_pose() {
+        let mdl = Mdl::open_from_bytes(include_bytes!("../../../mdl/src/tests/chick.mdl")).unwrap();
+        let transformations = setup_studio_model_transformations(&mdl);
+
+        let frame = transformations[0][0][0]
+            .iter()
+            .map(|(pos, rot)| {
+                (
+                    Vec3::new(pos.x, pos.y, pos.z),
+                    Quat::from_xyzw(rot.v.x, rot.v.y, rot.v.z, rot.s),
+                )
+            })
+            .collect::<Vec<BoneTransform>>();
+
+        let round_trip = model_pose(&mdl, &local_pose(&mdl, &frame));
+
+        assert!(frame.iter().zip(&round_trip).all(|(a, b)| close(*a, *b)));
+    }
+
+    #[test]
+    fn counter_clockwise() {
+        let mdl = Mdl::open_from_bytes(include_bytes!("../../../mdl/src/tests/chick.mdl")).unwrap();
+        let bind = model_pose(&mdl, &rest_pose(&mdl));
+
+        let (mut front, mut back) = (0, 0);
+
+        for primitive in submodel_primitives(
+            &mdl.bodyparts[0].models[0],
+            &mdl.textures,
+            &bind,
+            &HashMap::new(),
+        ) {
+            assert_eq!(primitive.joints.len(), primitive.positions.len());
+
+            for triangle in primitive.indices.chunks_exact(3) {
+                let [a, b, c] =
+                    [0, 1, 2].map(|i| Vec3::from_array(primitive.positions[triangle[i] as usize]));
+                let normal = [0, 1, 2]
+                    .map(|i| Vec3::from_array(primitive.normals[triangle[i] as usize]))
+                    .into_iter()
+                    .sum::<Vec3>();
+
+                if (b - a).cross(c - a).dot(normal) > 0. {
+                    front += 1;
+                } else {
+                    back += 1;
+                }
+            }
+        }
+
+        assert!(front > back * 10, "{front} {back}");
+    }
+
+    #[test]
+    fn write() {
+        let out_dir = std::env::temp_dir().join("gchimp_mdl2gltf");
+        let _ = std::fs::remove_dir_all(&out_dir);
+        std::fs::create_dir_all(&out_dir).unwrap();
+
+        let out_path = out_dir.join("chick.gltf");
+        let mdl = Mdl::open_from_bytes(include_bytes!("../../../mdl/src/tests/chick.mdl")).unwrap();
+
+        write_mdl_gltf(&mdl, &Mdl2GltfOptions::new(), &out_path).unwrap();
+
+        let gltf: Value =
+            serde_json::from_str(&std::fs::read_to_string(&out_path).unwrap()).unwrap();
+        let bin_len = std::fs::metadata(out_path.with_extension("bin"))
+            .unwrap()
+            .len();
+
+        assert_eq!(gltf["buffers"][0]["byteLength"], bin_len);
+        assert_eq!(
+            gltf["skins"][0]["joints"].as_array().unwrap().len(),
+            mdl.bones.len()
+        );
+        assert_eq!(
+            gltf["animations"].as_array().unwrap().len(),
+            mdl.sequences
+                .iter()
+                .filter(|sequence| sequence.header.num_frames > 0)
+                .count()
+        );
+        assert!(
+            out_dir
+                .join(format!(
+                    "chick_textures/{}",
+                    texture_file_name(&format!("chick_{}", mdl.textures[0].name()))
+                ))
+                .exists()
+        );
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
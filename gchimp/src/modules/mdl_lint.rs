@@ -0,0 +1,740 @@
+//! Checks an MDL against the limits of the engine and what usually breaks it.
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use common::constants::{
+    MAX_GOLDSRC_MODEL_TEXTURE_COUNT, MAX_GOLDSRC_TEXTURE_SIZE, MAX_SMD_VERTEX,
+};
+use mdl::{Mdl, TextureFlag, name_to_string};
+use serde::Serialize;
+
+use crate::utils::misc::find_files_recursively;
+
+/// "IDST"
+const MDL_ID: i32 = i32::from_le_bytes(*b"IDST");
+const MDL_VERSION: i32 = 10;
+const MAX_STUDIO_BONES: usize = 128;
+const MAX_STUDIO_CONTROLLERS: usize = 8;
+const MAX_STUDIO_SKIN_FAMILIES: usize = 100;
+/// 0 to 3 are normal controllers, 4 is the mouth
+const MAX_CONTROLLER_INDEX: i32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    /// The engine or the compiler refuses it, or it crashes
+    Error,
+    /// Loads but probably does not look right
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LintIssue {
+    BadHeader {
+        id: i32,
+        version: i32,
+    },
+    /// Length in the header is not the size of the file
+    LengthMismatch {
+        header: i32,
+        file: usize,
+    },
+    OverLimit {
+        name: String,
+        count: usize,
+        max: usize,
+    },
+    BadTextureSize {
+        texture: String,
+        width: i32,
+        height: i32,
+    },
+    /// Pixels are not width times height
+    BadTextureData {
+        texture: String,
+        expected: usize,
+        found: usize,
+    },
+    BadBoneParent {
+        bone: String,
+        parent: i32,
+    },
+    BadBoneController {
+        bone: String,
+        controller: i32,
+    },
+    BadControllerBone {
+        controller: usize,
+        bone: i32,
+    },
+    BadControllerIndex {
+        controller: usize,
+        index: i32,
+    },
+    /// Every family needs one texture for every skin reference
+    BadSkinFamilyLength {
+        family: usize,
+        count: usize,
+        expected: usize,
+    },
+    BadSkinFamilyTexture {
+        family: usize,
+        skin_ref: usize,
+        texture: i16,
+    },
+    BadMeshSkinRef {
+        model: String,
+        mesh: usize,
+        skin_ref: i32,
+    },
+    BadVertexIndex {
+        model: String,
+        mesh: usize,
+        vertex: i16,
+        normal: i16,
+    },
+    BadVertexBone {
+        model: String,
+        vertex: usize,
+        bone: u8,
+    },
+    ZeroFrameSequence {
+        sequence: String,
+    },
+    NonFinite {
+        what: String,
+    },
+    /// Texture coordinates outside of the texture, which does not tile in game
+    UvOutOfRange {
+        model: String,
+        mesh: usize,
+        texture: String,
+        triverts: usize,
+    },
+}
+
+impl LintIssue {
+    pub fn severity(&self) -> LintSeverity {
+        match self {
+            LintIssue::UvOutOfRange { .. } | LintIssue::BadBoneController { .. } => {
+                LintSeverity::Warning
+            }
+            _ => LintSeverity::Error,
+        }
+    }
+}
+
+impl Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintIssue::BadHeader { id, version } => {
+                write!(f, "Not a studio model: id {id:#x}, version {version}")
+            }
+            LintIssue::LengthMismatch { header, file } => {
+                write!(f, "Header length is {header} but the file is {file} bytes")
+            }
+            LintIssue::OverLimit { name, count, max } => {
+                write!(f, "{name} is over the limit: {count}/{max}")
+            }
+            LintIssue::BadTextureSize {
+                texture,
+                width,
+                height,
+            } => write!(
+                f,
+                "Texture {texture} is {width}x{height}, it has to be between 1 and {MAX_GOLDSRC_TEXTURE_SIZE}"
+            ),
+            LintIssue::BadTextureData {
+                texture,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Texture {texture} has {found} pixels instead of {expected}"
+            ),
+            LintIssue::BadBoneParent { bone, parent } => {
+                write!(f, "Bone {bone} has parent {parent} which is not before it")
+            }
+            LintIssue::BadBoneController { bone, controller } => {
+                write!(
+                    f,
+                    "Bone {bone} uses controller {controller} which does not exist"
+                )
+            }
+            LintIssue::BadControllerBone { controller, bone } => {
+                write!(
+                    f,
+                    "Controller {controller} moves bone {bone} which does not exist"
+                )
+            }
+            LintIssue::BadControllerIndex { controller, index } => write!(
+                f,
+                "Controller {controller} has index {index}, it has to be between 0 and {MAX_CONTROLLER_INDEX}"
+            ),
+            LintIssue::BadSkinFamilyLength {
+                family,
+                count,
+                expected,
+            } => write!(
+                f,
+                "Skin family {family} has {count} textures instead of {expected}"
+            ),
+            LintIssue::BadSkinFamilyTexture {
+                family,
+                skin_ref,
+                texture,
+            } => write!(
+                f,
+                "Skin family {family} uses texture {texture} for skin reference {skin_ref} which does not exist"
+            ),
+            LintIssue::BadMeshSkinRef {
+                model,
+                mesh,
+                skin_ref,
+            } => write!(
+                f,
+                "Mesh {mesh} of {model} uses skin reference {skin_ref} which does not exist"
+            ),
+            LintIssue::BadVertexIndex {
+                model,
+                mesh,
+                vertex,
+                normal,
+            } => write!(
+                f,
+                "Mesh {mesh} of {model} uses vertex {vertex} or normal {normal} which does not exist"
+            ),
+            LintIssue::BadVertexBone {
+                model,
+                vertex,
+                bone,
+            } => write!(
+                f,
+                "Vertex {vertex} of {model} is on bone {bone} which does not exist"
+            ),
+            LintIssue::ZeroFrameSequence { sequence } => {
+                write!(f, "Sequence {sequence} has no frames")
+            }
+            LintIssue::NonFinite { what } => write!(f, "{what} is not a number"),
+            LintIssue::UvOutOfRange {
+                model,
+                mesh,
+                texture,
+                triverts,
+            } => write!(
+                f,
+                "Mesh {mesh} of {model} has {triverts} texture coordinates outside of {texture}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintProblem {
+    pub severity: LintSeverity,
+    pub issue: LintIssue,
+}
+
+impl Display for LintProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            LintSeverity::Error => "error",
+            LintSeverity::Warning => "warning",
+        };
+
+        write!(f, "{severity}: {}", self.issue)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MdlLintReport {
+    pub path: Option<PathBuf>,
+    pub name: String,
+    /// Errors first
+    pub problems: Vec<LintProblem>,
+}
+
+impl MdlLintReport {
+    pub fn has_errors(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| problem.severity == LintSeverity::Error)
+    }
+}
+
+impl Display for MdlLintReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => writeln!(f, "{}", path.display())?,
+            None => writeln!(f, "{}", self.name)?,
+        }
+
+        if self.problems.is_empty() {
+            return writeln!(f, "No problems");
+        }
+
+        for problem in &self.problems {
+            writeln!(f, "{problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_finite(values: &[f32]) -> bool {
+    values.iter().all(|value| value.is_finite())
+}
+
+fn check_limits(mdl: &Mdl, issues: &mut Vec<LintIssue>) {
+    let over_limit = |name: &str, count: usize, max: usize| {
+        (count > max).then(|| LintIssue::OverLimit {
+            name: name.to_string(),
+            count,
+            max,
+        })
+    };
+
+    issues.extend(
+        [
+            over_limit(
+                "textures",
+                mdl.textures.len(),
+                MAX_GOLDSRC_MODEL_TEXTURE_COUNT,
+            ),
+            over_limit("bones", mdl.bones.len(), MAX_STUDIO_BONES),
+            over_limit(
+                "bone controllers",
+                mdl.bone_controllers.len(),
+                MAX_STUDIO_CONTROLLERS,
+            ),
+            over_limit(
+                "skin families",
+                mdl.skin_families.len(),
+                MAX_STUDIO_SKIN_FAMILIES,
+            ),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+
+    for bodypart in &mdl.bodyparts {
+        for model in &bodypart.models {
+            let name = name_to_string(&model.header.name);
+
+            issues.extend(over_limit(
+                &format!("{name} vertices"),
+                model.header.num_verts as usize,
+                MAX_SMD_VERTEX,
+            ));
+            issues.extend(over_limit(
+                &format!("{name} normals"),
+                model.header.num_norms as usize,
+                MAX_SMD_VERTEX,
+            ));
+        }
+    }
+}
+
+fn check_textures(mdl: &Mdl, issues: &mut Vec<LintIssue>) {
+    let max_size = MAX_GOLDSRC_TEXTURE_SIZE as i32;
+
+    for texture in &mdl.textures {
+        let name = name_to_string(&texture.header.name);
+        let (width, height) = (texture.header.width, texture.header.height);
+
+        if !(1..=max_size).contains(&width) || !(1..=max_size).contains(&height) {
+            issues.push(LintIssue::BadTextureSize {
+                texture: name.clone(),
+                width,
+                height,
+            });
+        }
+
+        let expected = width.max(0) as usize * height.max(0) as usize;
+
+        if texture.image.len() != expected {
+            issues.push(LintIssue::BadTextureData {
+                texture: name,
+                expected,
+                found: texture.image.len(),
+            });
+        }
+    }
+}
+
+fn check_bones(mdl: &Mdl, issues: &mut Vec<LintIssue>) {
+    for (bone_idx, bone) in mdl.bones.iter().enumerate() {
+        let name = name_to_string(&bone.name);
+
+        // parents come first so they are set up before their children
+        if bone.parent < -1 || bone.parent >= bone_idx as i32 {
+            issues.push(LintIssue::BadBoneParent {
+                bone: name.clone(),
+                parent: bone.parent,
+            });
+        }
+
+        for &controller in &bone.bone_controller {
+            if controller != -1 && !(0..mdl.bone_controllers.len() as i32).contains(&controller) {
+                issues.push(LintIssue::BadBoneController {
+                    bone: name.clone(),
+                    controller,
+                });
+            }
+        }
+
+        if !is_finite(&bone.value) || !is_finite(&bone.scale) {
+            issues.push(LintIssue::NonFinite {
+                what: format!("Bone {name}"),
+            });
+        }
+    }
+
+    for (controller_idx, controller) in mdl.bone_controllers.iter().enumerate() {
+        if !(0..mdl.bones.len() as i32).contains(&controller.bone) {
+            issues.push(LintIssue::BadControllerBone {
+                controller: controller_idx,
+                bone: controller.bone,
+            });
+        }
+
+        if !(0..=MAX_CONTROLLER_INDEX).contains(&controller.index) {
+            issues.push(LintIssue::BadControllerIndex {
+                controller: controller_idx,
+                index: controller.index,
+            });
+        }
+
+        if !is_finite(&[controller.start, controller.end]) {
+            issues.push(LintIssue::NonFinite {
+                what: format!("Controller {controller_idx}"),
+            });
+        }
+    }
+}
+
+fn check_skin_families(mdl: &Mdl, issues: &mut Vec<LintIssue>) {
+    let num_skin_ref = mdl.header.num_skin_ref.max(0) as usize;
+
+    for (family_idx, family) in mdl.skin_families.iter().enumerate() {
+        if family.len() != num_skin_ref {
+            issues.push(LintIssue::BadSkinFamilyLength {
+                family: family_idx,
+                count: family.len(),
+                expected: num_skin_ref,
+            });
+        }
+
+        // textures could be in another file
+        if mdl.textures.is_empty() {
+            continue;
+        }
+
+        for (skin_ref, &texture) in family.iter().enumerate() {
+            if !(0..mdl.textures.len() as i16).contains(&texture) {
+                issues.push(LintIssue::BadSkinFamilyTexture {
+                    family: family_idx,
+                    skin_ref,
+                    texture,
+                });
+            }
+        }
+    }
+}
+
+fn check_sequences(mdl: &Mdl, issues: &mut Vec<LintIssue>) {
+    for sequence in &mdl.sequences {
+        let header = &sequence.header;
+        let name = name_to_string(&header.label);
+
+        if header.num_frames <= 0 {
+            issues.push(LintIssue::ZeroFrameSequence {
+                sequence: name.clone(),
+            });
+        }
+
+        let floats = [
+            [header.fps].as_slice(),
+            &header.linear_movement.to_array(),
+            &header.bbmin.to_array(),
+            &header.bbmax.to_array(),
+            &header.blend_start,
+            &header.blend_end,
+        ]
+        .concat();
+
+        if !is_finite(&floats) {
+            issues.push(LintIssue::NonFinite {
+                what: format!("Sequence {name}"),
+            });
+        }
+    }
+}
+
+fn check_meshes(mdl: &Mdl, issues: &mut Vec<LintIssue>) {
+    for bodypart in &mdl.bodyparts {
+        for model in &bodypart.models {
+            let name = name_to_string(&model.header.name);
+
+            for (vertex, &bone) in model.vertex_info.iter().enumerate() {
+                if bone as usize >= mdl.bones.len() {
+                    issues.push(LintIssue::BadVertexBone {
+                        model: name.clone(),
+                        vertex,
+                        bone,
+                    });
+                }
+            }
+
+            let mut non_finite = false;
+
+            for (mesh_idx, mesh) in model.meshes.iter().enumerate() {
+                let skin_ref = mesh.header.skin_ref;
+
+                // skin references are in the texture model with the textures
+                if !mdl.textures.is_empty() && !(0..mdl.header.num_skin_ref).contains(&skin_ref) {
+                    issues.push(LintIssue::BadMeshSkinRef {
+                        model: name.clone(),
+                        mesh: mesh_idx,
+                        skin_ref,
+                    });
+                }
+
+                let triverts = mesh
+                    .triangles
+                    .iter()
+                    .flat_map(|triangles| triangles.get_triverts());
+
+                let mut bad_index = None;
+                let mut out_of_range = 0;
+
+                // the default skin is what the mesh shows
+                let texture = mdl
+                    .skin_families
+                    .first()
+                    .and_then(|family| family.get(skin_ref.max(0) as usize))
+                    .and_then(|&texture| mdl.textures.get(texture.max(0) as usize));
+
+                for trivert in triverts {
+                    let header = &trivert.header;
+
+                    if bad_index.is_none()
+                        && (!(0..model.header.num_verts).contains(&(header.vert_index as i32))
+                            || !(0..model.header.num_norms).contains(&(header.norm_index as i32)))
+                    {
+                        bad_index = Some((header.vert_index, header.norm_index));
+                    }
+
+                    non_finite |= !is_finite(&trivert.vertex.to_array())
+                        || !is_finite(&trivert.normal.to_array());
+
+                    // chrome is mapped from the view, the coordinates do not matter
+                    if let Some(texture) = texture
+                        && !texture.header.flags.contains(TextureFlag::CHROME)
+                        && (!(0..=texture.header.width).contains(&(header.s as i32))
+                            || !(0..=texture.header.height).contains(&(header.t as i32)))
+                    {
+                        out_of_range += 1;
+                    }
+                }
+
+                if let Some((vertex, normal)) = bad_index {
+                    issues.push(LintIssue::BadVertexIndex {
+                        model: name.clone(),
+                        mesh: mesh_idx,
+                        vertex,
+                        normal,
+                    });
+                }
+
+                if out_of_range > 0
+                    && let Some(texture) = texture
+                {
+                    issues.push(LintIssue::UvOutOfRange {
+                        model: name.clone(),
+                        mesh: mesh_idx,
+                        texture: name_to_string(&texture.header.name),
+                        triverts: out_of_range,
+                    });
+                }
+            }
+
+            if non_finite {
+                issues.push(LintIssue::NonFinite {
+                    what: format!("Vertices of {name}"),
+                });
+            }
+        }
+    }
+}
+
+/// `file_length` is the size of the file the model is read from, to compare with the header
+pub fn mdl_lint(mdl: &Mdl, file_length: Option<usize>) -> MdlLintReport {
+    let mut issues = vec![];
+
+    if mdl.header.id != MDL_ID || mdl.header.version != MDL_VERSION {
+        issues.push(LintIssue::BadHeader {
+            id: mdl.header.id,
+            version: mdl.header.version,
+        });
+    }
+
+    if let Some(file_length) = file_length
+        && mdl.header.length as usize != file_length
+    {
+        issues.push(LintIssue::LengthMismatch {
+            header: mdl.header.length,
+            file: file_length,
+        });
+    }
+
+    check_limits(mdl, &mut issues);
+    check_textures(mdl, &mut issues);
+    check_bones(mdl, &mut issues);
+    check_skin_families(mdl, &mut issues);
+    check_sequences(mdl, &mut issues);
+    check_meshes(mdl, &mut issues);
+
+    let mut problems = issues
+        .into_iter()
+        .map(|issue| LintProblem {
+            severity: issue.severity(),
+            issue,
+        })
+        .collect::<Vec<LintProblem>>();
+
+    problems.sort_by_key(|problem| problem.severity == LintSeverity::Warning);
+
+    MdlLintReport {
+        path: None,
+        name: name_to_string(&mdl.header.name),
+        problems,
+    }
+}
+
+pub fn mdl_lint_file(path: &Path) -> eyre::Result<MdlLintReport> {
+    let bytes = fs::read(path)?;
+    let mdl = Mdl::open_from_bytes(&bytes)?;
+
+    let mut report = mdl_lint(&mdl, Some(bytes.len()));
+    report.path = Some(path.to_path_buf());
+
+    Ok(report)
+}
+
+/// Every .mdl inside the folder and its subfolders, sorted by path
+pub fn mdls_in_folder(folder: &Path) -> Vec<PathBuf> {
+    let mut paths = find_files_recursively(folder, &["mdl"]);
+    paths.sort();
+
+    paths
+}
+
+pub fn mdl_lint_to_json(reports: &[MdlLintReport]) -> eyre::Result<String> {
+    Ok(serde_json::to_string_pretty(reports)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHICK: &[u8] = include_bytes!("../../../mdl/src/tests/chick.mdl");
+
+    fn has_issue(report: &MdlLintReport, f: impl Fn(&LintIssue) -> bool) -> bool {
+        report.problems.iter().any(|problem| f(&problem.issue))
+    }
+
+    #[test]
+    fn shipped_model() {
+        let mdl = Mdl::open_from_bytes(CHICK).unwrap();
+        let report = mdl_lint(&mdl, Some(CHICK.len()));
+
+        assert!(!report.has_errors(), "{report}");
+    }
+
+    #[test]
+    fn external_textures() {
+        let bytes = include_bytes!("../../../mdl/src/tests/orange.mdl");
+        let mdl = Mdl::open_from_bytes(bytes).unwrap();
+        let report = mdl_lint(&mdl, Some(bytes.len()));
+
+        assert!(!report.has_errors(), "{report}");
+    }
+
+    #[test]
+    fn broken_model() {
+        let mut mdl = Mdl::open_from_bytes(CHICK).unwrap();
+
+        mdl.sequences[0].header.num_frames = 0;
+        mdl.bones[1].value[0] = f32::NAN;
+        mdl.bones[0].parent = 5;
+        mdl.skin_families[0][0] = 200;
+        mdl.textures[0].header.width = 1024;
+        mdl.bodyparts[0].models[0].header.num_verts = MAX_SMD_VERTEX as i32 + 1;
+
+        let report = mdl_lint(&mdl, Some(CHICK.len() + 1));
+
+        assert!(report.has_errors());
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::ZeroFrameSequence { .. }
+        )));
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::NonFinite { what } if what.starts_with("Bone")
+        )));
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::BadBoneParent { parent: 5, .. }
+        )));
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::BadSkinFamilyTexture { texture: 200, .. }
+        )));
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::BadTextureSize { width: 1024, .. }
+        )));
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::OverLimit { name, .. } if name.ends_with("vertices")
+        )));
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::LengthMismatch { .. }
+        )));
+
+        // errors first
+        assert_eq!(report.problems[0].severity, LintSeverity::Error);
+
+        let json = mdl_lint_to_json(&[report]).unwrap();
+        assert!(json.contains("zero_frame_sequence"));
+    }
+
+    #[test]
+    fn uv_out_of_range() {
+        let mut mdl = Mdl::open_from_bytes(CHICK).unwrap();
+
+        let texture = &mut mdl.textures[mdl.skin_families[0][0] as usize];
+        texture.header.flags.remove(TextureFlag::CHROME);
+        let width = texture.header.width;
+
+        let mesh = mdl.bodyparts[0].models[0]
+            .meshes
+            .iter_mut()
+            .find(|mesh| mesh.header.skin_ref == 0)
+            .unwrap();
+        mesh.triangles[0].get_triverts_mut()[0].header.s = (width + 10) as i16;
+
+        let report = mdl_lint(&mdl, None);
+
+        assert!(has_issue(&report, |issue| matches!(
+            issue,
+            LintIssue::UvOutOfRange { .. }
+        )));
+    }
+}
@@ -0,0 +1,458 @@
+//! Edits textures and skin families of a compiled model without decompiling it.
+use std::path::{Path, PathBuf};
+
+use common::{
+    constants::MAX_GOLDSRC_TEXTURE_SIZE,
+    img_stuffs::{
+        GoldSrcBmp, generate_rgba8_from_image_path,
+        maybe_resize_due_to_exceeding_max_goldsrc_texture_size, rgba8_to_8bpp,
+    },
+};
+use image::{RgbaImage, imageops};
+use mdl::{Mdl, PALETTE_COUNT, Texture, TextureFlag};
+
+use crate::err;
+
+/// Pixels more transparent than this become the transparent color of masked textures
+const MASKED_ALPHA_THRESHOLD: u8 = 128;
+
+/// Masked textures use the last palette color as transparent
+const MASKED_INDEX: u8 = 255;
+const MASKED_COLOR: [u8; 3] = [0, 0, 255];
+
+/// Flags that can be toggled by name
+pub const TEXTURE_FLAG_NAMES: &[(&str, TextureFlag)] = &[
+    ("chrome", TextureFlag::CHROME),
+    ("additive", TextureFlag::ADDITIVE),
+    ("masked", TextureFlag::MASKED),
+    ("flatshade", TextureFlag::FLATSHADE),
+    ("fullbright", TextureFlag::FULLBRIGHT),
+];
+
+pub fn texture_flag_from_name(name: &str) -> Option<TextureFlag> {
+    TEXTURE_FLAG_NAMES
+        .iter()
+        .find(|(curr, _)| curr.eq_ignore_ascii_case(name))
+        .map(|(_, flag)| flag.clone())
+}
+
+#[derive(Debug, Clone)]
+pub enum MdlTextureEdit {
+    /// Replaces the pixels with an image. Without `resize`, the image keeps its own size.
+    Replace {
+        texture: String,
+        image: PathBuf,
+        resize: Option<(u32, u32)>,
+    },
+    Rename {
+        texture: String,
+        name: String,
+    },
+    SetFlag {
+        texture: String,
+        flag: TextureFlag,
+        enabled: bool,
+    },
+    /// New skin family where each texture is swapped with an existing texture or an image.
+    ///
+    /// Textures from images are added to the model and named after the image.
+    AddSkinFamily {
+        replacements: Vec<(String, String)>,
+    },
+    RemoveSkinFamily {
+        family: usize,
+    },
+}
+
+/// Texture by index or by name
+pub fn find_texture(mdl: &Mdl, texture: &str) -> eyre::Result<usize> {
+    if mdl.textures.is_empty() {
+        return err!("Model textures are stored in an external file");
+    }
+
+    if let Ok(index) = texture.parse::<usize>() {
+        if index >= mdl.textures.len() {
+            return err!(
+                "Texture {index} does not exist, model has {} textures",
+                mdl.textures.len()
+            );
+        }
+
+        return Ok(index);
+    }
+
+    mdl.find_texture(texture)
+        .ok_or_else(|| eyre::eyre!("Cannot find texture `{texture}`"))
+}
+
+/// Quantizes the image into a model texture.
+///
+/// Pixels with low alpha are transparent when `masked` is set.
+pub fn image_to_texture_data(
+    image: RgbaImage,
+    resize: Option<(u32, u32)>,
+    masked: bool,
+) -> eyre::Result<GoldSrcBmp> {
+    let image = match resize {
+        Some((width, height)) if (width, height) != image.dimensions() => {
+            if width == 0 || height == 0 {
+                return err!("Cannot resize texture to {width}x{height}");
+            }
+
+            imageops::resize(&image, width, height, imageops::FilterType::Triangle)
+        }
+        _ => image,
+    };
+
+    let image = if image.width().max(image.height()) > MAX_GOLDSRC_TEXTURE_SIZE {
+        maybe_resize_due_to_exceeding_max_goldsrc_texture_size(&image)
+    } else {
+        image
+    };
+
+    let transparent_pixels = image
+        .pixels()
+        .map(|pixel| pixel.0[3] < MASKED_ALPHA_THRESHOLD)
+        .collect::<Vec<bool>>();
+
+    // quantized to 255 colors so the last color is always free
+    let mut res = rgba8_to_8bpp(image)?;
+    res.pad_palette();
+
+    if masked {
+        res.palette[MASKED_INDEX as usize] = MASKED_COLOR;
+
+        res.image
+            .iter_mut()
+            .zip(transparent_pixels)
+            .filter(|(_, transparent)| *transparent)
+            .for_each(|(pixel, _)| *pixel = MASKED_INDEX);
+    }
+
+    Ok(res)
+}
+
+fn palette_array(palette: &[[u8; 3]]) -> [[u8; 3]; PALETTE_COUNT] {
+    std::array::from_fn(|index| palette.get(index).copied().unwrap_or_default())
+}
+
+pub fn replace_texture(
+    mdl: &mut Mdl,
+    index: usize,
+    image: RgbaImage,
+    resize: Option<(u32, u32)>,
+) -> eyre::Result<()> {
+    let Some(texture) = mdl.textures.get(index) else {
+        return err!("Texture {index} does not exist");
+    };
+
+    let masked = texture.header.flags.contains(TextureFlag::MASKED);
+    let GoldSrcBmp {
+        image,
+        palette,
+        dimensions,
+    } = image_to_texture_data(image, resize, masked)?;
+
+    mdl.replace_texture(index, image, palette_array(&palette), dimensions)?;
+
+    Ok(())
+}
+
+/// Texture as RGBA. Transparent color of masked textures has zero alpha.
+pub fn texture_to_rgba(texture: &Texture) -> RgbaImage {
+    let (width, height) = texture.dimensions();
+    let masked = texture.header.flags.contains(TextureFlag::MASKED);
+
+    let pixels = texture
+        .image
+        .iter()
+        .flat_map(|&pixel| {
+            let [r, g, b] = texture.palette[pixel as usize];
+            let a = if masked && pixel == MASKED_INDEX {
+                0
+            } else {
+                255
+            };
+
+            [r, g, b, a]
+        })
+        .collect();
+
+    RgbaImage::from_raw(width, height, pixels).expect("texture size matches dimensions")
+}
+
+/// Textures with their sizes and flags followed by skin families
+pub fn describe_textures(mdl: &Mdl) -> String {
+    if mdl.textures.is_empty() {
+        return "Model textures are stored in an external file\n".to_string();
+    }
+
+    let mut res = String::new();
+
+    for (index, texture) in mdl.textures.iter().enumerate() {
+        let (width, height) = texture.dimensions();
+        let flags = TEXTURE_FLAG_NAMES
+            .iter()
+            .filter(|(_, flag)| texture.header.flags.contains(flag.clone()))
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        let line = format!("{index}: {} {width}x{height} {flags}", texture.name());
+
+        res += line.trim_end();
+        res += "\n";
+    }
+
+    for (index, family) in mdl.skin_families.iter().enumerate() {
+        let family = family
+            .iter()
+            .map(|texture| texture.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        res += &format!("skin {index}: {family}\n");
+    }
+
+    res
+}
+
+/// Writes every texture as `<texture name>.png` into the folder and returns their paths
+pub fn extract_textures(mdl: &Mdl, out_dir: impl AsRef<Path>) -> eyre::Result<Vec<PathBuf>> {
+    let out_dir = out_dir.as_ref();
+
+    if mdl.textures.is_empty() {
+        return err!("Model textures are stored in an external file");
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+
+    mdl.textures
+        .iter()
+        .map(|texture| {
+            let path = out_dir.join(texture.name()).with_extension("png");
+
+            texture_to_rgba(texture).save(&path)?;
+
+            Ok(path)
+        })
+        .collect()
+}
+
+fn add_skin_family(mdl: &mut Mdl, replacements: &[(String, String)]) -> eyre::Result<usize> {
+    if mdl.textures.is_empty() {
+        return err!("Model textures are stored in an external file");
+    }
+
+    if mdl.skin_families.is_empty() {
+        mdl.rebuild_data_for_export();
+    }
+
+    let mut family = mdl.skin_families[0].clone();
+
+    for (slot, replacement) in replacements {
+        let slot = find_texture(mdl, slot)?;
+
+        let replacement = match find_texture(mdl, replacement) {
+            Ok(index) => index,
+            Err(_) => {
+                let path = Path::new(replacement);
+
+                if !path.exists() {
+                    return err!("`{replacement}` is neither a texture nor an image");
+                }
+
+                let source = &mdl.textures[slot];
+                let flags = source.header.flags.clone();
+                let masked = flags.contains(TextureFlag::MASKED);
+                let name = path
+                    .with_extension("bmp")
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default()
+                    .to_string();
+
+                let GoldSrcBmp {
+                    image,
+                    palette,
+                    dimensions,
+                } = image_to_texture_data(generate_rgba8_from_image_path(path)?, None, masked)?;
+
+                mdl.add_texture(Texture::new_texture(
+                    &name,
+                    dimensions,
+                    image,
+                    palette_array(&palette),
+                    flags,
+                ))?
+            }
+        };
+
+        if family.len() < mdl.textures.len() {
+            family.extend(family.len() as i16..mdl.textures.len() as i16);
+        }
+
+        family[slot] = replacement as i16;
+    }
+
+    Ok(mdl.add_skin_family(family)?)
+}
+
+pub fn edit_mdl_textures(mdl: &mut Mdl, edits: &[MdlTextureEdit]) -> eyre::Result<()> {
+    for edit in edits {
+        match edit {
+            MdlTextureEdit::Replace {
+                texture,
+                image,
+                resize,
+            } => {
+                let index = find_texture(mdl, texture)?;
+
+                replace_texture(mdl, index, generate_rgba8_from_image_path(image)?, *resize)?;
+            }
+            MdlTextureEdit::Rename { texture, name } => {
+                let index = find_texture(mdl, texture)?;
+
+                mdl.rename_texture(index, name)?;
+            }
+            MdlTextureEdit::SetFlag {
+                texture,
+                flag,
+                enabled,
+            } => {
+                let index = find_texture(mdl, texture)?;
+
+                mdl.set_texture_flag(index, flag.clone(), *enabled)?;
+            }
+            MdlTextureEdit::AddSkinFamily { replacements } => {
+                add_skin_family(mdl, replacements)?;
+            }
+            MdlTextureEdit::RemoveSkinFamily { family } => {
+                mdl.remove_skin_family(*family)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the edits and writes the model to `out_path` or overwrites it
+pub fn edit_mdl_textures_file(
+    mdl_path: impl AsRef<Path>,
+    edits: &[MdlTextureEdit],
+    out_path: Option<&Path>,
+) -> eyre::Result<()> {
+    let mdl_path = mdl_path.as_ref();
+    let mut mdl = Mdl::open_from_file(mdl_path)?;
+
+    edit_mdl_textures(&mut mdl, edits)?;
+
+    mdl.rebuild_data_for_export();
+    mdl.write_to_file(out_path.unwrap_or(mdl_path))?;
+
+    Ok(())
+}
+
+/// Extracts the textures into `<mdl name>_textures` next to the model
+pub fn extract_textures_file(mdl_path: impl AsRef<Path>) -> eyre::Result<PathBuf> {
+    let mdl_path = mdl_path.as_ref();
+    let mdl = Mdl::open_from_file(mdl_path)?;
+
+    let stem = mdl_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let out_dir = mdl_path.with_file_name(format!("{stem}_textures"));
+
+    extract_textures(&mdl, &out_dir)?;
+
+    Ok(out_dir)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extract_replace() {
+        let out_dir = std::env::temp_dir().join("gchimp_mdl_texture_extract");
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        let mut mdl =
+            Mdl::open_from_bytes(include_bytes!("../../../mdl/src/tests/chick.mdl")).unwrap();
+        let paths = extract_textures(&mdl, &out_dir).unwrap();
+        assert_eq!(paths.len(), mdl.textures.len());
+
+        let (width, height) = mdl.textures[0].dimensions();
+        let image = image::open(&paths[0]).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (width, height));
+
+        replace_texture(&mut mdl, 0, image, Some((width / 2, height / 2))).unwrap();
+        mdl.rebuild_data_for_export();
+
+        let mdl = Mdl::open_from_bytes(&mdl.write_to_bytes()).unwrap();
+        assert_eq!(mdl.textures[0].dimensions(), (width / 2, height / 2));
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn masked() {
+        let mut image = RgbaImage::from_pixel(4, 4, image::Rgba([200, 10, 10, 255]));
+        image.put_pixel(1, 2, image::Rgba([0, 0, 0, 0]));
+
+        let res = image_to_texture_data(image.clone(), None, true).unwrap();
+        assert_eq!(res.palette[255], MASKED_COLOR);
+        assert_eq!(res.image[2 * 4 + 1], MASKED_INDEX);
+        assert_eq!(res.image.iter().filter(|&&p| p == MASKED_INDEX).count(), 1);
+
+        let texture = Texture::new_texture(
+            "masked.bmp",
+            res.dimensions,
+            res.image,
+            palette_array(&res.palette),
+            TextureFlag::MASKED,
+        );
+        let rgba = texture_to_rgba(&texture);
+        assert_eq!(rgba.get_pixel(1, 2).0[3], 0);
+        assert_eq!(rgba.get_pixel(0, 0).0[3], 255);
+    }
+
+    #[test]
+    fn edits() {
+        let mut mdl =
+            Mdl::open_from_bytes(include_bytes!("../../../mdl/src/tests/chick.mdl")).unwrap();
+        let original = mdl.textures[0].name();
+
+        edit_mdl_textures(
+            &mut mdl,
+            &[
+                MdlTextureEdit::SetFlag {
+                    texture: "0".to_string(),
+                    flag: texture_flag_from_name("Chrome").unwrap(),
+                    enabled: true,
+                },
+                MdlTextureEdit::AddSkinFamily {
+                    replacements: vec![(original.clone(), original.clone())],
+                },
+                MdlTextureEdit::Rename {
+                    texture: original,
+                    name: "renamed.bmp".to_string(),
+                },
+            ],
+        )
+        .unwrap();
+
+        mdl.rebuild_data_for_export();
+        let mut mdl = Mdl::open_from_bytes(&mdl.write_to_bytes()).unwrap();
+
+        assert!(mdl.textures[0].header.flags.contains(TextureFlag::CHROME));
+        assert_eq!(find_texture(&mdl, "renamed.bmp").unwrap(), 0);
+        assert_eq!(mdl.skin_families.len(), 2);
+
+        assert!(find_texture(&mdl, "missing.bmp").is_err());
+        assert!(
+            edit_mdl_textures(&mut mdl, &[MdlTextureEdit::RemoveSkinFamily { family: 2 }]).is_err()
+        );
+    }
+}
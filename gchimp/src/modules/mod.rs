@@ -1,16 +1,27 @@
 pub mod blender_lightmap_baker_helper;
+pub mod bsp2gltf;
+pub mod bsp2mdl;
+pub mod bsp2wad;
+pub mod bsp_ent;
+pub mod bsp_limits;
 pub mod check_illegal_brush;
 pub mod check_missing_texture;
 pub mod custom_script;
 pub mod dem2cam;
-// pub mod demdoc;
-pub mod bsp2wad;
+pub mod demdoc;
 pub mod duplicate_triangle;
 pub mod find_low_scaling;
 pub mod join_mdl;
+pub mod leak_check;
 pub mod light_scale;
+pub mod lightmap_edit;
 pub mod loop_wave;
+pub mod map2bsp;
 pub mod map2mdl;
+pub mod mdl2gltf;
+pub mod mdl_lint;
+pub mod mdl_texture;
+pub mod rad;
 pub mod rename_texture;
 pub mod resmake;
 pub mod rotate_prop_static;
@@ -19,6 +30,7 @@ pub mod skymod;
 pub mod split_model;
 pub mod textile;
 pub mod texture_scale;
+pub mod wad_embed;
 pub mod waddy;
 
 pub mod ___random_specific_stuffs;
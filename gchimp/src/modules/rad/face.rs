@@ -0,0 +1,141 @@
+use bsp::{Bsp, LUXEL_SIZE, LeafContent};
+use glam::{DMat3, DVec3};
+
+use crate::utils::simple_calculs::newell_normal;
+
+use super::trace::{WorldTracer, dvec3};
+
+/// Samples are moved off the surface by this much so they do not hit their own face
+const SAMPLE_OFFSET: f64 = 1.;
+
+/// A face that gets a lightmap
+pub struct LightFace {
+    /// Index of the face in the BSP
+    pub face: usize,
+    pub normal: DVec3,
+    pub vertices: Vec<DVec3>,
+    pub area: f64,
+    /// Texture space vectors and offsets from texinfo
+    pub s: (DVec3, f64),
+    pub t: (DVec3, f64),
+    /// Lightmap texture coordinates of the first luxel, in luxels
+    pub luxel_mins: [i32; 2],
+    pub width: usize,
+    pub height: usize,
+    /// World position of every luxel, row by row
+    pub samples: Vec<DVec3>,
+    pub reflectivity: DVec3,
+    /// Light given off by texlights
+    pub emission: DVec3,
+}
+
+impl LightFace {
+    /// Returns `None` for faces without lightmap
+    pub fn new(bsp: &Bsp, face_idx: usize, offset: DVec3, tracer: &WorldTracer) -> Option<Self> {
+        let face = &bsp.faces[face_idx];
+        let texinfo = &bsp.texinfo[face.texinfo as usize];
+
+        // same as the engine, extents are computed from the original vertices
+        let extents = bsp.face_lightmap_extents(face_idx)?;
+        let (luxel_mins, width, height) = (extents.mins, extents.width, extents.height);
+
+        let plane = &bsp.planes[face.plane as usize];
+        let (mut normal, mut distance) = (dvec3(plane.normal), plane.distance as f64);
+
+        if face.side != 0 {
+            (normal, distance) = (-normal, -distance);
+        }
+
+        let vertices = bsp
+            .face_vertices(face_idx)
+            .into_iter()
+            .map(|vertex| dvec3(vertex) + offset)
+            .collect::<Vec<DVec3>>();
+
+        if vertices.len() < 3 {
+            return None;
+        }
+
+        let s = (dvec3(texinfo.u), texinfo.u_offset as f64);
+        let t = (dvec3(texinfo.v), texinfo.v_offset as f64);
+
+        // texture space to world space
+        let to_world = DMat3::from_cols(s.0, t.0, normal).transpose();
+
+        if to_world.determinant().abs() < f64::EPSILON {
+            return None;
+        }
+
+        let to_world = to_world.inverse();
+        let center = vertices.iter().sum::<DVec3>() / vertices.len() as f64;
+
+        let samples = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let luxel_s = (luxel_mins[0] + x as i32) as f64 * LUXEL_SIZE;
+                let luxel_t = (luxel_mins[1] + y as i32) as f64 * LUXEL_SIZE;
+
+                let point = to_world * DVec3::new(luxel_s - s.1, luxel_t - t.1, distance)
+                    + offset
+                    + normal * SAMPLE_OFFSET;
+
+                find_sample_point(tracer, point, center + normal * SAMPLE_OFFSET)
+            })
+            .collect();
+
+        let area = newell_normal(&vertices).length() / 2.;
+
+        Some(Self {
+            face: face_idx,
+            normal,
+            vertices,
+            area,
+            s,
+            t,
+            luxel_mins,
+            width,
+            height,
+            samples,
+            reflectivity: DVec3::ZERO,
+            emission: DVec3::ZERO,
+        })
+    }
+
+    pub fn luxel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Index of the luxel nearest to the point
+    pub fn luxel_at(&self, point: DVec3) -> usize {
+        let x = ((point.dot(self.s.0) + self.s.1) / LUXEL_SIZE).round() as i32 - self.luxel_mins[0];
+        let y = ((point.dot(self.t.0) + self.t.1) / LUXEL_SIZE).round() as i32 - self.luxel_mins[1];
+
+        let x = x.clamp(0, self.width as i32 - 1) as usize;
+        let y = y.clamp(0, self.height as i32 - 1) as usize;
+
+        y * self.width + x
+    }
+
+    /// Point on the face plane is inside the face polygon
+    pub fn contains_point(&self, point: DVec3) -> bool {
+        const EPSILON: f64 = 0.1;
+
+        // vertices wind clockwise looking at the front
+        (0..self.vertices.len()).all(|idx| {
+            let curr = self.vertices[idx];
+            let next = self.vertices[(idx + 1) % self.vertices.len()];
+            let edge_normal = self.normal.cross(next - curr).normalize_or_zero();
+
+            edge_normal.dot(point - curr) <= EPSILON
+        })
+    }
+}
+
+/// Luxels outside of the face can end up inside walls, they are pulled towards the face center
+fn find_sample_point(tracer: &WorldTracer, point: DVec3, center: DVec3) -> DVec3 {
+    [0., 0.25, 0.5, 0.75, 1.]
+        .into_iter()
+        .map(|fraction| point.lerp(center, fraction))
+        .find(|point| tracer.point_contents(*point) != LeafContent::ContentsSolid)
+        .unwrap_or(point)
+}
@@ -0,0 +1,180 @@
+use bsp::Entity;
+use glam::DVec3;
+
+/// Distance where a point light lights a surface facing it with its full brightness
+const POINT_LIGHT_DISTANCE: f64 = 128.;
+
+pub enum LightKind {
+    Point,
+    Spot {
+        direction: DVec3,
+        /// Cosine of the inner and outer cone angles
+        cone: (f64, f64),
+    },
+    Sun {
+        /// Where the light travels to
+        direction: DVec3,
+        /// Light coming from the whole sky
+        diffuse: DVec3,
+    },
+}
+
+pub struct Light {
+    pub kind: LightKind,
+    pub origin: DVec3,
+    pub intensity: DVec3,
+    pub style: u8,
+}
+
+fn parse_floats(s: &str) -> Vec<f64> {
+    s.split_whitespace()
+        .filter_map(|e| e.parse::<f64>().ok())
+        .collect()
+}
+
+/// "r g b brightness", "r g b" or just "brightness"
+fn parse_intensity(s: &str) -> Option<DVec3> {
+    match parse_floats(s).as_slice() {
+        [r, g, b, brightness] => Some(DVec3::new(*r, *g, *b) / 255. * *brightness),
+        [r, g, b] => Some(DVec3::new(*r, *g, *b)),
+        [brightness] => Some(DVec3::splat(*brightness)),
+        _ => None,
+    }
+}
+
+/// Direction from "angles", "angle" and "pitch" keys like the other compilers
+fn parse_direction(entity: &Entity) -> DVec3 {
+    let angles = entity
+        .get("angles")
+        .map(|angles| parse_floats(angles))
+        .unwrap_or_default();
+
+    let mut pitch = angles.first().copied().unwrap_or(0.);
+    let mut yaw = angles.get(1).copied().unwrap_or(0.);
+
+    if let Some(angle) = entity
+        .get("angle")
+        .and_then(|angle| angle.parse::<f64>().ok())
+    {
+        match angle {
+            -1. => return DVec3::Z,
+            -2. => return DVec3::NEG_Z,
+            _ => yaw = angle,
+        }
+    }
+
+    if let Some(value) = entity
+        .get("pitch")
+        .and_then(|pitch| pitch.parse::<f64>().ok())
+    {
+        pitch = value;
+    }
+
+    let (pitch, yaw) = (pitch.to_radians(), yaw.to_radians());
+
+    DVec3::new(
+        yaw.cos() * pitch.cos(),
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+    )
+}
+
+impl Light {
+    pub fn from_entity(entity: &Entity) -> Option<Self> {
+        let classname = entity.get("classname")?;
+
+        if !classname.starts_with("light") {
+            return None;
+        }
+
+        let origin = entity
+            .get("origin")
+            .map(|origin| parse_floats(origin))
+            .filter(|origin| origin.len() == 3)
+            .map(|origin| DVec3::from_slice(&origin))
+            .unwrap_or_default();
+
+        let intensity = entity
+            .get("_light")
+            .or(entity.get("light"))
+            .and_then(|light| parse_intensity(light))
+            .unwrap_or(DVec3::splat(200.));
+
+        let style = entity
+            .get("style")
+            .and_then(|style| style.parse::<u8>().ok())
+            .unwrap_or(0);
+
+        let kind = match classname.as_str() {
+            "light" => LightKind::Point,
+            "light_spot" => {
+                let cone_angle = |key: &str, default: f64| {
+                    entity
+                        .get(key)
+                        .and_then(|cone| cone.parse::<f64>().ok())
+                        .unwrap_or(default)
+                        .to_radians()
+                        .cos()
+                };
+
+                let inner = cone_angle("_cone", 10.);
+                let outer = cone_angle("_cone2", 20.).min(inner);
+
+                LightKind::Spot {
+                    direction: parse_direction(entity),
+                    cone: (inner, outer),
+                }
+            }
+            "light_environment" => LightKind::Sun {
+                direction: parse_direction(entity),
+                diffuse: entity
+                    .get("_diffuse_light")
+                    .and_then(|light| parse_intensity(light))
+                    .unwrap_or_default(),
+            },
+            _ => return None,
+        };
+
+        Some(Self {
+            kind,
+            origin,
+            intensity,
+            style,
+        })
+    }
+
+    /// Light arriving at a point with normal, without checking for occlusion
+    pub fn point_intensity(&self, point: DVec3, normal: DVec3) -> DVec3 {
+        let to_light = self.origin - point;
+        let distance = to_light.length().max(1.);
+        let to_light = to_light / distance;
+        let dot = normal.dot(to_light);
+
+        if dot <= 0. {
+            return DVec3::ZERO;
+        }
+
+        let cone = match self.kind {
+            LightKind::Point => 1.,
+            LightKind::Spot {
+                direction,
+                cone: (inner, outer),
+            } => {
+                let angle = direction.dot(-to_light);
+
+                if angle >= inner {
+                    1.
+                } else if angle <= outer {
+                    0.
+                } else {
+                    (angle - outer) / (inner - outer)
+                }
+            }
+            LightKind::Sun { .. } => unreachable!("sun is not a point light"),
+        };
+
+        let falloff = (POINT_LIGHT_DISTANCE / distance).powi(2);
+
+        self.intensity * dot * cone * falloff
+    }
+}
@@ -0,0 +1,723 @@
+//! Bakes lightmaps into a compiled BSP, the same job as hlrad.
+//!
+//! Direct light comes from light entities, `light_environment` and texlights.
+//! Bounced light is gathered by shooting rays from every luxel into the previous bounce.
+//! Only style 0 bounces.
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    path::{Path, PathBuf},
+};
+
+use bsp::{Bsp, LeafContent, MAX_LIGHTMAPS, Texture};
+use glam::DVec3;
+use rayon::prelude::*;
+use wad::types::Wad;
+
+use crate::err;
+
+mod face;
+mod light;
+mod trace;
+
+use face::LightFace;
+use light::{Light, LightKind};
+use trace::WorldTracer;
+
+/// Far enough to reach any sky
+const SKY_DISTANCE: f64 = 65536.;
+
+/// Used when a texture cannot be found
+const DEFAULT_REFLECTIVITY: f64 = 0.5;
+
+/// Face with texture named `texture` gives off light
+#[derive(Debug, Clone, PartialEq)]
+pub struct TexLight {
+    pub texture: String,
+    pub intensity: DVec3,
+}
+
+/// Parses texlights from a lights.rad file.
+///
+/// Every line is `<texture> <r> <g> <b> [brightness]`, `//` starts a comment.
+pub fn parse_lights_rad(s: &str) -> eyre::Result<Vec<TexLight>> {
+    s.lines()
+        .map(|line| line.split("//").next().unwrap_or_default().trim())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_idx, line)| {
+            let mut tokens = line.split_whitespace();
+            let texture = tokens.next().unwrap_or_default().to_uppercase();
+            let values = tokens
+                .map(|token| token.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>();
+
+            let intensity = match values.as_deref() {
+                Ok([r, g, b, brightness]) => DVec3::new(*r, *g, *b) / 255. * *brightness,
+                Ok([r, g, b]) => DVec3::new(*r, *g, *b),
+                _ => return err!("Cannot parse texlight at line {}", line_idx + 1),
+            };
+
+            Ok(TexLight { texture, intensity })
+        })
+        .collect()
+}
+
+pub struct RadOptions {
+    /// Number of times light bounces off surfaces
+    pub bounces: u32,
+    /// Rays shot from every luxel for each bounce
+    pub bounce_samples: u32,
+    /// Rays shot from every luxel to find sky for `_diffuse_light`
+    pub sky_samples: u32,
+    pub gamma: f64,
+    pub texlights: Vec<TexLight>,
+    /// WAD files searched for texture colors after the ones in worldspawn "wad" key
+    pub wad_paths: Vec<PathBuf>,
+}
+
+impl Default for RadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RadOptions {
+    pub fn new() -> Self {
+        Self {
+            bounces: 3,
+            bounce_samples: 32,
+            sky_samples: 32,
+            gamma: 0.55,
+            texlights: vec![],
+            wad_paths: vec![],
+        }
+    }
+}
+
+/// A piece of a texlight face
+struct Emitter {
+    point: DVec3,
+    normal: DVec3,
+    area: f64,
+    intensity: DVec3,
+}
+
+/// Light of every luxel of a face, one entry per style
+type FaceLight = Vec<(u8, Vec<DVec3>)>;
+
+fn add_style_light(face_light: &mut FaceLight, style: u8, luxel: usize, size: usize, light: DVec3) {
+    let idx = face_light
+        .iter()
+        .position(|(curr, _)| *curr == style)
+        .unwrap_or_else(|| {
+            face_light.push((style, vec![DVec3::ZERO; size]));
+            face_light.len() - 1
+        });
+
+    face_light[idx].1[luxel] += light;
+}
+
+/// Cosine weighted directions around the normal.
+///
+/// `seed` rotates the set so neighboring luxels do not get the same pattern.
+fn hemisphere_directions(normal: DVec3, count: u32, seed: usize) -> impl Iterator<Item = DVec3> {
+    const GOLDEN_RATIO: f64 = 0.618_033_988_749_895;
+
+    let (tangent, bitangent) = normal.any_orthonormal_pair();
+    let rotation = (seed as f64 * GOLDEN_RATIO).fract();
+
+    (0..count).map(move |idx| {
+        let height = (idx as f64 + 0.5) / count as f64;
+        let angle = 2. * PI * (idx as f64 * GOLDEN_RATIO + rotation).fract();
+        let radius = height.sqrt();
+
+        tangent * radius * angle.cos()
+            + bitangent * radius * angle.sin()
+            + normal * (1. - height).sqrt()
+    })
+}
+
+fn average_color(texture: &Texture) -> Option<DVec3> {
+    if texture.is_external() || texture.width == 0 || texture.height == 0 {
+        return None;
+    }
+
+    let (image, _) = texture.to_rgb();
+    let sum = image.chunks_exact(3).fold(DVec3::ZERO, |acc, rgb| {
+        acc + DVec3::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64)
+    });
+
+    Some(sum / (image.len() / 3).max(1) as f64 / 255.)
+}
+
+/// Reflectivity of every texture in the BSP, from embedded textures or WAD files
+fn texture_reflectivity(bsp: &Bsp, options: &RadOptions, warnings: &mut Vec<String>) -> Vec<DVec3> {
+    let mut wad_colors: Option<HashMap<String, DVec3>> = None;
+
+    bsp.textures
+        .iter()
+        .map(|texture| {
+            if let Some(color) = average_color(texture) {
+                return color;
+            }
+
+            // only opens WAD files when some texture is not embedded
+            let wad_colors = wad_colors.get_or_insert_with(|| {
+                let wad_paths = bsp
+                    .entities
+                    .first()
+                    .and_then(|entity| entity.get("wad"))
+                    .map(|wad| {
+                        wad.split_terminator(';')
+                            .filter(|path| !path.is_empty())
+                            .map(PathBuf::from)
+                            .collect::<Vec<PathBuf>>()
+                    })
+                    .unwrap_or_default();
+
+                // earlier WAD files win
+                wad_paths
+                    .iter()
+                    .chain(options.wad_paths.iter())
+                    .filter_map(|path| Wad::from_file(path).ok())
+                    .collect::<Vec<Wad>>()
+                    .iter()
+                    .rev()
+                    .flat_map(|wad| wad.entries.iter())
+                    .filter_map(|entry| {
+                        entry
+                            .file_entry
+                            .get_mip_tex()
+                            .and_then(average_color)
+                            .map(|color| (entry.texture_name().to_uppercase(), color))
+                    })
+                    .collect()
+            });
+
+            let name = texture.texture_name.get_string_standard().to_uppercase();
+
+            wad_colors.get(&name).copied().unwrap_or_else(|| {
+                warnings.push(format!("Cannot find texture {name} for its color"));
+                DVec3::splat(DEFAULT_REFLECTIVITY)
+            })
+        })
+        .collect()
+}
+
+/// Origin of every brush model
+fn model_offsets(bsp: &Bsp) -> Vec<DVec3> {
+    let mut res = vec![DVec3::ZERO; bsp.models.len()];
+
+    bsp.entities.iter().for_each(|entity| {
+        let Some(model_idx) = entity
+            .get("model")
+            .and_then(|model| model.strip_prefix('*'))
+            .and_then(|model| model.parse::<usize>().ok())
+        else {
+            return;
+        };
+
+        let Some(origin) = entity.get("origin").map(|origin| {
+            origin
+                .split_whitespace()
+                .filter_map(|e| e.parse::<f64>().ok())
+                .collect::<Vec<f64>>()
+        }) else {
+            return;
+        };
+
+        if let Some(offset) = res.get_mut(model_idx)
+            && origin.len() == 3
+        {
+            *offset = DVec3::from_slice(&origin);
+        }
+    });
+
+    res
+}
+
+fn direct_light(
+    face: &LightFace,
+    tracer: &WorldTracer,
+    lights: &[Light],
+    emitters: &[Emitter],
+    options: &RadOptions,
+) -> FaceLight {
+    let mut res: FaceLight = vec![(0, vec![DVec3::ZERO; face.luxel_count()])];
+
+    for (luxel, &sample) in face.samples.iter().enumerate() {
+        for light in lights {
+            let LightKind::Sun { direction, diffuse } = light.kind else {
+                let intensity = light.point_intensity(sample, face.normal);
+
+                if intensity != DVec3::ZERO && tracer.is_visible(sample, light.origin) {
+                    add_style_light(&mut res, light.style, luxel, face.luxel_count(), intensity);
+                }
+
+                continue;
+            };
+
+            let hits_sky = |direction: DVec3| {
+                tracer
+                    .trace(sample, sample + direction * SKY_DISTANCE)
+                    .is_some_and(|hit| hit.contents == LeafContent::ContentsSky)
+            };
+
+            let dot = face.normal.dot(-direction);
+
+            if dot > 0. && hits_sky(-direction) {
+                let intensity = light.intensity * dot;
+                add_style_light(&mut res, light.style, luxel, face.luxel_count(), intensity);
+            }
+
+            if diffuse != DVec3::ZERO && options.sky_samples > 0 {
+                let sky_count = hemisphere_directions(face.normal, options.sky_samples, luxel)
+                    .filter(|direction| hits_sky(*direction))
+                    .count();
+
+                let intensity = diffuse * sky_count as f64 / options.sky_samples as f64;
+                add_style_light(&mut res, light.style, luxel, face.luxel_count(), intensity);
+            }
+        }
+
+        let from_emitters = emitters
+            .iter()
+            .filter_map(|emitter| {
+                let delta = emitter.point - sample;
+                let distance_squared = delta.length_squared();
+                let direction = delta / distance_squared.sqrt();
+
+                let (cos_receiver, cos_emitter) =
+                    (face.normal.dot(direction), -emitter.normal.dot(direction));
+
+                if cos_receiver <= 0. || cos_emitter <= 0. {
+                    return None;
+                }
+
+                if !tracer.is_visible(sample, emitter.point) {
+                    return None;
+                }
+
+                Some(
+                    emitter.intensity * emitter.area * cos_receiver * cos_emitter
+                        / (PI * distance_squared.max(emitter.area)),
+                )
+            })
+            .sum::<DVec3>();
+
+        res[0].1[luxel] += from_emitters;
+    }
+
+    res
+}
+
+/// Light arriving at every luxel after one more bounce
+fn bounce_light(
+    bsp: &Bsp,
+    faces: &[LightFace],
+    face_lookup: &[Option<usize>],
+    tracer: &WorldTracer,
+    previous: &[Vec<DVec3>],
+    options: &RadOptions,
+) -> Vec<Vec<DVec3>> {
+    let gather = |sample: DVec3, direction: DVec3| -> DVec3 {
+        let Some(hit) = tracer.trace(sample, sample + direction * SKY_DISTANCE) else {
+            return DVec3::ZERO;
+        };
+
+        let Some(node) = hit.node else {
+            return DVec3::ZERO;
+        };
+
+        let node = &bsp.nodes[node];
+        let first_face = node.first_face as usize;
+
+        (first_face..first_face + node.face_count as usize)
+            .filter_map(|face_idx| face_lookup[face_idx])
+            .find(|&light_face_idx| {
+                let face = &faces[light_face_idx];
+
+                face.normal.dot(direction) < 0. && face.contains_point(hit.point)
+            })
+            .map(|light_face_idx| {
+                let face = &faces[light_face_idx];
+
+                previous[light_face_idx][face.luxel_at(hit.point)] * face.reflectivity
+            })
+            .unwrap_or_default()
+    };
+
+    faces
+        .par_iter()
+        .map(|face| {
+            face.samples
+                .iter()
+                .enumerate()
+                .map(|(luxel, &sample)| {
+                    hemisphere_directions(face.normal, options.bounce_samples, luxel)
+                        .map(|direction| gather(sample, direction))
+                        .sum::<DVec3>()
+                        / options.bounce_samples.max(1) as f64
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Scales down too bright luxels keeping the color then applies gamma
+fn luxel_to_rgb(light: DVec3, gamma: f64) -> [u8; 3] {
+    let light = light.max(DVec3::ZERO);
+    let max = light.max_element();
+    let light = if max > 255. {
+        light * 255. / max
+    } else {
+        light
+    };
+
+    (light / 255.)
+        .powf(gamma)
+        .to_array()
+        .map(|e| (e * 255.).round() as u8)
+}
+
+/// Bakes lightmaps, replacing the existing ones.
+///
+/// Returns warnings.
+pub fn rad(bsp: &mut Bsp, options: &RadOptions) -> eyre::Result<Vec<String>> {
+    if bsp.models.is_empty() {
+        return err!("BSP has no model");
+    }
+
+    let mut warnings = vec![];
+
+    let reflectivity = texture_reflectivity(bsp, options, &mut warnings);
+    let offsets = model_offsets(bsp);
+
+    let tracer = WorldTracer::new(bsp);
+
+    let face_offsets = bsp
+        .models
+        .iter()
+        .enumerate()
+        .flat_map(|(model_idx, model)| {
+            (model.first_face..model.first_face + model.face_count)
+                .map(move |face_idx| (face_idx as usize, model_idx))
+        })
+        .fold(
+            vec![DVec3::ZERO; bsp.faces.len()],
+            |mut acc, (face_idx, model_idx)| {
+                if let Some(offset) = acc.get_mut(face_idx) {
+                    *offset = offsets[model_idx];
+                }
+
+                acc
+            },
+        );
+
+    let texlights = options
+        .texlights
+        .iter()
+        .map(|texlight| (texlight.texture.to_uppercase(), texlight.intensity))
+        .collect::<HashMap<String, DVec3>>();
+
+    let faces = (0..bsp.faces.len())
+        .into_par_iter()
+        .filter_map(|face_idx| {
+            let mut face = LightFace::new(bsp, face_idx, face_offsets[face_idx], &tracer)?;
+
+            let texture_idx = bsp.texinfo[bsp.faces[face_idx].texinfo as usize].texture_index;
+            let texture_name = bsp
+                .textures
+                .get(texture_idx as usize)
+                .map(|texture| texture.texture_name.get_string_standard().to_uppercase())
+                .unwrap_or_default();
+
+            face.reflectivity = reflectivity
+                .get(texture_idx as usize)
+                .copied()
+                .unwrap_or(DVec3::splat(DEFAULT_REFLECTIVITY));
+            face.emission = texlights.get(&texture_name).copied().unwrap_or_default();
+
+            Some(face)
+        })
+        .collect::<Vec<LightFace>>();
+
+    let face_lookup = faces.iter().enumerate().fold(
+        vec![None; bsp.faces.len()],
+        |mut acc, (light_face_idx, face)| {
+            acc[face.face] = Some(light_face_idx);
+            acc
+        },
+    );
+
+    let lights = bsp
+        .entities
+        .iter()
+        .filter_map(Light::from_entity)
+        .collect::<Vec<Light>>();
+
+    let emitters = faces
+        .iter()
+        .filter(|face| face.emission != DVec3::ZERO)
+        .flat_map(|face| {
+            let area = face.area / face.luxel_count() as f64;
+
+            face.samples.iter().map(move |&point| Emitter {
+                point,
+                normal: face.normal,
+                area,
+                intensity: face.emission,
+            })
+        })
+        .collect::<Vec<Emitter>>();
+
+    if lights.is_empty() && emitters.is_empty() {
+        warnings.push("No lights found, map is fullbright".to_string());
+    }
+
+    let mut face_lights = faces
+        .par_iter()
+        .map(|face| direct_light(face, &tracer, &lights, &emitters, options))
+        .collect::<Vec<FaceLight>>();
+
+    let mut previous = face_lights
+        .iter()
+        .map(|face_light| face_light[0].1.clone())
+        .collect::<Vec<Vec<DVec3>>>();
+
+    for _ in 0..options.bounces {
+        previous = bounce_light(bsp, &faces, &face_lookup, &tracer, &previous, options);
+
+        face_lights
+            .iter_mut()
+            .zip(previous.iter())
+            .for_each(|(face_light, bounced)| {
+                face_light[0]
+                    .1
+                    .iter_mut()
+                    .zip(bounced.iter())
+                    .for_each(|(light, bounced)| *light += *bounced);
+            });
+    }
+
+    // texlights are as bright as they shine
+    faces
+        .iter()
+        .zip(face_lights.iter_mut())
+        .filter(|(face, _)| face.emission != DVec3::ZERO)
+        .for_each(|(face, face_light)| {
+            face_light[0]
+                .1
+                .iter_mut()
+                .for_each(|light| *light += face.emission);
+        });
+
+    bsp.lightmap.clear();
+    bsp.faces.iter_mut().for_each(|face| {
+        face.styles = [255; 4];
+        face.lightmap_offset = -1;
+    });
+
+    let gamma = options.gamma;
+
+    for (face, mut face_light) in faces.iter().zip(face_lights) {
+        // style 0 stays first
+        face_light[1..].sort_by_key(|(style, _)| *style);
+
+        if face_light.len() > MAX_LIGHTMAPS {
+            warnings.push(format!(
+                "Face {} has {} light styles, only {MAX_LIGHTMAPS} are kept",
+                face.face,
+                face_light.len()
+            ));
+
+            face_light.truncate(MAX_LIGHTMAPS);
+        }
+
+        let bsp_face = &mut bsp.faces[face.face];
+
+        bsp_face.lightmap_offset = (bsp.lightmap.len() * 3).try_into()?;
+
+        for (style_idx, (style, light)) in face_light.into_iter().enumerate() {
+            bsp_face.styles[style_idx] = style;
+            bsp.lightmap
+                .extend(light.into_iter().map(|light| luxel_to_rgb(light, gamma)));
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Bakes lightmaps and writes the BSP back to the same path.
+///
+/// Texlights from `<bsp name>.rad` next to the BSP are added to the options.
+pub fn rad_file(bsp_path: impl AsRef<Path>, options: &RadOptions) -> eyre::Result<Vec<String>> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let mut texlights = options.texlights.clone();
+    let map_rad = bsp_path.with_extension("rad");
+
+    if map_rad.exists() {
+        let s = std::fs::read_to_string(&map_rad)?;
+        texlights.extend(parse_lights_rad(&s)?);
+    }
+
+    let options = RadOptions {
+        texlights,
+        wad_paths: options.wad_paths.clone(),
+        ..*options
+    };
+
+    let warnings = rad(&mut bsp, &options)?;
+
+    bsp.write_to_file(bsp_path)?;
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod test {
+    use map::{Attributes, Entity, Map};
+
+    use crate::{
+        modules::map2bsp::{Map2BspOptions, map2bsp},
+        utils::map_stuffs::hollow_box,
+    };
+
+    use super::*;
+
+    fn lit_box(top_texture: &str, light: &[(&str, &str)]) -> Bsp {
+        let mut map = Map::new();
+
+        map.entities.push(Entity {
+            attributes: Attributes::from([("classname".to_string(), "worldspawn".to_string())]),
+            brushes: Some(hollow_box(128., 16., Some(top_texture))),
+        });
+        map.entities.push(Entity {
+            attributes: light
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            brushes: None,
+        });
+
+        map2bsp(&map, &Map2BspOptions::default()).unwrap().bsp
+    }
+
+    /// Average style 0 brightness of faces facing `normal`
+    fn brightness(bsp: &Bsp, normal: DVec3) -> f64 {
+        let tracer = WorldTracer::new(bsp);
+
+        let luxels = (0..bsp.faces.len())
+            .filter_map(|face_idx| LightFace::new(bsp, face_idx, DVec3::ZERO, &tracer))
+            .filter(|face| face.normal.dot(normal) > 0.99)
+            .flat_map(|face| {
+                let start = bsp.faces[face.face].lightmap_offset as usize / 3;
+
+                bsp.lightmap[start..start + face.luxel_count()].to_vec()
+            })
+            .collect::<Vec<[u8; 3]>>();
+
+        luxels
+            .iter()
+            .map(|luxel| luxel.iter().map(|&e| e as f64).sum::<f64>() / 3.)
+            .sum::<f64>()
+            / luxels.len().max(1) as f64
+    }
+
+    fn options() -> RadOptions {
+        RadOptions {
+            bounces: 1,
+            bounce_samples: 8,
+            sky_samples: 8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn point_light() {
+        let mut bsp = lit_box(
+            "A",
+            &[
+                ("classname", "light"),
+                ("origin", "0 0 96"),
+                ("_light", "255 255 255 200"),
+            ],
+        );
+
+        rad(&mut bsp, &options()).unwrap();
+
+        assert!(bsp.faces.iter().all(|face| face.lightmap_offset >= 0));
+        assert!(
+            bsp.faces
+                .iter()
+                .all(|face| face.styles == [0, 255, 255, 255])
+        );
+
+        let (ceiling, floor) = (brightness(&bsp, DVec3::NEG_Z), brightness(&bsp, DVec3::Z));
+
+        assert!(ceiling > floor);
+        assert!(floor > 0.);
+
+        let bytes = bsp.write_to_bytes();
+        let parsed = Bsp::from_bytes(&bytes).unwrap();
+
+        assert_eq!(parsed.lightmap, bsp.lightmap);
+    }
+
+    #[test]
+    fn sun_through_sky() {
+        let mut bsp = lit_box(
+            "SKY",
+            &[
+                ("classname", "light_environment"),
+                ("origin", "0 0 0"),
+                ("pitch", "-90"),
+                ("_light", "255 255 255 100"),
+            ],
+        );
+
+        rad(&mut bsp, &options()).unwrap();
+
+        // sky has no lightmap
+        assert!(bsp.faces.iter().any(|face| face.lightmap_offset == -1));
+        assert!(brightness(&bsp, DVec3::Z) > 100.);
+    }
+
+    #[test]
+    fn light_style() {
+        let mut bsp = lit_box(
+            "A",
+            &[("classname", "light"), ("origin", "0 0 0"), ("style", "32")],
+        );
+
+        rad(&mut bsp, &options()).unwrap();
+
+        assert!(
+            bsp.faces
+                .iter()
+                .all(|face| face.styles == [0, 32, 255, 255])
+        );
+    }
+
+    #[test]
+    fn parse_lights() {
+        let texlights = parse_lights_rad(
+            "\
+// comment
+~light1 255 255 255 100
++0~LIGHT2 100 50 25 // trailing
+",
+        )
+        .unwrap();
+
+        assert_eq!(texlights.len(), 2);
+        assert_eq!(texlights[0].texture, "~LIGHT1");
+        assert_eq!(texlights[0].intensity, DVec3::splat(100.));
+        assert_eq!(texlights[1].intensity, DVec3::new(100., 50., 25.));
+
+        assert!(parse_lights_rad("~light1 255 255").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use bsp::{Bsp, LeafContent};
+use glam::DVec3;
+
+const ON_EPSILON: f64 = 0.01;
+
+pub fn dvec3(v: bsp::Vec3) -> DVec3 {
+    DVec3::new(v.x as f64, v.y as f64, v.z as f64)
+}
+
+/// What a line hits first
+pub struct Hit {
+    pub point: DVec3,
+    pub contents: LeafContent,
+    /// Node whose plane the line crossed to get into the blocking leaf.
+    ///
+    /// `None` when the line starts inside the blocking leaf.
+    pub node: Option<usize>,
+}
+
+fn is_blocking(contents: LeafContent) -> bool {
+    matches!(
+        contents,
+        LeafContent::ContentsSolid | LeafContent::ContentsSky
+    )
+}
+
+/// Hull 0 of the world model
+pub struct WorldTracer<'a> {
+    bsp: &'a Bsp,
+    head: i32,
+}
+
+impl<'a> WorldTracer<'a> {
+    pub fn new(bsp: &'a Bsp) -> Self {
+        Self {
+            bsp,
+            head: bsp
+                .models
+                .first()
+                .map(|model| model.head_nodes[0])
+                .unwrap_or(0),
+        }
+    }
+
+    fn plane_distance(&self, node: usize, point: DVec3) -> f64 {
+        let plane = &self.bsp.planes[self.bsp.nodes[node].plane as usize];
+
+        dvec3(plane.normal).dot(point) - plane.distance as f64
+    }
+
+    pub fn point_contents(&self, point: DVec3) -> LeafContent {
+        let mut node = self.head;
+
+        while node >= 0 {
+            let side = self.plane_distance(node as usize, point) < 0.;
+            node = self.bsp.nodes[node as usize].children[side as usize] as i32;
+        }
+
+        self.bsp.leaves[(-node - 1) as usize].contents
+    }
+
+    /// Finds the first solid or sky leaf from `start` to `end`
+    pub fn trace(&self, start: DVec3, end: DVec3) -> Option<Hit> {
+        self.trace_r(self.head, start, end)
+    }
+
+    fn trace_r(&self, node: i32, start: DVec3, end: DVec3) -> Option<Hit> {
+        if node < 0 {
+            let contents = self.bsp.leaves[(-node - 1) as usize].contents;
+
+            return is_blocking(contents).then_some(Hit {
+                point: start,
+                contents,
+                node: None,
+            });
+        }
+
+        let children = self.bsp.nodes[node as usize].children;
+        let front = self.plane_distance(node as usize, start);
+        let back = self.plane_distance(node as usize, end);
+
+        if front >= -ON_EPSILON && back >= -ON_EPSILON {
+            return self.trace_r(children[0] as i32, start, end);
+        }
+
+        if front < ON_EPSILON && back < ON_EPSILON {
+            return self.trace_r(children[1] as i32, start, end);
+        }
+
+        let side = (front < 0.) as usize;
+        let mid = start + (end - start) * (front / (front - back));
+
+        if let Some(hit) = self.trace_r(children[side] as i32, start, mid) {
+            return Some(hit);
+        }
+
+        self.trace_r(children[side ^ 1] as i32, mid, end)
+            .map(|hit| Hit {
+                node: hit.node.or(Some(node as usize)),
+                ..hit
+            })
+    }
+
+    /// Line does not hit anything between the two points
+    pub fn is_visible(&self, start: DVec3, end: DVec3) -> bool {
+        self.trace(start, end).is_none()
+    }
+}
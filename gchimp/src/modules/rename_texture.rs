@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 
+use bsp::Bsp;
+use common::img_stuffs::{GenerateMipmapsResult, generate_mipmaps_from_rgba_image};
+use image::{RgbaImage, imageops};
 use map::{Map, TextureName};
+use wad::types::MipTex;
+
+use crate::err;
 
 pub const RENAMETEX_ENTITY_NAME: &str = "gchimp_renametex";
 
@@ -54,3 +60,239 @@ pub fn rename_texture(map: &mut Map) -> usize {
 
     count
 }
+
+/// Texinfos of the BSP using a texture
+#[derive(Debug, Clone)]
+pub struct BspTextureUsage {
+    pub texture_idx: usize,
+    pub name: String,
+    pub embedded: bool,
+    pub dimensions: (u32, u32),
+    pub texinfos: Vec<usize>,
+    pub face_count: usize,
+}
+
+pub fn bsp_texture_usage(bsp: &Bsp) -> Vec<BspTextureUsage> {
+    let mut res = bsp
+        .textures
+        .iter()
+        .enumerate()
+        .map(|(texture_idx, texture)| BspTextureUsage {
+            texture_idx,
+            name: texture.texture_name.get_string(),
+            embedded: !texture.is_external(),
+            dimensions: (texture.width, texture.height),
+            texinfos: vec![],
+            face_count: 0,
+        })
+        .collect::<Vec<BspTextureUsage>>();
+
+    bsp.texinfo
+        .iter()
+        .enumerate()
+        .for_each(|(texinfo_idx, texinfo)| {
+            if let Some(usage) = res.get_mut(texinfo.texture_index as usize) {
+                usage.texinfos.push(texinfo_idx);
+            }
+        });
+
+    bsp.faces.iter().for_each(|face| {
+        let texture_idx = bsp.texinfo[face.texinfo as usize].texture_index as usize;
+
+        if let Some(usage) = res.get_mut(texture_idx) {
+            usage.face_count += 1;
+        }
+    });
+
+    res
+}
+
+/// Renames textures of the BSP with case insensitive keys and returns how many are renamed
+pub fn rename_bsp_textures(
+    bsp: &mut Bsp,
+    mapping: &HashMap<String, String>,
+) -> eyre::Result<usize> {
+    let mapping: HashMap<String, &String> = mapping
+        .iter()
+        .map(|(key, value)| (key.to_uppercase(), value))
+        .collect();
+
+    let mut count = 0;
+
+    for texture in bsp.textures.iter_mut() {
+        let Some(new_name) = mapping.get(&texture.texture_name.get_string_standard()) else {
+            continue;
+        };
+
+        if let Err(err) = texture.texture_name.set_name(new_name.as_str()) {
+            return err!("Cannot rename texture to `{new_name}`: {err}");
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Replaces the pixels of an embedded texture, the image is resized to the texture.
+///
+/// Transparent pixels become the last palette color for `{` textures.
+pub fn replace_bsp_texture(bsp: &mut Bsp, name: &str, image: RgbaImage) -> eyre::Result<()> {
+    let Some(texture) = bsp
+        .textures
+        .iter_mut()
+        .find(|texture| texture.texture_name.get_string_standard() == name.to_uppercase())
+    else {
+        return err!("Cannot find texture `{name}`");
+    };
+
+    if texture.is_external() {
+        return err!("Texture `{name}` is not embedded in the BSP");
+    }
+
+    let (width, height) = (texture.width, texture.height);
+
+    let image = if image.dimensions() == (width, height) {
+        image
+    } else {
+        imageops::resize(&image, width, height, imageops::FilterType::Triangle)
+    };
+
+    let transparent_pixel = image.pixels().position(|pixel| pixel.0[3] == 0);
+
+    let GenerateMipmapsResult {
+        mut mips,
+        mut palette,
+        dimensions,
+    } = generate_mipmaps_from_rgba_image(image)?;
+
+    if dimensions != (width, height) {
+        return err!(
+            "Texture `{name}` is {width}x{height} which a GoldSrc texture cannot be replaced with"
+        );
+    }
+
+    palette.resize(256, [0; 3]);
+
+    // transparent color must be the last one
+    if let Some(pixel_idx) = transparent_pixel.filter(|_| name.starts_with('{')) {
+        let transparent = mips[0][pixel_idx];
+
+        palette.swap(transparent as usize, 255);
+
+        mips.iter_mut().flatten().for_each(|palette_idx| {
+            if *palette_idx == transparent {
+                *palette_idx = 255;
+            } else if *palette_idx == 255 {
+                *palette_idx = transparent;
+            }
+        });
+    }
+
+    let mips = mips
+        .iter()
+        .map(|mip| mip.as_slice())
+        .collect::<Vec<&[u8]>>();
+
+    *texture = MipTex::new(
+        texture.texture_name.get_string(),
+        dimensions,
+        &mips,
+        palette,
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rename_bsp() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let old_name = bsp.textures[0].texture_name.get_string();
+
+        let count = rename_bsp_textures(
+            &mut bsp,
+            &HashMap::from([(old_name.to_lowercase(), "RENAMED".to_string())]),
+        )
+        .unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(bsp.textures[0].texture_name.get_string(), "RENAMED");
+
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+
+        assert_eq!(bsp.textures[0].texture_name.get_string(), "RENAMED");
+    }
+
+    #[test]
+    fn rename_bsp_too_long() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let old_name = bsp.textures[0].texture_name.get_string();
+
+        assert!(
+            rename_bsp_textures(
+                &mut bsp,
+                &HashMap::from([(old_name, "A_VERY_LONG_TEXTURE_NAME".to_string())]),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn usage() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let usage = bsp_texture_usage(&bsp);
+
+        assert_eq!(usage.len(), bsp.textures.len());
+        assert_eq!(
+            usage
+                .iter()
+                .map(|usage| usage.texinfos.len())
+                .sum::<usize>(),
+            bsp.texinfo.len()
+        );
+        assert_eq!(
+            usage.iter().map(|usage| usage.face_count).sum::<usize>(),
+            bsp.faces.len()
+        );
+    }
+
+    #[test]
+    fn replace() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+
+        let texture_idx = bsp
+            .textures
+            .iter()
+            .position(|texture| !texture.is_external())
+            .unwrap();
+        let texture = &bsp.textures[texture_idx];
+        let (name, dimensions) = (
+            texture.texture_name.get_string(),
+            (texture.width, texture.height),
+        );
+
+        // wrong size on purpose
+        let image = RgbaImage::from_pixel(64, 32, image::Rgba([255, 0, 0, 255]));
+
+        replace_bsp_texture(&mut bsp, &name, image).unwrap();
+
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        let texture = &bsp.textures[texture_idx];
+
+        assert_eq!(texture.texture_name.get_string(), name);
+        assert_eq!((texture.width, texture.height), dimensions);
+
+        let (image, _) = texture.to_rgb();
+
+        // quantizing can shift the color a bit
+        assert!(
+            image
+                .chunks_exact(3)
+                .all(|pixel| pixel[0] > 200 && pixel[1] < 32 && pixel[2] < 32)
+        );
+    }
+}
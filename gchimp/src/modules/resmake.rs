@@ -475,7 +475,7 @@ impl ResMake {
     }
 }
 
-fn need_external_wad(bsp: &Bsp) -> HashSet<String> {
+pub(crate) fn need_external_wad(bsp: &Bsp) -> HashSet<String> {
     let mut external_textures: HashSet<String> = HashSet::<String>::new();
 
     for texture in &bsp.textures {
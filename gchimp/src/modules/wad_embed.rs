@@ -0,0 +1,312 @@
+use std::path::{Path, PathBuf};
+
+use bsp::Bsp;
+use wad::types::{Entry, MipTex, Wad};
+
+use crate::{
+    err,
+    modules::resmake::need_external_wad,
+    utils::{
+        misc::{COMMON_GAME_MODS, DefaultResource, find_files_with_ext_in_folder},
+        wad_stuffs::external_miptex,
+    },
+};
+
+pub struct WadEmbedOptions {
+    /// Only embeds textures from WADs that do not come with the game
+    pub only_custom: bool,
+    /// WADs looked up before the ones in the game folder
+    pub wad_paths: Vec<PathBuf>,
+    /// Folder with the game mods, defaults to the one the BSP is in
+    pub game_dir: Option<PathBuf>,
+}
+
+impl Default for WadEmbedOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WadEmbedOptions {
+    pub fn new() -> Self {
+        Self {
+            only_custom: false,
+            wad_paths: vec![],
+            game_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WadEmbedResult {
+    pub embedded: Vec<String>,
+    /// Default textures left out with `only_custom`
+    pub skipped: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+fn wad_file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// File names of the WADs in a worldspawn `wad` key
+fn wad_key_files(wad_key: &str) -> Vec<String> {
+    wad_key
+        .split_terminator(';')
+        .filter(|path| !path.is_empty())
+        .map(|path| wad_file_name(Path::new(&path.replace('\\', "/"))))
+        .collect()
+}
+
+/// WADs in the game mod folders, those in the worldspawn `wad` key first
+fn game_wads(game_dir: &Path, bsp: &Bsp) -> Vec<PathBuf> {
+    let wanted = bsp
+        .entities
+        .first()
+        .and_then(|entity| entity.get("wad"))
+        .map(|wad_key| wad_key_files(wad_key))
+        .unwrap_or_default();
+
+    let mut res = COMMON_GAME_MODS
+        .iter()
+        .filter_map(|game_mod| find_files_with_ext_in_folder(&game_dir.join(game_mod), "wad").ok())
+        .flatten()
+        .collect::<Vec<PathBuf>>();
+
+    res.sort_by_key(|path| {
+        let file_name = wad_file_name(path);
+
+        wanted
+            .iter()
+            .position(|wad| *wad == file_name)
+            .unwrap_or(wanted.len())
+    });
+
+    res
+}
+
+/// `<game>/<mod>/maps/<map>.bsp`
+pub(crate) fn bsp_game_dir(bsp_path: &Path) -> Option<PathBuf> {
+    bsp_path
+        .parent()
+        .and_then(|maps| maps.parent())
+        .and_then(|game_mod| game_mod.parent())
+        .map(Path::to_path_buf)
+}
+
+/// WADs that can be read from the paths and then from the game folder
+pub(crate) fn load_wads(
+    bsp: &Bsp,
+    wad_paths: &[PathBuf],
+    game_dir: Option<&Path>,
+) -> Vec<(PathBuf, Wad)> {
+    let wad_paths = wad_paths
+        .iter()
+        .cloned()
+        .chain(
+            game_dir
+                .map(|game_dir| game_wads(game_dir, bsp))
+                .unwrap_or_default(),
+        )
+        .collect::<Vec<PathBuf>>();
+
+    // some WADs in the game folder are not WAD3
+    wad_paths
+        .into_iter()
+        .filter_map(|path| Wad::from_file(&path).ok().map(|wad| (path, wad)))
+        .collect()
+}
+
+/// Embeds external textures found in the WADs, earlier WADs go first
+pub fn embed_wad_textures(
+    bsp: &mut Bsp,
+    wads: &[(PathBuf, Wad)],
+    only_custom: bool,
+) -> WadEmbedResult {
+    let mut res = WadEmbedResult::default();
+    let mut external = need_external_wad(bsp).into_iter().collect::<Vec<String>>();
+
+    external.sort();
+
+    for texture_name in external {
+        let found = wads.iter().find_map(|(path, wad)| {
+            wad.entries
+                .iter()
+                .find(|entry| entry.texture_name_standard() == texture_name)
+                .and_then(|entry| entry.file_entry.get_mip_tex())
+                .map(|miptex| (path, miptex))
+        });
+
+        let Some((path, miptex)) = found else {
+            res.missing.push(texture_name);
+            continue;
+        };
+
+        if only_custom && DefaultResource.is_default_resource(wad_file_name(path)) {
+            res.skipped.push(texture_name);
+            continue;
+        }
+
+        bsp.textures
+            .iter_mut()
+            .filter(|texture| texture.texture_name.get_string_standard() == texture_name)
+            .for_each(|texture| {
+                // keeps the name as it is in the BSP
+                *texture = MipTex {
+                    texture_name: texture.texture_name.clone(),
+                    ..miptex.clone()
+                };
+            });
+
+        res.embedded.push(texture_name);
+    }
+
+    res
+}
+
+/// Moves embedded textures into a new WAD and adds it to the worldspawn `wad` key
+pub fn strip_wad_textures(bsp: &mut Bsp, wad_name: &str) -> Wad {
+    let mut wad = Wad::new();
+
+    bsp.textures
+        .iter_mut()
+        .filter(|texture| !texture.is_external())
+        .for_each(|texture| {
+            let mip_maps = texture
+                .mip_images
+                .iter()
+                .map(|image| image.data.get_bytes().as_slice())
+                .collect::<Vec<&[u8]>>();
+
+            wad.entries.push(Entry::new(
+                texture.texture_name.get_string(),
+                (texture.width, texture.height),
+                &mip_maps,
+                texture.palette.get_bytes().as_slice(),
+            ));
+            wad.header.num_dirs += 1;
+
+            *texture = external_miptex(texture.clone());
+        });
+
+    if wad.entries.is_empty() {
+        return wad;
+    }
+
+    if let Some(worldspawn) = bsp.entities.first_mut() {
+        let wads = worldspawn.entry("wad".to_string()).or_default();
+
+        if !wad_key_files(wads).contains(&wad_name.to_lowercase()) {
+            if !wads.is_empty() && !wads.ends_with(';') {
+                wads.push(';');
+            }
+
+            wads.push_str(wad_name);
+            wads.push(';');
+        }
+    }
+
+    wad
+}
+
+/// Embeds external textures of the BSP in place
+pub fn embed_wad_textures_file(
+    bsp_path: impl AsRef<Path>,
+    options: &WadEmbedOptions,
+) -> eyre::Result<WadEmbedResult> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let game_dir = options.game_dir.clone().or_else(|| bsp_game_dir(bsp_path));
+
+    let wads = load_wads(&bsp, &options.wad_paths, game_dir.as_deref());
+
+    if wads.is_empty() {
+        return err!("Cannot find any WAD to embed textures from");
+    }
+
+    let res = embed_wad_textures(&mut bsp, &wads, options.only_custom);
+
+    bsp.write_to_file(bsp_path)?;
+
+    Ok(res)
+}
+
+/// Strips embedded textures of the BSP in place into `<bsp>.wad` and returns its path
+pub fn strip_wad_textures_file(bsp_path: impl AsRef<Path>) -> eyre::Result<PathBuf> {
+    let bsp_path = bsp_path.as_ref();
+    let wad_path = bsp_path.with_extension("wad");
+
+    let mut bsp = Bsp::from_file(bsp_path)?;
+    let wad = strip_wad_textures(&mut bsp, &wad_file_name(&wad_path));
+
+    if wad.entries.is_empty() {
+        return err!("BSP has no embedded textures");
+    }
+
+    wad.write_to_file(&wad_path)?;
+    bsp.write_to_file(bsp_path)?;
+
+    Ok(wad_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strip_embed() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let embedded = bsp.textures.iter().filter(|t| !t.is_external()).count();
+
+        let wad = strip_wad_textures(&mut bsp, "normal.wad");
+
+        assert_eq!(wad.entries.len(), embedded);
+        assert!(bsp.textures.iter().all(|texture| texture.is_external()));
+        assert!(bsp.entities[0]["wad"].ends_with("normal.wad;"));
+
+        // goes through bytes like it would on disk
+        let wad = Wad::from_bytes(&wad.write_to_bytes()).unwrap();
+        let mut bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+
+        let res = embed_wad_textures(&mut bsp, &[(PathBuf::from("normal.wad"), wad)], false);
+
+        assert_eq!(res.embedded.len(), embedded);
+        assert!(res.missing.is_empty());
+
+        let original =
+            Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+
+        bsp.textures
+            .iter()
+            .zip(original.textures.iter())
+            .for_each(|(texture, original)| {
+                assert_eq!(texture.is_external(), original.is_external());
+                assert_eq!(
+                    texture
+                        .mip_images
+                        .first()
+                        .map(|image| image.data.get_bytes()),
+                    original
+                        .mip_images
+                        .first()
+                        .map(|image| image.data.get_bytes())
+                );
+            });
+    }
+
+    #[test]
+    fn only_custom() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let wad = strip_wad_textures(&mut bsp, "normal.wad");
+        let count = wad.entries.len();
+
+        let res = embed_wad_textures(&mut bsp, &[(PathBuf::from("halflife.wad"), wad)], true);
+
+        assert!(res.embedded.is_empty());
+        assert_eq!(res.skipped.len(), count);
+    }
+}
@@ -0,0 +1,523 @@
+//! Brush BSP, the part of a map compiler that turns brushes into a tree of convex leaves.
+//!
+//! Brushes are split by their own sides until every leaf is either fully inside or fully outside
+//! of each brush. Portals are then generated between the leaves so we can flood fill the tree.
+//!
+//! Unlike [`Solid3D`], brush sides here have their normals pointing OUT of the brush.
+use std::collections::HashMap;
+
+use bsp::LeafContent;
+use glam::DVec3;
+use map::Brush;
+
+use common::constants::{CLIP_TEXTURE, CONTENTWATER_TEXTURE, ORIGIN_TEXTURE};
+
+use super::{
+    map_stuffs::brush_to_solid3d,
+    simple_calculs::{Plane3D, Point3D, Polygon3D, Solid3D},
+};
+
+mod portal;
+mod tree;
+
+pub use portal::*;
+pub use tree::*;
+
+/// Anything beyond this is not a real map
+pub const BOGUS_RANGE: f64 = 65536.;
+/// How far a point can be from a plane and still be considered on it
+pub const ON_EPSILON: f64 = 0.1;
+/// Brushes barely crossing a plane by this much are not split
+pub const PLANESIDE_EPSILON: f64 = 0.001;
+/// Polygons without 3 edges longer than this are discarded
+pub const EDGE_LENGTH: f64 = 0.2;
+
+const NORMAL_EPSILON: f64 = 0.00001;
+const DIST_EPSILON: f64 = 0.01;
+
+/// Deduplicated list of planes.
+///
+/// Every plane is stored next to its flipped self so `plane ^ 1` is always the opposite plane.
+/// The even one is the one facing the positive axis.
+#[derive(Debug, Default, Clone)]
+pub struct PlaneSet {
+    planes: Vec<Plane3D>,
+    lookup: HashMap<[i64; 4], usize>,
+}
+
+impl PlaneSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, idx: usize) -> &Plane3D {
+        &self.planes[idx]
+    }
+
+    pub fn len(&self) -> usize {
+        self.planes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.planes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Plane3D> {
+        self.planes.iter()
+    }
+
+    /// Returns true if the plane is perpendicular to one of the axes
+    pub fn is_axial(&self, idx: usize) -> bool {
+        let normal = self.planes[idx].normal();
+
+        normal.as_array().iter().any(|e| e.abs() == 1.)
+    }
+
+    /// Returns the index of the plane, inserting it if needed.
+    pub fn find_or_insert(&mut self, plane: &Plane3D) -> usize {
+        let plane = snap_plane(plane);
+        let key = plane_key(&plane);
+
+        if let Some(&idx) = self.lookup.get(&key) {
+            return idx;
+        }
+
+        let flipped = plane.get_backplane();
+
+        let (positive, negative, is_flipped) = if is_positive_facing(&plane) {
+            (plane, flipped, false)
+        } else {
+            (flipped, plane, true)
+        };
+
+        let idx = self.planes.len();
+
+        self.lookup.insert(plane_key(&positive), idx);
+        self.lookup.insert(plane_key(&negative), idx + 1);
+        self.planes.push(positive);
+        self.planes.push(negative);
+
+        if is_flipped { idx + 1 } else { idx }
+    }
+}
+
+fn snap_plane(plane: &Plane3D) -> Plane3D {
+    let plane = plane.normalized();
+    let mut normal = plane.normal().as_array();
+
+    // snap to axis
+    if let Some(axis) = (0..3).find(|&axis| (normal[axis].abs() - 1.).abs() < NORMAL_EPSILON) {
+        normal = [0.; 3];
+        normal[axis] = plane.normal().as_array()[axis].signum();
+    }
+
+    let mut distance = plane.distance();
+
+    if (distance - distance.round()).abs() < DIST_EPSILON {
+        distance = distance.round();
+    }
+
+    Plane3D::new(normal[0], normal[1], normal[2], distance)
+}
+
+fn plane_key(plane: &Plane3D) -> [i64; 4] {
+    [
+        (plane.x / NORMAL_EPSILON).round() as i64,
+        (plane.y / NORMAL_EPSILON).round() as i64,
+        (plane.z / NORMAL_EPSILON).round() as i64,
+        (plane.w / DIST_EPSILON).round() as i64,
+    ]
+}
+
+fn is_positive_facing(plane: &Plane3D) -> bool {
+    let normal = plane.normal().as_array();
+    let major = (0..3)
+        .max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs()))
+        .unwrap();
+
+    normal[major] > 0.
+}
+
+#[derive(Debug, Clone)]
+pub struct BrushSide {
+    /// Index into [`PlaneSet`], normal points out of the brush
+    pub plane: usize,
+    pub winding: Polygon3D,
+    /// Index into whatever texture info table the caller has, `None` for sides created by splits
+    pub texinfo: Option<usize>,
+    /// Sides from the original brush, which are preferred as splitters
+    pub visible: bool,
+    /// Already used as a splitter by some parent node
+    pub tested: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BspBrush {
+    pub sides: Vec<BrushSide>,
+    pub contents: LeafContent,
+    pub mins: DVec3,
+    pub maxs: DVec3,
+    /// Index of the entity in the original map
+    pub entity: usize,
+    /// Index of the brush inside the entity
+    pub brush: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaneSide {
+    Front,
+    Back,
+    Both,
+}
+
+impl BspBrush {
+    /// Creates a brush from planes with normals pointing outward.
+    ///
+    /// Returns `None` if the planes do not make a closed brush.
+    pub fn from_planes(
+        plane_set: &mut PlaneSet,
+        planes: &[(Plane3D, Option<usize>)],
+        contents: LeafContent,
+        entity: usize,
+        brush: usize,
+    ) -> Option<Self> {
+        let mut sides: Vec<BrushSide> = vec![];
+
+        for (plane, texinfo) in planes {
+            let plane = plane_set.find_or_insert(plane);
+
+            // duplicate planes are just ignored
+            if sides.iter().any(|side| side.plane == plane) {
+                continue;
+            }
+
+            sides.push(BrushSide {
+                plane,
+                winding: Polygon3D::default(),
+                texinfo: *texinfo,
+                visible: true,
+                tested: false,
+            });
+        }
+
+        let mut res = Self {
+            sides,
+            contents,
+            mins: DVec3::MAX,
+            maxs: DVec3::MIN,
+            entity,
+            brush,
+        };
+
+        res.create_windings(plane_set);
+        res.sides.retain(|side| !side.winding.vertices().is_empty());
+
+        if res.sides.len() < 4 || !res.bound() {
+            return None;
+        }
+
+        Some(res)
+    }
+
+    /// Creates a brush from a .map brush.
+    ///
+    /// `texinfo` is called for every brush plane in order.
+    pub fn from_map_brush(
+        plane_set: &mut PlaneSet,
+        brush: &Brush,
+        contents: LeafContent,
+        entity: usize,
+        brush_idx: usize,
+        mut texinfo: impl FnMut(usize) -> Option<usize>,
+    ) -> Option<Self> {
+        let solid: Solid3D = brush_to_solid3d(brush);
+
+        // solid3d normals point inward
+        let planes = solid
+            .faces()
+            .iter()
+            .enumerate()
+            .map(|(idx, plane)| (plane.get_backplane(), texinfo(idx)))
+            .collect::<Vec<_>>();
+
+        Self::from_planes(plane_set, &planes, contents, entity, brush_idx)
+    }
+
+    fn create_windings(&mut self, plane_set: &PlaneSet) {
+        let planes = self.sides.iter().map(|side| side.plane).collect::<Vec<_>>();
+
+        self.sides.iter_mut().for_each(|side| {
+            let mut winding = Polygon3D::base_winding(plane_set.get(side.plane), BOGUS_RANGE);
+
+            for &other in planes.iter() {
+                if other == side.plane || winding.vertices().is_empty() {
+                    continue;
+                }
+
+                // keep the part behind the other plane
+                winding = winding.clip(plane_set.get(other ^ 1), 0.);
+            }
+
+            side.winding = winding;
+        });
+    }
+
+    /// Updates bounds, returns false if the brush is bogus
+    pub fn bound(&mut self) -> bool {
+        self.mins = DVec3::MAX;
+        self.maxs = DVec3::MIN;
+
+        self.sides
+            .iter()
+            .flat_map(|side| side.winding.vertices())
+            .for_each(|vertex| {
+                self.mins = self.mins.min(vertex.to_dvec3());
+                self.maxs = self.maxs.max(vertex.to_dvec3());
+            });
+
+        self.mins.cmpge(DVec3::splat(-BOGUS_RANGE / 2.)).all()
+            && self.maxs.cmple(DVec3::splat(BOGUS_RANGE / 2.)).all()
+            && self.mins.cmplt(self.maxs).all()
+    }
+
+    pub fn volume(&self, plane_set: &PlaneSet) -> f64 {
+        let Some(corner) = self
+            .sides
+            .iter()
+            .find_map(|side| side.winding.vertices().first())
+        else {
+            return 0.;
+        };
+
+        self.sides
+            .iter()
+            .map(|side| {
+                let plane = plane_set.get(side.plane);
+                let height = -plane.distance_to_point(*corner);
+
+                height * side.winding.area() / 3.
+            })
+            .sum()
+    }
+
+    /// Which side of the plane the brush is on, and whether the brush has a side on that plane
+    pub fn test_plane(&self, plane_set: &PlaneSet, plane: usize) -> (PlaneSide, bool) {
+        if self.sides.iter().any(|side| side.plane == plane) {
+            return (PlaneSide::Back, true);
+        }
+
+        if self.sides.iter().any(|side| side.plane == plane ^ 1) {
+            return (PlaneSide::Front, true);
+        }
+
+        let split_plane = plane_set.get(plane);
+
+        let (d_front, d_back) = self
+            .sides
+            .iter()
+            .flat_map(|side| side.winding.vertices())
+            .map(|vertex| split_plane.distance_to_point(*vertex))
+            .fold((0f64, 0f64), |(front, back), d| (front.max(d), back.min(d)));
+
+        let side = match (d_front > ON_EPSILON, d_back < -ON_EPSILON) {
+            (true, true) => PlaneSide::Both,
+            (true, false) => PlaneSide::Front,
+            (false, true) => PlaneSide::Back,
+            // very tiny brush, just put it somewhere
+            (false, false) => {
+                if d_front > -d_back {
+                    PlaneSide::Front
+                } else {
+                    PlaneSide::Back
+                }
+            }
+        };
+
+        (side, false)
+    }
+
+    fn most_on_side(&self, split_plane: &Plane3D) -> PlaneSide {
+        let (d_front, d_back) = self
+            .sides
+            .iter()
+            .flat_map(|side| side.winding.vertices())
+            .map(|vertex| split_plane.distance_to_point(*vertex))
+            .fold((0f64, 0f64), |(front, back), d| (front.max(d), back.min(d)));
+
+        if d_front > -d_back {
+            PlaneSide::Front
+        } else {
+            PlaneSide::Back
+        }
+    }
+
+    /// Splits the brush with a plane, returning the front and back part
+    pub fn split(&self, plane_set: &PlaneSet, plane: usize) -> (Option<Self>, Option<Self>) {
+        let split_plane = plane_set.get(plane);
+
+        let (d_front, d_back) = self
+            .sides
+            .iter()
+            .flat_map(|side| side.winding.vertices())
+            .map(|vertex| split_plane.distance_to_point(*vertex))
+            .fold((0f64, 0f64), |(front, back), d| (front.max(d), back.min(d)));
+
+        if d_front < PLANESIDE_EPSILON {
+            return (None, Some(self.clone()));
+        }
+
+        if d_back > -PLANESIDE_EPSILON {
+            return (Some(self.clone()), None);
+        }
+
+        // the new face on the split plane
+        let mut mid_winding = Polygon3D::base_winding(split_plane, BOGUS_RANGE);
+
+        for side in &self.sides {
+            if mid_winding.vertices().is_empty() {
+                break;
+            }
+
+            mid_winding = mid_winding.clip(plane_set.get(side.plane ^ 1), 0.);
+        }
+
+        if mid_winding.vertices().is_empty() || mid_winding.is_tiny(EDGE_LENGTH) {
+            return match self.most_on_side(split_plane) {
+                PlaneSide::Front => (Some(self.clone()), None),
+                _ => (None, Some(self.clone())),
+            };
+        }
+
+        let mut halves = [self.clone(), self.clone()];
+
+        halves.iter_mut().for_each(|half| half.sides.clear());
+
+        for side in &self.sides {
+            let (front, back) = side.winding.chop(split_plane, 0.);
+
+            for (half, winding) in halves.iter_mut().zip([front, back]) {
+                if winding.vertices().is_empty() {
+                    continue;
+                }
+
+                half.sides.push(BrushSide {
+                    winding,
+                    ..side.clone()
+                });
+            }
+        }
+
+        let valid = halves
+            .iter_mut()
+            .map(|half| half.sides.len() >= 3 && half.bound())
+            .collect::<Vec<bool>>();
+
+        match (valid[0], valid[1]) {
+            (true, true) => (),
+            (true, false) => return (Some(self.clone()), None),
+            (false, true) => return (None, Some(self.clone())),
+            (false, false) => return (None, None),
+        }
+
+        // front half faces backward on the split plane and vice versa
+        halves.iter_mut().enumerate().for_each(|(idx, half)| {
+            half.sides.push(BrushSide {
+                plane: plane ^ idx ^ 1,
+                winding: if idx == 0 {
+                    mid_winding.flip()
+                } else {
+                    mid_winding.clone()
+                },
+                texinfo: None,
+                visible: false,
+                tested: false,
+            });
+        });
+
+        let [front, back] = halves;
+
+        (Some(front), Some(back))
+    }
+}
+
+/// Returns the contents of a .map brush based on its textures.
+///
+/// Returns `None` for brushes that do not belong in the visible hull, such as HINT and SKIP.
+pub fn map_brush_contents(brush: &Brush) -> Option<LeafContent> {
+    let textures = brush
+        .planes
+        .iter()
+        .map(|plane| plane.texture_name.get_string_standard())
+        .collect::<Vec<String>>();
+
+    if textures.iter().any(|texture| texture == ORIGIN_TEXTURE) {
+        return Some(LeafContent::ContentsOrigin);
+    }
+
+    if textures
+        .iter()
+        .all(|texture| matches!(texture.as_str(), "HINT" | "SKIP" | "BEVEL" | "CONTENTEMPTY"))
+    {
+        return None;
+    }
+
+    if textures.iter().all(|texture| texture == CLIP_TEXTURE) {
+        return Some(LeafContent::ContentsClip);
+    }
+
+    if let Some(liquid) = textures.iter().find(|texture| texture.starts_with('!')) {
+        return Some(if liquid.contains("LAVA") {
+            LeafContent::ContentsLava
+        } else if liquid.contains("SLIME") {
+            LeafContent::ContentsSlime
+        } else {
+            LeafContent::ContentsWater
+        });
+    }
+
+    if textures
+        .iter()
+        .any(|texture| texture == CONTENTWATER_TEXTURE)
+    {
+        return Some(LeafContent::ContentsWater);
+    }
+
+    if textures.iter().all(|texture| texture == "SKY") {
+        return Some(LeafContent::ContentsSky);
+    }
+
+    Some(LeafContent::ContentsSolid)
+}
+
+/// Contents that block flood fill and sight
+pub fn is_opaque(contents: LeafContent) -> bool {
+    matches!(
+        contents,
+        LeafContent::ContentsSolid | LeafContent::ContentsSky
+    )
+}
+
+/// When brushes with different contents end up in the same leaf, the stronger one wins
+pub fn contents_priority(contents: LeafContent) -> u32 {
+    match contents {
+        LeafContent::ContentsSolid => 7,
+        LeafContent::ContentsSky => 6,
+        LeafContent::ContentsLava => 5,
+        LeafContent::ContentsSlime => 4,
+        LeafContent::ContentsWater => 3,
+        LeafContent::ContentsCurrent0
+        | LeafContent::ContentsCurrent90
+        | LeafContent::ContentsCurrent180
+        | LeafContent::ContentsCurrent270
+        | LeafContent::ContentsCurrentUp
+        | LeafContent::ContentsCurrentDown => 2,
+        LeafContent::ContentsTranslucent => 1,
+        _ => 0,
+    }
+}
+
+pub(super) fn point_bounds(points: impl Iterator<Item = Point3D>) -> (DVec3, DVec3) {
+    points.fold((DVec3::MAX, DVec3::MIN), |(mins, maxs), point| {
+        (mins.min(point.to_dvec3()), maxs.max(point.to_dvec3()))
+    })
+}
@@ -0,0 +1,376 @@
+use std::collections::VecDeque;
+
+use bsp::LeafContent;
+use glam::DVec3;
+
+use crate::utils::simple_calculs::{Plane3D, Point3D, Polygon3D};
+
+use super::{BOGUS_RANGE, BrushTree, BspNode, EDGE_LENGTH, ON_EPSILON, is_opaque, point_bounds};
+
+/// Extra space between the world and the outside leaf
+const HEADNODE_PADDING: f64 = 8.;
+const SPLIT_EPSILON: f64 = 0.001;
+
+#[derive(Debug, Clone)]
+pub struct Portal {
+    /// Normal points toward `nodes[0]`
+    pub plane: Plane3D,
+    /// Node whose plane made the portal, `None` for portals to the outside leaf
+    pub on_node: Option<usize>,
+    pub nodes: [usize; 2],
+    pub winding: Polygon3D,
+}
+
+impl Portal {
+    /// The other node of the portal
+    pub fn other(&self, node: usize) -> usize {
+        if self.nodes[0] == node {
+            self.nodes[1]
+        } else {
+            self.nodes[0]
+        }
+    }
+}
+
+impl BrushTree {
+    fn add_portal(&mut self, portal: Portal) -> usize {
+        let idx = self.portals.len();
+
+        self.attach_portal(idx, portal.nodes);
+        self.portals.push(portal);
+
+        idx
+    }
+
+    fn attach_portal(&mut self, portal: usize, nodes: [usize; 2]) {
+        nodes
+            .iter()
+            .for_each(|&node| self.nodes[node].portals.push(portal));
+
+        if let Some(portal) = self.portals.get_mut(portal) {
+            portal.nodes = nodes;
+        }
+    }
+
+    fn detach_portal(&mut self, portal: usize) {
+        let nodes = self.portals[portal].nodes;
+
+        nodes
+            .iter()
+            .for_each(|&node| self.nodes[node].portals.retain(|&p| p != portal));
+    }
+
+    /// Creates portals between every leaf, including a leaf outside of the world.
+    pub fn make_portals(&mut self) {
+        self.portals.clear();
+        self.nodes.iter_mut().for_each(|node| node.portals.clear());
+
+        // an empty tree still gets sensible bounds
+        let (mins, maxs) = if self.mins.cmple(self.maxs).all() {
+            (self.mins, self.maxs)
+        } else {
+            (DVec3::ZERO, DVec3::ZERO)
+        };
+
+        let bounds = [
+            mins - DVec3::splat(HEADNODE_PADDING),
+            maxs + DVec3::splat(HEADNODE_PADDING),
+        ];
+
+        let outside = match self.outside {
+            Some(outside) => outside,
+            None => {
+                self.nodes
+                    .push(BspNode::leaf(None, LeafContent::ContentsEmpty, vec![]));
+                self.nodes.len() - 1
+            }
+        };
+
+        self.outside = Some(outside);
+
+        // six planes facing inward
+        let planes = (0..2)
+            .flat_map(|side| (0..3).map(move |axis| (side, axis)))
+            .map(|(side, axis)| {
+                let mut normal = [0.; 3];
+
+                if side == 0 {
+                    normal[axis] = 1.;
+                    Plane3D::new(normal[0], normal[1], normal[2], bounds[0][axis])
+                } else {
+                    normal[axis] = -1.;
+                    Plane3D::new(normal[0], normal[1], normal[2], -bounds[1][axis])
+                }
+            })
+            .collect::<Vec<Plane3D>>();
+
+        for (idx, plane) in planes.iter().enumerate() {
+            let winding = planes
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != idx)
+                .fold(
+                    Polygon3D::base_winding(plane, BOGUS_RANGE),
+                    |winding, (_, other)| winding.clip(other, ON_EPSILON),
+                );
+
+            self.add_portal(Portal {
+                plane: *plane,
+                on_node: None,
+                nodes: [self.head, outside],
+                winding,
+            });
+        }
+
+        self.make_tree_portals_r(self.head);
+
+        self.nodes
+            .iter_mut()
+            .filter(|node| node.is_leaf())
+            .for_each(|node| {
+                node.mins = DVec3::MAX;
+                node.maxs = DVec3::MIN;
+            });
+
+        for portal in &self.portals {
+            let (mins, maxs) = point_bounds(portal.winding.vertices().iter().copied());
+
+            for node in portal.nodes {
+                let node = &mut self.nodes[node];
+
+                node.mins = node.mins.min(mins);
+                node.maxs = node.maxs.max(maxs);
+            }
+        }
+    }
+
+    fn make_tree_portals_r(&mut self, node: usize) {
+        if self.nodes[node].is_leaf() {
+            return;
+        }
+
+        self.make_node_portal(node);
+        self.split_node_portals(node);
+
+        let [front, back] = self.nodes[node].children;
+
+        self.make_tree_portals_r(front);
+        self.make_tree_portals_r(back);
+    }
+
+    /// Big winding on the node plane, clipped by all parent planes
+    fn base_winding_for_node(&self, node: usize) -> Polygon3D {
+        let plane = self.nodes[node].plane.expect("node must not be a leaf");
+        let mut winding = Polygon3D::base_winding(self.planes.get(plane), BOGUS_RANGE);
+
+        let mut child = node;
+
+        while let Some(parent) = self.nodes[child].parent {
+            if winding.vertices().is_empty() {
+                break;
+            }
+
+            let parent_plane = self.nodes[parent].plane.expect("parent must not be a leaf");
+
+            // keep the side the child is on
+            let plane = if self.nodes[parent].children[0] == child {
+                parent_plane
+            } else {
+                parent_plane ^ 1
+            };
+
+            winding = winding.clip(self.planes.get(plane), SPLIT_EPSILON);
+            child = parent;
+        }
+
+        winding
+    }
+
+    fn make_node_portal(&mut self, node: usize) {
+        let mut winding = self.base_winding_for_node(node);
+
+        for &portal in &self.nodes[node].portals {
+            if winding.vertices().is_empty() {
+                break;
+            }
+
+            let portal = &self.portals[portal];
+
+            let plane = if portal.nodes[0] == node {
+                portal.plane
+            } else {
+                portal.plane.get_backplane()
+            };
+
+            winding = winding.clip(&plane, ON_EPSILON);
+        }
+
+        if winding.vertices().is_empty() || winding.is_tiny(EDGE_LENGTH) {
+            return;
+        }
+
+        let plane = self.nodes[node].plane.expect("node must not be a leaf");
+
+        self.add_portal(Portal {
+            plane: *self.planes.get(plane),
+            on_node: Some(node),
+            nodes: self.nodes[node].children,
+            winding,
+        });
+    }
+
+    /// Moves the portals of a node down to its children, splitting them if needed
+    fn split_node_portals(&mut self, node: usize) {
+        let plane = *self
+            .planes
+            .get(self.nodes[node].plane.expect("node must not be a leaf"));
+        let [front, back] = self.nodes[node].children;
+
+        let portals = std::mem::take(&mut self.nodes[node].portals);
+
+        for portal in portals {
+            let side = if self.portals[portal].nodes[0] == node {
+                0
+            } else {
+                1
+            };
+            let other = self.portals[portal].nodes[1 - side];
+
+            self.detach_portal(portal);
+
+            let (front_winding, back_winding) =
+                self.portals[portal].winding.chop(&plane, SPLIT_EPSILON);
+
+            let front_winding = (!front_winding.vertices().is_empty()
+                && !front_winding.is_tiny(EDGE_LENGTH))
+            .then_some(front_winding);
+            let back_winding = (!back_winding.vertices().is_empty()
+                && !back_winding.is_tiny(EDGE_LENGTH))
+            .then_some(back_winding);
+
+            let with_child = |child: usize| {
+                if side == 0 {
+                    [child, other]
+                } else {
+                    [other, child]
+                }
+            };
+
+            match (front_winding, back_winding) {
+                // tiny on both sides, the portal is gone
+                (None, None) => (),
+                (Some(_), None) => self.attach_portal(portal, with_child(front)),
+                (None, Some(_)) => self.attach_portal(portal, with_child(back)),
+                (Some(front_winding), Some(back_winding)) => {
+                    self.portals[portal].winding = front_winding;
+                    self.attach_portal(portal, with_child(front));
+
+                    let new_portal = Portal {
+                        winding: back_winding,
+                        nodes: with_child(back),
+                        ..self.portals[portal].clone()
+                    };
+
+                    self.add_portal(new_portal);
+                }
+            }
+        }
+    }
+
+    /// Portals connecting the leaf to the rest of the tree
+    pub fn leaf_portals(&self, leaf: usize) -> impl Iterator<Item = (usize, &Portal)> {
+        self.nodes[leaf]
+            .portals
+            .iter()
+            .map(|&portal| (portal, &self.portals[portal]))
+    }
+
+    /// Whether something can pass through the portal
+    pub fn is_portal_passable(&self, portal: usize) -> bool {
+        self.portals[portal]
+            .nodes
+            .iter()
+            .all(|&node| !is_opaque(self.nodes[node].contents))
+    }
+
+    /// Breadth first flood fill through passable portals.
+    ///
+    /// Returns the distance in portals from `start` to every node, `None` if not reached,
+    /// along with the portal used to get there.
+    pub fn flood(&self, start: usize) -> Vec<Option<(usize, Option<usize>)>> {
+        let mut res: Vec<Option<(usize, Option<usize>)>> = vec![None; self.nodes.len()];
+
+        if is_opaque(self.nodes[start].contents) {
+            return res;
+        }
+
+        let mut queue = VecDeque::from([start]);
+        res[start] = Some((0, None));
+
+        while let Some(node) = queue.pop_front() {
+            let (distance, _) = res[node].unwrap();
+
+            for (portal_idx, portal) in self.leaf_portals(node) {
+                if !self.is_portal_passable(portal_idx) {
+                    continue;
+                }
+
+                let other = portal.other(node);
+
+                if res[other].is_some() {
+                    continue;
+                }
+
+                res[other] = Some((distance + 1, Some(portal_idx)));
+                queue.push_back(other);
+            }
+        }
+
+        res
+    }
+
+    /// Path of portals from `leaf` back to the start of a flood.
+    pub fn flood_path(&self, flood: &[Option<(usize, Option<usize>)>], leaf: usize) -> Vec<usize> {
+        let mut res = vec![];
+        let mut node = leaf;
+
+        while let Some((_, Some(portal))) = flood[node] {
+            res.push(portal);
+            node = self.portals[portal].other(node);
+        }
+
+        res
+    }
+
+    /// Turns every leaf that cannot be reached from inside into solid.
+    ///
+    /// Returns the number of leaves filled.
+    pub fn fill_outside(&mut self, inside: &[Point3D]) -> usize {
+        let mut reached = vec![false; self.nodes.len()];
+
+        for &point in inside {
+            let leaf = self.point_in_leaf(point);
+
+            self.flood(leaf)
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.is_some())
+                .for_each(|(node, _)| reached[node] = true);
+        }
+
+        let mut count = 0;
+
+        self.nodes
+            .iter_mut()
+            .enumerate()
+            .filter(|(idx, node)| node.is_leaf() && !reached[*idx])
+            .for_each(|(_, node)| {
+                if !is_opaque(node.contents) {
+                    node.contents = LeafContent::ContentsSolid;
+                    count += 1;
+                }
+            });
+
+        count
+    }
+}
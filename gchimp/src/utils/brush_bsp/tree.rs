@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+
+use bsp::LeafContent;
+use glam::DVec3;
+
+use crate::utils::simple_calculs::Point3D;
+
+use super::{BspBrush, PlaneSet, PlaneSide, contents_priority};
+
+#[derive(Debug, Clone)]
+pub struct BspNode {
+    /// Index into [`PlaneSet`], `None` for leaves
+    pub plane: Option<usize>,
+    /// Front and back child
+    pub children: [usize; 2],
+    pub parent: Option<usize>,
+    /// Leaf contents, only meaningful for leaves
+    pub contents: LeafContent,
+    /// Brushes that fill the leaf, only meaningful for leaves
+    pub brushes: Vec<BspBrush>,
+    /// Indices into [`BrushTree::portals`]
+    pub portals: Vec<usize>,
+    /// Bounds calculated from portals
+    pub mins: DVec3,
+    pub maxs: DVec3,
+}
+
+impl BspNode {
+    pub(super) fn leaf(
+        parent: Option<usize>,
+        contents: LeafContent,
+        brushes: Vec<BspBrush>,
+    ) -> Self {
+        Self {
+            plane: None,
+            children: [0; 2],
+            parent,
+            contents,
+            brushes,
+            portals: vec![],
+            mins: DVec3::MAX,
+            maxs: DVec3::MIN,
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.plane.is_none()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BrushTree {
+    pub planes: PlaneSet,
+    pub nodes: Vec<BspNode>,
+    pub head: usize,
+    /// Leaf surrounding the whole world, only exists after portals are made
+    pub outside: Option<usize>,
+    pub portals: Vec<super::Portal>,
+    /// Bounds of all brushes
+    pub mins: DVec3,
+    pub maxs: DVec3,
+}
+
+impl BrushTree {
+    /// Builds a tree out of brushes with outward facing sides.
+    ///
+    /// The brushes should be made with the same `planes`.
+    pub fn build(planes: PlaneSet, brushes: Vec<BspBrush>) -> Self {
+        let (mins, maxs) = brushes
+            .iter()
+            .fold((DVec3::MAX, DVec3::MIN), |(mins, maxs), brush| {
+                (mins.min(brush.mins), maxs.max(brush.maxs))
+            });
+
+        let mut res = Self {
+            planes,
+            nodes: vec![],
+            head: 0,
+            outside: None,
+            portals: vec![],
+            mins,
+            maxs,
+        };
+
+        res.head = res.build_r(brushes, None);
+
+        res
+    }
+
+    fn build_r(&mut self, mut brushes: Vec<BspBrush>, parent: Option<usize>) -> usize {
+        let Some(split_plane) = self.select_split_plane(&brushes) else {
+            let contents = brushes
+                .iter()
+                .map(|brush| brush.contents)
+                .max_by_key(|contents| contents_priority(*contents))
+                .unwrap_or(LeafContent::ContentsEmpty);
+
+            self.nodes.push(BspNode::leaf(parent, contents, brushes));
+
+            return self.nodes.len() - 1;
+        };
+
+        let mut front = vec![];
+        let mut back = vec![];
+
+        brushes.iter_mut().for_each(|brush| {
+            // once a plane is used, it cannot be used again below this node
+            brush
+                .sides
+                .iter_mut()
+                .filter(|side| side.plane | 1 == split_plane | 1)
+                .for_each(|side| side.tested = true);
+        });
+
+        for brush in brushes {
+            match brush.test_plane(&self.planes, split_plane).0 {
+                PlaneSide::Front => front.push(brush),
+                PlaneSide::Back => back.push(brush),
+                PlaneSide::Both => {
+                    let (f, b) = brush.split(&self.planes, split_plane);
+
+                    front.extend(f);
+                    back.extend(b);
+                }
+            }
+        }
+
+        // mark the new sides from splitting as tested as well
+        front.iter_mut().chain(back.iter_mut()).for_each(|brush| {
+            brush
+                .sides
+                .iter_mut()
+                .filter(|side| side.plane | 1 == split_plane | 1)
+                .for_each(|side| side.tested = true);
+        });
+
+        let node_idx = self.nodes.len();
+
+        self.nodes.push(BspNode {
+            plane: Some(split_plane),
+            ..BspNode::leaf(parent, LeafContent::Unknown, vec![])
+        });
+
+        let front = self.build_r(front, Some(node_idx));
+        let back = self.build_r(back, Some(node_idx));
+
+        self.nodes[node_idx].children = [front, back];
+
+        node_idx
+    }
+
+    /// Picks the brush side plane that splits the least while balancing the tree.
+    ///
+    /// Always returns the plane facing the positive axis.
+    fn select_split_plane(&self, brushes: &[BspBrush]) -> Option<usize> {
+        // sides from the original brushes are tried first
+        for visible_only in [true, false] {
+            let candidates = brushes
+                .iter()
+                .flat_map(|brush| brush.sides.iter())
+                .filter(|side| !side.tested && (side.visible || !visible_only))
+                .map(|side| side.plane & !1)
+                .collect::<HashSet<usize>>();
+
+            let best = candidates
+                .into_iter()
+                .map(|plane| {
+                    let (mut front, mut back, mut splits, mut facing) = (0i64, 0i64, 0i64, 0i64);
+
+                    brushes.iter().for_each(|brush| {
+                        let (side, is_facing) = brush.test_plane(&self.planes, plane);
+
+                        match side {
+                            PlaneSide::Front => front += 1,
+                            PlaneSide::Back => back += 1,
+                            PlaneSide::Both => {
+                                front += 1;
+                                back += 1;
+                                splits += 1;
+                            }
+                        }
+
+                        if is_facing {
+                            facing += 1;
+                        }
+                    });
+
+                    let mut value = 5 * facing - 5 * splits - (front - back).abs();
+
+                    if self.planes.is_axial(plane) {
+                        value += 5;
+                    }
+
+                    (plane, value)
+                })
+                // prefer lower plane index on ties so the result is deterministic
+                .max_by(|(plane_a, a), (plane_b, b)| a.cmp(b).then(plane_b.cmp(plane_a)));
+
+            if let Some((plane, _)) = best {
+                return Some(plane);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the leaf containing the point.
+    ///
+    /// A point exactly on a plane goes to the front.
+    pub fn point_in_leaf(&self, point: Point3D) -> usize {
+        let mut node = self.head;
+
+        while let Some(plane) = self.nodes[node].plane {
+            let side = self.planes.get(plane).distance_to_point(point);
+
+            node = if side >= 0. {
+                self.nodes[node].children[0]
+            } else {
+                self.nodes[node].children[1]
+            };
+        }
+
+        node
+    }
+
+    pub fn leaves(&self) -> impl Iterator<Item = (usize, &BspNode)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.is_leaf())
+    }
+}
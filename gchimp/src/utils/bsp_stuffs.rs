@@ -0,0 +1,23 @@
+use bsp::Bsp;
+use common::constants::NoRenderTexture;
+
+/// Whether the texture is drawn as it is in game.
+///
+/// Tool textures are not drawn. Sky is not either, the engine draws the sky box on every texture
+/// starting with `sky` instead.
+pub fn is_drawn_texture(name: &str) -> bool {
+    let name = name.to_uppercase();
+
+    !name.starts_with("SKY") && !NoRenderTexture.contains(&name)
+}
+
+/// Same as [`is_drawn_texture`] with the texture of the face
+pub fn is_drawn_face(bsp: &Bsp, face_idx: usize) -> bool {
+    let texinfo = &bsp.texinfo[bsp.faces[face_idx].texinfo as usize];
+
+    is_drawn_texture(
+        &bsp.textures[texinfo.texture_index as usize]
+            .texture_name
+            .get_string(),
+    )
+}
@@ -143,7 +143,7 @@ pub fn demo_ghost_parse(filename: &str, demo: &Demo) -> eyre::Result<GhostInfo>
                     viewangles: Vec3::from_array(viewangles),
                     frametime: Some(frame.time as f64), /* time here is accummulative, will fix
                                                          * after */
-                    buttons: None,
+                    buttons: Some(box_type.as_ref().1.info.usercmd.buttons as u32),
                     anim: Some(GhostFrameAnim {
                         sequence,
                         frame: anim_frame,
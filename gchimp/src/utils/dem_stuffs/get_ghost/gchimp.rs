@@ -0,0 +1,131 @@
+//! gchimp ghost, `.gchimp.json`
+//!
+//! Keeps everything [`GhostFrame`] has so nothing is lost going through it.
+//!
+//! ```json
+//! {
+//!     "name": "run",
+//!     "frames": [
+//!         {
+//!             "origin": [0.0, 0.0, 36.0],
+//!             "viewangles": [0.0, 90.0, 0.0],
+//!             "frametime": 0.01,
+//!             "buttons": 2,
+//!             "fov": 90.0,
+//!             "anim": {
+//!                 "sequence": 4,
+//!                 "frame": 0.5,
+//!                 "animtime": 1.25,
+//!                 "gaitsequence": 1,
+//!                 "blending": [127, 0]
+//!             }
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! Origin and viewangles are in GoldSrc coordinates and degrees.
+//! Frametime is how long the frame lasts in seconds.
+//! Every field except frames and their origin and viewangles can be left out.
+//! The file name is used when there is no name.
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GchimpGhostInfo {
+    #[serde(default)]
+    name: String,
+    frames: Vec<GchimpGhostFrame>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GchimpGhostFrame {
+    origin: [f32; 3],
+    viewangles: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frametime: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    buttons: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fov: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anim: Option<GchimpGhostFrameAnim>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GchimpGhostFrameAnim {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frame: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    animtime: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gaitsequence: Option<i32>,
+    #[serde(default)]
+    blending: [u8; 2],
+}
+
+pub fn gchimp_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
+    let pathbuf = PathBuf::from(filename.to_owned());
+    let file = std::fs::read_to_string(pathbuf)?;
+
+    let gchimp_ghost: GchimpGhostInfo = serde_json::from_str(&file)?;
+
+    let ghost_name = if gchimp_ghost.name.is_empty() {
+        filename.to_owned()
+    } else {
+        gchimp_ghost.name
+    };
+
+    Ok(GhostInfo {
+        ghost_name,
+        frames: gchimp_ghost
+            .frames
+            .into_iter()
+            .map(|ghost| GhostFrame {
+                origin: Vec3::from_array(ghost.origin),
+                viewangles: Vec3::from_array(ghost.viewangles),
+                frametime: ghost.frametime,
+                buttons: ghost.buttons,
+                anim: ghost.anim.map(|anim| GhostFrameAnim {
+                    sequence: anim.sequence,
+                    frame: anim.frame,
+                    animtime: anim.animtime,
+                    gaitsequence: anim.gaitsequence,
+                    blending: anim.blending,
+                }),
+                fov: ghost.fov,
+            })
+            .collect(),
+    })
+}
+
+pub fn gchimp_ghost_write(ghost: &GhostInfo) -> eyre::Result<String> {
+    let gchimp_ghost = GchimpGhostInfo {
+        name: ghost.ghost_name.clone(),
+        frames: ghost
+            .frames
+            .iter()
+            .map(|frame| GchimpGhostFrame {
+                origin: frame.origin.to_array(),
+                viewangles: frame.viewangles.to_array(),
+                frametime: frame.frametime,
+                buttons: frame.buttons,
+                fov: frame.fov,
+                anim: frame.anim.as_ref().map(|anim| GchimpGhostFrameAnim {
+                    sequence: anim.sequence,
+                    frame: anim.frame,
+                    animtime: anim.animtime,
+                    gaitsequence: anim.gaitsequence,
+                    blending: anim.blending,
+                }),
+            })
+            .collect(),
+    };
+
+    Ok(serde_json::to_string_pretty(&gchimp_ghost)?)
+}
@@ -1,5 +1,6 @@
 use std::array::from_fn;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use dem::open_demo;
 use dem::types::Demo;
@@ -8,12 +9,14 @@ use glam::{FloatExt, Vec3};
 use crate::err;
 
 use self::demo::demo_ghost_parse;
+use self::gchimp::{gchimp_ghost_parse, gchimp_ghost_write};
 // use rayon::prelude::*;
-use self::romanian_jumpers::romanian_jumpers_ghost_parse;
-use self::simen::simen_ghost_parse;
-use self::surf_gateway::surf_gateway_ghost_parse;
+use self::romanian_jumpers::{romanian_jumpers_ghost_parse, romanian_jumpers_ghost_write};
+use self::simen::{simen_ghost_parse, simen_ghost_write};
+use self::surf_gateway::{surf_gateway_ghost_parse, surf_gateway_ghost_write};
 
 mod demo;
+pub mod gchimp;
 mod romanian_jumpers;
 mod simen;
 mod surf_gateway;
@@ -22,25 +25,96 @@ pub fn get_ghost_demo(filename: &str, demo: &Demo) -> eyre::Result<GhostInfo> {
     demo_ghost_parse(filename, demo)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GhostFormat {
+    Demo,
+    Simen,
+    SurfGateway,
+    RomanianJumpers,
+    Gchimp,
+}
+
+impl GhostFormat {
+    pub const ALL: [GhostFormat; 5] = [
+        GhostFormat::Demo,
+        GhostFormat::Simen,
+        GhostFormat::SurfGateway,
+        GhostFormat::RomanianJumpers,
+        GhostFormat::Gchimp,
+    ];
+
+    /// End of the file name, including the extension
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            GhostFormat::Demo => ".dem",
+            GhostFormat::Simen => ".simen.txt",
+            GhostFormat::SurfGateway => ".sg.json",
+            GhostFormat::RomanianJumpers => ".rj.json",
+            GhostFormat::Gchimp => ".gchimp.json",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+
+        Self::ALL
+            .into_iter()
+            .find(|format| name.ends_with(format.suffix()))
+    }
+
+    /// File name without the suffix
+    pub fn file_stem(&self, path: &Path) -> String {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        name.strip_suffix(self.suffix())
+            .unwrap_or(&name)
+            .to_string()
+    }
+}
+
+impl FromStr for GhostFormat {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dem" | "demo" => Ok(GhostFormat::Demo),
+            "simen" => Ok(GhostFormat::Simen),
+            "sg" | "surf_gateway" => Ok(GhostFormat::SurfGateway),
+            "rj" | "romanian_jumpers" => Ok(GhostFormat::RomanianJumpers),
+            "gchimp" => Ok(GhostFormat::Gchimp),
+            _ => err!("Unknown ghost format {s}"),
+        }
+    }
+}
+
 pub fn get_ghost(filename: &str) -> eyre::Result<GhostInfo> {
     let pathbuf = PathBuf::from(filename);
-    let path = pathbuf.to_str().unwrap();
-
-    if path.ends_with(".dem") {
-        let demo = open_demo(pathbuf)?;
-
-        demo_ghost_parse(filename, &demo)
-    } else if path.ends_with(".simen.txt") {
-        // Either this, or use enum in main file.
-        simen_ghost_parse(filename)
-    } else if path.ends_with(".sg.json") {
-        // Surf Gateway
-        surf_gateway_ghost_parse(filename)
-    } else if path.ends_with(".rj.json") {
-        // Romanian-Jumpers
-        romanian_jumpers_ghost_parse(filename)
-    } else {
-        err!("Unknown ghost file extension.")
+
+    match GhostFormat::from_path(&pathbuf) {
+        Some(GhostFormat::Demo) => {
+            let demo = open_demo(pathbuf)?;
+
+            demo_ghost_parse(filename, &demo)
+        }
+        Some(GhostFormat::Simen) => simen_ghost_parse(filename),
+        Some(GhostFormat::SurfGateway) => surf_gateway_ghost_parse(filename),
+        Some(GhostFormat::RomanianJumpers) => romanian_jumpers_ghost_parse(filename),
+        Some(GhostFormat::Gchimp) => gchimp_ghost_parse(filename),
+        None => err!("Unknown ghost file extension."),
+    }
+}
+
+/// Writes the ghost in a format other than demo.
+///
+/// Surf Gateway ghosts say which map they are on so it has to be given.
+/// Demos need the map itself, they are made with ghost2dem.
+pub fn write_ghost(ghost: &GhostInfo, format: GhostFormat, map_name: &str) -> eyre::Result<String> {
+    match format {
+        GhostFormat::Demo => err!("Demos are made with ghost2dem"),
+        GhostFormat::Simen => simen_ghost_write(ghost),
+        GhostFormat::SurfGateway => surf_gateway_ghost_write(ghost, map_name),
+        GhostFormat::RomanianJumpers => romanian_jumpers_ghost_write(ghost),
+        GhostFormat::Gchimp => gchimp_ghost_write(ghost),
     }
 }
 
@@ -75,83 +149,56 @@ impl GhostInfo {
     ///
     /// Takes an optional argument to force frametime.
     pub fn get_frame(&self, time: f64, frametime: Option<f64>) -> Option<GhostFrame> {
-        let frame0 = self.frames.first()?;
+        interpolate_frame(&self.frames, time, frametime).map(|(frame, _)| frame)
+    }
 
-        // No frame time, not sure how to accumulate correctly
-        if frame0.frametime.is_none() && frametime.is_none() {
-            return None;
+    /// Interpolated frames every `frametime` seconds.
+    ///
+    /// Takes an optional argument to force frametime of the ghost like [`GhostInfo::get_frame`].
+    pub fn resample(
+        &self,
+        frametime: f64,
+        ghost_frametime: Option<f64>,
+    ) -> eyre::Result<GhostInfo> {
+        if frametime <= 0. {
+            return err!("Frametime must be positive");
         }
 
-        let mut from_time = 0f64;
-        let mut to_time = 0f64;
-        let mut to_index = 0usize;
+        let mut frames: Vec<GhostFrame> = vec![];
 
-        for (index, frame) in self.frames.iter().enumerate() {
-            let add_time = if let Some(frametime) = frametime {
-                frametime
-            } else {
-                frame.frametime.unwrap()
-            };
+        // starting later so it does not go through all the frames every time
+        let mut start = 0usize;
+        let mut start_time = 0f64;
 
-            // only exit when greater means we are having the "to" frame
-            if to_time > time {
+        loop {
+            let time = frames.len() as f64 * frametime;
+
+            let Some((mut frame, to_index)) =
+                interpolate_frame(&self.frames[start..], time - start_time, ghost_frametime)
+            else {
                 break;
-            }
+            };
 
-            from_time = to_time;
-            to_time += add_time;
-            to_index = index;
-        }
+            frame.frametime = Some(frametime);
+            frames.push(frame);
 
-        if to_index == 0 {
-            return Some(frame0.clone());
-        }
+            // it interpolates from the frame before
+            let new_start = start + to_index.saturating_sub(1);
 
-        // If exceeding the number of available frames then we have nothing.
-        // This is to make sure that we know when it ends.
-        if to_index == self.frames.len() - 1 && time >= to_time {
-            return None;
+            start_time += self.frames[start..new_start]
+                .iter()
+                .map(|frame| ghost_frametime.or(frame.frametime).unwrap_or_default())
+                .sum::<f64>();
+            start = new_start;
         }
 
-        let to_frame = self.frames.get(to_index)?;
-
-        let from_frame = self.frames.get(to_index - 1).unwrap();
-
-        let target = (time - from_time) / (to_time - from_time);
-        // clamp because vec lerp extrapolates as well.
-        let target = target.clamp(0., 1.);
-
-        let new_origin = from_frame.origin.lerp(to_frame.origin, target as f32);
-
-        let viewangles_diff: [f32; 3] = from_fn(|i| {
-            angle_diff(
-                // normalize is not what we want as we are in between +/-
-                from_frame.viewangles[i],
-                to_frame.viewangles[i],
-            )
-        });
-        let viewangles_diff = Vec3::from(viewangles_diff);
-        let new_viewangles = from_frame
-            .viewangles
-            // attention, lerp to `from + diff`
-            .lerp(from_frame.viewangles + viewangles_diff, target as f32);
-
-        let new_fov = if let Some(from_fov) = from_frame.fov
-            && let Some(to_fov) = to_frame.fov
-        {
-            Some(from_fov.lerp(to_fov, target as f32))
-        } else {
-            None
-        };
+        if frames.is_empty() {
+            return err!("Ghost has no frametime to resample from");
+        }
 
-        // Maybe do some interpolation for sequence in the future? Though only demo would have it.
-        Some(GhostFrame {
-            origin: new_origin,
-            viewangles: new_viewangles,
-            frametime: from_frame.frametime,
-            buttons: from_frame.buttons,
-            anim: from_frame.anim.clone(),
-            fov: new_fov,
+        Ok(GhostInfo {
+            ghost_name: self.ghost_name.clone(),
+            frames,
         })
     }
 
@@ -199,3 +246,92 @@ pub fn angle_diff(curr: f32, next: f32) -> f32 {
 
     (-(curr - next).sin()).asin().to_degrees()
 }
+
+/// [`GhostInfo::get_frame`] with the index of the frame it goes to
+fn interpolate_frame(
+    frames: &[GhostFrame],
+    time: f64,
+    frametime: Option<f64>,
+) -> Option<(GhostFrame, usize)> {
+    let frame0 = frames.first()?;
+
+    // No frame time, not sure how to accumulate correctly
+    if frame0.frametime.is_none() && frametime.is_none() {
+        return None;
+    }
+
+    let mut from_time = 0f64;
+    let mut to_time = 0f64;
+    let mut to_index = 0usize;
+
+    for (index, frame) in frames.iter().enumerate() {
+        let add_time = if let Some(frametime) = frametime {
+            frametime
+        } else {
+            frame.frametime.unwrap()
+        };
+
+        // only exit when greater means we are having the "to" frame
+        if to_time > time {
+            break;
+        }
+
+        from_time = to_time;
+        to_time += add_time;
+        to_index = index;
+    }
+
+    if to_index == 0 {
+        return Some((frame0.clone(), 0));
+    }
+
+    // If exceeding the number of available frames then we have nothing.
+    // This is to make sure that we know when it ends.
+    if to_index == frames.len() - 1 && time >= to_time {
+        return None;
+    }
+
+    let to_frame = frames.get(to_index)?;
+
+    let from_frame = frames.get(to_index - 1).unwrap();
+
+    let target = (time - from_time) / (to_time - from_time);
+    // clamp because vec lerp extrapolates as well.
+    let target = target.clamp(0., 1.);
+
+    let new_origin = from_frame.origin.lerp(to_frame.origin, target as f32);
+
+    let viewangles_diff: [f32; 3] = from_fn(|i| {
+        angle_diff(
+            // normalize is not what we want as we are in between +/-
+            from_frame.viewangles[i],
+            to_frame.viewangles[i],
+        )
+    });
+    let viewangles_diff = Vec3::from(viewangles_diff);
+    let new_viewangles = from_frame
+        .viewangles
+        // attention, lerp to `from + diff`
+        .lerp(from_frame.viewangles + viewangles_diff, target as f32);
+
+    let new_fov = if let Some(from_fov) = from_frame.fov
+        && let Some(to_fov) = to_frame.fov
+    {
+        Some(from_fov.lerp(to_fov, target as f32))
+    } else {
+        None
+    };
+
+    // Maybe do some interpolation for sequence in the future? Though only demo would have it.
+    Some((
+        GhostFrame {
+            origin: new_origin,
+            viewangles: new_viewangles,
+            frametime: from_frame.frametime,
+            buttons: from_frame.buttons,
+            anim: from_frame.anim.clone(),
+            fov: new_fov,
+        },
+        to_index,
+    ))
+}
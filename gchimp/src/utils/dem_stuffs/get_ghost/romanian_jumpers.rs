@@ -45,3 +45,28 @@ pub fn romanian_jumpers_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
             .collect(),
     })
 }
+
+/// Every frame needs a frametime
+pub fn romanian_jumpers_ghost_write(ghost: &GhostInfo) -> eyre::Result<String> {
+    let mut time = 0f32;
+    let mut frames = vec![];
+
+    for (frame_idx, frame) in ghost.frames.iter().enumerate() {
+        let Some(frametime) = frame.frametime else {
+            return err!("Ghost frame {frame_idx} has no frametime");
+        };
+
+        frames.push(RjGhostFrame {
+            // their Y is up
+            origin: [frame.origin[0], frame.origin[2], -frame.origin[1]],
+            viewangles: [frame.viewangles[0], frame.viewangles[1]],
+            frametime: frametime as f32,
+            time,
+            buttons: frame.buttons.unwrap_or(0),
+        });
+
+        time += frametime as f32;
+    }
+
+    Ok(serde_json::to_string(&RjGhostInfo { frames })?)
+}
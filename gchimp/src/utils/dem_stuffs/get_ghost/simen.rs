@@ -16,6 +16,7 @@ struct SimenGhostFrame {
     moves: [f32; 2],
 }
 
+/// Frames do not have their own frametime so it is the time in the header spread evenly
 pub fn simen_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
     let pathbuf = PathBuf::from(filename.to_owned());
     let file = std::fs::read_to_string(pathbuf)?;
@@ -23,7 +24,7 @@ pub fn simen_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
     // clippy is wrong
     #[allow(clippy::let_and_return)]
     let res = match map(
-        preceded(
+        tuple((
             simen_wrbot_header,
             all_consuming(delimited(
                 opt(multispace0),
@@ -35,10 +36,20 @@ pub fn simen_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
                 ),
                 opt(multispace0),
             )),
-        ),
-        |frames| GhostInfo {
-            ghost_name: filename.to_owned(),
-            frames,
+        )),
+        |(time, mut frames)| {
+            let frametime = time
+                .filter(|&time| time > 0. && !frames.is_empty())
+                .map(|time| time as f64 / frames.len() as f64);
+
+            frames
+                .iter_mut()
+                .for_each(|frame| frame.frametime = frametime);
+
+            GhostInfo {
+                ghost_name: filename.to_owned(),
+                frames,
+            }
         },
     )(&file)
     {
@@ -50,24 +61,86 @@ pub fn simen_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
     res
 }
 
-fn simen_wrbot_header(i: &str) -> IResult<&str, u8> {
+/// Velocity comes from the frametime, it is zero without
+pub fn simen_ghost_write(ghost: &GhostInfo) -> eyre::Result<String> {
+    let total_time: f64 = ghost
+        .frames
+        .iter()
+        .filter_map(|frame| frame.frametime)
+        .sum();
+
+    // time, name, steamid, date, location and the unknown line
+    let mut res = format!(
+        "{:.3}\n{}\nSTEAM_ID_LAN\n0\n0\n0\n",
+        total_time, ghost.ghost_name
+    );
+
+    let lines = ghost
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(frame_idx, frame)| {
+            let velocity = frame_idx
+                .checked_sub(1)
+                .and_then(|prev_idx| {
+                    let prev = &ghost.frames[prev_idx];
+                    let frametime = prev.frametime? as f32;
+
+                    (frametime > 0.).then(|| (frame.origin - prev.origin) / frametime)
+                })
+                .unwrap_or(Vec3::ZERO);
+
+            format!(
+                "{} {} {} {} {} {} {} {} {} 0 0",
+                frame.viewangles[0],
+                frame.viewangles[1],
+                frame.origin[0],
+                frame.origin[1],
+                frame.origin[2],
+                velocity[0],
+                velocity[1],
+                velocity[2],
+                frame.buttons.unwrap_or(0)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    res += &lines;
+
+    Ok(res)
+}
+
+/// Total time of the run, if it is a number
+fn simen_wrbot_header(i: &str) -> IResult<&str, Option<f32>> {
     map(
         tuple((
-            skip_line, // Time
+            take_till(|c| c == '\n'), // Time
+            take(1usize),
             skip_line, // Name
             skip_line, // SteamID
             skip_line, // Date
             skip_line, // Location
             skip_line, // ??
         )),
-        |_| 0u8,
+        |(time, _, _, _, _, _, _): (&str, _, _, _, _, _, _)| time.trim().parse().ok(),
     )(i)
 }
 
 fn simen_wrbot_line(i: &str) -> IResult<&str, SimenGhostFrame> {
     map(
         tuple((
-            float, float, float, float, float, float, float, float, u32, float, float,
+            float,
+            float,
+            float,
+            float,
+            float,
+            float,
+            float,
+            float,
+            preceded(space0, u32),
+            float,
+            float,
         )),
         |(pitch, yaw, posx, posy, posz, velx, vely, velz, buttons, move1, move2)| SimenGhostFrame {
             frame: GhostFrame {
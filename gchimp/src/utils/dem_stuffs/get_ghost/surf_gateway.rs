@@ -23,7 +23,8 @@ struct SgGhostFrame {
     moves: [f32; 3],
     buttons: u32,
     impulses: u32,
-    frametime: u32, // This one is something else.
+    /// Milliseconds like usercmd msec
+    frametime: u32,
 }
 
 pub fn surf_gateway_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
@@ -49,3 +50,54 @@ pub fn surf_gateway_ghost_parse(filename: &str) -> eyre::Result<GhostInfo> {
             .collect(),
     })
 }
+
+/// The map name is only written down, nothing is checked against it.
+///
+/// Start velocity comes from the first two frames, it is zero without frametime.
+pub fn surf_gateway_ghost_write(ghost: &GhostInfo, map_name: &str) -> eyre::Result<String> {
+    let time: f64 = ghost
+        .frames
+        .iter()
+        .filter_map(|frame| frame.frametime)
+        .sum();
+
+    let startvel = match ghost.frames.as_slice() {
+        [first, second, ..] => first
+            .frametime
+            .filter(|&frametime| frametime > 0.)
+            .map(|frametime| (second.origin - first.origin) / frametime as f32)
+            .unwrap_or(Vec3::ZERO),
+        _ => Vec3::ZERO,
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or(0);
+
+    let surf_gateway_ghost = SgGhostInfo {
+        map: map_name.to_owned(),
+        timestamp,
+        name: ghost.ghost_name.clone(),
+        authid: "STEAM_ID_LAN".to_owned(),
+        time: time as f32,
+        startvel: startvel.to_array(),
+        frames: ghost
+            .frames
+            .iter()
+            .map(|frame| SgGhostFrame {
+                origin: frame.origin.to_array(),
+                viewangles: frame.viewangles.to_array(),
+                moves: [0.; 3],
+                buttons: frame.buttons.unwrap_or(0),
+                impulses: 0,
+                frametime: frame
+                    .frametime
+                    .map(|frametime| (frametime * 1000.).round() as u32)
+                    .unwrap_or(0),
+            })
+            .collect(),
+    };
+
+    Ok(serde_json::to_string(&surf_gateway_ghost)?)
+}
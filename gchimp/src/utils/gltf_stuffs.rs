@@ -0,0 +1,376 @@
+//! Just enough glTF 2.0 to write meshes with textures, skins and animations.
+//!
+//! Everything goes into one `.gltf` with a `.bin` buffer next to it. Images are referenced by URI.
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const REPEAT: u32 = 10497;
+const LINEAR: u32 = 9729;
+const LINEAR_MIPMAP_LINEAR: u32 = 9987;
+
+/// One draw call worth of triangles
+#[derive(Debug, Default, Clone)]
+pub struct GltfPrimitive {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// `TEXCOORD_0`, `TEXCOORD_1` and so on, same length as positions
+    pub uvs: Vec<Vec<[f32; 2]>>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+    /// `JOINTS_0` and `WEIGHTS_0`, empty for static meshes
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+impl GltfPrimitive {
+    /// Adds a vertex and returns its index
+    pub fn push_vertex(&mut self, position: [f32; 3], normal: [f32; 3], uvs: &[[f32; 2]]) -> u32 {
+        if self.uvs.len() < uvs.len() {
+            self.uvs.resize(uvs.len(), vec![]);
+        }
+
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs
+            .iter_mut()
+            .zip(uvs)
+            .for_each(|(set, uv)| set.push(*uv));
+
+        self.positions.len() as u32 - 1
+    }
+
+    /// Adds a vertex fully weighted to one joint and returns its index
+    pub fn push_skinned_vertex(
+        &mut self,
+        position: [f32; 3],
+        normal: [f32; 3],
+        uvs: &[[f32; 2]],
+        joint: u16,
+    ) -> u32 {
+        self.joints.push([joint, 0, 0, 0]);
+        self.weights.push([1., 0., 0., 0.]);
+
+        self.push_vertex(position, normal, uvs)
+    }
+}
+
+/// Texture names like `{blue` are not valid URIs
+fn uri_encode(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct GltfBuilder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    materials: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    skins: Vec<Value>,
+    animations: Vec<Value>,
+    /// Nodes without parent
+    scene_nodes: Vec<usize>,
+}
+
+impl GltfBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Animation and skin data have no target
+    fn add_buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // accessors need 4 bytes alignment
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+
+        let mut buffer_view = json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+        });
+
+        if let Some(target) = target {
+            buffer_view["target"] = target.into();
+        }
+
+        self.buffer_views.push(buffer_view);
+        self.buffer.extend_from_slice(bytes);
+
+        self.buffer_views.len() - 1
+    }
+
+    /// Adds `VEC2` or `VEC3` float data and returns the accessor index
+    pub fn add_vectors<const N: usize>(&mut self, values: &[[f32; N]]) -> usize {
+        self.add_float_accessor(values, Some(ARRAY_BUFFER))
+    }
+
+    /// Adds `SCALAR` float data like keyframe times and returns the accessor index
+    pub fn add_scalars(&mut self, values: &[f32]) -> usize {
+        let values = values
+            .iter()
+            .map(|&value| [value])
+            .collect::<Vec<[f32; 1]>>();
+
+        self.add_float_accessor(&values, None)
+    }
+
+    /// Adds `VEC3` or `VEC4` float data like keyframe values and returns the accessor index
+    pub fn add_animation_vectors<const N: usize>(&mut self, values: &[[f32; N]]) -> usize {
+        self.add_float_accessor(values, None)
+    }
+
+    /// Adds column-major `MAT4` data like inverse bind matrices and returns the accessor index
+    pub fn add_matrices(&mut self, values: &[[f32; 16]]) -> usize {
+        self.add_float_accessor(values, None)
+    }
+
+    /// Adds `VEC4` joint indices and returns the accessor index
+    pub fn add_joints(&mut self, joints: &[[u16; 4]]) -> usize {
+        let bytes = joints
+            .iter()
+            .flatten()
+            .flat_map(|joint| joint.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        let buffer_view = self.add_buffer_view(&bytes, Some(ARRAY_BUFFER));
+
+        self.accessors.push(json!({
+            "bufferView": buffer_view,
+            "componentType": UNSIGNED_SHORT,
+            "count": joints.len(),
+            "type": "VEC4",
+        }));
+
+        self.accessors.len() - 1
+    }
+
+    fn add_float_accessor<const N: usize>(
+        &mut self,
+        values: &[[f32; N]],
+        target: Option<u32>,
+    ) -> usize {
+        let bytes = values
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        let buffer_view = self.add_buffer_view(&bytes, target);
+
+        let (min, max) = values.iter().fold(
+            ([f32::MAX; N], [f32::MIN; N]),
+            |(mut min, mut max), value| {
+                (0..N).for_each(|i| {
+                    min[i] = min[i].min(value[i]);
+                    max[i] = max[i].max(value[i]);
+                });
+
+                (min, max)
+            },
+        );
+
+        let type_ = match N {
+            1 => "SCALAR".to_string(),
+            16 => "MAT4".to_string(),
+            _ => format!("VEC{N}"),
+        };
+
+        let mut accessor = json!({
+            "bufferView": buffer_view,
+            "componentType": FLOAT,
+            "count": values.len(),
+            "type": type_,
+        });
+
+        // only needed for positions and keyframe times but it does not hurt
+        if N <= 4 {
+            accessor["min"] = min.to_vec().into();
+            accessor["max"] = max.to_vec().into();
+        }
+
+        self.accessors.push(accessor);
+
+        self.accessors.len() - 1
+    }
+
+    pub fn add_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes = indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        let buffer_view = self.add_buffer_view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+
+        self.accessors.push(json!({
+            "bufferView": buffer_view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+
+        self.accessors.len() - 1
+    }
+
+    /// Adds an image relative to the `.gltf` and returns its texture index
+    pub fn add_texture(&mut self, path: &str) -> usize {
+        self.images.push(json!({ "uri": uri_encode(path) }));
+        self.textures.push(json!({
+            "source": self.images.len() - 1,
+            "sampler": 0,
+        }));
+
+        self.textures.len() - 1
+    }
+
+    pub fn add_material(&mut self, material: Value) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// Primitives without triangles are left out
+    pub fn add_mesh(&mut self, name: &str, primitives: &[GltfPrimitive]) -> usize {
+        let primitives = primitives
+            .iter()
+            .filter(|primitive| !primitive.indices.is_empty())
+            .map(|primitive| {
+                let mut attributes = json!({
+                    "POSITION": self.add_vectors(&primitive.positions),
+                    "NORMAL": self.add_vectors(&primitive.normals),
+                });
+
+                primitive.uvs.iter().enumerate().for_each(|(set, uvs)| {
+                    attributes[format!("TEXCOORD_{set}")] = self.add_vectors(uvs).into();
+                });
+
+                if !primitive.joints.is_empty() {
+                    attributes["JOINTS_0"] = self.add_joints(&primitive.joints).into();
+                    attributes["WEIGHTS_0"] = self.add_vectors(&primitive.weights).into();
+                }
+
+                let mut res = json!({
+                    "attributes": attributes,
+                    "indices": self.add_indices(&primitive.indices),
+                });
+
+                if let Some(material) = primitive.material {
+                    res["material"] = material.into();
+                }
+
+                res
+            })
+            .collect::<Vec<Value>>();
+
+        self.meshes.push(json!({
+            "name": name,
+            "primitives": primitives,
+        }));
+
+        self.meshes.len() - 1
+    }
+
+    /// Adds a node under `parent` or at the root of the scene
+    pub fn add_node(&mut self, node: Value, parent: Option<usize>) -> usize {
+        self.nodes.push(node);
+
+        let node_idx = self.nodes.len() - 1;
+
+        match parent {
+            Some(parent) => {
+                let children = self.nodes[parent]
+                    .as_object_mut()
+                    .expect("node is an object")
+                    .entry("children")
+                    .or_insert_with(|| json!([]));
+
+                children
+                    .as_array_mut()
+                    .expect("children is an array")
+                    .push(node_idx.into());
+            }
+            None => self.scene_nodes.push(node_idx),
+        }
+
+        node_idx
+    }
+
+    pub fn add_skin(&mut self, skin: Value) -> usize {
+        self.skins.push(skin);
+        self.skins.len() - 1
+    }
+
+    pub fn add_animation(&mut self, animation: Value) -> usize {
+        self.animations.push(animation);
+        self.animations.len() - 1
+    }
+
+    /// Writes the `.gltf` and a `.bin` with the same name
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let bin_path = path.with_extension("bin");
+        let bin_name = bin_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+
+        let mut root = json!({
+            "asset": {
+                "version": "2.0",
+                "generator": "gchimp",
+            },
+            "scene": 0,
+            "scenes": [{ "nodes": self.scene_nodes }],
+            "samplers": [{
+                "magFilter": LINEAR,
+                "minFilter": LINEAR_MIPMAP_LINEAR,
+                "wrapS": REPEAT,
+                "wrapT": REPEAT,
+            }],
+        });
+
+        // empty arrays are not allowed
+        for (key, values) in [
+            ("nodes", &self.nodes),
+            ("bufferViews", &self.buffer_views),
+            ("accessors", &self.accessors),
+            ("images", &self.images),
+            ("textures", &self.textures),
+            ("materials", &self.materials),
+            ("meshes", &self.meshes),
+            ("skins", &self.skins),
+            ("animations", &self.animations),
+        ] {
+            if !values.is_empty() {
+                root[key] = values.clone().into();
+            }
+        }
+
+        if !self.buffer.is_empty() {
+            root["buffers"] = json!([{
+                "uri": bin_name,
+                "byteLength": self.buffer.len(),
+            }]);
+        }
+
+        std::fs::write(&bin_path, &self.buffer)?;
+        std::fs::write(path, serde_json::to_string_pretty(&root)?)?;
+
+        Ok(())
+    }
+}
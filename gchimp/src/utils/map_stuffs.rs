@@ -332,6 +332,15 @@ pub fn textures_used_in_entity(entity: &Entity) -> HashSet<String> {
     acc
 }
 
+/// Entities whose brushes are part of the world, compiled into it and sealing it
+pub const WORLD_ENTITIES: &[&str] = &["worldspawn", "func_group", "func_detail"];
+
+pub fn is_world_entity(entity: &Entity) -> bool {
+    entity
+        .classname()
+        .is_some_and(|classname| WORLD_ENTITIES.contains(&classname.as_str()))
+}
+
 /// Creates a .map rectangular prism brush from two lists of mins and maxs
 pub fn brush_from_mins_maxs(mins: DVec3, maxs: DVec3, texture: &str) -> Brush {
     let (rotation, u_scale, v_scale) = (0., 1., 1.);
@@ -473,6 +482,67 @@ pub fn brush_from_mins_maxs(mins: DVec3, maxs: DVec3, texture: &str) -> Brush {
     }
 }
 
+/// Box room made of 6 brushes around the origin, without ceiling when `skip_top`
+/// Box with no top when `top_texture` is `None`
+#[cfg(test)]
+pub fn hollow_box(size: f64, thickness: f64, top_texture: Option<&str>) -> Vec<Brush> {
+    let (inner, outer) = (size, size + thickness);
+
+    let mut res = vec![
+        brush_from_mins_maxs([-outer; 3].into(), [outer, outer, -inner].into(), "A"),
+        brush_from_mins_maxs(
+            [-outer, -outer, -inner].into(),
+            [-inner, outer, inner].into(),
+            "A",
+        ),
+        brush_from_mins_maxs(
+            [inner, -outer, -inner].into(),
+            [outer, outer, inner].into(),
+            "A",
+        ),
+        brush_from_mins_maxs(
+            [-inner, -outer, -inner].into(),
+            [inner, -inner, inner].into(),
+            "A",
+        ),
+        brush_from_mins_maxs(
+            [-inner, inner, -inner].into(),
+            [inner, outer, inner].into(),
+            "A",
+        ),
+    ];
+
+    if let Some(top_texture) = top_texture {
+        res.push(brush_from_mins_maxs(
+            [-outer, -outer, inner].into(),
+            [outer; 3].into(),
+            top_texture,
+        ));
+    }
+
+    res
+}
+
+/// Worldspawn with `brushes` and an info_player_start at `origin`
+#[cfg(test)]
+pub fn map_with(brushes: Vec<Brush>, origin: &str) -> Map {
+    let mut map = Map::new();
+
+    map.entities.push(Entity {
+        attributes: map::Attributes::from([("classname".to_string(), "worldspawn".to_string())]),
+        brushes: Some(brushes),
+    });
+    map.entities.push(Entity {
+        attributes: map::Attributes::from([
+            ("classname".to_string(), "info_player_start".to_string()),
+            ("origin".to_string(), origin.to_string()),
+        ]),
+        brushes: None,
+    });
+
+    map
+}
+
 pub fn convert_used_texture_to_uppercase(map: &mut Map) {
     map.entities.iter_mut().for_each(|entity| {
         if let Some(brushes) = entity.brushes.as_mut() {
@@ -1,4 +1,7 @@
+pub mod brush_bsp;
+pub mod bsp_stuffs;
 pub mod dem_stuffs;
+pub mod gltf_stuffs;
 pub mod map_stuffs;
 pub mod mdl_stuffs;
 pub mod misc;
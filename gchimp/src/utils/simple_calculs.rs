@@ -571,6 +571,18 @@ impl Plane3D {
             w: new_w,
         }
     }
+
+    /// Returns the same plane with unit normal
+    pub fn normalized(&self) -> Self {
+        self.expand(0.)
+    }
+
+    /// Signed distance from a point to the plane, positive on the inside.
+    ///
+    /// Plane must be normalized for the result to be in map units.
+    pub fn distance_to_point(&self, point: Point3D) -> f64 {
+        self.normal().dot(point) - self.w
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -894,6 +906,144 @@ impl Polygon3D {
         Self(res)
     }
 
+    /// Creates a big square polygon lying on the plane
+    ///
+    /// Clip it with other planes to get the actual face, just like the compilers do.
+    pub fn base_winding(plane: &Plane3D, size: f64) -> Self {
+        let plane = plane.normalized();
+        let normal = plane.normal();
+
+        // pick the axis least aligned with the normal as "up"
+        let up: Point3D = if normal.z.abs() > normal.x.abs() && normal.z.abs() > normal.y.abs() {
+            [1., 0., 0.].into()
+        } else {
+            [0., 0., 1.].into()
+        };
+
+        let up = (up - normal * up.dot(normal)).normalize() * size;
+        let right = up.cross(normal);
+        let origin = normal * plane.distance();
+
+        Self(vec![
+            origin - right + up,
+            origin + right + up,
+            origin + right - up,
+            origin - right - up,
+        ])
+    }
+
+    /// Keeps the part of the polygon that is on the inside of the plane.
+    ///
+    /// Vertices must be in winding order. Returns an empty polygon if everything is clipped away.
+    pub fn clip(&self, plane: &Plane3D, epsilon: f64) -> Self {
+        let plane = plane.normalized();
+        let mut res = Self::default();
+
+        (0..self.0.len()).for_each(|idx| {
+            let curr = self.0[idx];
+            let next = self.0[(idx + 1) % self.0.len()];
+
+            let d1 = plane.distance_to_point(curr);
+            let d2 = plane.distance_to_point(next);
+
+            if d1 >= -epsilon {
+                res.add_vertex(curr);
+            }
+
+            if (d1 > epsilon && d2 < -epsilon) || (d1 < -epsilon && d2 > epsilon) {
+                let t = d1 / (d1 - d2);
+                res.add_vertex(curr + (next - curr) * t);
+            }
+        });
+
+        if res.0.len() < 3 {
+            return Self::default();
+        }
+
+        res
+    }
+
+    /// Splits the polygon into the part in front of the plane and the part behind it.
+    ///
+    /// A polygon lying on the plane goes to the back, like the compilers do.
+    pub fn chop(&self, plane: &Plane3D, epsilon: f64) -> (Self, Self) {
+        let plane = plane.normalized();
+        let distances = self
+            .0
+            .iter()
+            .map(|vertex| plane.distance_to_point(*vertex))
+            .collect::<Vec<f64>>();
+
+        let front_count = distances.iter().filter(|d| **d > epsilon).count();
+        let back_count = distances.iter().filter(|d| **d < -epsilon).count();
+
+        if front_count == 0 {
+            return (Self::default(), self.clone());
+        }
+
+        if back_count == 0 {
+            return (self.clone(), Self::default());
+        }
+
+        let mut front = Self::default();
+        let mut back = Self::default();
+
+        (0..self.0.len()).for_each(|idx| {
+            let next_idx = (idx + 1) % self.0.len();
+            let (curr, next) = (self.0[idx], self.0[next_idx]);
+            let (d1, d2) = (distances[idx], distances[next_idx]);
+
+            if d1 >= -epsilon {
+                front.add_vertex(curr);
+            }
+
+            if d1 <= epsilon {
+                back.add_vertex(curr);
+            }
+
+            if (d1 > epsilon && d2 < -epsilon) || (d1 < -epsilon && d2 > epsilon) {
+                let t = d1 / (d1 - d2);
+                let mid = curr + (next - curr) * t;
+
+                front.add_vertex(mid);
+                back.add_vertex(mid);
+            }
+        });
+
+        if front.0.len() < 3 {
+            front = Self::default();
+        }
+
+        if back.0.len() < 3 {
+            back = Self::default();
+        }
+
+        (front, back)
+    }
+
+    /// Returns true if the polygon has fewer than 3 edges that are long enough to matter
+    pub fn is_tiny(&self, edge_length: f64) -> bool {
+        (0..self.0.len())
+            .filter(|idx| (self.0[(idx + 1) % self.0.len()] - self.0[*idx]).length() > edge_length)
+            .count()
+            < 3
+    }
+
+    /// Area of a planar polygon with vertices in winding order
+    pub fn area(&self) -> f64 {
+        if self.0.len() < 3 {
+            return 0.;
+        }
+
+        let first = self.0[0];
+
+        (1..self.0.len() - 1)
+            .map(|idx| (self.0[idx] - first).cross(self.0[idx + 1] - first))
+            .fold(Point3D::default(), |acc, e| acc + e)
+            .length()
+            / 2.
+    }
+
     pub fn get_geogebra_points(&self) -> String {
         let mut res = String::new();
 
@@ -1390,6 +1540,57 @@ impl Solid3D {
     pub fn expand(&self, distance: f64) -> Self {
         Self(self.0.iter().map(|plane| plane.expand(distance)).collect())
     }
+
+    /// Returns one polygon for each face, in the same order as the faces.
+    ///
+    /// Faces that do not end up on the solid are empty polygons.
+    pub fn face_polygons(&self, size: f64, epsilon: f64) -> Vec<Polygon3D> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(face_idx, face)| {
+                let mut polygon = Polygon3D::base_winding(face, size);
+
+                for (other_idx, other) in self.0.iter().enumerate() {
+                    if other_idx == face_idx {
+                        continue;
+                    }
+
+                    polygon = polygon.clip(other, epsilon);
+
+                    if polygon.vertices().is_empty() {
+                        break;
+                    }
+                }
+
+                polygon
+            })
+            .collect()
+    }
+
+    /// Volume of the solid, computed from its face polygons
+    pub fn volume(&self, size: f64, epsilon: f64) -> f64 {
+        let polygons = self.face_polygons(size, epsilon);
+        let vertices = polygons
+            .iter()
+            .flat_map(|polygon| polygon.vertices())
+            .collect::<Vec<&Point3D>>();
+
+        if vertices.len() < 4 {
+            return 0.;
+        }
+
+        let center =
+            vertices.iter().fold(Point3D::default(), |acc, e| acc + **e) / vertices.len() as f64;
+
+        polygons
+            .iter()
+            .zip(&self.0)
+            .map(|(polygon, face)| {
+                polygon.area() * face.normalized().distance_to_point(center).abs() / 3.
+            })
+            .sum()
+    }
 }
 
 impl From<Vec<Plane3D>> for Solid3D {
@@ -1398,6 +1599,18 @@ impl From<Vec<Plane3D>> for Solid3D {
     }
 }
 
+/// Newell's method, normal of the polygon with twice its area as length.
+///
+/// Points towards the side the vertices are counter-clockwise from.
+pub fn newell_normal<T: Into<DVec3> + Copy>(vertices: &[T]) -> DVec3 {
+    (0..vertices.len()).fold(DVec3::ZERO, |acc, idx| {
+        let curr: DVec3 = vertices[idx].into();
+        let next: DVec3 = vertices[(idx + 1) % vertices.len()].into();
+
+        acc + curr.cross(next)
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1592,4 +1805,27 @@ mod test {
         assert_eq!(res[0].vertices().len(), 4);
         assert_eq!(res[1].vertices().len(), 3);
     }
+
+    #[test]
+    fn solid_face_polygons() {
+        // 64 unit cube with normals pointing inward
+        let solid = Solid3D(vec![
+            Plane3D::new(1., 0., 0., 0.),
+            Plane3D::new(-1., 0., 0., -64.),
+            Plane3D::new(0., 1., 0., 0.),
+            Plane3D::new(0., -1., 0., -64.),
+            Plane3D::new(0., 0., 1., 0.),
+            Plane3D::new(0., 0., -1., -64.),
+        ]);
+
+        let polygons = solid.face_polygons(8192., 0.01);
+
+        assert_eq!(polygons.len(), 6);
+        polygons.iter().for_each(|polygon| {
+            assert_eq!(polygon.vertices().len(), 4);
+            assert!((polygon.area() - 64. * 64.).abs() < 0.01);
+        });
+
+        assert!((solid.volume(8192., 0.01) - 64. * 64. * 64.).abs() < 0.1);
+    }
 }
@@ -4,7 +4,7 @@ use std::{
 };
 
 use eyre::eyre;
-use wad::types::{FileEntry, Wad};
+use wad::types::{FileEntry, MipTex, Palette, Wad};
 
 use common::img_stuffs::write_8bpp_to_file;
 
@@ -178,3 +178,14 @@ pub fn export_texture(
         Err(eyre!("Cannot find texture: {}", texture_name))
     }
 }
+
+/// Keeps only the name and dimensions so the game loads the texture from a WAD
+pub fn external_miptex(miptex: MipTex) -> MipTex {
+    MipTex {
+        mip_offsets: vec![0; 4],
+        mip_images: vec![],
+        colors_used: 0,
+        palette: Palette::new(vec![]),
+        ..miptex
+    }
+}
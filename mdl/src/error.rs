@@ -26,6 +26,25 @@ pub enum MdlError {
     #[error("Too many textures in model: {len}")]
     TooManyTextures { len: usize },
 
+    #[error("Cannot find texture {index}")]
+    TextureNotFound { index: usize },
+    #[error("Texture name `{name}` is longer than 63 bytes")]
+    TextureNameTooLong { name: String },
+    #[error("Texture name `{name}` is already used")]
+    DuplicateTextureName { name: String },
+    #[error("Texture image has {len} pixels but dimensions {width}x{height}")]
+    BadTextureData { len: usize, width: u32, height: u32 },
+    #[error("Model textures are stored in an external file")]
+    ExternalTextures,
+    #[error("Cannot find skin family {index}")]
+    SkinFamilyNotFound { index: usize },
+    #[error("Skin family has {len} entries but model has {expected} textures")]
+    BadSkinFamilyLength { len: usize, expected: usize },
+    #[error("Skin family references texture {texture} which does not exist")]
+    BadSkinFamilyTexture { texture: i16 },
+    #[error("Cannot remove the only skin family")]
+    LastSkinFamily,
+
     #[error("Intermediate mesh is not built. Try invoking [`Mdl.maybe_build_agnostic_data()`]")]
     AgnosticMeshNotBuilt,
     #[error("IOError: {source}")]
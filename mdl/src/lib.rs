@@ -13,7 +13,7 @@ mod writer;
 pub use constants::*;
 pub use types::Mdl;
 pub use types::*;
-pub use utils::TrivertAffineTransformation;
+pub use utils::{TrivertAffineTransformation, name_to_string};
 
 #[cfg(test)]
 mod test {
@@ -262,4 +262,160 @@ mod test {
         mdl.write_to_file("/home/khang/gchimp/examples/skybox/cyberwave0_parse_write.mdl")
             .unwrap();
     }
+
+    fn max_s(mdl: &Mdl) -> i16 {
+        mdl.bodyparts
+            .iter()
+            .flat_map(|bp| bp.models.iter())
+            .flat_map(|model| model.meshes.iter())
+            .flat_map(|mesh| mesh.triangles.iter())
+            .flat_map(|tris| match tris {
+                crate::MeshTriangles::Strip(triverts) | crate::MeshTriangles::Fan(triverts) => {
+                    triverts.iter().map(|trivert| trivert.header.s)
+                }
+            })
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn edit_textures_write_parse() {
+        let bytes = include_bytes!("./tests/chick.mdl");
+        let mut mdl = Mdl::open_from_bytes(bytes).unwrap();
+
+        let original = mdl.textures[0].clone();
+        let (width, height) = original.dimensions();
+        let original_max_s = max_s(&mdl);
+
+        // twice the width
+        let image: Vec<u8> = original
+            .image
+            .iter()
+            .flat_map(|&pixel| [pixel, pixel])
+            .collect();
+
+        mdl.replace_texture(0, image, original.palette, (width * 2, height))
+            .unwrap();
+        mdl.rename_texture(0, "renamed.bmp").unwrap();
+        mdl.set_texture_flag(0, crate::TextureFlag::CHROME, true)
+            .unwrap();
+
+        let mut alternate = original.clone();
+        alternate.header.name = [0; 64];
+        alternate.header.name[..13].copy_from_slice(b"alternate.bmp");
+
+        let alternate_index = mdl.add_texture(alternate).unwrap();
+        let family = mdl.add_skin_family(vec![alternate_index as i16]).unwrap();
+
+        mdl.rebuild_data_for_export();
+
+        let mdl = Mdl::open_from_bytes(&mdl.write_to_bytes()).unwrap();
+
+        assert_eq!(mdl.find_texture("RENAMED.BMP"), Some(0));
+        assert_eq!(mdl.textures[0].dimensions(), (width * 2, height));
+        assert!(
+            mdl.textures[0]
+                .header
+                .flags
+                .contains(crate::TextureFlag::CHROME)
+        );
+        assert_eq!(mdl.find_texture("alternate.bmp"), Some(alternate_index));
+        assert_eq!(mdl.skin_families.len(), family + 1);
+        assert_eq!(mdl.skin_families[family][0], alternate_index as i16);
+        assert_eq!(mdl.skin_families[0].len(), mdl.textures.len());
+        assert!((max_s(&mdl) - original_max_s * 2).abs() <= 2);
+    }
+
+    #[test]
+    fn edit_textures_errors() {
+        let bytes = include_bytes!("./tests/chick.mdl");
+        let mut mdl = Mdl::open_from_bytes(bytes).unwrap();
+
+        assert!(
+            mdl.replace_texture(0, vec![0; 3], [[0; 3]; 256], (2, 2))
+                .is_err()
+        );
+        assert!(mdl.rename_texture(mdl.textures.len(), "a.bmp").is_err());
+        assert!(mdl.rename_texture(0, &"a".repeat(64)).is_err());
+        assert!(mdl.add_skin_family(vec![-1]).is_err());
+        assert!(mdl.remove_skin_family(0).is_err());
+
+        let bytes = include_bytes!("./tests/orange.mdl");
+        let mut mdl = Mdl::open_from_bytes(bytes).unwrap();
+
+        assert!(
+            mdl.set_texture_flag(0, crate::TextureFlag::MASKED, true)
+                .is_err()
+        );
+    }
+
+    /// Triangles of every model with corners rotated so the smallest comes first
+    fn sorted_triangles(mdl: &mut Mdl) -> Vec<Vec<[[u64; 5]; 3]>> {
+        mdl.rebuild_data_for_export();
+
+        mdl.bodyparts
+            .iter()
+            .flat_map(|bp| bp.models.iter())
+            .map(|model| {
+                let mut tris: Vec<[[u64; 5]; 3]> = model
+                    .agnostic_mesh
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .map(|tri| {
+                        let corners: [[u64; 5]; 3] = std::array::from_fn(|i| {
+                            let v = &tri.vertices[i];
+
+                            [
+                                v.pos.x.to_bits(),
+                                v.pos.y.to_bits(),
+                                v.pos.z.to_bits(),
+                                v.uv.x.to_bits(),
+                                v.uv.y.to_bits(),
+                            ]
+                        });
+
+                        let min = (0..3).min_by_key(|&i| corners[i]).unwrap();
+
+                        std::array::from_fn(|i| corners[(min + i) % 3])
+                    })
+                    .collect();
+
+                tris.sort();
+                tris
+            })
+            .collect()
+    }
+
+    fn trivert_count(mdl: &Mdl) -> (usize, usize) {
+        mdl.bodyparts
+            .iter()
+            .flat_map(|bp| bp.models.iter())
+            .flat_map(|model| model.meshes.iter())
+            .flat_map(|mesh| mesh.triangles.iter())
+            .fold((0, 0), |(runs, triverts), run| {
+                (runs + 1, triverts + run.get_triverts().len())
+            })
+    }
+
+    #[test]
+    fn strip_fan_write_parse() {
+        for bytes in [
+            include_bytes!("./tests/static_tree.mdl").as_slice(),
+            include_bytes!("./tests/chick.mdl").as_slice(),
+        ] {
+            let mut mdl = Mdl::open_from_bytes(bytes).unwrap();
+            let original = sorted_triangles(&mut mdl);
+            let triangle_count = mdl.triangle_count();
+
+            let mut written = Mdl::open_from_bytes(&mdl.write_to_bytes()).unwrap();
+            let (runs, triverts) = trivert_count(&written);
+
+            // one run of 3 per triangle without strips and fans
+            assert!(runs < triangle_count);
+            assert!(triverts < triangle_count * 3);
+
+            assert_eq!(sorted_triangles(&mut written), original);
+        }
+    }
 }
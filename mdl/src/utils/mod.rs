@@ -4,6 +4,14 @@ use crate::{AnimValues, Bone, Header, Hitbox, MeshTriangles, SequenceGroup, Triv
 use crate::{Mdl, Sequence, SequenceHeader};
 
 mod model_to_smd;
+mod texture;
+
+/// Text of a fixed size name like bone or texture names, up to the first null
+pub fn name_to_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
 
 impl Bone {
     pub fn new_empty() -> Self {
@@ -183,8 +191,7 @@ impl Mdl {
         })
     }
 
-    /// In order to export the model file, must invoke this function before exporting.
-    pub fn rebuild_data_for_export(&mut self) {
+    fn maybe_build_agnostic_data(&mut self) {
         // only rebuild mesh if agnostic mesh is all empty
         if !self.has_agnostic_mesh_data() {
             self.bodyparts.iter_mut().for_each(|bodypart| {
@@ -194,7 +201,11 @@ impl Mdl {
                     .for_each(|model| model.build_agnostic_data(&self.textures))
             });
         }
+    }
 
+    /// In order to export the model file, must invoke this function before exporting.
+    pub fn rebuild_data_for_export(&mut self) {
+        self.maybe_build_agnostic_data();
         self.maybe_build_skin_families();
         self.maybe_build_extents();
     }
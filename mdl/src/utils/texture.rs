@@ -0,0 +1,202 @@
+use crate::{Mdl, PALETTE_COUNT, Texture, TextureFlag, error::MdlError, name_to_string};
+
+impl Texture {
+    pub fn name(&self) -> String {
+        name_to_string(&self.header.name)
+    }
+}
+
+// Texture editing operations.
+//
+// Mesh data is converted to agnostic mesh before any change so texture coordinates survive new
+// texture dimensions and triangles follow renamed textures.
+// The model can be written with [`Mdl::write_to_file`] afterwards.
+impl Mdl {
+    /// Index of the texture with the given name, case insensitive
+    pub fn find_texture(&self, name: &str) -> Option<usize> {
+        self.textures
+            .iter()
+            .position(|texture| texture.name().eq_ignore_ascii_case(name))
+    }
+
+    fn check_texture_index(&self, index: usize) -> Result<(), MdlError> {
+        if self.textures.is_empty() {
+            return Err(MdlError::ExternalTextures);
+        }
+
+        if index >= self.textures.len() {
+            return Err(MdlError::TextureNotFound { index });
+        }
+
+        Ok(())
+    }
+
+    /// Replaces texture pixels. Dimensions can be different from the current ones.
+    pub fn replace_texture(
+        &mut self,
+        index: usize,
+        image: Vec<u8>,
+        palette: [[u8; 3]; PALETTE_COUNT],
+        dimensions: (u32, u32),
+    ) -> Result<(), MdlError> {
+        self.check_texture_index(index)?;
+
+        if image.len() != (dimensions.0 * dimensions.1) as usize {
+            return Err(MdlError::BadTextureData {
+                len: image.len(),
+                width: dimensions.0,
+                height: dimensions.1,
+            });
+        }
+
+        self.maybe_build_agnostic_data();
+
+        let texture = &mut self.textures[index];
+
+        texture.image = image;
+        texture.palette = palette;
+        texture.header.width = dimensions.0 as i32;
+        texture.header.height = dimensions.1 as i32;
+
+        Ok(())
+    }
+
+    pub fn rename_texture(&mut self, index: usize, name: &str) -> Result<(), MdlError> {
+        self.check_texture_index(index)?;
+
+        // last byte is null terminator
+        if name.len() >= 64 {
+            return Err(MdlError::TextureNameTooLong {
+                name: name.to_string(),
+            });
+        }
+
+        if self
+            .find_texture(name)
+            .is_some_and(|other_index| other_index != index)
+        {
+            return Err(MdlError::DuplicateTextureName {
+                name: name.to_string(),
+            });
+        }
+
+        self.maybe_build_agnostic_data();
+
+        let old_name = self.textures[index].name();
+
+        self.bodyparts
+            .iter_mut()
+            .flat_map(|bodypart| bodypart.models.iter_mut())
+            .filter_map(|model| model.agnostic_mesh.as_mut())
+            .flatten()
+            .filter(|tri| tri.material == old_name)
+            .for_each(|tri| tri.material = name.to_string());
+
+        let mut new_name = [0u8; 64];
+        new_name[..name.len()].copy_from_slice(name.as_bytes());
+
+        self.textures[index].header.name = new_name;
+
+        Ok(())
+    }
+
+    pub fn set_texture_flag(
+        &mut self,
+        index: usize,
+        flag: TextureFlag,
+        enabled: bool,
+    ) -> Result<(), MdlError> {
+        self.check_texture_index(index)?;
+
+        self.textures[index].header.flags.set(flag, enabled);
+
+        Ok(())
+    }
+
+    /// Adds a texture that is not used by any mesh, for new skin families.
+    ///
+    /// Returns the new texture index.
+    pub fn add_texture(&mut self, texture: Texture) -> Result<usize, MdlError> {
+        if self.textures.is_empty() {
+            return Err(MdlError::ExternalTextures);
+        }
+
+        if self.textures.len() >= crate::MAX_TEXTURE {
+            return Err(MdlError::TooManyTextures {
+                len: self.textures.len() + 1,
+            });
+        }
+
+        let name = texture.name();
+
+        if self.find_texture(&name).is_some() {
+            return Err(MdlError::DuplicateTextureName { name });
+        }
+
+        self.maybe_build_agnostic_data();
+        self.maybe_build_skin_families();
+
+        self.textures.push(texture);
+        self.fit_skin_families();
+
+        Ok(self.textures.len() - 1)
+    }
+
+    // skin reference count is written as texture count so every family must cover all textures
+    fn fit_skin_families(&mut self) {
+        let texture_count = self.textures.len();
+
+        self.skin_families.iter_mut().for_each(|family| {
+            let start = family.len();
+
+            if start < texture_count {
+                family.extend(start as i16..texture_count as i16);
+            }
+        });
+    }
+
+    /// Adds a skin family mapping each texture to another texture.
+    ///
+    /// A family shorter than the texture count keeps the remaining textures as they are.
+    ///
+    /// Returns the new skin family index.
+    pub fn add_skin_family(&mut self, family: Vec<i16>) -> Result<usize, MdlError> {
+        if self.textures.is_empty() {
+            return Err(MdlError::ExternalTextures);
+        }
+
+        if family.len() > self.textures.len() {
+            return Err(MdlError::BadSkinFamilyLength {
+                len: family.len(),
+                expected: self.textures.len(),
+            });
+        }
+
+        if let Some(&texture) = family
+            .iter()
+            .find(|&&texture| texture < 0 || texture as usize >= self.textures.len())
+        {
+            return Err(MdlError::BadSkinFamilyTexture { texture });
+        }
+
+        self.maybe_build_skin_families();
+
+        self.skin_families.push(family);
+        self.fit_skin_families();
+
+        Ok(self.skin_families.len() - 1)
+    }
+
+    /// Returns the removed skin family
+    pub fn remove_skin_family(&mut self, index: usize) -> Result<Vec<i16>, MdlError> {
+        if index >= self.skin_families.len() {
+            return Err(MdlError::SkinFamilyNotFound { index });
+        }
+
+        if self.skin_families.len() == 1 {
+            return Err(MdlError::LastSkinFamily);
+        }
+
+        Ok(self.skin_families.remove(index))
+    }
+}
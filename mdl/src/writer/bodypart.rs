@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use byte_writer::ByteWriter;
 use glam::Vec3;
 
 use crate::{
-    Bodypart, BodypartHeader, MeshHeader, Model, ModelHeader, Texture,
+    Bodypart, BodypartHeader, MeshHeader, MeshTriangles, Model, ModelHeader, Texture, Trivert,
+    TrivertHeader,
     error::MdlError,
     writer::{
         WriteToWriter,
@@ -98,9 +99,8 @@ impl WriteToWriterModel for Model {
             // unique normal is stored per mesh, not per model, very cool, FUCKFUCKFUCKFUCKFUCKFUCKFUCK
             let mut unique_normal_map = HashMap::new();
 
-            // ((vert, norm) (u, v))
             // all indices basically stores the vertex index buffer aka how many vertices
-            let mut all_indices = Vec::new();
+            let mut all_indices: Vec<[TriCorner; 3]> = Vec::new();
 
             // need to track mesh count differently so data is correct
             let mesh_norm_index_start = unique_norms.len() as i32;
@@ -139,7 +139,15 @@ impl WriteToWriterModel for Model {
                 all_indices.push(tri_indices);
             }
 
-            mesh_entries.push((skin_ref, all_indices, mesh_norm_index_start, mesh_num_norms));
+            let runs = build_triangle_runs(&all_indices, &unique_verts, &unique_norms);
+
+            mesh_entries.push((
+                skin_ref,
+                all_indices.len(),
+                runs,
+                mesh_norm_index_start,
+                mesh_num_norms,
+            ));
         }
 
         // write to file now
@@ -168,15 +176,15 @@ impl WriteToWriterModel for Model {
         // write the triangle runs aka mesh
         let mut tri_run_offsets = Vec::new();
 
-        for (_, mesh_tris, _, _) in &mesh_entries {
+        for (_, _, runs, _, _) in &mesh_entries {
             tri_run_offsets.push(writer.get_offset());
-            for tri in mesh_tris {
-                writer.append_i16(3); // always output as strip because we're livinging 2020
-                for &((vert, norm), (s, t)) in tri {
-                    writer.append_i16(vert); // vert_index
-                    writer.append_i16(norm); // norm_index
-                    writer.append_i16(s); // s
-                    writer.append_i16(t); // t
+            for run in runs {
+                writer.append_i16(run.len_and_type()); // negative for fan
+                for trivert in run.get_triverts() {
+                    writer.append_i16(trivert.header.vert_index); // vert_index
+                    writer.append_i16(trivert.header.norm_index); // norm_index
+                    writer.append_i16(trivert.header.s); // s
+                    writer.append_i16(trivert.header.t); // t
                 }
             }
             writer.append_i16(0); // End of this mesh's triangle runs
@@ -184,12 +192,12 @@ impl WriteToWriterModel for Model {
 
         // write mesh headers
         let mesh_index = writer.get_offset();
-        for (i, (skin_ref, mesh_tris, mesh_norm_index_start, mesh_num_norms)) in
+        for (i, (skin_ref, num_tris, _, mesh_norm_index_start, mesh_num_norms)) in
             mesh_entries.iter().enumerate()
         {
             let curr_mesh_index = writer.get_offset();
 
-            writer.append_i32(mesh_tris.len() as i32); // num_tris
+            writer.append_i32(*num_tris as i32); // num_tris
             writer.append_i32(tri_run_offsets[i] as i32); // tri_index
             writer.append_i32(*skin_ref); // skin_ref
 
@@ -221,6 +229,139 @@ impl WriteToWriterModel for Model {
     }
 }
 
+/// ((vert, norm) (s, t))
+type TriCorner = ((i16, i16), (i16, i16));
+
+/// Greedily joins triangles into strips and fans like studiomdl.
+///
+/// Starting from every unused triangle, tries all three rotations as both strip and fan then keeps
+/// the longest run. Winding is kept so triangles come back the same when parsed.
+fn build_triangle_runs(
+    tris: &[[TriCorner; 3]],
+    verts: &[Vec3],
+    norms: &[Vec3],
+) -> Vec<MeshTriangles> {
+    // directed edge -> triangles having that edge in their winding
+    let mut edges: HashMap<(TriCorner, TriCorner), Vec<usize>> = HashMap::new();
+
+    for (tri_idx, tri) in tris.iter().enumerate() {
+        for i in 0..3 {
+            edges
+                .entry((tri[i], tri[(i + 1) % 3]))
+                .or_default()
+                .push(tri_idx);
+        }
+    }
+
+    let mut used = vec![false; tris.len()];
+    let mut res = vec![];
+
+    for start in 0..tris.len() {
+        if used[start] {
+            continue;
+        }
+
+        let tri = tris[start];
+        let mut best: Option<(bool, Vec<TriCorner>, Vec<usize>)> = None;
+
+        for rotation in 0..3 {
+            let first = [
+                tri[rotation],
+                tri[(rotation + 1) % 3],
+                tri[(rotation + 2) % 3],
+            ];
+
+            // strip goes first so it wins ties
+            for is_strip in [true, false] {
+                let (corners, run_tris) = grow_run(first, start, is_strip, tris, &edges, &used);
+
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, _, best_tris)| run_tris.len() > best_tris.len())
+                {
+                    best = Some((is_strip, corners, run_tris));
+                }
+            }
+        }
+
+        let (is_strip, corners, run_tris) = best.expect("there is always one triangle");
+
+        run_tris
+            .into_iter()
+            .for_each(|tri_idx| used[tri_idx] = true);
+
+        let triverts = corners
+            .into_iter()
+            .map(|((vert_index, norm_index), (s, t))| Trivert {
+                header: TrivertHeader {
+                    vert_index,
+                    norm_index,
+                    s,
+                    t,
+                },
+                vertex: verts[vert_index as usize],
+                normal: norms[norm_index as usize],
+            })
+            .collect();
+
+        res.push(if is_strip {
+            MeshTriangles::Strip(triverts)
+        } else {
+            MeshTriangles::Fan(triverts)
+        });
+    }
+
+    res
+}
+
+/// Returns the corners of the run and the triangles it covers
+fn grow_run(
+    first: [TriCorner; 3],
+    start: usize,
+    is_strip: bool,
+    tris: &[[TriCorner; 3]],
+    edges: &HashMap<(TriCorner, TriCorner), Vec<usize>>,
+    used: &[bool],
+) -> (Vec<TriCorner>, Vec<usize>) {
+    let mut corners = first.to_vec();
+    let mut run_tris = vec![start];
+    let mut in_run = HashSet::from([start]);
+
+    // run length is stored as i16
+    while corners.len() < i16::MAX as usize {
+        // the next triangle shares this edge in its own winding
+        // strip flips every other triangle
+        let next_tri = corners.len() - 2;
+        let edge = if !is_strip {
+            (corners[0], corners[corners.len() - 1])
+        } else if next_tri.is_multiple_of(2) {
+            (corners[next_tri], corners[next_tri + 1])
+        } else {
+            (corners[next_tri + 1], corners[next_tri])
+        };
+
+        let Some(&tri_idx) = edges.get(&edge).and_then(|candidates| {
+            candidates
+                .iter()
+                .find(|&&tri_idx| !used[tri_idx] && !in_run.contains(&tri_idx))
+        }) else {
+            break;
+        };
+
+        let tri = &tris[tri_idx];
+        let third = (0..3)
+            .find(|&i| tri[i] == edge.0 && tri[(i + 1) % 3] == edge.1)
+            .map(|i| tri[(i + 2) % 3])
+            .expect("edge belongs to the triangle");
+
+        corners.push(third);
+        run_tris.push(tri_idx);
+        in_run.insert(tri_idx);
+    }
+
+    (corners, run_tris)
+}
+
 impl WriteToWriterModels for &[Model] {
     fn write_to_writer(&self, writer: &mut ByteWriter, textures: &[Texture]) -> usize {
         let headers: Vec<ModelHeader> = self